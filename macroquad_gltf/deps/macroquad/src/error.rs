@@ -6,6 +6,8 @@ pub enum Error {
         path: String,
     },
     ShaderError(miniquad::ShaderError),
+    GltfError(nanogltf::GltfError),
+    ImageError(String),
     //ImageError(image::ImageError),
     UnknownError(&'static str),
 }
@@ -22,6 +24,12 @@ impl From<miniquad::ShaderError> for Error {
     }
 }
 
+impl From<nanogltf::GltfError> for Error {
+    fn from(s: nanogltf::GltfError) -> Self {
+        Error::GltfError(s)
+    }
+}
+
 // impl From<image::ImageError> for Error {
 //     fn from(s: image::ImageError) -> Self {
 //         Error::ImageError(s)
@@ -0,0 +1,551 @@
+//! Write models back into .gltf and .glb files.
+//!
+//! The node hierarchy, transforms, geometry, material factors and lights are exported,
+//! textures, skins, morph targets and animations are not.
+
+use nanogltf::{
+    gltf::{
+        Accessor, Asset, Buffer, BufferView, BufferViewTarget, ComponentType, Extensions,
+        LightSpot, LightsPunctual, Material, Mesh, NodeExtensions, NodeLight, PBRMetallicRoughness,
+        Primitive, PrimitiveMode, Scene,
+    },
+    Gltf,
+};
+use quad_gl::{
+    math::{Vec2, Vec3},
+    models::CpuMesh,
+    scene::{
+        light::{Light, LightKind},
+        AlphaMode, Material2, MeshData, Model,
+    },
+};
+
+use std::collections::HashMap;
+
+/// Gltf json and the data of its only buffer.
+/// The buffer has no uri: it is either the BIN chunk of a .glb, or should be given one.
+pub fn model_to_gltf(model: &Model) -> (Gltf, Vec<u8>) {
+    let mut writer = Writer::new();
+
+    for node in &model.nodes {
+        let mesh = if node.data.is_empty() {
+            None
+        } else {
+            let primitives = node
+                .data
+                .iter()
+                .zip(&node.materials)
+                .map(|(data, material)| {
+                    let material = writer.material(material);
+                    writer.primitive(&data.mesh, Some(material))
+                })
+                .collect();
+            Some(writer.mesh(&node.name, primitives))
+        };
+
+        let extensions = node.light.as_ref().map(|light| NodeExtensions {
+            khr_lights_punctual: Some(NodeLight {
+                light: writer.light(light),
+                extensions: None,
+                extras: None,
+            }),
+            ..Default::default()
+        });
+
+        let transform = &node.transform;
+        writer.gltf.nodes.push(nanogltf::Node {
+            camera: None,
+            children: node.children.clone(),
+            skin: None,
+            matrix: None,
+            mesh,
+            rotation: Some(transform.rotation.to_array().map(|x| x as f64)),
+            scale: Some(transform.scale.to_array().map(|x| x as f64)),
+            translation: Some(transform.translation.to_array().map(|x| x as f64)),
+            weights: None,
+            name: Some(node.name.clone()),
+            extensions,
+            extras: None,
+        });
+    }
+    let roots = (0..model.nodes.len())
+        .filter(|ix| model.nodes[*ix].parent.is_none())
+        .collect();
+
+    writer.finish(roots)
+}
+
+/// A .glb file with the whole model.
+pub fn model_to_glb(model: &Model) -> Vec<u8> {
+    let (gltf, bin) = model_to_gltf(model);
+    nanogltf::glb::write(&gltf.to_json(), Some(&bin))
+}
+
+/// A self-contained .gltf file, with the buffer embedded as a base64 uri.
+pub fn model_to_gltf_json(model: &Model) -> String {
+    let (mut gltf, bin) = model_to_gltf(model);
+    gltf.buffers[0].uri = Some(nanogltf::utils::data_uri(&bin));
+    gltf.to_json()
+}
+
+/// A .glb file with a single node made out of a procedural mesh, like `models::sphere`.
+/// Viewers will draw it with their default material.
+pub fn mesh_to_glb(mesh: &CpuMesh) -> Vec<u8> {
+    let mut writer = Writer::new();
+    let primitive = writer.primitive(&MeshData::from(mesh), None);
+    let mesh = writer.mesh("mesh", vec![primitive]);
+    writer.gltf.nodes.push(nanogltf::Node {
+        camera: None,
+        children: vec![],
+        skin: None,
+        matrix: None,
+        mesh: Some(mesh),
+        rotation: None,
+        scale: None,
+        translation: None,
+        weights: None,
+        name: Some("mesh".to_string()),
+        extensions: None,
+        extras: None,
+    });
+
+    let (gltf, bin) = writer.finish(vec![0]);
+    nanogltf::glb::write(&gltf.to_json(), Some(&bin))
+}
+
+struct Writer {
+    gltf: Gltf,
+    bin: Vec<u8>,
+    lights: Vec<nanogltf::Light>,
+}
+
+impl Writer {
+    fn new() -> Writer {
+        Writer {
+            gltf: Gltf {
+                accessors: vec![],
+                asset: Asset {
+                    generator: Some("macroquad".to_string()),
+                    ..Default::default()
+                },
+                buffers: vec![],
+                buffer_views: vec![],
+                images: vec![],
+                scene: Some(0),
+                scenes: vec![],
+                materials: vec![],
+                meshes: vec![],
+                nodes: vec![],
+                textures: vec![],
+                samplers: vec![],
+                skins: vec![],
+                animations: vec![],
+                extensions_used: vec![],
+                extensions: None,
+                extras: None,
+            },
+            bin: vec![],
+            lights: vec![],
+        }
+    }
+
+    fn finish(mut self, roots: Vec<usize>) -> (Gltf, Vec<u8>) {
+        self.gltf.scenes.push(Scene {
+            nodes: roots,
+            name: None,
+            extensions: None,
+            extras: None,
+        });
+        self.gltf.buffers.push(Buffer {
+            uri: None,
+            byte_length: self.bin.len(),
+            name: None,
+            extensions: None,
+            extras: None,
+        });
+        if !self.lights.is_empty() {
+            self.gltf
+                .extensions_used
+                .push("KHR_lights_punctual".to_string());
+            self.gltf.extensions = Some(Extensions {
+                khr_lights_punctual: Some(LightsPunctual {
+                    lights: self.lights,
+                    extensions: None,
+                    extras: None,
+                }),
+                ..Default::default()
+            });
+        }
+        (self.gltf, self.bin)
+    }
+
+    /// Append a tightly packed view to the buffer.
+    fn view(&mut self, bytes: &[u8], target: BufferViewTarget) -> usize {
+        // accessors' offsets should be aligned to their component size
+        while self.bin.len() % 4 != 0 {
+            self.bin.push(0);
+        }
+        self.gltf.buffer_views.push(BufferView {
+            buffer: 0,
+            byte_offset: self.bin.len(),
+            byte_length: bytes.len(),
+            byte_stride: None,
+            target: Some(target),
+            name: None,
+            extensions: None,
+            extras: None,
+        });
+        self.bin.extend_from_slice(bytes);
+        self.gltf.buffer_views.len() - 1
+    }
+
+    fn accessor(
+        &mut self,
+        bytes: &[u8],
+        target: BufferViewTarget,
+        component_type: ComponentType,
+        type_: &str,
+        count: usize,
+        bounds: Option<(Vec<f64>, Vec<f64>)>,
+    ) -> usize {
+        let view = self.view(bytes, target);
+        let (min, max) = bounds.unzip();
+        self.gltf.accessors.push(Accessor {
+            buffer_view: Some(view),
+            byte_offset: 0,
+            component_type,
+            normalized: false,
+            count,
+            max,
+            min,
+            sparse: None,
+            name: None,
+            type_: Some(type_.to_string()),
+            extensions: None,
+            extras: None,
+        });
+        self.gltf.accessors.len() - 1
+    }
+
+    fn vec3s(&mut self, data: &[Vec3], with_bounds: bool) -> usize {
+        let bytes: Vec<u8> = data
+            .iter()
+            .flat_map(|v| v.to_array())
+            .flat_map(f32::to_le_bytes)
+            .collect();
+        // POSITION requires min and max
+        let bounds = with_bounds.then(|| {
+            let min = data.iter().fold(Vec3::splat(f32::MAX), |a, b| a.min(*b));
+            let max = data.iter().fold(Vec3::splat(-f32::MAX), |a, b| a.max(*b));
+            (
+                min.to_array().map(|x| x as f64).to_vec(),
+                max.to_array().map(|x| x as f64).to_vec(),
+            )
+        });
+        self.accessor(
+            &bytes,
+            BufferViewTarget::ArrayBuffer,
+            ComponentType::Float,
+            "VEC3",
+            data.len(),
+            bounds,
+        )
+    }
+
+    fn vec2s(&mut self, data: &[Vec2]) -> usize {
+        let bytes: Vec<u8> = data
+            .iter()
+            .flat_map(|v| v.to_array())
+            .flat_map(f32::to_le_bytes)
+            .collect();
+        self.accessor(
+            &bytes,
+            BufferViewTarget::ArrayBuffer,
+            ComponentType::Float,
+            "VEC2",
+            data.len(),
+            None,
+        )
+    }
+
+    fn indices(&mut self, indices: &[u32], vertices_count: usize) -> usize {
+        let (bytes, component_type): (Vec<u8>, _) = if vertices_count <= u16::MAX as usize + 1 {
+            let bytes = indices
+                .iter()
+                .flat_map(|ix| (*ix as u16).to_le_bytes())
+                .collect();
+            (bytes, ComponentType::UnsignedShort)
+        } else {
+            let bytes = indices.iter().flat_map(|ix| ix.to_le_bytes()).collect();
+            (bytes, ComponentType::UnsignedInt)
+        };
+        self.accessor(
+            &bytes,
+            BufferViewTarget::ElementArrayBuffer,
+            component_type,
+            "SCALAR",
+            indices.len(),
+            None,
+        )
+    }
+
+    fn primitive(&mut self, mesh: &MeshData, material: Option<usize>) -> Primitive {
+        let mut attributes = HashMap::new();
+        attributes.insert("POSITION".to_string(), self.vec3s(&mesh.positions, true));
+        attributes.insert("NORMAL".to_string(), self.vec3s(&mesh.normals, false));
+        if !mesh.uvs.is_empty() {
+            attributes.insert("TEXCOORD_0".to_string(), self.vec2s(&mesh.uvs));
+        }
+        let indices = self.indices(&mesh.indices, mesh.positions.len());
+
+        Primitive {
+            attributes,
+            indices: Some(indices),
+            material,
+            mode: Some(PrimitiveMode::Triangles),
+            targets: vec![],
+            extensions: None,
+            extras: None,
+        }
+    }
+
+    fn mesh(&mut self, name: &str, primitives: Vec<Primitive>) -> usize {
+        self.gltf.meshes.push(Mesh {
+            primitives,
+            weights: None,
+            name: Some(name.to_string()),
+            extensions: None,
+            extras: None,
+        });
+        self.gltf.meshes.len() - 1
+    }
+
+    fn material(&mut self, material: &Material2) -> usize {
+        let features = material.shader.features;
+        self.gltf.materials.push(Material {
+            name: None,
+            pbr_metallic_roughness: PBRMetallicRoughness {
+                base_color_factor: material.color.map(|x| x as f64),
+                base_color_texture: None,
+                metallic_factor: material.metallic as f64,
+                roughness_factor: material.roughness as f64,
+                metallic_roughness_texture: None,
+                extensions: None,
+                extras: None,
+            },
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+            emissive_factor: [0.0; 3],
+            alpha_mode: match features.alpha_mode {
+                AlphaMode::Opaque => nanogltf::AlphaMode::Opaque,
+                AlphaMode::Mask => nanogltf::AlphaMode::Mask,
+                AlphaMode::Blend => nanogltf::AlphaMode::Blend,
+            },
+            alpha_cutoff: material.alpha_cutoff as f64,
+            double_sided: features.double_sided,
+            extensions: None,
+            extras: None,
+        });
+        self.gltf.materials.len() - 1
+    }
+
+    /// Node lights are always exported at the node's origin, shining down its -Z,
+    /// their own position and direction are ignored.
+    fn light(&mut self, light: &Light) -> usize {
+        let (type_, spot) = match light.kind {
+            LightKind::Directional => ("directional", None),
+            LightKind::Point => ("point", None),
+            LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => (
+                "spot",
+                Some(LightSpot {
+                    inner_cone_angle: inner_cone_angle as f64,
+                    outer_cone_angle: outer_cone_angle as f64,
+                    extensions: None,
+                    extras: None,
+                }),
+            ),
+        };
+        self.lights.push(nanogltf::Light {
+            name: None,
+            color: [light.color.r, light.color.g, light.color.b].map(|x| x as f64),
+            intensity: light.intensity as f64,
+            type_: type_.to_string(),
+            range: light.range.map(|range| range as f64),
+            spot,
+            extensions: None,
+            extras: None,
+        });
+        self.lights.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miniquad::{BufferSource, BufferType, BufferUsage, RenderingBackend, SoftwareContext};
+    use nanogltf::AccessorReader;
+    use quad_gl::{
+        math::{vec3, Quat},
+        models,
+        scene::{IndexType, Node, NodeData, Shader, Transform, AABB},
+    };
+    use std::sync::Arc;
+
+    /// A sphere node with a child, built on the software backend.
+    fn model() -> (Model, MeshData) {
+        let mut ctx = SoftwareContext::new(1, 1);
+        let mesh = MeshData::from(&models::sphere(1.0, 4, 6));
+        let index_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&mesh.indices),
+        );
+        let mut shader = Shader::new(&mut ctx, vec![], None, None);
+        shader.features.alpha_mode = AlphaMode::Mask;
+        let material = Material2 {
+            color: [0.5, 0.25, 1.0, 0.75],
+            base_color_texture: None,
+            emissive_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            metallic_roughness_texture: None,
+            metallic: 0.25,
+            roughness: 0.5,
+            alpha_cutoff: 0.25,
+            shader,
+        };
+        let node = |name: &str, data, materials, parent, children| Node {
+            name: name.to_string(),
+            data,
+            materials,
+            transform: Transform {
+                translation: vec3(1.0, 2.0, 3.0),
+                scale: vec3(2.0, 2.0, 2.0),
+                rotation: Quat::from_rotation_y(0.5),
+            },
+            parent,
+            children,
+            skin: None,
+            weights: vec![],
+            light: None,
+        };
+        let data = NodeData {
+            vertex_buffers: vec![],
+            index_buffer,
+            index_type: IndexType::U32,
+            morph_targets: None,
+            mesh: Arc::new(mesh.clone()),
+        };
+        let model = Model {
+            nodes: vec![
+                node("sphere", vec![data], vec![material], None, vec![1]),
+                node("child", vec![], vec![], Some(0), vec![]),
+            ],
+            aabb: AABB {
+                min: Vec3::splat(-1.0),
+                max: Vec3::splat(1.0),
+            },
+            skins: vec![],
+            animations: vec![],
+        };
+        (model, mesh)
+    }
+
+    fn check(gltf: &Gltf, buffers: &[Vec<u8>], mesh: &MeshData) {
+        assert_eq!(gltf.scenes[0].nodes, [0]);
+        assert_eq!(gltf.nodes.len(), 2);
+        assert_eq!(gltf.nodes[0].name.as_deref(), Some("sphere"));
+        assert_eq!(gltf.nodes[0].children, [1]);
+        assert_eq!(gltf.nodes[0].translation, Some([1.0, 2.0, 3.0]));
+        assert_eq!(gltf.nodes[0].scale, Some([2.0, 2.0, 2.0]));
+        assert_eq!(gltf.nodes[1].name.as_deref(), Some("child"));
+        assert_eq!(gltf.nodes[1].mesh, None);
+
+        let material = &gltf.materials[0];
+        let pbr = &material.pbr_metallic_roughness;
+        assert_eq!(pbr.base_color_factor, [0.5, 0.25, 1.0, 0.75]);
+        assert_eq!(pbr.metallic_factor, 0.25);
+        assert_eq!(pbr.roughness_factor, 0.5);
+        assert_eq!(material.alpha_mode, nanogltf::AlphaMode::Mask);
+        assert_eq!(material.alpha_cutoff, 0.25);
+
+        let primitive = &gltf.meshes[gltf.nodes[0].mesh.unwrap()].primitives[0];
+        assert_eq!(primitive.material, Some(0));
+        let reader = AccessorReader::new(gltf, buffers);
+        let positions = reader
+            .read_f32::<3>(primitive.attributes["POSITION"])
+            .unwrap();
+        let normals = reader
+            .read_f32::<3>(primitive.attributes["NORMAL"])
+            .unwrap();
+        let uvs = reader
+            .read_f32::<2>(primitive.attributes["TEXCOORD_0"])
+            .unwrap();
+        let indices = reader.read_indices(primitive.indices.unwrap()).unwrap();
+        assert_eq!(
+            positions,
+            mesh.positions
+                .iter()
+                .map(|v| v.to_array())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            normals,
+            mesh.normals
+                .iter()
+                .map(|v| v.to_array())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            uvs,
+            mesh.uvs.iter().map(|v| v.to_array()).collect::<Vec<_>>()
+        );
+        assert_eq!(indices, mesh.indices);
+    }
+
+    #[test]
+    fn glb_round_trip() {
+        let (model, mesh) = model();
+        let bytes = model_to_glb(&model);
+        let glb = nanogltf::glb::parse(&bytes).unwrap();
+        let gltf = Gltf::from_json(glb.json).unwrap();
+        check(&gltf, &[glb.bin.unwrap().to_vec()], &mesh);
+    }
+
+    #[test]
+    fn gltf_json_round_trip() {
+        let (model, mesh) = model();
+        let gltf = Gltf::from_json(&model_to_gltf_json(&model)).unwrap();
+        let buffer = match nanogltf::utils::buffer_source(&gltf.buffers[0]).unwrap() {
+            nanogltf::utils::UriData::Bytes(bytes) => bytes,
+            _ => panic!("buffer is not embedded"),
+        };
+        check(&gltf, &[buffer], &mesh);
+    }
+
+    #[test]
+    fn mesh_round_trip() {
+        let cpu_mesh = models::sphere(1.0, 4, 6);
+        let bytes = mesh_to_glb(&cpu_mesh);
+        let glb = nanogltf::glb::parse(&bytes).unwrap();
+        let gltf = Gltf::from_json(glb.json).unwrap();
+        let buffers = [glb.bin.unwrap().to_vec()];
+        let reader = AccessorReader::new(&gltf, &buffers);
+        let primitive = &gltf.meshes[0].primitives[0];
+        let positions = reader
+            .read_f32::<3>(primitive.attributes["POSITION"])
+            .unwrap();
+        let indices = reader.read_indices(primitive.indices.unwrap()).unwrap();
+        assert_eq!(positions.len(), cpu_mesh.0.len());
+        assert_eq!(positions[5], cpu_mesh.0[5].to_array());
+        assert!(indices
+            .iter()
+            .copied()
+            .eq(cpu_mesh.3.iter().map(|ix| *ix as u32)));
+        assert_eq!(primitive.material, None);
+    }
+}
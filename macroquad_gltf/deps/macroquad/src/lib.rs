@@ -71,6 +71,7 @@ pub use ::log as logging;
 pub use miniquad;
 
 pub mod compat;
+pub mod export;
 pub mod gizmos;
 pub mod resources;
 
@@ -428,7 +429,9 @@ pub fn start<F: Fn(Context) -> Fut + 'static, Fut: Future<Output = ()> + 'static
     miniquad::start(conf::Conf { ..config }, move || {
         let mut ctx = Context::new();
 
-        unsafe { miniquad::gl::glEnable(miniquad::gl::GL_TEXTURE_CUBE_MAP_SEAMLESS) };
+        if ctx.quad_ctx.lock().unwrap().info().backend == miniquad::Backend::OpenGl {
+            unsafe { miniquad::gl::glEnable(miniquad::gl::GL_TEXTURE_CUBE_MAP_SEAMLESS) };
+        }
 
         Box::new(Stage {
             input: ctx.input.clone(),
@@ -1,4 +1,4 @@
-use crate::file::{load_file, load_string};
+use crate::file::load_file;
 
 use miniquad::{
     BlendFactor, BlendState, BlendValue, BufferLayout, BufferSource, BufferType, BufferUsage,
@@ -7,13 +7,156 @@ use miniquad::{
 };
 use quad_gl::{
     image,
-    math::{vec3, Quat, Vec3},
-    scene::{shader, Material2, Model, Node, NodeData, Transform, AABB},
+    math::Mat4,
+    math::{vec3, Quat, Vec2, Vec3},
+    scene::{
+        animation::{self, AnimationClip, AnimationTarget, Bone, Skin},
+        instancing::Instance,
+        light::Light,
+        morph::{MorphTarget, MorphTargets},
+        shader, AlphaMode, IndexType, Material2, MeshData, Model, Node, NodeData, ShaderFeatures,
+        Transform, AABB,
+    },
     texture::FilterMode,
     Error,
 };
 
-use std::sync::{Arc, Mutex};
+use nanogltf::GltfError;
+
+use std::{
+    f32::consts::PI,
+    sync::{Arc, Mutex},
+};
+
+fn node_transform(node: &nanogltf::Node) -> Transform {
+    if let Some(matrix) = node.matrix {
+        let matrix = Mat4::from_cols_array(&matrix.map(|x| x as f32));
+        let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+        return Transform {
+            translation,
+            rotation,
+            scale,
+        };
+    }
+    let translation = node
+        .translation
+        .map_or(Vec3::ZERO, |t| vec3(t[0] as f32, t[1] as f32, t[2] as f32));
+    let rotation = node.rotation.map_or(Quat::IDENTITY, |t| {
+        Quat::from_xyzw(t[0] as f32, t[1] as f32, t[2] as f32, t[3] as f32)
+    });
+    let scale = node.scale.map_or(vec3(1.0, 1.0, 1.0), |t| {
+        vec3(t[0] as f32, t[1] as f32, t[2] as f32)
+    });
+    Transform {
+        translation,
+        rotation,
+        scale,
+    }
+}
+
+/// Expand an indexed vertex attribute into a non-indexed one.
+fn unindex<T: Copy + Default>(data: &[T], indices: &[u32]) -> Vec<T> {
+    if data.is_empty() {
+        return vec![];
+    }
+    indices
+        .iter()
+        .map(|ix| data.get(*ix as usize).cloned().unwrap_or_default())
+        .collect()
+}
+
+/// Errors unless the attribute has an element for each of the `vertices`.
+fn check_count<T>(attribute: &str, data: &[T], vertices: usize) -> Result<(), GltfError> {
+    if data.len() != vertices {
+        return Err(GltfError::MismatchedAttributeCount {
+            attribute: attribute.to_string(),
+            expected: vertices,
+            found: data.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Face normals for non-indexed triangles.
+fn flat_normals(positions: &[[f32; 3]]) -> Vec<[f32; 3]> {
+    let mut normals = Vec::with_capacity(positions.len());
+    for triangle in positions.chunks(3) {
+        if triangle.len() < 3 {
+            normals.extend(std::iter::repeat([0.0; 3]).take(triangle.len()));
+            continue;
+        }
+        let a = Vec3::from(triangle[0]);
+        let b = Vec3::from(triangle[1]);
+        let c = Vec3::from(triangle[2]);
+        let normal = (b - a).cross(c - a).normalize_or_zero();
+        normals.extend_from_slice(&[normal.to_array(); 3]);
+    }
+    normals
+}
+
+/// Primitives with the same features share the shader and its pipeline.
+fn cached_shader(
+    ctx: &mut miniquad::Context,
+    shaders: &mut Vec<quad_gl::scene::Shader>,
+    features: ShaderFeatures,
+) -> quad_gl::scene::Shader {
+    if let Some(shader) = shaders.iter().find(|shader| shader.features == features) {
+        return shader.clone();
+    }
+    let shader = quad_gl::scene::Shader::with_features(ctx, vec![], None, None, features);
+    shaders.push(shader.clone());
+    shader
+}
+
+/// The node's KHR_lights_punctual light, in the node space.
+/// Unknown light types are skipped.
+fn node_light(gltf: &nanogltf::Gltf, node: &nanogltf::Node) -> Result<Option<Light>, GltfError> {
+    let ix = match node.extensions.as_ref() {
+        Some(nanogltf::NodeExtensions {
+            khr_lights_punctual: Some(light),
+            ..
+        }) => light.light,
+        _ => return Ok(None),
+    };
+    let light = gltf
+        .extensions
+        .as_ref()
+        .and_then(|extensions| extensions.khr_lights_punctual.as_ref())
+        .and_then(|lights| lights.lights.get(ix))
+        .ok_or(GltfError::BadIndex {
+            kind: "light",
+            index: ix,
+        })?;
+
+    let color = quad_gl::color::Color::new(
+        light.color[0] as f32,
+        light.color[1] as f32,
+        light.color[2] as f32,
+        1.0,
+    );
+    let intensity = light.intensity as f32;
+    let range = light.range.map(|range| range as f32);
+    let forward = vec3(0.0, 0.0, -1.0);
+    Ok(match light.type_.as_str() {
+        "directional" => Some(Light::directional(forward, color, intensity)),
+        "point" => Some(Light::point(Vec3::ZERO, color, intensity, range)),
+        "spot" => {
+            let (inner, outer) = light.spot.as_ref().map_or((0.0, PI / 4.0), |spot| {
+                (spot.inner_cone_angle as f32, spot.outer_cone_angle as f32)
+            });
+            Some(Light::spot(
+                Vec3::ZERO,
+                forward,
+                color,
+                intensity,
+                range,
+                inner,
+                outer,
+            ))
+        }
+        _ => None,
+    })
+}
 
 pub struct Resources {
     quad_ctx: Arc<Mutex<Box<miniquad::Context>>>,
@@ -24,18 +167,41 @@ impl Resources {
         Resources { quad_ctx }
     }
 
+    /// Load the default scene of a .gltf or .glb file.
+    /// Malformed or unsupported files fail with `Error::GltfError`.
+    ///
+    /// Morph targets only use their POSITION and NORMAL deltas, TANGENT deltas are ignored:
+    /// tangents are not loaded at all and are derived in the fragment shader instead.
     pub async fn load_gltf(&self, path: &str) -> Result<Model, crate::Error> {
+        self.load_gltf_scene(path, None).await
+    }
+
+    /// Load the given scene of a .gltf or .glb file.
+    /// With `None` the file's default scene is used, or the first one if there is no default.
+    pub async fn load_gltf_scene(
+        &self,
+        path: &str,
+        scene: Option<usize>,
+    ) -> Result<Model, crate::Error> {
         use nanogltf::{utils, Gltf};
         use std::borrow::Cow;
 
         let mut ctx = self.quad_ctx.lock().unwrap();
 
-        let gltf = load_string(path).await?;
-        let gltf = Gltf::from_json(&gltf).unwrap();
+        let bytes = load_file(path).await?;
+        let (gltf, mut bin) = if nanogltf::glb::is_glb(&bytes) {
+            let glb = nanogltf::glb::parse(&bytes).map_err(GltfError::from)?;
+            let gltf = Gltf::from_json(glb.json).map_err(GltfError::from)?;
+            (gltf, glb.bin.map(|bin| bin.to_vec()))
+        } else {
+            let json = String::from_utf8_lossy(&bytes);
+            (Gltf::from_json(&json).map_err(GltfError::from)?, None)
+        };
         //println!("{:#?}", &gltf);
         let mut buffers = vec![];
         for buffer in &gltf.buffers {
-            let bytes = match utils::parse_uri(&buffer.uri) {
+            let bytes = match utils::buffer_source(buffer)? {
+                utils::UriData::Embedded => bin.take().ok_or(GltfError::MissingBinChunk)?,
                 utils::UriData::Bytes(bytes) => bytes,
                 utils::UriData::RelativePath(uri) => {
                     // examples/assets/a.gltf -> examples/assets
@@ -48,11 +214,9 @@ impl Resources {
             buffers.push(bytes);
         }
 
-        assert!(gltf.scenes.len() == 1);
-
         let mut textures = vec![];
-        for image in &gltf.images {
-            let source = utils::image_source(&gltf, image);
+        for (i, image) in gltf.images.iter().enumerate() {
+            let source = utils::image_source(&gltf, image)?;
             let bytes = match source {
                 utils::ImageSource::Bytes(ref bytes) => Cow::from(bytes),
                 utils::ImageSource::RelativePath(ref uri) => {
@@ -65,9 +229,12 @@ impl Resources {
                     buffer,
                     offset,
                     length,
-                } => Cow::from(&buffers[0][offset..offset + length]),
+                } => Cow::from(utils::buffer_slice(&buffers, (buffer, offset, length))?),
             };
-            let image = image::decode(&bytes).unwrap();
+            let image = image::decode(&bytes).map_err(|err| GltfError::InvalidImage {
+                image: i,
+                message: err.to_string(),
+            })?;
             let texture =
                 ctx.new_texture_from_rgba8(image.width as u16, image.height as u16, &image.data);
             ctx.texture_set_wrap(texture, TextureWrap::Repeat, TextureWrap::Repeat);
@@ -75,133 +242,366 @@ impl Resources {
         }
 
         let mut nodes = vec![];
-        let scene = &gltf.scenes[0];
-        let mut aabb = AABB {
-            min: vec3(std::f32::MAX, std::f32::MAX, std::f32::MAX),
-            max: vec3(-std::f32::MAX, -std::f32::MAX, -std::f32::MAX),
+        let roots = if gltf.scenes.is_empty() {
+            // no scenes: every node without a parent is a root
+            let mut is_child = vec![false; gltf.nodes.len()];
+            for node in &gltf.nodes {
+                for child in &node.children {
+                    *is_child.get_mut(*child).ok_or(GltfError::BadIndex {
+                        kind: "node",
+                        index: *child,
+                    })? = true;
+                }
+            }
+            (0..gltf.nodes.len()).filter(|ix| !is_child[*ix]).collect()
+        } else {
+            let scene = scene.or(gltf.scene).unwrap_or(0);
+            gltf.scenes
+                .get(scene)
+                .ok_or(GltfError::BadIndex {
+                    kind: "scene",
+                    index: scene,
+                })?
+                .nodes
+                .clone()
         };
-        for node in &scene.nodes {
-            let node = &gltf.nodes[*node];
-            if node.children.len() != 0 {
+        let mut aabb = AABB::EMPTY;
+
+        // all the scene nodes, parents before children
+        let mut parents = vec![None; gltf.nodes.len()];
+        let mut globals = vec![Mat4::IDENTITY; gltf.nodes.len()];
+        let mut hierarchy = vec![];
+        let mut visited = vec![false; gltf.nodes.len()];
+        let mut stack: Vec<usize> = roots.iter().rev().cloned().collect();
+        while let Some(ix) = stack.pop() {
+            if ix >= gltf.nodes.len() {
+                return Err(GltfError::BadIndex {
+                    kind: "node",
+                    index: ix,
+                }
+                .into());
+            }
+            // a node could be listed as a child twice, or even form a cycle
+            if visited[ix] {
                 continue;
             }
-            let translation = node
-                .translation
-                .map_or(Vec3::ZERO, |t| vec3(t[0] as f32, t[1] as f32, t[2] as f32));
-            let rotation = node.rotation.map_or(Quat::IDENTITY, |t| {
-                Quat::from_xyzw(t[0] as f32, t[1] as f32, t[2] as f32, t[3] as f32)
-            });
-            let scale = node.scale.map_or(vec3(1.0, 1.0, 1.0), |t| {
-                vec3(t[0] as f32, t[1] as f32, t[2] as f32)
-            });
-            let transform = Transform {
-                translation,
-                rotation,
-                scale,
-            };
-            let mesh = node.mesh.unwrap();
-            let mesh = &gltf.meshes[mesh];
+            visited[ix] = true;
+            let local = node_transform(&gltf.nodes[ix]).matrix();
+            globals[ix] = parents[ix].map_or(local, |parent: usize| globals[parent] * local);
+            hierarchy.push(ix);
+            for child in gltf.nodes[ix].children.iter().rev() {
+                if visited.get(*child) == Some(&false) {
+                    parents[*child] = Some(ix);
+                }
+                stack.push(*child);
+            }
+        }
+
+        let reader = nanogltf::AccessorReader::new(&gltf, &buffers);
+        let mut shaders = vec![];
+        let mut nodes_map = vec![None; gltf.nodes.len()];
+        for gltf_ix in hierarchy.iter().cloned() {
+            let node = &gltf.nodes[gltf_ix];
+            let transform = node_transform(node);
+            let parent = parents[gltf_ix].and_then(|parent| nodes_map[parent]);
+            let ix = nodes.len();
+            nodes_map[gltf_ix] = Some(ix);
+            if let Some(parent) = parent {
+                let parent: &mut Node = &mut nodes[parent];
+                parent.children.push(ix);
+            }
+            let mesh = node
+                .mesh
+                .map(|mesh| {
+                    gltf.meshes.get(mesh).ok_or(GltfError::BadIndex {
+                        kind: "mesh",
+                        index: mesh,
+                    })
+                })
+                .transpose()?;
+            let primitives = mesh.map_or(&[][..], |mesh| &mesh.primitives[..]);
             let mut bindings = Vec::new();
             let mut materials = Vec::new();
 
-            for primitive in &mesh.primitives {
-                let material = &gltf.materials[primitive.material.unwrap()];
-                let color = material.pbr_metallic_roughness.base_color_factor;
-
-                let base_color_texture = &material.pbr_metallic_roughness.base_color_texture;
-                let base_color_texture = base_color_texture
-                    .as_ref()
-                    .map(|t| textures[t.index].clone());
-                let metallic_roughness_texture = material
-                    .pbr_metallic_roughness
-                    .metallic_roughness_texture
-                    .as_ref()
-                    .map(|t| textures[t.index].clone());
-                let emissive_texture = material
-                    .emissive_texture
-                    .as_ref()
-                    .map(|t| textures[t.index].clone());
-                let occlusion_texture = material
-                    .occlusion_texture
-                    .as_ref()
-                    .map(|t| textures[t.index].clone());
-                let normal_texture = material
-                    .normal_texture
-                    .as_ref()
-                    .map(|t| textures[t.index].clone());
-                let color = [
-                    color[0] as f32,
-                    color[1] as f32,
-                    color[2] as f32,
-                    color[3] as f32,
-                ];
-                let indices = utils::attribute_bytes(&gltf, primitive.indices.unwrap());
-                let indices = &buffers[indices.0][indices.1..indices.1 + indices.2];
+            for primitive in primitives {
+                let texture = |index: usize| {
+                    textures.get(index).cloned().ok_or(GltfError::BadIndex {
+                        kind: "texture",
+                        index,
+                    })
+                };
+                let material = match primitive.material {
+                    Some(material) => {
+                        let material = gltf.materials.get(material).ok_or(GltfError::BadIndex {
+                            kind: "material",
+                            index: material,
+                        })?;
+                        let pbr = &material.pbr_metallic_roughness;
+                        let color = pbr.base_color_factor;
+                        let features = ShaderFeatures {
+                            alpha_mode: match material.alpha_mode {
+                                nanogltf::AlphaMode::Opaque => AlphaMode::Opaque,
+                                nanogltf::AlphaMode::Mask => AlphaMode::Mask,
+                                nanogltf::AlphaMode::Blend => AlphaMode::Blend,
+                            },
+                            double_sided: material.double_sided,
+                            ..Default::default()
+                        };
+                        Material2 {
+                            color: [
+                                color[0] as f32,
+                                color[1] as f32,
+                                color[2] as f32,
+                                color[3] as f32,
+                            ],
+                            base_color_texture: pbr
+                                .base_color_texture
+                                .as_ref()
+                                .map(|t| texture(t.index))
+                                .transpose()?,
+                            emissive_texture: material
+                                .emissive_texture
+                                .as_ref()
+                                .map(|t| texture(t.index))
+                                .transpose()?,
+                            normal_texture: material
+                                .normal_texture
+                                .as_ref()
+                                .map(|t| texture(t.index))
+                                .transpose()?,
+                            occlusion_texture: material
+                                .occlusion_texture
+                                .as_ref()
+                                .map(|t| texture(t.index))
+                                .transpose()?,
+                            metallic_roughness_texture: pbr
+                                .metallic_roughness_texture
+                                .as_ref()
+                                .map(|t| texture(t.index))
+                                .transpose()?,
+                            metallic: pbr.metallic_factor as f32,
+                            roughness: pbr.roughness_factor as f32,
+                            alpha_cutoff: material.alpha_cutoff as f32,
+                            shader: cached_shader(ctx.as_mut(), &mut shaders, features),
+                        }
+                    }
+                    // the default material from the gltf spec
+                    None => Material2 {
+                        color: [1.0, 1.0, 1.0, 1.0],
+                        base_color_texture: None,
+                        emissive_texture: None,
+                        normal_texture: None,
+                        occlusion_texture: None,
+                        metallic_roughness_texture: None,
+                        metallic: 1.0,
+                        roughness: 1.0,
+                        alpha_cutoff: 0.5,
+                        shader: cached_shader(ctx.as_mut(), &mut shaders, Default::default()),
+                    },
+                };
+
+                let attributes = &primitive.attributes;
+                let mut positions: Vec<[f32; 3]> = match attributes.get("POSITION") {
+                    Some(accessor) => reader.read_f32(*accessor)?,
+                    None => return Err(GltfError::MissingAttribute("POSITION".to_string()).into()),
+                };
+                let mut normals: Option<Vec<[f32; 3]>> = attributes
+                    .get("NORMAL")
+                    .map(|accessor| reader.read_f32(*accessor))
+                    .transpose()?;
+                let mut uvs: Option<Vec<[f32; 2]>> = attributes
+                    .get("TEXCOORD_0")
+                    .map(|accessor| reader.read_f32(*accessor))
+                    .transpose()?;
+                let skinning = node.skin.is_some()
+                    && attributes.contains_key("JOINTS_0")
+                    && attributes.contains_key("WEIGHTS_0");
+                let mut joints: Vec<[f32; 4]> = vec![];
+                let mut weights: Vec<[f32; 4]> = vec![];
+                if skinning {
+                    joints = reader
+                        .read_u32::<4>(attributes["JOINTS_0"])?
+                        .iter()
+                        .map(|joint| joint.map(|ix| ix as f32))
+                        .collect();
+                    weights = reader.read_f32(attributes["WEIGHTS_0"])?;
+                }
+                let mut morph_targets = primitive
+                    .targets
+                    .iter()
+                    .map(|target| {
+                        let delta = |name| {
+                            target
+                                .get(name)
+                                .map(|accessor| reader.read_f32::<3>(*accessor))
+                                .transpose()
+                                .map(Option::unwrap_or_default)
+                        };
+                        // TANGENT is ignored, there is no tangent attribute to morph
+                        Ok((delta("POSITION")?, delta("NORMAL")?))
+                    })
+                    .collect::<Result<Vec<_>, GltfError>>()?;
+                let mut indices = primitive
+                    .indices
+                    .map(|indices| reader.read_indices(indices))
+                    .transpose()?;
+
+                let count = positions.len();
+                if let Some(normals) = &normals {
+                    check_count("NORMAL", normals, count)?;
+                }
+                if let Some(uvs) = &uvs {
+                    check_count("TEXCOORD_0", uvs, count)?;
+                }
+                if skinning {
+                    check_count("JOINTS_0", &joints, count)?;
+                    check_count("WEIGHTS_0", &weights, count)?;
+                }
+                for (target, (positions, normals)) in morph_targets.iter().enumerate() {
+                    // missing deltas are empty
+                    if !positions.is_empty() {
+                        check_count(&format!("targets[{}].POSITION", target), positions, count)?;
+                    }
+                    if !normals.is_empty() {
+                        check_count(&format!("targets[{}].NORMAL", target), normals, count)?;
+                    }
+                }
+                if let Some(index) = indices.iter().flatten().find(|ix| **ix as usize >= count) {
+                    return Err(GltfError::BadIndex {
+                        kind: "vertex",
+                        index: *index as usize,
+                    }
+                    .into());
+                }
+
+                // Flat normals need a separate vertex for each triangle corner,
+                // so indexed primitives without normals are un-indexed first.
+                if normals.is_none() {
+                    if let Some(indices) = indices.take() {
+                        positions = unindex(&positions, &indices);
+                        uvs = uvs.map(|uvs| unindex(&uvs, &indices));
+                        joints = unindex(&joints, &indices);
+                        weights = unindex(&weights, &indices);
+                        for (positions, normals) in &mut morph_targets {
+                            *positions = unindex(positions, &indices);
+                            *normals = unindex(normals, &indices);
+                        }
+                    }
+                    normals = Some(flat_normals(&positions));
+                }
+                let normals = normals.unwrap();
+                let vertices_count = positions.len();
+                let uvs = uvs.unwrap_or_else(|| vec![[0.0; 2]; vertices_count]);
+                let indices = match indices {
+                    Some(indices) => indices,
+                    None => (0..vertices_count as u32).collect(),
+                };
 
                 {
-                    let accessor = &gltf.accessors[primitive.attributes["POSITION"]];
-                    let matrix = transform.matrix();
-
-                    let min = accessor.min.as_ref().unwrap();
-                    let min = vec3(min[0] as f32, min[1] as f32, min[2] as f32);
-                    let min = matrix.transform_point3(min);
-                    aabb.min = aabb.min.min(min);
-                    let max = accessor.max.as_ref().unwrap();
-                    let max = vec3(max[0] as f32, max[1] as f32, max[2] as f32);
-                    let max = matrix.transform_point3(max);
-                    aabb.max = aabb.max.max(max);
-                }
-
-                let vertices = utils::attribute_bytes(&gltf, primitive.attributes["POSITION"]);
-                let vertices: &[u8] = &buffers[vertices.0][vertices.1..vertices.1 + vertices.2];
-                let uvs = utils::attribute_bytes(&gltf, primitive.attributes["TEXCOORD_0"]);
-                let uvs = &buffers[uvs.0][uvs.1..uvs.1 + uvs.2];
-                let normals = utils::attribute_bytes(&gltf, primitive.attributes["NORMAL"]);
-                let normals = &buffers[normals.0][normals.1..normals.1 + normals.2];
-                let vertex_buffer =
-                    ctx.new_buffer(BufferType::VertexBuffer, BufferUsage::Immutable, unsafe {
-                        BufferSource::pointer(vertices.as_ptr(), vertices.len(), 4 * 3)
-                    });
-                let normals_buffer =
-                    ctx.new_buffer(BufferType::VertexBuffer, BufferUsage::Immutable, unsafe {
-                        BufferSource::pointer(normals.as_ptr(), normals.len(), 4 * 3)
-                    });
-                let uvs_buffer =
-                    ctx.new_buffer(BufferType::VertexBuffer, BufferUsage::Immutable, unsafe {
-                        BufferSource::pointer(uvs.as_ptr(), uvs.len(), 4 * 2)
-                    });
-                let index_buffer =
-                    ctx.new_buffer(BufferType::IndexBuffer, BufferUsage::Immutable, unsafe {
-                        BufferSource::pointer(indices.as_ptr(), indices.len(), 2)
-                    });
-                let instancing = vec![vec3(0.0, 0.0, 0.0)];
-                let instancing_buffer =
-                    ctx.new_buffer(BufferType::VertexBuffer, BufferUsage::Immutable, unsafe {
-                        BufferSource::slice(&instancing[..])
-                    });
+                    let matrix = globals[gltf_ix];
+                    for position in &positions {
+                        let position = matrix.transform_point3(Vec3::from(*position));
+                        aabb.min = aabb.min.min(position);
+                        aabb.max = aabb.max.max(position);
+                    }
+                }
+
+                let vertex_buffer = ctx.new_buffer(
+                    BufferType::VertexBuffer,
+                    BufferUsage::Immutable,
+                    BufferSource::slice(&positions[..]),
+                );
+                let normals_buffer = ctx.new_buffer(
+                    BufferType::VertexBuffer,
+                    BufferUsage::Immutable,
+                    BufferSource::slice(&normals[..]),
+                );
+                let uvs_buffer = ctx.new_buffer(
+                    BufferType::VertexBuffer,
+                    BufferUsage::Immutable,
+                    BufferSource::slice(&uvs[..]),
+                );
+                // 16 bit indices are enough for most of the meshes and take half the memory
+                let (index_buffer, index_type) = if vertices_count <= u16::MAX as usize + 1 {
+                    let indices: Vec<u16> = indices.iter().map(|ix| *ix as u16).collect();
+                    let buffer = ctx.new_buffer(
+                        BufferType::IndexBuffer,
+                        BufferUsage::Immutable,
+                        BufferSource::slice(&indices[..]),
+                    );
+                    (buffer, IndexType::U16)
+                } else {
+                    let buffer = ctx.new_buffer(
+                        BufferType::IndexBuffer,
+                        BufferUsage::Immutable,
+                        BufferSource::slice(&indices[..]),
+                    );
+                    (buffer, IndexType::U32)
+                };
+                let instancing_buffer = Instance::default_buffer(ctx.as_mut());
+
+                let mut vertex_buffers =
+                    vec![vertex_buffer, uvs_buffer, normals_buffer, instancing_buffer];
+                let mut material = material;
+                if skinning {
+                    vertex_buffers.push(ctx.new_buffer(
+                        BufferType::VertexBuffer,
+                        BufferUsage::Immutable,
+                        BufferSource::slice(&joints[..]),
+                    ));
+                    vertex_buffers.push(ctx.new_buffer(
+                        BufferType::VertexBuffer,
+                        BufferUsage::Immutable,
+                        BufferSource::slice(&weights[..]),
+                    ));
+                    let features = ShaderFeatures {
+                        skinning,
+                        ..material.shader.features
+                    };
+                    material.shader = cached_shader(ctx.as_mut(), &mut shaders, features);
+                }
+
+                let vec3s = |data: &[[f32; 3]]| data.iter().cloned().map(Vec3::from).collect();
+                let morph_targets = if morph_targets.is_empty() {
+                    None
+                } else {
+                    Some(Arc::new(MorphTargets {
+                        positions: vec3s(&positions),
+                        normals: vec3s(&normals),
+                        targets: morph_targets
+                            .iter()
+                            .map(|(positions, normals)| MorphTarget {
+                                positions: vec3s(positions),
+                                normals: vec3s(normals),
+                            })
+                            .collect(),
+                    }))
+                };
 
-                let shader = quad_gl::scene::Shader::default(ctx.as_mut());
+                let mesh = Arc::new(MeshData {
+                    positions: vec3s(&positions),
+                    uvs: uvs.iter().cloned().map(Vec2::from).collect(),
+                    normals: vec3s(&normals),
+                    indices,
+                });
 
                 bindings.push(NodeData {
-                    vertex_buffers: vec![
-                        vertex_buffer,
-                        uvs_buffer,
-                        normals_buffer,
-                        instancing_buffer,
-                    ],
+                    vertex_buffers,
                     index_buffer,
+                    index_type,
+                    morph_targets,
+                    mesh,
                 });
-                materials.push(Material2 {
-                    color,
-                    base_color_texture,
-                    emissive_texture,
-                    normal_texture,
-                    occlusion_texture,
-                    metallic_roughness_texture,
-                    metallic: material.pbr_metallic_roughness.metallic_factor as f32,
-                    roughness: material.pbr_metallic_roughness.roughness_factor as f32,
-                    shader,
-                });
+                materials.push(material);
+            }
+
+            let targets_count = primitives.iter().map(|p| p.targets.len()).max();
+            let mut weights = vec![0.0; targets_count.unwrap_or(0)];
+            let mesh_weights = mesh.and_then(|mesh| mesh.weights.as_ref());
+            if let Some(default_weights) = node.weights.as_ref().or(mesh_weights) {
+                for (weight, default_weight) in weights.iter_mut().zip(default_weights) {
+                    *weight = *default_weight as f32;
+                }
             }
 
             nodes.push(Node {
@@ -213,30 +613,352 @@ impl Resources {
                 data: bindings,
                 materials,
                 transform,
+                parent,
+                children: vec![],
+                skin: node.skin,
+                weights,
+                light: node_light(&gltf, node)?,
+            });
+        }
+
+        let mut skins = vec![];
+        let mut bones_map = vec![];
+        for (skin_index, skin) in gltf.skins.iter().enumerate() {
+            if skin.joints.len() > animation::MAX_JOINTS {
+                return Err(GltfError::TooManyJoints {
+                    skin: skin_index,
+                    joints: skin.joints.len(),
+                    max: animation::MAX_JOINTS,
+                }
+                .into());
+            }
+            // joints and all their ancestors, so the skeleton is self-contained
+            let mut is_bone = vec![false; gltf.nodes.len()];
+            for joint in &skin.joints {
+                if *joint >= gltf.nodes.len() {
+                    return Err(GltfError::BadIndex {
+                        kind: "joint node",
+                        index: *joint,
+                    }
+                    .into());
+                }
+                let mut ix = Some(*joint);
+                while let Some(node) = ix {
+                    is_bone[node] = true;
+                    ix = parents[node];
+                }
+            }
+            let mut map = vec![None; gltf.nodes.len()];
+            let mut bones = vec![];
+            for ix in hierarchy.iter().cloned().filter(|ix| is_bone[*ix]) {
+                map[ix] = Some(bones.len());
+                bones.push(Bone {
+                    name: gltf.nodes[ix].name.clone().unwrap_or("unnamed".to_string()),
+                    parent: parents[ix].and_then(|parent| map[parent]),
+                    transform: node_transform(&gltf.nodes[ix]),
+                });
+            }
+            let inverse_bind_matrices = match skin.inverse_bind_matrices {
+                Some(accessor) => reader
+                    .read_f32::<16>(accessor)?
+                    .iter()
+                    .map(Mat4::from_cols_array)
+                    .collect(),
+                None => vec![Mat4::IDENTITY; skin.joints.len()],
+            };
+            if inverse_bind_matrices.len() != skin.joints.len() {
+                return Err(GltfError::InvalidSkin {
+                    skin: skin_index,
+                    message: "inverse bind matrices count differs from the joints count",
+                }
+                .into());
+            }
+            // JOINTS_0 refers to the joints by their position in the list, keep it as is
+            let joints = skin
+                .joints
+                .iter()
+                .map(|joint| map[*joint])
+                .collect::<Option<Vec<_>>>()
+                .ok_or(GltfError::InvalidSkin {
+                    skin: skin_index,
+                    message: "joint is not a part of the scene",
+                })?;
+            skins.push(Skin {
+                joints,
+                bones,
+                inverse_bind_matrices,
+            });
+            bones_map.push(map);
+        }
+
+        let mut animations = vec![];
+        for (i, gltf_animation) in gltf.animations.iter().enumerate() {
+            let mut channels = vec![];
+            let mut duration: f32 = 0.0;
+            for channel in &gltf_animation.channels {
+                let property = match channel.target.path {
+                    nanogltf::TargetPath::Translation => animation::Property::Translation,
+                    nanogltf::TargetPath::Rotation => animation::Property::Rotation,
+                    nanogltf::TargetPath::Scale => animation::Property::Scale,
+                    nanogltf::TargetPath::Weights => animation::Property::Weights,
+                    // extension paths, like KHR_animation_pointer, are not supported
+                    nanogltf::TargetPath::Unknown(_) => continue,
+                };
+                let target = match channel.target.node {
+                    Some(target) if target < gltf.nodes.len() => target,
+                    _ => continue,
+                };
+                let mut targets = vec![];
+                if let Some(node) = nodes_map[target] {
+                    targets.push(AnimationTarget::Node(node));
+                }
+                if property != animation::Property::Weights {
+                    for (skin, map) in bones_map.iter().enumerate() {
+                        if let Some(bone) = map[target] {
+                            targets.push(AnimationTarget::Bone { skin, bone });
+                        }
+                    }
+                }
+
+                let sampler =
+                    gltf_animation
+                        .samplers
+                        .get(channel.sampler)
+                        .ok_or(GltfError::BadIndex {
+                            kind: "animation sampler",
+                            index: channel.sampler,
+                        })?;
+                let times = reader.read_f32_flat(sampler.input)?;
+                let values = reader.read_f32_flat(sampler.output)?;
+                let interpolation = match sampler.interpolation {
+                    nanogltf::Interpolation::Linear => animation::Interpolation::Linear,
+                    nanogltf::Interpolation::Step => animation::Interpolation::Step,
+                    nanogltf::Interpolation::CubicSpline => animation::Interpolation::CubicSpline,
+                };
+                duration = duration.max(times.last().cloned().unwrap_or(0.0));
+                // in-tangent, value and out-tangent for each cubic spline keyframe
+                let keys = match interpolation {
+                    animation::Interpolation::CubicSpline => times.len() * 3,
+                    _ => times.len(),
+                };
+                let components = match property {
+                    animation::Property::Rotation => 4,
+                    animation::Property::Weights => values.len() / keys.max(1),
+                    _ => 3,
+                };
+                let invalid = |message| GltfError::InvalidAnimationSampler {
+                    animation: i,
+                    sampler: channel.sampler,
+                    message,
+                };
+                if values.len() != keys * components {
+                    return Err(invalid("output count doesn't match the input count").into());
+                }
+                if !times.is_sorted() {
+                    return Err(invalid("input times are not sorted").into());
+                }
+                for target in targets {
+                    channels.push(animation::Channel {
+                        target,
+                        property,
+                        interpolation,
+                        times: times.clone(),
+                        values: values.clone(),
+                        components,
+                    });
+                }
+            }
+            animations.push(AnimationClip {
+                name: gltf_animation
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("animation{}", i)),
+                duration,
+                channels,
             });
         }
-        Ok(Model { nodes, aabb })
+
+        Ok(Model {
+            nodes,
+            aabb,
+            skins,
+            animations,
+        })
     }
 
+    /// Load a cubemap from six face images, in the +X, -X, +Y, -Y, +Z, -Z order,
+    /// or from a single equirectangular (latitude-longitude) panorama.
+    /// Radiance .hdr files work for both.
     pub async fn load_cubemap(
         &self,
-        texture_px: &str,
-        texture_nx: &str,
-        texture_py: &str,
-        texture_ny: &str,
-        texture_pz: &str,
-        texture_nz: &str,
+        paths: &[&str],
     ) -> Result<quad_gl::cubemap::Cubemap, crate::Error> {
-        let cubemap = [
-            &load_file(texture_px).await?[..],
-            &load_file(texture_nx).await?[..],
-            &load_file(texture_py).await?[..],
-            &load_file(texture_ny).await?[..],
-            &load_file(texture_pz).await?[..],
-            &load_file(texture_nz).await?[..],
-        ];
+        let mut bytes = Vec::with_capacity(paths.len());
+        for path in paths {
+            bytes.push(load_file(path).await?);
+        }
         let mut quad_ctx = self.quad_ctx.lock().unwrap();
-        let cubemap = quad_gl::cubemap::Cubemap::new(quad_ctx.as_mut(), &cubemap[..]);
-        Ok(cubemap)
+        match &bytes[..] {
+            [panorama] => {
+                quad_gl::cubemap::Cubemap::from_equirectangular(quad_ctx.as_mut(), panorama)
+            }
+            faces => {
+                let faces: Vec<&[u8]> = faces.iter().map(|face| &face[..]).collect();
+                quad_gl::cubemap::Cubemap::new(quad_ctx.as_mut(), &faces)
+            }
+        }
+        .map_err(|err| crate::Error::ImageError(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miniquad::SoftwareContext;
+    use std::{
+        future::Future,
+        task::{Context, Poll, Waker},
+    };
+
+    /// Load a .gltf written to a temporary file, on the software backend.
+    fn load(name: &str, json: &str) -> Result<Model, crate::Error> {
+        let path = std::env::temp_dir().join(format!("{}_{}.gltf", name, std::process::id()));
+        std::fs::write(&path, json).unwrap();
+        let ctx: Box<miniquad::Context> = Box::new(SoftwareContext::new(1, 1));
+        let resources = Resources::new(Arc::new(Mutex::new(ctx)));
+        let mut future = std::pin::pin!(resources.load_gltf(path.to_str().unwrap()));
+        // desktop file loading is synchronous, nothing is left to wait for
+        let result = match future
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+        {
+            Poll::Ready(result) => result,
+            Poll::Pending => panic!("load_gltf didn't finish"),
+        };
+        std::fs::remove_file(&path).unwrap();
+        result
+    }
+
+    /// A node with a triangle mesh of the given primitive. The buffer holds three positions,
+    /// u16 indices 0, 1, 5 and two normals, for accessors 0, 1 and 2. Accessors 3 and 4
+    /// are the float times [0, 1] and [1, 0]. `extra` is added to the top level object.
+    fn triangle(primitive: &str, extra: &str) -> String {
+        format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "buffers": [{{"byteLength": 68, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAABAAUAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAgD8="}}],
+                "bufferViews": [{{"buffer": 0, "byteLength": 68}}],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}},
+                    {{"bufferView": 0, "byteOffset": 36, "componentType": 5123, "count": 3, "type": "SCALAR"}},
+                    {{"bufferView": 0, "byteOffset": 44, "componentType": 5126, "count": 2, "type": "VEC3"}},
+                    {{"bufferView": 0, "byteOffset": 8, "componentType": 5126, "count": 2, "type": "SCALAR"}},
+                    {{"bufferView": 0, "byteOffset": 12, "componentType": 5126, "count": 2, "type": "SCALAR"}}
+                ],
+                "meshes": [{{"primitives": [{}]}}],
+                "nodes": [{{"mesh": 0}}]{}
+            }}"#,
+            primitive, extra
+        )
+    }
+
+    #[test]
+    fn mesh_counts() {
+        let model = load(
+            "triangle",
+            &triangle(r#"{"attributes": {"POSITION": 0}}"#, ""),
+        )
+        .unwrap();
+        assert_eq!(model.nodes.len(), 1);
+
+        let json = triangle(r#"{"attributes": {"POSITION": 0}, "indices": 1}"#, "");
+        match load("bad_index", &json) {
+            Err(crate::Error::GltfError(GltfError::BadIndex {
+                kind: "vertex",
+                index: 5,
+            })) => {}
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+
+        let json = triangle(r#"{"attributes": {"POSITION": 0, "NORMAL": 2}}"#, "");
+        match load("short_normals", &json) {
+            Err(crate::Error::GltfError(GltfError::MismatchedAttributeCount {
+                attribute,
+                expected: 3,
+                found: 2,
+            })) => assert_eq!(attribute, "NORMAL"),
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+
+        let json = triangle(
+            r#"{"attributes": {"POSITION": 0}, "targets": [{"POSITION": 2}]}"#,
+            "",
+        );
+        match load("short_target", &json) {
+            Err(crate::Error::GltfError(GltfError::MismatchedAttributeCount {
+                attribute, ..
+            })) => assert_eq!(attribute, "targets[0].POSITION"),
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn animation_samplers() {
+        let animation = |input, output| {
+            let extra = format!(
+                r#", "animations": [{{
+                    "channels": [{{"sampler": 0, "target": {{"node": 0, "path": "translation"}}}}],
+                    "samplers": [{{"input": {}, "output": {}}}]
+                }}]"#,
+                input, output
+            );
+            load(
+                "animation",
+                &triangle(r#"{"attributes": {"POSITION": 0}}"#, &extra),
+            )
+        };
+
+        let model = animation(3, 2).unwrap();
+        assert_eq!(model.animations[0].duration, 1.0);
+
+        // 2 keyframes, 3 translations
+        match animation(3, 0) {
+            Err(crate::Error::GltfError(GltfError::InvalidAnimationSampler {
+                animation: 0,
+                sampler: 0,
+                ..
+            })) => {}
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+        // times going backwards
+        match animation(4, 2) {
+            Err(crate::Error::GltfError(GltfError::InvalidAnimationSampler {
+                message, ..
+            })) => {
+                assert_eq!(message, "input times are not sorted")
+            }
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn bad_images() {
+        for (name, uri) in [
+            (
+                "truncated_png",
+                "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAQ=",
+            ),
+            ("gif", "data:image/gif;base64,R0lGODlhAQA="),
+        ] {
+            let json = format!(
+                r#"{{"asset": {{"version": "2.0"}}, "images": [{{"uri": "{}"}}]}}"#,
+                uri
+            );
+            match load(name, &json) {
+                Err(crate::Error::GltfError(GltfError::InvalidImage { image: 0, .. })) => {}
+                Err(err) => panic!("{}: unexpected error {}", name, err),
+                Ok(_) => panic!("{}: loaded", name),
+            }
+        }
     }
 }
@@ -126,6 +126,14 @@ pub struct Platform {
     /// Whether to draw the default window decorations on Wayland.
     /// Only works when using the Wayland backend.
     pub wayland_use_fallback_decorations: bool,
+
+    /// Run without a window and without a GPU: `start` calls update() and draw()
+    /// this many times and returns. `window::new_rendering_backend` creates a
+    /// `SoftwareContext` with a window_width x window_height default framebuffer.
+    /// Meant for tests and CI machines without a display.
+    ///
+    /// Defaults to None.
+    pub headless_frames: Option<u32>,
 }
 
 impl Default for Platform {
@@ -139,6 +147,7 @@ impl Default for Platform {
             swap_interval: None,
             framebuffer_alpha: false,
             wayland_use_fallback_decorations: true,
+            headless_frames: None,
         }
     }
 }
@@ -7,6 +7,8 @@ use std::{error::Error, fmt::Display};
 //pub use texture::{FilterMode, TextureAccess, TextureFormat, TextureParams, TextureWrap};
 
 mod gl;
+pub mod recorder;
+mod software;
 
 pub use gl::raw_gl;
 
@@ -14,11 +16,13 @@ pub use gl::raw_gl;
 mod metal;
 
 pub use gl::GlContext;
+pub use recorder::RecordingContext;
+pub use software::SoftwareContext;
 
 #[cfg(target_vendor = "apple")]
 pub use metal::MetalContext;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum UniformType {
     /// One 32-bit wide float (equivalent to `f32`)
     Float1,
@@ -57,14 +61,14 @@ impl UniformType {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct UniformDesc {
     pub name: String,
     pub uniform_type: UniformType,
     pub array_count: usize,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct UniformBlockLayout {
     pub uniforms: Vec<UniformDesc>,
 }
@@ -86,7 +90,7 @@ impl UniformDesc {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ShaderMeta {
     pub uniforms: UniformBlockLayout,
     pub images: Vec<String>,
@@ -214,7 +218,7 @@ impl Default for VertexStep {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct BufferLayout {
     pub stride: i32,
     pub step_func: VertexStep,
@@ -231,7 +235,7 @@ impl Default for BufferLayout {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct VertexAttribute {
     pub name: &'static str,
     pub format: VertexFormat,
@@ -314,6 +318,7 @@ impl Error for ShaderError {
 pub enum TextureFormat {
     RGB8,
     RGBA8,
+    /// Little endian half floats, 8 bytes per pixel.
     RGBA16F,
     Depth,
     Depth32,
@@ -372,7 +377,7 @@ pub enum TextureKind {
     CubeMap,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct TextureParams {
     pub kind: TextureKind,
     pub format: TextureFormat,
@@ -523,6 +528,7 @@ pub enum CompareFunc {
 
 type ColorMask = (bool, bool, bool, bool);
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PassAction {
     Nothing,
     Clear {
@@ -552,7 +558,7 @@ impl Default for PassAction {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct RenderPass(usize);
 
 pub const MAX_VERTEX_ATTRIBUTES: usize = 16;
@@ -1042,6 +1048,8 @@ pub struct GlslSupport {
 pub enum Backend {
     Metal,
     OpenGl,
+    /// `SoftwareContext`, shader sources are ignored.
+    Software,
 }
 
 #[derive(Clone, Debug)]
@@ -1064,7 +1072,7 @@ pub struct ContextInfo {
 impl ContextInfo {
     pub fn has_integer_attributes(&self) -> bool {
         match self.backend {
-            Backend::Metal => true,
+            Backend::Metal | Backend::Software => true,
             Backend::OpenGl => {
                 self.glsl_support.v150 | self.glsl_support.v300es | self.glsl_support.v330
             }
@@ -57,7 +57,7 @@ impl From<TextureFormat> for (GLenum, GLenum, GLenum) {
         match format {
             TextureFormat::RGB8 => (GL_RGB, GL_RGB, GL_UNSIGNED_BYTE),
             TextureFormat::RGBA8 => (GL_RGBA, GL_RGBA, GL_UNSIGNED_BYTE),
-            TextureFormat::RGBA16F => (GL_RGBA16F, GL_RGBA, GL_FLOAT),
+            TextureFormat::RGBA16F => (GL_RGBA16F, GL_RGBA, GL_HALF_FLOAT),
             TextureFormat::Depth => (GL_DEPTH_COMPONENT, GL_DEPTH_COMPONENT, GL_UNSIGNED_SHORT),
             TextureFormat::Depth32 => (GL_DEPTH_COMPONENT, GL_DEPTH_COMPONENT, GL_FLOAT),
             #[cfg(target_arch = "wasm32")]
@@ -210,8 +210,12 @@ impl Texture {
                     }
                     for (cubemap_face, mipmaps) in array.iter().enumerate() {
                         if mipmaps.len() != 1 {
-                            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_BASE_LEVEL, 0);
-                            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAX_LEVEL, array.len() as _);
+                            glTexParameteri(params.kind.into(), GL_TEXTURE_BASE_LEVEL, 0);
+                            glTexParameteri(
+                                params.kind.into(),
+                                GL_TEXTURE_MAX_LEVEL,
+                                (mipmaps.len() - 1) as _,
+                            );
                         }
                         for (mipmap_level, bytes) in mipmaps.iter().enumerate() {
                             let target = match params.kind {
@@ -224,8 +228,8 @@ impl Texture {
                                 target,
                                 mipmap_level as _,
                                 internal_format as i32,
-                                params.width as i32,
-                                params.height as i32,
+                                (params.width >> mipmap_level).max(1) as i32,
+                                (params.height >> mipmap_level).max(1) as i32,
                                 0,
                                 format,
                                 pixel_type,
@@ -983,7 +987,7 @@ impl RenderingBackend for GlContext {
                 for i in 0..color_img.len() {
                     attachments.push(GL_COLOR_ATTACHMENT0 + i as u32);
                 }
-                glDrawBuffers(2, attachments.as_ptr() as _);
+                glDrawBuffers(attachments.len() as _, attachments.as_ptr() as _);
             }
 
             glBindFramebuffer(GL_FRAMEBUFFER, self.default_framebuffer);
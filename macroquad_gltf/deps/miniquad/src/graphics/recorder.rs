@@ -0,0 +1,1227 @@
+//! Command stream capture: `RecordingContext` forwards every call to another backend
+//! and keeps a log of it, which can be inspected in tests, saved with `serialize`
+//! and played back onto any backend with `Replay`.
+//!
+//! Queries, like `texture_read_pixels` or `buffer_size`, are forwarded but not recorded.
+
+use super::*;
+
+use std::{cell::RefCell, collections::HashMap, hash::Hash};
+
+const MAGIC: &[u8; 6] = b"MQLOG\0";
+const VERSION: u32 = 1;
+
+/// Shader sources, owned.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShaderCode {
+    Glsl { vertex: String, fragment: String },
+    Msl { program: String },
+}
+
+impl ShaderCode {
+    fn new(source: &ShaderSource) -> ShaderCode {
+        match *source {
+            ShaderSource::Glsl { vertex, fragment } => ShaderCode::Glsl {
+                vertex: vertex.to_string(),
+                fragment: fragment.to_string(),
+            },
+            ShaderSource::Msl { program } => ShaderCode::Msl {
+                program: program.to_string(),
+            },
+        }
+    }
+
+    fn source(&self) -> ShaderSource<'_> {
+        match self {
+            ShaderCode::Glsl { vertex, fragment } => ShaderSource::Glsl { vertex, fragment },
+            ShaderCode::Msl { program } => ShaderSource::Msl { program },
+        }
+    }
+}
+
+/// `TextureSource`, owned.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextureData {
+    Empty,
+    Bytes(Vec<u8>),
+    /// `[cubemap_face][mipmap_level][bytes]`
+    Array(Vec<Vec<Vec<u8>>>),
+}
+
+impl TextureData {
+    fn new(source: &TextureSource) -> TextureData {
+        match *source {
+            TextureSource::Empty => TextureData::Empty,
+            TextureSource::Bytes(bytes) => TextureData::Bytes(bytes.to_vec()),
+            TextureSource::Array(faces) => TextureData::Array(
+                faces
+                    .iter()
+                    .map(|mips| mips.iter().map(|mip| mip.to_vec()).collect())
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// `BufferSource`, owned.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BufferData {
+    Slice { bytes: Vec<u8>, element_size: usize },
+    Empty { size: usize, element_size: usize },
+}
+
+impl BufferData {
+    fn new(source: &BufferSource) -> BufferData {
+        match source {
+            BufferSource::Slice(arg) => BufferData::Slice {
+                bytes: unsafe { std::slice::from_raw_parts(arg.ptr as *const u8, arg.size) }
+                    .to_vec(),
+                element_size: arg.element_size,
+            },
+            BufferSource::Empty { size, element_size } => BufferData::Empty {
+                size: *size,
+                element_size: *element_size,
+            },
+        }
+    }
+
+    fn source(&self) -> BufferSource<'_> {
+        match self {
+            BufferData::Slice {
+                bytes,
+                element_size,
+            } => unsafe { BufferSource::pointer(bytes.as_ptr(), bytes.len(), *element_size) },
+            BufferData::Empty { size, element_size } => BufferSource::Empty {
+                size: *size,
+                element_size: *element_size,
+            },
+        }
+    }
+}
+
+macro_rules! commands {
+    ($($tag:literal $(#[$attr:meta])* $variant:ident { $($field:ident: $ty:ty),* },)*) => {
+        /// One recorded `RenderingBackend` call.
+        /// Ids are the ones the recorded backend returned, `Replay` maps them to new ones.
+        #[derive(Clone, Debug, PartialEq)]
+        pub enum Command {
+            $($(#[$attr])* $variant { $($field: $ty),* },)*
+        }
+
+        impl Encode for Command {
+            fn write(&self, w: &mut Vec<u8>) {
+                match self {
+                    $(Command::$variant { $($field),* } => {
+                        w.push($tag);
+                        $($field.write(w);)*
+                    })*
+                }
+            }
+
+            fn read(r: &mut Reader) -> Result<Command, LogError> {
+                Ok(match u8::read(r)? {
+                    $($tag => Command::$variant { $($field: <$ty>::read(r)?),* },)*
+                    tag => return Err(r.error(&format!("unknown command {}", tag))),
+                })
+            }
+        }
+    };
+}
+
+commands! {
+    0 NewShader { shader: ShaderId, code: ShaderCode, meta: ShaderMeta },
+    1 NewTexture { texture: TextureId, access: TextureAccess, data: TextureData, params: TextureParams },
+    2 TextureSetMinFilter { texture: TextureId, filter: FilterMode, mipmap_filter: MipmapFilterMode },
+    3 TextureSetMagFilter { texture: TextureId, filter: FilterMode },
+    4 TextureSetWrap { texture: TextureId, wrap_x: TextureWrap, wrap_y: TextureWrap },
+    5 TextureGenerateMipmaps { texture: TextureId },
+    6 TextureResize { texture: TextureId, width: u32, height: u32, bytes: Option<Vec<u8>> },
+    7 TextureUpdatePart {
+        texture: TextureId,
+        x_offset: i32,
+        y_offset: i32,
+        width: i32,
+        height: i32,
+        bytes: Vec<u8>
+    },
+    8 NewRenderPass { pass: RenderPass, colors: Vec<TextureId>, depth: Option<TextureId> },
+    9 DeleteRenderPass { pass: RenderPass },
+    10 NewPipeline {
+        pipeline: Pipeline,
+        buffer_layout: Vec<BufferLayout>,
+        attributes: Vec<VertexAttribute>,
+        shader: ShaderId,
+        params: PipelineParams
+    },
+    11 ApplyPipeline { pipeline: Pipeline },
+    12 DeletePipeline { pipeline: Pipeline },
+    13 NewBuffer { buffer: BufferId, type_: BufferType, usage: BufferUsage, data: BufferData },
+    14 BufferUpdate { buffer: BufferId, data: BufferData },
+    15 DeleteBuffer { buffer: BufferId },
+    16 DeleteTexture { texture: TextureId },
+    17 DeleteShader { shader: ShaderId },
+    18 ApplyViewport { x: i32, y: i32, w: i32, h: i32 },
+    19 ApplyScissorRect { x: i32, y: i32, w: i32, h: i32 },
+    20 ApplyBindings { vertex_buffers: Vec<BufferId>, index_buffer: BufferId, textures: Vec<TextureId> },
+    21 ApplyUniforms { bytes: Vec<u8> },
+    22 Clear { color: Option<(f32, f32, f32, f32)>, depth: Option<f32>, stencil: Option<i32> },
+    23 BeginDefaultPass { action: PassAction },
+    24 BeginPass { pass: Option<RenderPass>, action: PassAction },
+    25 EndRenderPass {},
+    26 CommitFrame {},
+    27 Draw { base_element: i32, num_elements: i32, num_instances: i32 },
+}
+
+/// Backend wrapper recording every call into a `Command` log.
+/// ```ignore
+/// let mut ctx = RecordingContext::new(window::new_rendering_backend());
+/// stage.draw(&mut ctx);
+/// let draws = ctx.commands().iter().filter(|c| matches!(c, Command::Draw { .. })).count();
+/// ```
+pub struct RecordingContext {
+    inner: Box<dyn RenderingBackend>,
+    commands: RefCell<Vec<Command>>,
+}
+
+impl RecordingContext {
+    pub fn new(inner: Box<dyn RenderingBackend>) -> RecordingContext {
+        RecordingContext {
+            inner,
+            commands: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn commands(&self) -> std::cell::Ref<'_, Vec<Command>> {
+        self.commands.borrow()
+    }
+
+    /// Empty the log, returning what was recorded so far.
+    /// Note that replaying commands taken after the resources were created will fail.
+    pub fn take_commands(&mut self) -> Vec<Command> {
+        std::mem::take(self.commands.get_mut())
+    }
+
+    /// The wrapped backend. Calls made directly on it are not recorded.
+    pub fn inner(&mut self) -> &mut dyn RenderingBackend {
+        &mut *self.inner
+    }
+
+    fn record(&self, command: Command) {
+        self.commands.borrow_mut().push(command);
+    }
+}
+
+impl RenderingBackend for RecordingContext {
+    fn info(&self) -> ContextInfo {
+        self.inner.info()
+    }
+
+    fn new_shader(
+        &mut self,
+        shader: ShaderSource,
+        meta: ShaderMeta,
+    ) -> Result<ShaderId, ShaderError> {
+        let code = ShaderCode::new(&shader);
+        let id = self.inner.new_shader(shader, meta.clone())?;
+        self.record(Command::NewShader {
+            shader: id,
+            code,
+            meta,
+        });
+        Ok(id)
+    }
+
+    fn new_texture(
+        &mut self,
+        access: TextureAccess,
+        data: TextureSource,
+        params: TextureParams,
+    ) -> TextureId {
+        let recorded = TextureData::new(&data);
+        let texture = self.inner.new_texture(access, data, params);
+        self.record(Command::NewTexture {
+            texture,
+            access,
+            data: recorded,
+            params,
+        });
+        texture
+    }
+
+    fn texture_params(&self, texture: TextureId) -> TextureParams {
+        self.inner.texture_params(texture)
+    }
+
+    unsafe fn texture_raw_id(&self, texture: TextureId) -> RawId {
+        self.inner.texture_raw_id(texture)
+    }
+
+    fn texture_set_min_filter(
+        &mut self,
+        texture: TextureId,
+        filter: FilterMode,
+        mipmap_filter: MipmapFilterMode,
+    ) {
+        self.inner
+            .texture_set_min_filter(texture, filter, mipmap_filter);
+        self.record(Command::TextureSetMinFilter {
+            texture,
+            filter,
+            mipmap_filter,
+        });
+    }
+
+    fn texture_set_mag_filter(&mut self, texture: TextureId, filter: FilterMode) {
+        self.inner.texture_set_mag_filter(texture, filter);
+        self.record(Command::TextureSetMagFilter { texture, filter });
+    }
+
+    fn texture_set_wrap(&mut self, texture: TextureId, wrap_x: TextureWrap, wrap_y: TextureWrap) {
+        self.inner.texture_set_wrap(texture, wrap_x, wrap_y);
+        self.record(Command::TextureSetWrap {
+            texture,
+            wrap_x,
+            wrap_y,
+        });
+    }
+
+    fn texture_generate_mipmaps(&mut self, texture: TextureId) {
+        self.inner.texture_generate_mipmaps(texture);
+        self.record(Command::TextureGenerateMipmaps { texture });
+    }
+
+    fn texture_resize(
+        &mut self,
+        texture: TextureId,
+        width: u32,
+        height: u32,
+        bytes: Option<&[u8]>,
+    ) {
+        self.inner.texture_resize(texture, width, height, bytes);
+        self.record(Command::TextureResize {
+            texture,
+            width,
+            height,
+            bytes: bytes.map(|bytes| bytes.to_vec()),
+        });
+    }
+
+    fn texture_read_pixels(&mut self, texture: TextureId, bytes: &mut [u8]) {
+        self.inner.texture_read_pixels(texture, bytes)
+    }
+
+    fn texture_update_part(
+        &mut self,
+        texture: TextureId,
+        x_offset: i32,
+        y_offset: i32,
+        width: i32,
+        height: i32,
+        bytes: &[u8],
+    ) {
+        self.inner
+            .texture_update_part(texture, x_offset, y_offset, width, height, bytes);
+        self.record(Command::TextureUpdatePart {
+            texture,
+            x_offset,
+            y_offset,
+            width,
+            height,
+            bytes: bytes.to_vec(),
+        });
+    }
+
+    fn new_render_pass_mrt(
+        &mut self,
+        color_img: &[TextureId],
+        depth_img: Option<TextureId>,
+    ) -> RenderPass {
+        let pass = self.inner.new_render_pass_mrt(color_img, depth_img);
+        self.record(Command::NewRenderPass {
+            pass,
+            colors: color_img.to_vec(),
+            depth: depth_img,
+        });
+        pass
+    }
+
+    fn render_pass_color_attachments(&self, render_pass: RenderPass) -> &[TextureId] {
+        self.inner.render_pass_color_attachments(render_pass)
+    }
+
+    fn delete_render_pass(&mut self, render_pass: RenderPass) {
+        self.inner.delete_render_pass(render_pass);
+        self.record(Command::DeleteRenderPass { pass: render_pass });
+    }
+
+    fn new_pipeline(
+        &mut self,
+        buffer_layout: &[BufferLayout],
+        attributes: &[VertexAttribute],
+        shader: ShaderId,
+        params: PipelineParams,
+    ) -> Pipeline {
+        let pipeline = self
+            .inner
+            .new_pipeline(buffer_layout, attributes, shader, params);
+        self.record(Command::NewPipeline {
+            pipeline,
+            buffer_layout: buffer_layout.to_vec(),
+            attributes: attributes.to_vec(),
+            shader,
+            params,
+        });
+        pipeline
+    }
+
+    fn apply_pipeline(&mut self, pipeline: &Pipeline) {
+        self.inner.apply_pipeline(pipeline);
+        self.record(Command::ApplyPipeline {
+            pipeline: *pipeline,
+        });
+    }
+
+    fn delete_pipeline(&mut self, pipeline: Pipeline) {
+        self.inner.delete_pipeline(pipeline);
+        self.record(Command::DeletePipeline { pipeline });
+    }
+
+    fn new_buffer(
+        &mut self,
+        type_: BufferType,
+        usage: BufferUsage,
+        data: BufferSource,
+    ) -> BufferId {
+        let recorded = BufferData::new(&data);
+        let buffer = self.inner.new_buffer(type_, usage, data);
+        self.record(Command::NewBuffer {
+            buffer,
+            type_,
+            usage,
+            data: recorded,
+        });
+        buffer
+    }
+
+    fn buffer_update(&mut self, buffer: BufferId, data: BufferSource) {
+        let recorded = BufferData::new(&data);
+        self.inner.buffer_update(buffer, data);
+        self.record(Command::BufferUpdate {
+            buffer,
+            data: recorded,
+        });
+    }
+
+    fn buffer_size(&mut self, buffer: BufferId) -> usize {
+        self.inner.buffer_size(buffer)
+    }
+
+    fn delete_buffer(&mut self, buffer: BufferId) {
+        self.inner.delete_buffer(buffer);
+        self.record(Command::DeleteBuffer { buffer });
+    }
+
+    fn delete_texture(&mut self, texture: TextureId) {
+        self.inner.delete_texture(texture);
+        self.record(Command::DeleteTexture { texture });
+    }
+
+    fn delete_shader(&mut self, program: ShaderId) {
+        self.inner.delete_shader(program);
+        self.record(Command::DeleteShader { shader: program });
+    }
+
+    fn apply_viewport(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        self.inner.apply_viewport(x, y, w, h);
+        self.record(Command::ApplyViewport { x, y, w, h });
+    }
+
+    fn apply_scissor_rect(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        self.inner.apply_scissor_rect(x, y, w, h);
+        self.record(Command::ApplyScissorRect { x, y, w, h });
+    }
+
+    fn apply_bindings_from_slice(
+        &mut self,
+        vertex_buffers: &[BufferId],
+        index_buffer: BufferId,
+        textures: &[TextureId],
+    ) {
+        self.inner
+            .apply_bindings_from_slice(vertex_buffers, index_buffer, textures);
+        self.record(Command::ApplyBindings {
+            vertex_buffers: vertex_buffers.to_vec(),
+            index_buffer,
+            textures: textures.to_vec(),
+        });
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn apply_uniforms_from_bytes(&mut self, uniform_ptr: *const u8, size: usize) {
+        self.inner.apply_uniforms_from_bytes(uniform_ptr, size);
+        let bytes = unsafe { std::slice::from_raw_parts(uniform_ptr, size) };
+        self.record(Command::ApplyUniforms {
+            bytes: bytes.to_vec(),
+        });
+    }
+
+    fn clear(
+        &mut self,
+        color: Option<(f32, f32, f32, f32)>,
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) {
+        self.inner.clear(color, depth, stencil);
+        self.record(Command::Clear {
+            color,
+            depth,
+            stencil,
+        });
+    }
+
+    fn begin_default_pass(&mut self, action: PassAction) {
+        self.inner.begin_default_pass(action);
+        self.record(Command::BeginDefaultPass { action });
+    }
+
+    fn begin_pass(&mut self, pass: Option<RenderPass>, action: PassAction) {
+        self.inner.begin_pass(pass, action);
+        self.record(Command::BeginPass { pass, action });
+    }
+
+    fn end_render_pass(&mut self) {
+        self.inner.end_render_pass();
+        self.record(Command::EndRenderPass {});
+    }
+
+    fn commit_frame(&mut self) {
+        self.inner.commit_frame();
+        self.record(Command::CommitFrame {});
+    }
+
+    fn draw(&self, base_element: i32, num_elements: i32, num_instances: i32) {
+        self.inner.draw(base_element, num_elements, num_instances);
+        self.record(Command::Draw {
+            base_element,
+            num_elements,
+            num_instances,
+        });
+    }
+}
+
+/// Plays recorded commands onto a backend, keeping track of the resources it created.
+/// The same `Replay` should be used for all the commands of a log: ids of resources
+/// created by earlier commands are mapped to the ones the backend returned.
+#[derive(Default)]
+pub struct Replay {
+    shaders: HashMap<ShaderId, ShaderId>,
+    textures: HashMap<TextureId, TextureId>,
+    passes: HashMap<RenderPass, RenderPass>,
+    pipelines: HashMap<Pipeline, Pipeline>,
+    buffers: HashMap<BufferId, BufferId>,
+}
+
+fn mapped<T: Copy + Eq + Hash + std::fmt::Debug>(ids: &HashMap<T, T>, id: T) -> T {
+    *ids.get(&id)
+        .unwrap_or_else(|| panic!("{:?} was not created by the replayed commands", id))
+}
+
+impl Replay {
+    pub fn new() -> Replay {
+        Replay::default()
+    }
+
+    /// Texture created for a recorded one, to read back the replayed render targets.
+    /// Raw textures are not managed by miniquad and are returned unchanged.
+    pub fn texture(&self, recorded: TextureId) -> TextureId {
+        match recorded.0 {
+            TextureIdInner::Raw(_) => recorded,
+            TextureIdInner::Managed(_) => mapped(&self.textures, recorded),
+        }
+    }
+
+    pub fn replay(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        commands: &[Command],
+    ) -> Result<(), ShaderError> {
+        for command in commands {
+            self.apply(ctx, command)?;
+        }
+        Ok(())
+    }
+
+    /// Fails only if the backend can't compile a recorded shader,
+    /// for example GLSL replayed onto Metal.
+    pub fn apply(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        command: &Command,
+    ) -> Result<(), ShaderError> {
+        match command {
+            Command::NewShader { shader, code, meta } => {
+                let id = ctx.new_shader(code.source(), meta.clone())?;
+                self.shaders.insert(*shader, id);
+            }
+            Command::NewTexture {
+                texture,
+                access,
+                data,
+                params,
+            } => {
+                let id = match data {
+                    TextureData::Empty => ctx.new_texture(*access, TextureSource::Empty, *params),
+                    TextureData::Bytes(bytes) => {
+                        ctx.new_texture(*access, TextureSource::Bytes(bytes), *params)
+                    }
+                    TextureData::Array(faces) => {
+                        let mips: Vec<Vec<&[u8]>> = faces
+                            .iter()
+                            .map(|mips| mips.iter().map(|mip| &mip[..]).collect())
+                            .collect();
+                        let faces: Vec<&[&[u8]]> = mips.iter().map(|mips| &mips[..]).collect();
+                        ctx.new_texture(*access, TextureSource::Array(&faces), *params)
+                    }
+                };
+                self.textures.insert(*texture, id);
+            }
+            Command::TextureSetMinFilter {
+                texture,
+                filter,
+                mipmap_filter,
+            } => ctx.texture_set_min_filter(self.texture(*texture), *filter, *mipmap_filter),
+            Command::TextureSetMagFilter { texture, filter } => {
+                ctx.texture_set_mag_filter(self.texture(*texture), *filter)
+            }
+            Command::TextureSetWrap {
+                texture,
+                wrap_x,
+                wrap_y,
+            } => ctx.texture_set_wrap(self.texture(*texture), *wrap_x, *wrap_y),
+            Command::TextureGenerateMipmaps { texture } => {
+                ctx.texture_generate_mipmaps(self.texture(*texture))
+            }
+            Command::TextureResize {
+                texture,
+                width,
+                height,
+                bytes,
+            } => ctx.texture_resize(self.texture(*texture), *width, *height, bytes.as_deref()),
+            Command::TextureUpdatePart {
+                texture,
+                x_offset,
+                y_offset,
+                width,
+                height,
+                bytes,
+            } => ctx.texture_update_part(
+                self.texture(*texture),
+                *x_offset,
+                *y_offset,
+                *width,
+                *height,
+                bytes,
+            ),
+            Command::NewRenderPass {
+                pass,
+                colors,
+                depth,
+            } => {
+                let colors: Vec<TextureId> =
+                    colors.iter().map(|color| self.texture(*color)).collect();
+                let depth = depth.map(|depth| self.texture(depth));
+                let id = ctx.new_render_pass_mrt(&colors, depth);
+                self.passes.insert(*pass, id);
+            }
+            Command::DeleteRenderPass { pass } => {
+                ctx.delete_render_pass(mapped(&self.passes, *pass));
+                self.passes.remove(pass);
+            }
+            Command::NewPipeline {
+                pipeline,
+                buffer_layout,
+                attributes,
+                shader,
+                params,
+            } => {
+                let shader = mapped(&self.shaders, *shader);
+                let id = ctx.new_pipeline(buffer_layout, attributes, shader, *params);
+                self.pipelines.insert(*pipeline, id);
+            }
+            Command::ApplyPipeline { pipeline } => {
+                ctx.apply_pipeline(&mapped(&self.pipelines, *pipeline))
+            }
+            Command::DeletePipeline { pipeline } => {
+                ctx.delete_pipeline(mapped(&self.pipelines, *pipeline));
+                self.pipelines.remove(pipeline);
+            }
+            Command::NewBuffer {
+                buffer,
+                type_,
+                usage,
+                data,
+            } => {
+                let id = ctx.new_buffer(*type_, *usage, data.source());
+                self.buffers.insert(*buffer, id);
+            }
+            Command::BufferUpdate { buffer, data } => {
+                ctx.buffer_update(mapped(&self.buffers, *buffer), data.source())
+            }
+            Command::DeleteBuffer { buffer } => {
+                ctx.delete_buffer(mapped(&self.buffers, *buffer));
+                self.buffers.remove(buffer);
+            }
+            Command::DeleteTexture { texture } => {
+                ctx.delete_texture(self.texture(*texture));
+                self.textures.remove(texture);
+            }
+            Command::DeleteShader { shader } => {
+                ctx.delete_shader(mapped(&self.shaders, *shader));
+                self.shaders.remove(shader);
+            }
+            Command::ApplyViewport { x, y, w, h } => ctx.apply_viewport(*x, *y, *w, *h),
+            Command::ApplyScissorRect { x, y, w, h } => ctx.apply_scissor_rect(*x, *y, *w, *h),
+            Command::ApplyBindings {
+                vertex_buffers,
+                index_buffer,
+                textures,
+            } => {
+                let vertex_buffers: Vec<BufferId> = vertex_buffers
+                    .iter()
+                    .map(|buffer| mapped(&self.buffers, *buffer))
+                    .collect();
+                let textures: Vec<TextureId> = textures
+                    .iter()
+                    .map(|texture| self.texture(*texture))
+                    .collect();
+                ctx.apply_bindings_from_slice(
+                    &vertex_buffers,
+                    mapped(&self.buffers, *index_buffer),
+                    &textures,
+                );
+            }
+            Command::ApplyUniforms { bytes } => {
+                ctx.apply_uniforms_from_bytes(bytes.as_ptr(), bytes.len())
+            }
+            Command::Clear {
+                color,
+                depth,
+                stencil,
+            } => ctx.clear(*color, *depth, *stencil),
+            Command::BeginDefaultPass { action } => ctx.begin_default_pass(*action),
+            Command::BeginPass { pass, action } => {
+                let pass = pass.map(|pass| mapped(&self.passes, pass));
+                ctx.begin_pass(pass, *action);
+            }
+            Command::EndRenderPass {} => ctx.end_render_pass(),
+            Command::CommitFrame {} => ctx.commit_frame(),
+            Command::Draw {
+                base_element,
+                num_elements,
+                num_instances,
+            } => ctx.draw(*base_element, *num_elements, *num_instances),
+        }
+        Ok(())
+    }
+}
+
+/// Binary log, to be saved to a file and read back with `deserialize`.
+///
+/// Panics on textures created with `TextureId::from_raw_id` on Metal.
+pub fn serialize(commands: &[Command]) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    VERSION.write(&mut bytes);
+    commands.len().write(&mut bytes);
+    for command in commands {
+        command.write(&mut bytes);
+    }
+    bytes
+}
+
+pub fn deserialize(bytes: &[u8]) -> Result<Vec<Command>, LogError> {
+    let mut reader = Reader { bytes, offset: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(reader.error("not a command log"));
+    }
+    let version = u32::read(&mut reader)?;
+    if version != VERSION {
+        return Err(reader.error(&format!("unsupported log version {}", version)));
+    }
+    let commands = Vec::read(&mut reader)?;
+    if reader.offset != bytes.len() {
+        return Err(reader.error("trailing bytes"));
+    }
+    Ok(commands)
+}
+
+#[derive(Clone, Debug)]
+pub struct LogError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl Display for LogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl Error for LogError {}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LogError> {
+        if self.bytes.len() - self.offset < len {
+            return Err(self.error("unexpected end of log"));
+        }
+        self.offset += len;
+        Ok(&self.bytes[self.offset - len..self.offset])
+    }
+
+    fn error(&self, message: &str) -> LogError {
+        LogError {
+            offset: self.offset,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Little endian, lengths and ids as u64.
+trait Encode: Sized {
+    fn write(&self, w: &mut Vec<u8>);
+    fn read(r: &mut Reader) -> Result<Self, LogError>;
+}
+
+macro_rules! numbers {
+    ($($ty:ty),*) => { $(
+        impl Encode for $ty {
+            fn write(&self, w: &mut Vec<u8>) {
+                w.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn read(r: &mut Reader) -> Result<$ty, LogError> {
+                let bytes = r.take(std::mem::size_of::<$ty>())?;
+                Ok(<$ty>::from_le_bytes(bytes.try_into().unwrap()))
+            }
+        }
+    )* };
+}
+
+numbers!(u8, u32, i32, u64, f32);
+
+impl Encode for usize {
+    fn write(&self, w: &mut Vec<u8>) {
+        (*self as u64).write(w)
+    }
+
+    fn read(r: &mut Reader) -> Result<usize, LogError> {
+        usize::try_from(u64::read(r)?).map_err(|_| r.error("value out of range"))
+    }
+}
+
+impl Encode for bool {
+    fn write(&self, w: &mut Vec<u8>) {
+        w.push(*self as u8)
+    }
+
+    fn read(r: &mut Reader) -> Result<bool, LogError> {
+        match u8::read(r)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(r.error("invalid bool")),
+        }
+    }
+}
+
+impl Encode for String {
+    fn write(&self, w: &mut Vec<u8>) {
+        self.len().write(w);
+        w.extend_from_slice(self.as_bytes());
+    }
+
+    fn read(r: &mut Reader) -> Result<String, LogError> {
+        let len = usize::read(r)?;
+        let bytes = r.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| r.error("invalid utf8"))
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn write(&self, w: &mut Vec<u8>) {
+        self.len().write(w);
+        for x in self {
+            x.write(w);
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<Vec<T>, LogError> {
+        let len = usize::read(r)?;
+        // every element takes at least a byte, don't trust the length any further
+        if len > r.bytes.len() - r.offset {
+            return Err(r.error("unexpected end of log"));
+        }
+        (0..len).map(|_| T::read(r)).collect()
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn write(&self, w: &mut Vec<u8>) {
+        self.is_some().write(w);
+        if let Some(x) = self {
+            x.write(w);
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<Option<T>, LogError> {
+        Ok(if bool::read(r)? {
+            Some(T::read(r)?)
+        } else {
+            None
+        })
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn write(&self, w: &mut Vec<u8>) {
+        self.0.write(w);
+        self.1.write(w);
+    }
+
+    fn read(r: &mut Reader) -> Result<(A, B), LogError> {
+        Ok((A::read(r)?, B::read(r)?))
+    }
+}
+
+impl<T: Encode> Encode for (T, T, T, T) {
+    fn write(&self, w: &mut Vec<u8>) {
+        self.0.write(w);
+        self.1.write(w);
+        self.2.write(w);
+        self.3.write(w);
+    }
+
+    fn read(r: &mut Reader) -> Result<(T, T, T, T), LogError> {
+        Ok((T::read(r)?, T::read(r)?, T::read(r)?, T::read(r)?))
+    }
+}
+
+/// Fieldless enums, as their index in the list.
+macro_rules! variants {
+    ($($ty:ident { $($variant:ident),* })*) => { $(
+        impl Encode for $ty {
+            fn write(&self, w: &mut Vec<u8>) {
+                let ix = [$($ty::$variant),*].iter().position(|v| v == self).unwrap();
+                w.push(ix as u8);
+            }
+
+            fn read(r: &mut Reader) -> Result<$ty, LogError> {
+                let ix = u8::read(r)? as usize;
+                [$($ty::$variant),*]
+                    .get(ix)
+                    .copied()
+                    .ok_or_else(|| r.error(concat!("invalid ", stringify!($ty))))
+            }
+        }
+    )* };
+}
+
+variants! {
+    UniformType { Float1, Float2, Float3, Float4, Int1, Int2, Int3, Int4, Mat4 }
+    VertexFormat {
+        Float1, Float2, Float3, Float4, Byte1, Byte2, Byte3, Byte4,
+        Short1, Short2, Short3, Short4, Int1, Int2, Int3, Int4, Mat4
+    }
+    VertexStep { PerVertex, PerInstance }
+    TextureFormat { RGB8, RGBA8, RGBA16F, Depth, Depth32, Alpha }
+    TextureWrap { Repeat, Mirror, Clamp }
+    FilterMode { Linear, Nearest }
+    MipmapFilterMode { None, Linear, Nearest }
+    TextureAccess { Static, RenderTarget }
+    TextureKind { Texture2D, CubeMap }
+    StencilOp {
+        Keep, Zero, Replace, IncrementClamp, DecrementClamp, Invert, IncrementWrap, DecrementWrap
+    }
+    CompareFunc { Always, Never, Less, Equal, LessOrEqual, Greater, NotEqual, GreaterOrEqual }
+    CullFace { Nothing, Front, Back }
+    FrontFaceOrder { Clockwise, CounterClockwise }
+    Comparison { Never, Less, LessOrEqual, Greater, GreaterOrEqual, Equal, NotEqual, Always }
+    Equation { Add, Subtract, ReverseSubtract }
+    BlendValue { SourceColor, SourceAlpha, DestinationColor, DestinationAlpha }
+    PrimitiveType { Triangles, Lines, Points }
+    BufferType { VertexBuffer, IndexBuffer }
+    BufferUsage { Immutable, Dynamic, Stream }
+}
+
+/// Structs, field by field.
+macro_rules! fields {
+    ($($ty:ident { $($field:ident),* })*) => { $(
+        impl Encode for $ty {
+            fn write(&self, w: &mut Vec<u8>) {
+                $(self.$field.write(w);)*
+            }
+
+            fn read(r: &mut Reader) -> Result<$ty, LogError> {
+                Ok($ty { $($field: Encode::read(r)?),* })
+            }
+        }
+    )* };
+}
+
+fields! {
+    UniformDesc { name, uniform_type, array_count }
+    UniformBlockLayout { uniforms }
+    ShaderMeta { uniforms, images }
+    BufferLayout { stride, step_func, step_rate }
+    TextureParams {
+        kind, format, wrap, min_filter, mag_filter, mipmap_filter, width, height, allocate_mipmaps
+    }
+    BlendState { equation, sfactor, dfactor }
+    StencilState { front, back }
+    StencilFaceState {
+        fail_op, depth_fail_op, pass_op, test_func, test_ref, test_mask, write_mask
+    }
+    PipelineParams {
+        cull_face, front_face_order, depth_test, depth_write, depth_write_offset,
+        color_blend, alpha_blend, stencil_test, color_write, primitive_type
+    }
+}
+
+macro_rules! ids {
+    ($($ty:ident),*) => { $(
+        impl Encode for $ty {
+            fn write(&self, w: &mut Vec<u8>) {
+                self.0.write(w)
+            }
+
+            fn read(r: &mut Reader) -> Result<$ty, LogError> {
+                Ok($ty(usize::read(r)?))
+            }
+        }
+    )* };
+}
+
+ids!(ShaderId, Pipeline, RenderPass, BufferId);
+
+impl Encode for TextureId {
+    fn write(&self, w: &mut Vec<u8>) {
+        match self.0 {
+            TextureIdInner::Managed(ix) => {
+                w.push(0);
+                ix.write(w);
+            }
+            TextureIdInner::Raw(RawId::OpenGl(id)) => {
+                w.push(1);
+                id.write(w);
+            }
+            #[allow(unreachable_patterns)]
+            _ => panic!("raw Metal textures can't be serialized"),
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<TextureId, LogError> {
+        match u8::read(r)? {
+            0 => Ok(TextureId(TextureIdInner::Managed(usize::read(r)?))),
+            1 => Ok(TextureId::from_raw_id(RawId::OpenGl(u32::read(r)?))),
+            _ => Err(r.error("invalid TextureId")),
+        }
+    }
+}
+
+impl Encode for VertexAttribute {
+    fn write(&self, w: &mut Vec<u8>) {
+        self.name.to_string().write(w);
+        self.format.write(w);
+        self.buffer_index.write(w);
+        self.gl_pass_as_float.write(w);
+    }
+
+    /// Attribute names are leaked: pipelines want them `'static`.
+    fn read(r: &mut Reader) -> Result<VertexAttribute, LogError> {
+        Ok(VertexAttribute {
+            name: Box::leak(String::read(r)?.into_boxed_str()),
+            format: VertexFormat::read(r)?,
+            buffer_index: usize::read(r)?,
+            gl_pass_as_float: bool::read(r)?,
+        })
+    }
+}
+
+impl Encode for BlendFactor {
+    fn write(&self, w: &mut Vec<u8>) {
+        match self {
+            BlendFactor::Zero => w.push(0),
+            BlendFactor::One => w.push(1),
+            BlendFactor::Value(value) => {
+                w.push(2);
+                value.write(w);
+            }
+            BlendFactor::OneMinusValue(value) => {
+                w.push(3);
+                value.write(w);
+            }
+            BlendFactor::SourceAlphaSaturate => w.push(4),
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<BlendFactor, LogError> {
+        Ok(match u8::read(r)? {
+            0 => BlendFactor::Zero,
+            1 => BlendFactor::One,
+            2 => BlendFactor::Value(BlendValue::read(r)?),
+            3 => BlendFactor::OneMinusValue(BlendValue::read(r)?),
+            4 => BlendFactor::SourceAlphaSaturate,
+            _ => return Err(r.error("invalid BlendFactor")),
+        })
+    }
+}
+
+impl Encode for PassAction {
+    fn write(&self, w: &mut Vec<u8>) {
+        match self {
+            PassAction::Nothing => w.push(0),
+            PassAction::Clear {
+                color,
+                depth,
+                stencil,
+            } => {
+                w.push(1);
+                color.write(w);
+                depth.write(w);
+                stencil.write(w);
+            }
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<PassAction, LogError> {
+        Ok(match u8::read(r)? {
+            0 => PassAction::Nothing,
+            1 => PassAction::Clear {
+                color: Encode::read(r)?,
+                depth: Encode::read(r)?,
+                stencil: Encode::read(r)?,
+            },
+            _ => return Err(r.error("invalid PassAction")),
+        })
+    }
+}
+
+impl Encode for ShaderCode {
+    fn write(&self, w: &mut Vec<u8>) {
+        match self {
+            ShaderCode::Glsl { vertex, fragment } => {
+                w.push(0);
+                vertex.write(w);
+                fragment.write(w);
+            }
+            ShaderCode::Msl { program } => {
+                w.push(1);
+                program.write(w);
+            }
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<ShaderCode, LogError> {
+        Ok(match u8::read(r)? {
+            0 => ShaderCode::Glsl {
+                vertex: String::read(r)?,
+                fragment: String::read(r)?,
+            },
+            1 => ShaderCode::Msl {
+                program: String::read(r)?,
+            },
+            _ => return Err(r.error("invalid ShaderCode")),
+        })
+    }
+}
+
+impl Encode for TextureData {
+    fn write(&self, w: &mut Vec<u8>) {
+        match self {
+            TextureData::Empty => w.push(0),
+            TextureData::Bytes(bytes) => {
+                w.push(1);
+                bytes.write(w);
+            }
+            TextureData::Array(faces) => {
+                w.push(2);
+                faces.write(w);
+            }
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<TextureData, LogError> {
+        Ok(match u8::read(r)? {
+            0 => TextureData::Empty,
+            1 => TextureData::Bytes(Encode::read(r)?),
+            2 => TextureData::Array(Encode::read(r)?),
+            _ => return Err(r.error("invalid TextureData")),
+        })
+    }
+}
+
+impl Encode for BufferData {
+    fn write(&self, w: &mut Vec<u8>) {
+        match self {
+            BufferData::Slice {
+                bytes,
+                element_size,
+            } => {
+                w.push(0);
+                bytes.write(w);
+                element_size.write(w);
+            }
+            BufferData::Empty { size, element_size } => {
+                w.push(1);
+                size.write(w);
+                element_size.write(w);
+            }
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<BufferData, LogError> {
+        Ok(match u8::read(r)? {
+            0 => BufferData::Slice {
+                bytes: Encode::read(r)?,
+                element_size: usize::read(r)?,
+            },
+            1 => BufferData::Empty {
+                size: usize::read(r)?,
+                element_size: usize::read(r)?,
+            },
+            _ => return Err(r.error("invalid BufferData")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::software::tests::{draw_triangle, pixel};
+    use crate::SoftwareContext;
+
+    #[test]
+    fn record_and_replay() {
+        let software = SoftwareContext::new(4, 4);
+        let screen = software.screen_texture();
+        let mut recorder = RecordingContext::new(Box::new(software));
+        draw_triangle(&mut recorder, None);
+        let mut recorded = vec![0; 4 * 4 * 4];
+        recorder.inner().texture_read_pixels(screen, &mut recorded);
+        assert_eq!(pixel(&recorded, 4, 0, 0), [0, 255, 0, 255]);
+        assert_eq!(pixel(&recorded, 4, 3, 3), [0, 0, 255, 255]);
+
+        let commands = recorder.take_commands();
+        let log = deserialize(&serialize(&commands)).unwrap();
+        assert_eq!(log, commands);
+
+        let mut ctx = SoftwareContext::new(4, 4);
+        Replay::new().replay(&mut ctx, &log).unwrap();
+        let mut replayed = vec![0; 4 * 4 * 4];
+        ctx.texture_read_pixels(ctx.screen_texture(), &mut replayed);
+        assert_eq!(replayed, recorded);
+    }
+}
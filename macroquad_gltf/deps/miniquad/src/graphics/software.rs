@@ -0,0 +1,1267 @@
+//! CPU implementation of `RenderingBackend`, for tests and machines without a GPU.
+//!
+//! Shader sources are ignored: every pipeline runs the same fixed-function program,
+//! which finds its inputs by the names the bundled shaders use.
+//! - position: the "in_position", "in_pos" or "position" attribute, or the first one;
+//! - texture coordinates: the "in_uv", "texcoord" or "uv" attribute;
+//! - vertex color: the "in_color" or "color0" attribute, Byte4 colors are in 0..255;
+//! - transform: the "mvp" uniform, or "Projection" * "Model", missing matrices are identity;
+//! - color: the "Color" uniform;
+//! - texture: the first image, sampled by the texture coordinates,
+//!   or by the position for cubemaps.
+//!
+//! The output is texture * vertex color * "Color", without any lighting.
+//!
+//! Triangles are rasterized with the pipeline's depth test, face culling, blending and
+//! color mask, the viewport and the scissor rect. Lines, points, stencil and mipmaps
+//! are not implemented.
+
+use super::*;
+use crate::ResourceManager;
+
+use std::cell::RefCell;
+
+const POSITION_NAMES: &[&str] = &["in_position", "in_pos", "position"];
+const UV_NAMES: &[&str] = &["in_uv", "texcoord", "uv"];
+const COLOR_NAMES: &[&str] = &["in_color", "color0"];
+
+/// Interpolated per-vertex values: uv, color and the position for cubemap lookups.
+type Varyings = [f32; 9];
+
+struct Buffer {
+    data: Vec<u8>,
+    element_size: usize,
+}
+
+#[derive(Clone)]
+struct Texture {
+    params: TextureParams,
+    wrap_y: TextureWrap,
+    /// RGBA texels, rows bottom to top, one image per cubemap face.
+    faces: Vec<Vec<[f32; 4]>>,
+}
+
+struct Shader {
+    images: usize,
+    /// (name, byte offset, type) of each uniform, in the order of the uniform block.
+    uniforms: Vec<(String, usize, UniformType)>,
+}
+
+struct Attribute {
+    format: VertexFormat,
+    buffer_index: usize,
+    offset: usize,
+    stride: usize,
+    /// 0 for per-vertex attributes.
+    divisor: usize,
+}
+
+struct PipelineInternal {
+    attributes: Vec<Attribute>,
+    position: Option<usize>,
+    uv: Option<usize>,
+    color: Option<usize>,
+    shader: ShaderId,
+    params: PipelineParams,
+}
+
+struct RenderPassInternal {
+    colors: Vec<TextureId>,
+    depth: Option<TextureId>,
+}
+
+#[derive(Default)]
+struct State {
+    pipeline: Option<Pipeline>,
+    vertex_buffers: Vec<BufferId>,
+    index_buffer: Option<BufferId>,
+    images: Vec<TextureId>,
+    uniforms: Vec<u8>,
+    colors: Vec<TextureId>,
+    depth: Option<TextureId>,
+    viewport: (i32, i32, i32, i32),
+    scissor: Option<(i32, i32, i32, i32)>,
+}
+
+pub struct SoftwareContext {
+    shaders: ResourceManager<Shader>,
+    pipelines: ResourceManager<PipelineInternal>,
+    passes: ResourceManager<RenderPassInternal>,
+    buffers: ResourceManager<Buffer>,
+    /// `draw` takes `&self`, but writes into the render targets.
+    textures: ResourceManager<RefCell<Texture>>,
+    screen: TextureId,
+    screen_depth: TextureId,
+    state: State,
+}
+
+impl SoftwareContext {
+    /// The default framebuffer is a `width` x `height` RGBA8 texture, see `screen_texture`.
+    pub fn new(width: u32, height: u32) -> SoftwareContext {
+        let mut ctx = SoftwareContext {
+            shaders: ResourceManager::default(),
+            pipelines: ResourceManager::default(),
+            passes: ResourceManager::default(),
+            buffers: ResourceManager::default(),
+            textures: ResourceManager::default(),
+            screen: TextureId(TextureIdInner::Managed(0)),
+            screen_depth: TextureId(TextureIdInner::Managed(0)),
+            state: State::default(),
+        };
+        let params = TextureParams {
+            width,
+            height,
+            ..Default::default()
+        };
+        ctx.screen = ctx.new_render_texture(params);
+        ctx.screen_depth = ctx.new_render_texture(TextureParams {
+            format: TextureFormat::Depth32,
+            ..params
+        });
+        ctx
+    }
+
+    /// The default framebuffer's color, to be read with `texture_read_pixels`.
+    pub fn screen_texture(&self) -> TextureId {
+        self.screen
+    }
+
+    fn texture(&self, texture: TextureId) -> &RefCell<Texture> {
+        match texture.0 {
+            TextureIdInner::Managed(texture) => &self.textures[texture],
+            TextureIdInner::Raw(_) => panic!("Raw textures are not supported by SoftwareContext"),
+        }
+    }
+
+    fn texture_mut(&mut self, texture: TextureId) -> &mut Texture {
+        match texture.0 {
+            TextureIdInner::Managed(texture) => self.textures[texture].get_mut(),
+            TextureIdInner::Raw(_) => panic!("Raw textures are not supported by SoftwareContext"),
+        }
+    }
+
+    fn uniform(&self, name: &str) -> Option<&[u8]> {
+        let pipeline = &self.pipelines[self.state.pipeline?.0];
+        let shader = &self.shaders[pipeline.shader.0];
+        let (_, offset, uniform_type) = shader.uniforms.iter().find(|u| u.0 == name)?;
+        self.state
+            .uniforms
+            .get(*offset..*offset + uniform_type.size())
+    }
+
+    fn uniform_floats<const N: usize>(&self, name: &str) -> Option<[f32; N]> {
+        let bytes = self.uniform(name)?;
+        let mut res = [0.0; N];
+        for (x, bytes) in res.iter_mut().zip(bytes.chunks_exact(4)) {
+            *x = f32::from_le_bytes(bytes.try_into().unwrap());
+        }
+        Some(res)
+    }
+
+    fn fetch(&self, attribute: &Attribute, vertex: usize, instance: usize) -> [f32; 4] {
+        let mut res = [0.0, 0.0, 0.0, 1.0];
+        let Some(buffer) = self.state.vertex_buffers.get(attribute.buffer_index) else {
+            return res;
+        };
+        let buffer = &self.buffers[buffer.0];
+        let element = match attribute.divisor {
+            0 => vertex,
+            divisor => instance / divisor,
+        };
+        let start = attribute.offset + element * attribute.stride;
+        let components = attribute.format.components().min(4) as usize;
+        let size = attribute.format.size_bytes() as usize / attribute.format.components() as usize;
+        for (i, x) in res.iter_mut().take(components).enumerate() {
+            let at = start + i * size;
+            let Some(bytes) = buffer.data.get(at..at + size) else {
+                break;
+            };
+            *x = match attribute.format {
+                VertexFormat::Float1
+                | VertexFormat::Float2
+                | VertexFormat::Float3
+                | VertexFormat::Float4
+                | VertexFormat::Mat4 => f32::from_le_bytes(bytes.try_into().unwrap()),
+                VertexFormat::Byte1
+                | VertexFormat::Byte2
+                | VertexFormat::Byte3
+                | VertexFormat::Byte4 => bytes[0] as f32,
+                VertexFormat::Short1
+                | VertexFormat::Short2
+                | VertexFormat::Short3
+                | VertexFormat::Short4 => u16::from_le_bytes(bytes.try_into().unwrap()) as f32,
+                _ => u32::from_le_bytes(bytes.try_into().unwrap()) as f32,
+            };
+        }
+        res
+    }
+
+    /// Clip-space position and varyings of a vertex.
+    fn vertex(
+        &self,
+        pipeline: &PipelineInternal,
+        matrix: &[f32; 16],
+        vertex: usize,
+        instance: usize,
+    ) -> ([f32; 4], Varyings) {
+        let attribute = |ix: Option<usize>| {
+            ix.map(|ix| (self.fetch(&pipeline.attributes[ix], vertex, instance), ix))
+        };
+        let position = attribute(pipeline.position).map_or([0.0, 0.0, 0.0, 1.0], |p| p.0);
+        let uv = attribute(pipeline.uv).map_or([0.0; 4], |uv| uv.0);
+        let color = attribute(pipeline.color).map_or([1.0; 4], |(color, ix)| {
+            match pipeline.attributes[ix].format {
+                VertexFormat::Byte1
+                | VertexFormat::Byte2
+                | VertexFormat::Byte3
+                | VertexFormat::Byte4 => color.map(|x| x / 255.0),
+                _ => color,
+            }
+        });
+
+        let clip = mul_vec(matrix, position);
+        let varyings = [
+            uv[0],
+            uv[1],
+            color[0],
+            color[1],
+            color[2],
+            color[3],
+            position[0],
+            position[1],
+            position[2],
+        ];
+        (clip, varyings)
+    }
+
+    fn clear_targets(&mut self, color: Option<(f32, f32, f32, f32)>, depth: Option<f32>) {
+        if let Some((r, g, b, a)) = color {
+            for texture in self.state.colors.clone() {
+                for face in &mut self.texture_mut(texture).faces {
+                    face.fill([r, g, b, a]);
+                }
+            }
+        }
+        if let (Some(depth), Some(texture)) = (depth, self.state.depth) {
+            for face in &mut self.texture_mut(texture).faces {
+                face.fill([depth, 0.0, 0.0, 1.0]);
+            }
+        }
+    }
+
+    fn rasterize(
+        &self,
+        pipeline: &PipelineInternal,
+        triangle: [&([f32; 4], Varyings); 3],
+        targets: &mut [std::cell::RefMut<Texture>],
+        depth: &mut Option<std::cell::RefMut<Texture>>,
+        sampler: &Sampler,
+        color_uniform: [f32; 4],
+    ) {
+        let params = &pipeline.params;
+        let (vx, vy, vw, vh) = self.state.viewport;
+        let (target_w, target_h) = match (targets.first(), depth.as_ref()) {
+            (Some(target), _) => (target.params.width as i32, target.params.height as i32),
+            (None, Some(depth)) => (depth.params.width as i32, depth.params.height as i32),
+            (None, None) => return,
+        };
+
+        // window space x, y, depth and 1/w
+        let screen = triangle.map(|(clip, _)| {
+            let inv_w = 1.0 / clip[3];
+            [
+                vx as f32 + (clip[0] * inv_w * 0.5 + 0.5) * vw as f32,
+                vy as f32 + (clip[1] * inv_w * 0.5 + 0.5) * vh as f32,
+                clip[2] * inv_w * 0.5 + 0.5,
+                inv_w,
+            ]
+        });
+        let [a, b, c] = screen;
+        let area = edge(a, b, c);
+        if area == 0.0 {
+            return;
+        }
+        let front = match params.front_face_order {
+            FrontFaceOrder::CounterClockwise => area > 0.0,
+            FrontFaceOrder::Clockwise => area < 0.0,
+        };
+        match params.cull_face {
+            CullFace::Back if !front => return,
+            CullFace::Front if front => return,
+            _ => {}
+        }
+
+        let (mut x0, mut y0, mut x1, mut y1) = (vx.max(0), vy.max(0), vx + vw, vy + vh);
+        if let Some((sx, sy, sw, sh)) = self.state.scissor {
+            x0 = x0.max(sx);
+            y0 = y0.max(sy);
+            x1 = x1.min(sx + sw);
+            y1 = y1.min(sy + sh);
+        }
+        let min_x = a[0].min(b[0]).min(c[0]).floor().max(x0 as f32) as i32;
+        let min_y = a[1].min(b[1]).min(c[1]).floor().max(y0 as f32) as i32;
+        let max_x = (a[0].max(b[0]).max(c[0]).ceil() as i32).min(x1.min(target_w));
+        let max_y = (a[1].max(b[1]).max(c[1]).ceil() as i32).min(y1.min(target_h));
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let p = [x as f32 + 0.5, y as f32 + 0.5, 0.0, 0.0];
+                let l = [
+                    edge(b, c, p) / area,
+                    edge(c, a, p) / area,
+                    edge(a, b, p) / area,
+                ];
+                if l.iter().any(|l| *l < 0.0) {
+                    continue;
+                }
+                let texel = (y * target_w + x) as usize;
+
+                let z = l[0] * a[2] + l[1] * b[2] + l[2] * c[2];
+                if let Some(depth) = depth.as_mut() {
+                    let stored = depth.faces[0][texel][0];
+                    if !compare(params.depth_test, z, stored) {
+                        continue;
+                    }
+                    if params.depth_write {
+                        depth.faces[0][texel][0] = z.clamp(0.0, 1.0);
+                    }
+                }
+
+                // perspective correct interpolation
+                let inv_w = l[0] * a[3] + l[1] * b[3] + l[2] * c[3];
+                let mut varyings: Varyings = [0.0; 9];
+                for (i, v) in varyings.iter_mut().enumerate() {
+                    *v = (l[0] * a[3] * triangle[0].1[i]
+                        + l[1] * b[3] * triangle[1].1[i]
+                        + l[2] * c[3] * triangle[2].1[i])
+                        / inv_w;
+                }
+
+                let texture = sampler.sample(&varyings);
+                let mut color = [0.0; 4];
+                for i in 0..4 {
+                    color[i] = texture[i] * varyings[2 + i] * color_uniform[i];
+                }
+
+                for target in targets.iter_mut() {
+                    let clamp = target.params.format != TextureFormat::RGBA16F;
+                    let dst = &mut target.faces[0][texel];
+                    let mut out = blend(params, color, *dst);
+                    if clamp {
+                        out = out.map(|x| x.clamp(0.0, 1.0));
+                    }
+                    let mask = params.color_write;
+                    let mask = [mask.0, mask.1, mask.2, mask.3];
+                    for i in 0..4 {
+                        if mask[i] {
+                            dst[i] = out[i];
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The first image of the draw call.
+struct Sampler<'a> {
+    texture: Option<&'a Texture>,
+}
+
+impl<'a> Sampler<'a> {
+    fn sample(&self, varyings: &Varyings) -> [f32; 4] {
+        let Some(texture) = self.texture else {
+            return [1.0; 4];
+        };
+        let (face, u, v) = match texture.params.kind {
+            TextureKind::Texture2D => (0, varyings[0], varyings[1]),
+            TextureKind::CubeMap => cube_face(varyings[6], varyings[7], varyings[8]),
+        };
+        let w = texture.params.width as i32;
+        let h = texture.params.height as i32;
+        if w == 0 || h == 0 {
+            return [0.0; 4];
+        }
+        let texels = &texture.faces[face];
+        let fetch = |x: i32, y: i32| {
+            let x = wrap(x, w, texture.params.wrap);
+            let y = wrap(y, h, texture.wrap_y);
+            texels[(y * w + x) as usize]
+        };
+        let x = u * w as f32 - 0.5;
+        let y = v * h as f32 - 0.5;
+        match texture.params.mag_filter {
+            FilterMode::Nearest => fetch(x.round() as i32, y.round() as i32),
+            FilterMode::Linear => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i32, y0 as i32);
+                let t00 = fetch(x0, y0);
+                let t10 = fetch(x0 + 1, y0);
+                let t01 = fetch(x0, y0 + 1);
+                let t11 = fetch(x0 + 1, y0 + 1);
+                let mut res = [0.0; 4];
+                for i in 0..4 {
+                    let top = t00[i] + (t10[i] - t00[i]) * fx;
+                    let bottom = t01[i] + (t11[i] - t01[i]) * fx;
+                    res[i] = top + (bottom - top) * fy;
+                }
+                res
+            }
+        }
+    }
+}
+
+fn wrap(x: i32, size: i32, wrap: TextureWrap) -> i32 {
+    match wrap {
+        TextureWrap::Clamp => x.clamp(0, size - 1),
+        TextureWrap::Repeat => x.rem_euclid(size),
+        TextureWrap::Mirror => {
+            let x = x.rem_euclid(size * 2);
+            if x < size {
+                x
+            } else {
+                size * 2 - 1 - x
+            }
+        }
+    }
+}
+
+/// Face and texture coordinates of a direction, in the GL order: +X, -X, +Y, -Y, +Z, -Z.
+fn cube_face(x: f32, y: f32, z: f32) -> (usize, f32, f32) {
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+    let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+        if x > 0.0 {
+            (0, -z, -y, ax)
+        } else {
+            (1, z, -y, ax)
+        }
+    } else if ay >= az {
+        if y > 0.0 {
+            (2, x, z, ay)
+        } else {
+            (3, x, -z, ay)
+        }
+    } else if z > 0.0 {
+        (4, x, -y, az)
+    } else {
+        (5, -x, -y, az)
+    };
+    let ma = ma.max(f32::MIN_POSITIVE);
+    (face, (sc / ma + 1.0) * 0.5, (tc / ma + 1.0) * 0.5)
+}
+
+/// Twice the signed area of the abc triangle, positive for counter-clockwise.
+fn edge(a: [f32; 4], b: [f32; 4], c: [f32; 4]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+fn compare(comparison: Comparison, value: f32, stored: f32) -> bool {
+    match comparison {
+        Comparison::Never => false,
+        Comparison::Less => value < stored,
+        Comparison::LessOrEqual => value <= stored,
+        Comparison::Greater => value > stored,
+        Comparison::GreaterOrEqual => value >= stored,
+        Comparison::Equal => value == stored,
+        Comparison::NotEqual => value != stored,
+        Comparison::Always => true,
+    }
+}
+
+fn blend(params: &PipelineParams, src: [f32; 4], dst: [f32; 4]) -> [f32; 4] {
+    fn factor(factor: BlendFactor, src: [f32; 4], dst: [f32; 4], channel: usize) -> f32 {
+        let value = |value: BlendValue| match value {
+            BlendValue::SourceColor => src[channel],
+            BlendValue::SourceAlpha => src[3],
+            BlendValue::DestinationColor => dst[channel],
+            BlendValue::DestinationAlpha => dst[3],
+        };
+        match factor {
+            BlendFactor::Zero => 0.0,
+            BlendFactor::One => 1.0,
+            BlendFactor::Value(v) => value(v),
+            BlendFactor::OneMinusValue(v) => 1.0 - value(v),
+            BlendFactor::SourceAlphaSaturate if channel == 3 => 1.0,
+            BlendFactor::SourceAlphaSaturate => src[3].min(1.0 - dst[3]),
+        }
+    }
+    let Some(color_blend) = params.color_blend else {
+        return src;
+    };
+    let mut res = [0.0; 4];
+    for (channel, res) in res.iter_mut().enumerate() {
+        let state = match params.alpha_blend {
+            Some(alpha_blend) if channel == 3 => alpha_blend,
+            _ => color_blend,
+        };
+        let s = src[channel] * factor(state.sfactor, src, dst, channel);
+        let d = dst[channel] * factor(state.dfactor, src, dst, channel);
+        *res = match state.equation {
+            Equation::Add => s + d,
+            Equation::Subtract => s - d,
+            Equation::ReverseSubtract => d - s,
+        };
+    }
+    res
+}
+
+/// Column-major 4x4 matrices, the same layout the uniforms have.
+fn mul_vec(m: &[f32; 16], v: [f32; 4]) -> [f32; 4] {
+    let mut res = [0.0; 4];
+    for (row, res) in res.iter_mut().enumerate() {
+        *res = (0..4).map(|col| m[col * 4 + row] * v[col]).sum();
+    }
+    res
+}
+
+fn mul_mat(a: &[f32; 16], b: &[f32; 16]) -> [f32; 16] {
+    let mut res = [0.0; 16];
+    for col in 0..4 {
+        let column = mul_vec(
+            a,
+            [b[col * 4], b[col * 4 + 1], b[col * 4 + 2], b[col * 4 + 3]],
+        );
+        res[col * 4..col * 4 + 4].copy_from_slice(&column);
+    }
+    res
+}
+
+const IDENTITY: [f32; 16] = [
+    1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+];
+
+/// Keep only the part of the polygon in front of the near plane, z > -w.
+fn clip_near(polygon: &[([f32; 4], Varyings)]) -> Vec<([f32; 4], Varyings)> {
+    let distance = |v: &([f32; 4], Varyings)| v.0[2] + v.0[3];
+    let mut res = vec![];
+    for (i, current) in polygon.iter().enumerate() {
+        let next = &polygon[(i + 1) % polygon.len()];
+        let (d0, d1) = (distance(current), distance(next));
+        if d0 >= 0.0 {
+            res.push(*current);
+        }
+        if (d0 >= 0.0) != (d1 >= 0.0) {
+            let t = d0 / (d0 - d1);
+            let mut clip = [0.0; 4];
+            let mut varyings = [0.0; 9];
+            for (i, x) in clip.iter_mut().enumerate() {
+                *x = current.0[i] + (next.0[i] - current.0[i]) * t;
+            }
+            for (i, x) in varyings.iter_mut().enumerate() {
+                *x = current.1[i] + (next.1[i] - current.1[i]) * t;
+            }
+            res.push((clip, varyings));
+        }
+    }
+    res
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+fn f32_to_half(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    if x.is_nan() {
+        return sign | 0x7e00;
+    }
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent >= 31 {
+        sign | 0x7c00
+    } else if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = (mantissa | 0x80_0000) >> (14 - exponent);
+        sign | mantissa as u16
+    } else {
+        sign | ((exponent as u16) << 10) | (mantissa >> 13) as u16
+    }
+}
+
+fn decode(format: TextureFormat, bytes: &[u8]) -> Vec<[f32; 4]> {
+    let u8s = |x: u8| x as f32 / 255.0;
+    match format {
+        TextureFormat::RGBA8 => bytes
+            .chunks_exact(4)
+            .map(|p| [u8s(p[0]), u8s(p[1]), u8s(p[2]), u8s(p[3])])
+            .collect(),
+        TextureFormat::RGB8 => bytes
+            .chunks_exact(3)
+            .map(|p| [u8s(p[0]), u8s(p[1]), u8s(p[2]), 1.0])
+            .collect(),
+        TextureFormat::RGBA16F => bytes
+            .chunks_exact(8)
+            .map(|p| {
+                let half = |i: usize| half_to_f32(u16::from_le_bytes([p[i * 2], p[i * 2 + 1]]));
+                [half(0), half(1), half(2), half(3)]
+            })
+            .collect(),
+        TextureFormat::Depth => bytes
+            .chunks_exact(2)
+            .map(|p| {
+                [
+                    u16::from_le_bytes([p[0], p[1]]) as f32 / 65535.0,
+                    0.0,
+                    0.0,
+                    1.0,
+                ]
+            })
+            .collect(),
+        TextureFormat::Depth32 => bytes
+            .chunks_exact(4)
+            .map(|p| [f32::from_le_bytes([p[0], p[1], p[2], p[3]]), 0.0, 0.0, 1.0])
+            .collect(),
+        TextureFormat::Alpha => bytes.iter().map(|a| [0.0, 0.0, 0.0, u8s(*a)]).collect(),
+    }
+}
+
+fn encode(format: TextureFormat, texels: &[[f32; 4]], bytes: &mut Vec<u8>) {
+    let u8s = |x: f32| (x.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+    for t in texels {
+        match format {
+            TextureFormat::RGBA8 => bytes.extend(t.map(u8s)),
+            TextureFormat::RGB8 => bytes.extend([u8s(t[0]), u8s(t[1]), u8s(t[2])]),
+            TextureFormat::RGBA16F => {
+                bytes.extend(t.iter().flat_map(|x| f32_to_half(*x).to_le_bytes()))
+            }
+            TextureFormat::Depth => {
+                bytes.extend((((t[0].clamp(0.0, 1.0)) * 65535.0) as u16).to_le_bytes())
+            }
+            TextureFormat::Depth32 => bytes.extend(t[0].to_le_bytes()),
+            TextureFormat::Alpha => bytes.push(u8s(t[3])),
+        }
+    }
+}
+
+impl RenderingBackend for SoftwareContext {
+    fn info(&self) -> ContextInfo {
+        ContextInfo {
+            backend: Backend::Software,
+            gl_version_string: String::new(),
+            glsl_support: GlslSupport {
+                v100: true,
+                ..Default::default()
+            },
+            features: Features::default(),
+        }
+    }
+
+    fn new_shader(
+        &mut self,
+        _shader: ShaderSource,
+        meta: ShaderMeta,
+    ) -> Result<ShaderId, ShaderError> {
+        let mut offset = 0;
+        let uniforms = meta
+            .uniforms
+            .uniforms
+            .iter()
+            .map(|uniform| {
+                let res = (uniform.name.clone(), offset, uniform.uniform_type);
+                offset += uniform.uniform_type.size() * uniform.array_count;
+                res
+            })
+            .collect();
+        let shader = Shader {
+            images: meta.images.len(),
+            uniforms,
+        };
+        Ok(ShaderId(self.shaders.add(shader)))
+    }
+
+    fn new_texture(
+        &mut self,
+        _access: TextureAccess,
+        data: TextureSource,
+        params: TextureParams,
+    ) -> TextureId {
+        let faces = match params.kind {
+            TextureKind::Texture2D => 1,
+            TextureKind::CubeMap => 6,
+        };
+        let size = (params.width * params.height) as usize;
+        let face_bytes = params.format.size(params.width, params.height) as usize;
+        let faces = (0..faces)
+            .map(|face| {
+                let mut texels = match data {
+                    TextureSource::Empty => vec![],
+                    TextureSource::Bytes(bytes) => bytes
+                        .get(face * face_bytes..(face + 1) * face_bytes)
+                        .map_or(vec![], |bytes| decode(params.format, bytes)),
+                    // only the first mipmap level is used
+                    TextureSource::Array(faces) => faces
+                        .get(face)
+                        .and_then(|levels| levels.first())
+                        .map_or(vec![], |bytes| decode(params.format, bytes)),
+                };
+                texels.resize(size, [0.0; 4]);
+                texels
+            })
+            .collect();
+        let texture = Texture {
+            params,
+            wrap_y: params.wrap,
+            faces,
+        };
+        TextureId(TextureIdInner::Managed(
+            self.textures.add(RefCell::new(texture)),
+        ))
+    }
+
+    fn texture_params(&self, texture: TextureId) -> TextureParams {
+        self.texture(texture).borrow().params
+    }
+
+    unsafe fn texture_raw_id(&self, texture: TextureId) -> RawId {
+        match texture.0 {
+            TextureIdInner::Managed(texture) => RawId::OpenGl(texture as _),
+            TextureIdInner::Raw(raw) => raw,
+        }
+    }
+
+    fn texture_set_min_filter(
+        &mut self,
+        texture: TextureId,
+        filter: FilterMode,
+        mipmap_filter: MipmapFilterMode,
+    ) {
+        let texture = self.texture_mut(texture);
+        texture.params.min_filter = filter;
+        texture.params.mipmap_filter = mipmap_filter;
+    }
+
+    fn texture_set_mag_filter(&mut self, texture: TextureId, filter: FilterMode) {
+        self.texture_mut(texture).params.mag_filter = filter;
+    }
+
+    fn texture_set_wrap(&mut self, texture: TextureId, wrap_x: TextureWrap, wrap_y: TextureWrap) {
+        let texture = self.texture_mut(texture);
+        texture.params.wrap = wrap_x;
+        texture.wrap_y = wrap_y;
+    }
+
+    fn texture_generate_mipmaps(&mut self, _texture: TextureId) {}
+
+    fn texture_resize(
+        &mut self,
+        texture: TextureId,
+        width: u32,
+        height: u32,
+        bytes: Option<&[u8]>,
+    ) {
+        let texture = self.texture_mut(texture);
+        texture.params.width = width;
+        texture.params.height = height;
+        let size = (width * height) as usize;
+        for face in &mut texture.faces {
+            *face = bytes.map_or(vec![], |bytes| decode(texture.params.format, bytes));
+            face.resize(size, [0.0; 4]);
+        }
+    }
+
+    fn texture_read_pixels(&mut self, texture: TextureId, bytes: &mut [u8]) {
+        let texture = self.texture(texture).borrow();
+        let mut data = vec![];
+        encode(texture.params.format, &texture.faces[0], &mut data);
+        let len = data.len().min(bytes.len());
+        bytes[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn texture_update_part(
+        &mut self,
+        texture: TextureId,
+        x_offset: i32,
+        y_offset: i32,
+        width: i32,
+        height: i32,
+        bytes: &[u8],
+    ) {
+        let texture = self.texture_mut(texture);
+        let texels = decode(texture.params.format, bytes);
+        let texture_width = texture.params.width as i32;
+        let texture_height = texture.params.height as i32;
+        for y in 0..height {
+            for x in 0..width {
+                let (tx, ty) = (x + x_offset, y + y_offset);
+                if tx < 0 || ty < 0 || tx >= texture_width || ty >= texture_height {
+                    continue;
+                }
+                if let Some(texel) = texels.get((y * width + x) as usize) {
+                    texture.faces[0][(ty * texture_width + tx) as usize] = *texel;
+                }
+            }
+        }
+    }
+
+    fn new_render_pass_mrt(
+        &mut self,
+        color_img: &[TextureId],
+        depth_img: Option<TextureId>,
+    ) -> RenderPass {
+        let pass = RenderPassInternal {
+            colors: color_img.to_vec(),
+            depth: depth_img,
+        };
+        RenderPass(self.passes.add(pass))
+    }
+
+    fn render_pass_color_attachments(&self, render_pass: RenderPass) -> &[TextureId] {
+        &self.passes[render_pass.0].colors
+    }
+
+    fn delete_render_pass(&mut self, render_pass: RenderPass) {
+        let pass = self.passes.remove(render_pass.0);
+        for texture in pass.colors.into_iter().chain(pass.depth) {
+            self.delete_texture(texture);
+        }
+    }
+
+    fn new_pipeline(
+        &mut self,
+        buffer_layout: &[BufferLayout],
+        attributes: &[VertexAttribute],
+        shader: ShaderId,
+        params: PipelineParams,
+    ) -> Pipeline {
+        let mut offsets = vec![0; buffer_layout.len()];
+        let mut internal = vec![];
+        for attribute in attributes {
+            let offset = &mut offsets[attribute.buffer_index];
+            internal.push(Attribute {
+                format: attribute.format,
+                buffer_index: attribute.buffer_index,
+                offset: *offset,
+                stride: 0,
+                divisor: match buffer_layout[attribute.buffer_index].step_func {
+                    VertexStep::PerVertex => 0,
+                    VertexStep::PerInstance => {
+                        buffer_layout[attribute.buffer_index].step_rate.max(1) as usize
+                    }
+                },
+            });
+            *offset += attribute.format.size_bytes() as usize;
+        }
+        for attribute in &mut internal {
+            let layout = &buffer_layout[attribute.buffer_index];
+            attribute.stride = match layout.stride {
+                0 => offsets[attribute.buffer_index],
+                stride => stride as usize,
+            };
+        }
+        let find = |names: &[&str]| attributes.iter().position(|a| names.contains(&a.name));
+        let pipeline = PipelineInternal {
+            attributes: internal,
+            position: find(POSITION_NAMES).or((!attributes.is_empty()).then_some(0)),
+            uv: find(UV_NAMES),
+            color: find(COLOR_NAMES),
+            shader,
+            params,
+        };
+        Pipeline(self.pipelines.add(pipeline))
+    }
+
+    fn apply_pipeline(&mut self, pipeline: &Pipeline) {
+        self.state.pipeline = Some(*pipeline);
+    }
+
+    fn delete_pipeline(&mut self, pipeline: Pipeline) {
+        self.pipelines.remove(pipeline.0);
+    }
+
+    fn new_buffer(
+        &mut self,
+        type_: BufferType,
+        _usage: BufferUsage,
+        data: BufferSource,
+    ) -> BufferId {
+        let (data, element_size) = match data {
+            BufferSource::Slice(data) => {
+                let bytes = unsafe { std::slice::from_raw_parts(data.ptr as *const u8, data.size) };
+                (bytes.to_vec(), data.element_size)
+            }
+            BufferSource::Empty { size, element_size } => (vec![0; size], element_size),
+        };
+        if type_ == BufferType::IndexBuffer {
+            assert!(
+                matches!(element_size, 1 | 2 | 4),
+                "unsupported index buffer dimension"
+            );
+        }
+        BufferId(self.buffers.add(Buffer { data, element_size }))
+    }
+
+    fn buffer_update(&mut self, buffer: BufferId, data: BufferSource) {
+        let BufferSource::Slice(data) = data else {
+            return;
+        };
+        let bytes = unsafe { std::slice::from_raw_parts(data.ptr as *const u8, data.size) };
+        let buffer = &mut self.buffers[buffer.0];
+        if buffer.data.len() < bytes.len() {
+            buffer.data.resize(bytes.len(), 0);
+        }
+        buffer.data[..bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn buffer_size(&mut self, buffer: BufferId) -> usize {
+        self.buffers[buffer.0].data.len()
+    }
+
+    fn delete_buffer(&mut self, buffer: BufferId) {
+        self.buffers.remove(buffer.0);
+    }
+
+    fn delete_texture(&mut self, texture: TextureId) {
+        if let TextureIdInner::Managed(texture) = texture.0 {
+            self.textures.remove(texture);
+        }
+    }
+
+    fn delete_shader(&mut self, program: ShaderId) {
+        self.shaders.remove(program.0);
+    }
+
+    fn apply_viewport(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        self.state.viewport = (x, y, w, h);
+    }
+
+    fn apply_scissor_rect(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        self.state.scissor = Some((x, y, w, h));
+    }
+
+    fn apply_bindings_from_slice(
+        &mut self,
+        vertex_buffers: &[BufferId],
+        index_buffer: BufferId,
+        textures: &[TextureId],
+    ) {
+        self.state.vertex_buffers = vertex_buffers.to_vec();
+        self.state.index_buffer = Some(index_buffer);
+        self.state.images = textures.to_vec();
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn apply_uniforms_from_bytes(&mut self, uniform_ptr: *const u8, size: usize) {
+        let bytes = unsafe { std::slice::from_raw_parts(uniform_ptr, size) };
+        self.state.uniforms = bytes.to_vec();
+    }
+
+    fn clear(
+        &mut self,
+        color: Option<(f32, f32, f32, f32)>,
+        depth: Option<f32>,
+        _stencil: Option<i32>,
+    ) {
+        self.clear_targets(color, depth);
+    }
+
+    fn begin_default_pass(&mut self, action: PassAction) {
+        self.begin_pass(None, action);
+    }
+
+    fn begin_pass(&mut self, pass: Option<RenderPass>, action: PassAction) {
+        let (colors, depth) = match pass {
+            Some(pass) => {
+                let pass = &self.passes[pass.0];
+                (pass.colors.clone(), pass.depth)
+            }
+            None => (vec![self.screen], Some(self.screen_depth)),
+        };
+        let (width, height) = self.texture_size(colors.first().copied().or(depth).unwrap());
+        self.state.colors = colors;
+        self.state.depth = depth;
+        self.state.viewport = (0, 0, width as i32, height as i32);
+        self.state.scissor = None;
+        if let PassAction::Clear { color, depth, .. } = action {
+            self.clear_targets(color, depth);
+        }
+    }
+
+    fn end_render_pass(&mut self) {
+        self.state.colors.clear();
+        self.state.depth = None;
+    }
+
+    fn commit_frame(&mut self) {}
+
+    fn draw(&self, base_element: i32, num_elements: i32, num_instances: i32) {
+        let pipeline = &self.pipelines[self
+            .state
+            .pipeline
+            .expect("Drawing without any binded pipeline")
+            .0];
+        if pipeline.params.primitive_type != PrimitiveType::Triangles {
+            return;
+        }
+        let index_buffer = &self.buffers[self
+            .state
+            .index_buffer
+            .expect("Drawing without an index buffer")
+            .0];
+        let indices: Vec<usize> = index_buffer
+            .data
+            .chunks_exact(index_buffer.element_size)
+            .skip(base_element.max(0) as usize)
+            .take(num_elements.max(0) as usize)
+            .map(|bytes| match bytes.len() {
+                1 => bytes[0] as usize,
+                2 => u16::from_le_bytes([bytes[0], bytes[1]]) as usize,
+                _ => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize,
+            })
+            .collect();
+
+        let matrix = match self.uniform_floats::<16>("mvp") {
+            Some(mvp) => mvp,
+            None => mul_mat(
+                &self.uniform_floats("Projection").unwrap_or(IDENTITY),
+                &self.uniform_floats("Model").unwrap_or(IDENTITY),
+            ),
+        };
+        let color_uniform = self.uniform_floats("Color").unwrap_or([1.0; 4]);
+        let shader = &self.shaders[pipeline.shader.0];
+        let image = (shader.images > 0)
+            .then(|| self.state.images.first().copied())
+            .flatten();
+        // a render target read by its own pass, GL leaves it undefined,
+        // here the draw samples a copy of the texture before the draw
+        let (copy, borrowed);
+        let sampler = Sampler {
+            texture: match image {
+                Some(image)
+                    if self.state.colors.contains(&image) || self.state.depth == Some(image) =>
+                {
+                    copy = self.texture(image).borrow().clone();
+                    Some(&copy)
+                }
+                Some(image) => {
+                    borrowed = self.texture(image).borrow();
+                    Some(&*borrowed)
+                }
+                None => None,
+            },
+        };
+
+        let mut targets: Vec<_> = self
+            .state
+            .colors
+            .iter()
+            .map(|texture| self.texture(*texture).borrow_mut())
+            .collect();
+        let mut depth = self
+            .state
+            .depth
+            .map(|texture| self.texture(texture).borrow_mut());
+
+        for instance in 0..num_instances.max(1) as usize {
+            for triangle in indices.chunks_exact(3) {
+                let vertices = [triangle[0], triangle[1], triangle[2]]
+                    .map(|ix| self.vertex(pipeline, &matrix, ix, instance));
+                let polygon = clip_near(&vertices);
+                for i in 1..polygon.len().saturating_sub(1) {
+                    self.rasterize(
+                        pipeline,
+                        [&polygon[0], &polygon[i], &polygon[i + 1]],
+                        &mut targets,
+                        &mut depth,
+                        &sampler,
+                        color_uniform,
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    #[repr(C)]
+    struct Vertex {
+        pos: [f32; 2],
+        color: [f32; 4],
+    }
+
+    /// Clear the screen to blue and draw a triangle over its lower left half, white
+    /// vertices tinted green by the "Color" uniform.
+    pub(crate) fn draw_triangle(
+        ctx: &mut dyn RenderingBackend,
+        scissor: Option<(i32, i32, i32, i32)>,
+    ) {
+        let vertices = [
+            Vertex {
+                pos: [-1.0, -1.0],
+                color: [1.0; 4],
+            },
+            Vertex {
+                pos: [1.0, -1.0],
+                color: [1.0; 4],
+            },
+            Vertex {
+                pos: [-1.0, 1.0],
+                color: [1.0; 4],
+            },
+        ];
+        let indices: [u16; 3] = [0, 1, 2];
+        let vertex_buffer = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&vertices),
+        );
+        let index_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&indices),
+        );
+        let shader = ctx
+            .new_shader(
+                ShaderSource::Glsl {
+                    vertex: "",
+                    fragment: "",
+                },
+                ShaderMeta {
+                    uniforms: UniformBlockLayout {
+                        uniforms: vec![UniformDesc::new("Color", UniformType::Float4)],
+                    },
+                    images: vec![],
+                },
+            )
+            .unwrap();
+        let pipeline = ctx.new_pipeline(
+            &[BufferLayout::default()],
+            &[
+                VertexAttribute::new("in_pos", VertexFormat::Float2),
+                VertexAttribute::new("in_color", VertexFormat::Float4),
+            ],
+            shader,
+            PipelineParams::default(),
+        );
+
+        ctx.begin_default_pass(PassAction::clear_color(0.0, 0.0, 1.0, 1.0));
+        ctx.apply_pipeline(&pipeline);
+        if let Some((x, y, w, h)) = scissor {
+            ctx.apply_scissor_rect(x, y, w, h);
+        }
+        ctx.apply_bindings(&Bindings {
+            vertex_buffers: vec![vertex_buffer],
+            index_buffer,
+            images: vec![],
+        });
+        ctx.apply_uniforms(UniformsSource::table(&[0.0f32, 1.0, 0.0, 1.0]));
+        ctx.draw(0, 3, 1);
+        ctx.end_render_pass();
+        ctx.commit_frame();
+    }
+
+    /// RGBA of the pixel at `x`, `y` counted from the bottom left corner.
+    pub(crate) fn pixel(pixels: &[u8], width: usize, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * width + x) * 4;
+        pixels[i..i + 4].try_into().unwrap()
+    }
+
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    #[test]
+    fn triangle() {
+        let mut ctx = SoftwareContext::new(4, 4);
+        draw_triangle(&mut ctx, None);
+        let mut pixels = vec![0; 4 * 4 * 4];
+        ctx.texture_read_pixels(ctx.screen_texture(), &mut pixels);
+
+        for (x, y) in [(0, 0), (1, 1), (2, 0), (0, 2), (1, 0)] {
+            assert_eq!(pixel(&pixels, 4, x, y), GREEN, "({x}, {y})");
+        }
+        for (x, y) in [(3, 3), (3, 2), (2, 3), (2, 2)] {
+            assert_eq!(pixel(&pixels, 4, x, y), BLUE, "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn triangle_scissor() {
+        let mut ctx = SoftwareContext::new(4, 4);
+        draw_triangle(&mut ctx, Some((0, 0, 1, 4)));
+        let mut pixels = vec![0; 4 * 4 * 4];
+        ctx.texture_read_pixels(ctx.screen_texture(), &mut pixels);
+
+        for (x, y) in [(0, 0), (0, 1), (0, 2)] {
+            assert_eq!(pixel(&pixels, 4, x, y), GREEN, "({x}, {y})");
+        }
+        for (x, y) in [(1, 0), (1, 1), (3, 0), (3, 3)] {
+            assert_eq!(pixel(&pixels, 4, x, y), BLUE, "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn feedback_loop() {
+        let mut ctx = SoftwareContext::new(4, 4);
+        // a triangle covering the whole screen
+        let vertices: [f32; 12] = [
+            -1.0, -1.0, 0.0, 0.0, 3.0, -1.0, 2.0, 0.0, -1.0, 3.0, 0.0, 2.0,
+        ];
+        let indices: [u16; 3] = [0, 1, 2];
+        let vertex_buffer = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&vertices),
+        );
+        let index_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&indices),
+        );
+        let shader = ctx
+            .new_shader(
+                ShaderSource::Glsl {
+                    vertex: "",
+                    fragment: "",
+                },
+                ShaderMeta {
+                    uniforms: UniformBlockLayout {
+                        uniforms: vec![UniformDesc::new("Color", UniformType::Float4)],
+                    },
+                    images: vec!["Texture".to_string()],
+                },
+            )
+            .unwrap();
+        let pipeline = ctx.new_pipeline(
+            &[BufferLayout::default()],
+            &[
+                VertexAttribute::new("in_pos", VertexFormat::Float2),
+                VertexAttribute::new("in_uv", VertexFormat::Float2),
+            ],
+            shader,
+            PipelineParams::default(),
+        );
+
+        // the screen is both the target and the sampled texture
+        ctx.begin_default_pass(PassAction::clear_color(0.0, 0.0, 1.0, 1.0));
+        ctx.apply_pipeline(&pipeline);
+        ctx.apply_bindings(&Bindings {
+            vertex_buffers: vec![vertex_buffer],
+            index_buffer,
+            images: vec![ctx.screen_texture()],
+        });
+        ctx.apply_uniforms(UniformsSource::table(&[1.0f32, 1.0, 0.5, 1.0]));
+        ctx.draw(0, 3, 1);
+        ctx.end_render_pass();
+        let mut pixels = vec![0; 4 * 4 * 4];
+        ctx.texture_read_pixels(ctx.screen_texture(), &mut pixels);
+
+        // every pixel sampled the screen as it was before the draw
+        for (x, y) in [(0, 0), (3, 0), (0, 3), (3, 3)] {
+            assert_eq!(pixel(&pixels, 4, x, y), [0, 0, 128, 255], "({x}, {y})");
+        }
+    }
+}
@@ -108,8 +108,18 @@ pub mod window {
     ///   Box::new(GlContext::new())
     /// };
     /// ```
-    /// but under #[cfg] gate to avoid MetalContext on non-apple platforms
+    /// but under #[cfg] gate to avoid MetalContext on non-apple platforms.
+    /// In `conf.platform.headless_frames` mode it is a `SoftwareContext`.
     pub fn new_rendering_backend() -> Box<dyn RenderingBackend> {
+        {
+            let d = native_display().lock().unwrap();
+            if d.headless {
+                return Box::new(SoftwareContext::new(
+                    d.screen_width as u32,
+                    d.screen_height as u32,
+                ));
+            }
+        }
         #[cfg(target_vendor = "apple")]
         {
             if window::apple_gfx_api() == conf::AppleGfxApi::Metal {
@@ -337,6 +347,11 @@ pub fn start<F>(conf: conf::Conf, f: F)
 where
     F: 'static + FnOnce() -> Box<dyn EventHandler>,
 {
+    if let Some(frames) = conf.platform.headless_frames {
+        native::headless::run(&conf, f, frames);
+        return;
+    }
+
     #[cfg(target_os = "linux")]
     {
         let mut f = Some(f);
@@ -19,6 +19,8 @@ pub(crate) struct NativeDisplayData {
     pub clipboard: Box<dyn Clipboard>,
     pub dropped_files: DroppedFiles,
     pub blocking_event_loop: bool,
+    /// Started with `conf.platform.headless_frames`, there is no window nor GL context.
+    pub headless: bool,
 
     #[cfg(target_vendor = "apple")]
     pub view: crate::native::apple::frameworks::ObjcId,
@@ -51,6 +53,7 @@ impl NativeDisplayData {
             clipboard,
             dropped_files: Default::default(),
             blocking_event_loop: false,
+            headless: false,
             #[cfg(target_vendor = "apple")]
             gfx_api: crate::conf::AppleGfxApi::OpenGl,
             #[cfg(target_vendor = "apple")]
@@ -80,6 +83,8 @@ pub trait Clipboard: Send + Sync {
 
 pub mod module;
 
+pub mod headless;
+
 #[cfg(target_os = "linux")]
 pub mod linux_x11;
 
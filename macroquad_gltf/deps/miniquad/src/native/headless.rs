@@ -0,0 +1,53 @@
+//! `conf.platform.headless_frames`: no window, no events, a fixed amount of frames.
+
+use crate::{
+    event::EventHandler,
+    native::{Clipboard, NativeDisplayData},
+};
+
+use std::sync::mpsc;
+
+#[derive(Default)]
+struct HeadlessClipboard(Option<String>);
+
+impl Clipboard for HeadlessClipboard {
+    fn get(&mut self) -> Option<String> {
+        self.0.clone()
+    }
+
+    fn set(&mut self, data: &str) {
+        self.0 = Some(data.to_string());
+    }
+}
+
+pub fn run<F>(conf: &crate::conf::Conf, f: F, frames: u32)
+where
+    F: 'static + FnOnce() -> Box<dyn EventHandler>,
+{
+    let (tx, rx) = mpsc::channel();
+    let display = NativeDisplayData {
+        headless: true,
+        ..NativeDisplayData::new(
+            conf.window_width,
+            conf.window_height,
+            tx,
+            Box::new(HeadlessClipboard::default()),
+        )
+    };
+    // tests may start miniquad more than once in the same process
+    match crate::NATIVE_DISPLAY.get() {
+        Some(d) => *d.lock().unwrap() = display,
+        None => crate::set_display(display),
+    }
+
+    let mut event_handler = f();
+    for _ in 0..frames {
+        // all the requests are about the window, there is nothing to do with them
+        while rx.try_recv().is_ok() {}
+        if crate::native_display().lock().unwrap().quit_ordered {
+            break;
+        }
+        event_handler.update();
+        event_handler.draw();
+    }
+}
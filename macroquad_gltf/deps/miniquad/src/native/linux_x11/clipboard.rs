@@ -99,7 +99,7 @@ unsafe fn get_clipboard(
 }
 
 // Next message for clipboard request
-static mut MESSAGE: Option<String> = None;
+static MESSAGE: std::sync::Mutex<Option<String>> = std::sync::Mutex::new(None);
 
 /// Claim that our app is X11 clipboard owner
 /// Now when some other linux app will ask X11 for clipboard content - it will be redirected to our app
@@ -118,7 +118,7 @@ unsafe fn claim_clipboard_ownership(
 
     (libx11.XSetSelectionOwner)(display, selection, window, 0 as libc::c_int as Time);
 
-    MESSAGE = Some(message);
+    *MESSAGE.lock().unwrap() = Some(message);
 }
 
 /// this function is supposed to be called from sapp's event loop
@@ -131,8 +131,7 @@ pub(crate) unsafe fn respond_to_clipboard_request(
 ) {
     assert!((*event).type_0 == 30); // is it really SelectionRequest
 
-    let empty_message = String::new();
-    let message = MESSAGE.as_ref().unwrap_or(&empty_message);
+    let message = MESSAGE.lock().unwrap().clone().unwrap_or_default();
 
     let UTF8 = (libx11.XInternAtom)(
         display,
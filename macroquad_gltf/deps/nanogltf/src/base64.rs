@@ -1,41 +1,85 @@
-#[rustfmt::ignore]
-const B64: [i32; 124] = [
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 62, 63, 62, 62, 63, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 0,
-    0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21,
-    22, 23, 24, 25, 0, 0, 0, 0, 63, 0, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40,
-    41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 0,
-];
-
-pub fn decode(data: &str) -> Vec<u8> {
-    let p = data.as_bytes();
-    let len = data.len();
-    let pad = len > 0 && (len % 4 != 0 || p[len - 1] == '=' as u8);
-    let l = ((len + 3) / 4 - pad as usize) * 4;
-    let mut res = vec![0; l / 4 * 3 + pad as usize];
-    let mut j = 0;
-
-    for i in (0..l as usize).step_by(4) {
-        let n = B64[p[i] as usize] << 18
-            | B64[p[i + 1] as usize] << 12
-            | B64[p[i + 2] as usize] << 6
-            | B64[p[i + 3] as usize];
-        res[j] = (n >> 16) as u8;
-        j += 1;
-        res[j] = (n >> 8 & 0xFF) as u8;
-        j += 1;
-        res[j] = (n & 0xFF) as u8;
-        j += 1;
+use crate::GltfError;
+
+fn sextet(c: u8) -> Result<u32, GltfError> {
+    match c {
+        b'A'..=b'Z' => Ok((c - b'A') as u32),
+        b'a'..=b'z' => Ok((c - b'a') as u32 + 26),
+        b'0'..=b'9' => Ok((c - b'0') as u32 + 52),
+        // url safe alphabet as well
+        b'+' | b'-' => Ok(62),
+        b'/' | b'_' => Ok(63),
+        _ => Err(GltfError::InvalidBase64),
     }
-    if pad {
-        let n = B64[p[l] as usize] << 18 | B64[p[l + 1] as usize] << 12;
-        let res_len = res.len();
-        res[res_len - 1] = (n >> 16) as u8;
+}
 
-        if len > l + 2 && p[l + 2] != '=' as u8 {
-            let n = n | B64[p[l + 2] as usize] << 6;
+/// Decode base64 with or without the trailing `=` padding.
+pub fn decode(data: &str) -> Result<Vec<u8>, GltfError> {
+    let data = data.trim_end_matches('=').as_bytes();
+    // a single leftover character does not make up a byte
+    if data.len() % 4 == 1 {
+        return Err(GltfError::InvalidBase64);
+    }
+    let mut res = Vec::with_capacity(data.len() / 4 * 3 + 2);
+
+    for chunk in data.chunks(4) {
+        let mut n = 0;
+        for (i, c) in chunk.iter().enumerate() {
+            n |= sextet(*c)? << (18 - 6 * i);
+        }
+        res.push((n >> 16) as u8);
+        if chunk.len() > 2 {
             res.push((n >> 8 & 0xFF) as u8);
         }
+        if chunk.len() > 3 {
+            res.push((n & 0xFF) as u8);
+        }
+    }
+    Ok(res)
+}
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn encode(data: &[u8]) -> String {
+    let mut res = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        res.push(ALPHABET[(n >> 18) as usize & 63] as char);
+        res.push(ALPHABET[(n >> 12) as usize & 63] as char);
+        if chunk.len() > 1 {
+            res.push(ALPHABET[(n >> 6) as usize & 63] as char);
+        } else {
+            res.push('=');
+        }
+        if chunk.len() > 2 {
+            res.push(ALPHABET[n as usize & 63] as char);
+        } else {
+            res.push('=');
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for len in 0..10 {
+            let data: Vec<u8> = (0..len).map(|i| (i * 37 + 200) as u8).collect();
+            assert_eq!(decode(&encode(&data)).unwrap(), data);
+        }
+        assert_eq!(decode("aGk").unwrap(), b"hi");
+        assert_eq!(decode("-_8=").unwrap(), decode("+/8=").unwrap());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(decode("aGk~").is_err());
+        assert!(decode("aGkÿ").is_err());
+        assert!(decode("aGlhY").is_err());
     }
-    return res;
 }
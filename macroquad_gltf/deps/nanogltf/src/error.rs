@@ -0,0 +1,182 @@
+use crate::glb::GlbError;
+
+#[derive(Debug)]
+pub enum GltfError {
+    Json(nanoserde::DeJsonErr),
+    Glb(GlbError),
+    /// A .glb buffer without an uri, but the file has no BIN chunk.
+    MissingBinChunk,
+    /// The attribute required to render the primitive is not present.
+    MissingAttribute(String),
+    /// Not one of the gltf component types, or not an integer one for indices.
+    UnsupportedComponentType(u32),
+    /// Accessor's "type" is not one of SCALAR, VEC2, VEC3, VEC4, MAT2, MAT3, MAT4.
+    UnsupportedAccessorType(String),
+    /// The accessor has no buffer view.
+    MissingBufferView {
+        accessor: usize,
+    },
+    /// Accessor or buffer view data does not fit into its buffer.
+    BadBufferRange {
+        buffer: usize,
+        offset: usize,
+        length: usize,
+    },
+    /// A buffer view's byteStride outside of the 4..=252 range the spec allows.
+    InvalidByteStride {
+        view: usize,
+        stride: usize,
+    },
+    /// An accessor without a buffer view with more elements than can be allocated.
+    AccessorTooLarge {
+        accessor: usize,
+        count: usize,
+    },
+    UnknownUriScheme(String),
+    /// A `data:` uri with malformed base64 data.
+    InvalidBase64,
+    /// A json string or number that is not one of the values allowed for the field.
+    UnknownEnumValue {
+        kind: &'static str,
+        value: String,
+    },
+    /// One of the gltf arrays was indexed out of bounds.
+    BadIndex {
+        kind: &'static str,
+        index: usize,
+    },
+    /// An image with neither uri nor buffer view.
+    MissingImageSource,
+    InvalidImage {
+        image: usize,
+        message: String,
+    },
+    /// The accessor has a different amount of components than the attribute requires,
+    /// like VEC2 for POSITION.
+    UnexpectedComponentsCount {
+        accessor: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// The skin has more joints than the renderer supports, `max`.
+    TooManyJoints {
+        skin: usize,
+        joints: usize,
+        max: usize,
+    },
+    /// The skin could not be turned into a skeleton.
+    InvalidSkin {
+        skin: usize,
+        message: &'static str,
+    },
+    /// The animation sampler's keyframes don't match its values.
+    InvalidAnimationSampler {
+        animation: usize,
+        sampler: usize,
+        message: &'static str,
+    },
+    /// A vertex attribute or morph target with a different element count than POSITION.
+    MismatchedAttributeCount {
+        attribute: String,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl std::fmt::Display for GltfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GltfError::Json(err) => write!(f, "{}", err),
+            GltfError::Glb(err) => write!(f, "{}", err),
+            GltfError::MissingBinChunk => write!(f, "Buffer without an uri, but no BIN chunk"),
+            GltfError::MissingAttribute(name) => write!(f, "Missing attribute: {}", name),
+            GltfError::UnsupportedComponentType(ty) => {
+                write!(f, "Unsupported component type: {}", ty)
+            }
+            GltfError::UnsupportedAccessorType(ty) => {
+                write!(f, "Unsupported accessor type: {:?}", ty)
+            }
+            GltfError::MissingBufferView { accessor } => {
+                write!(f, "Accessor {} has no buffer view", accessor)
+            }
+            GltfError::BadBufferRange {
+                buffer,
+                offset,
+                length,
+            } => write!(
+                f,
+                "Range {}..{} is out of buffer {}",
+                offset,
+                offset.saturating_add(*length),
+                buffer
+            ),
+            GltfError::InvalidByteStride { view, stride } => {
+                write!(
+                    f,
+                    "Buffer view {} has an invalid byte stride {}",
+                    view, stride
+                )
+            }
+            GltfError::AccessorTooLarge { accessor, count } => {
+                write!(f, "Accessor {} has too many elements: {}", accessor, count)
+            }
+            GltfError::UnknownUriScheme(uri) => write!(f, "Unknown uri scheme: {}", uri),
+            GltfError::InvalidBase64 => write!(f, "Invalid base64 data in uri"),
+            GltfError::UnknownEnumValue { kind, value } => write!(f, "Unknown {}: {}", kind, value),
+            GltfError::BadIndex { kind, index } => write!(f, "No {} with index {}", kind, index),
+            GltfError::MissingImageSource => write!(f, "Image has neither uri nor buffer view"),
+            GltfError::InvalidImage { image, message } => {
+                write!(f, "Failed to decode image {}: {}", image, message)
+            }
+            GltfError::UnexpectedComponentsCount {
+                accessor,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Accessor {} has {} components, expected {}",
+                accessor, found, expected
+            ),
+            GltfError::TooManyJoints { skin, joints, max } => write!(
+                f,
+                "Skin {} has {} joints, at most {} are supported",
+                skin, joints, max
+            ),
+            GltfError::InvalidSkin { skin, message } => {
+                write!(f, "Invalid skin {}: {}", skin, message)
+            }
+            GltfError::InvalidAnimationSampler {
+                animation,
+                sampler,
+                message,
+            } => write!(
+                f,
+                "Invalid sampler {} of animation {}: {}",
+                sampler, animation, message
+            ),
+            GltfError::MismatchedAttributeCount {
+                attribute,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Attribute {} has {} elements, expected {}",
+                attribute, found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GltfError {}
+
+impl From<nanoserde::DeJsonErr> for GltfError {
+    fn from(err: nanoserde::DeJsonErr) -> GltfError {
+        GltfError::Json(err)
+    }
+}
+
+impl From<GlbError> for GltfError {
+    fn from(err: GlbError) -> GltfError {
+        GltfError::Glb(err)
+    }
+}
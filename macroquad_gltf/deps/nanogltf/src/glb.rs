@@ -0,0 +1,173 @@
+//! Binary glTF container: a 12 byte header followed by a JSON chunk and an optional BIN chunk.
+//! <https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#glb-file-format-specification>
+
+const MAGIC: &[u8; 4] = b"glTF";
+const CHUNK_JSON: u32 = 0x4E4F534A;
+const CHUNK_BIN: u32 = 0x004E4942;
+
+#[derive(Debug)]
+pub enum GlbError {
+    /// The file does not start with the "glTF" magic.
+    BadMagic,
+    /// The file ends before the header or a chunk described in it.
+    UnexpectedEof,
+    UnsupportedVersion(u32),
+    /// The first chunk is not a JSON chunk.
+    MissingJson,
+    InvalidUtf8,
+}
+
+impl std::fmt::Display for GlbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GlbError::BadMagic => write!(f, "Not a glb file, no glTF magic"),
+            GlbError::UnexpectedEof => write!(f, "Unexpected end of the glb file"),
+            GlbError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported glb container version: {}", version)
+            }
+            GlbError::MissingJson => write!(f, "First glb chunk is not JSON"),
+            GlbError::InvalidUtf8 => write!(f, "glb JSON chunk is not a valid utf8"),
+        }
+    }
+}
+
+impl std::error::Error for GlbError {}
+
+/// Chunks of a .glb file, borrowed from the file bytes.
+pub struct Glb<'a> {
+    pub json: &'a str,
+    /// Data of the buffer without an uri, the first one in the `buffers` list.
+    pub bin: Option<&'a [u8]>,
+}
+
+/// Check for the "glTF" magic at the start of the file.
+pub fn is_glb(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, GlbError> {
+    let bytes = bytes
+        .get(offset..offset + 4)
+        .ok_or(GlbError::UnexpectedEof)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Glb<'_>, GlbError> {
+    if !is_glb(bytes) {
+        return Err(GlbError::BadMagic);
+    }
+    let version = read_u32(bytes, 4)?;
+    if version != 2 {
+        return Err(GlbError::UnsupportedVersion(version));
+    }
+    let length = read_u32(bytes, 8)? as usize;
+    let bytes = bytes.get(..length).ok_or(GlbError::UnexpectedEof)?;
+
+    let mut json = None;
+    let mut bin = None;
+    let mut offset = 12;
+    while offset < bytes.len() {
+        let chunk_length = read_u32(bytes, offset)? as usize;
+        let chunk_type = read_u32(bytes, offset + 4)?;
+        let data = bytes
+            .get(offset + 8..offset + 8 + chunk_length)
+            .ok_or(GlbError::UnexpectedEof)?;
+        match chunk_type {
+            CHUNK_JSON if json.is_none() => {
+                json = Some(std::str::from_utf8(data).map_err(|_| GlbError::InvalidUtf8)?)
+            }
+            CHUNK_BIN if bin.is_none() && json.is_some() => bin = Some(data),
+            // unknown chunks should be ignored
+            _ => {}
+        }
+        if json.is_none() {
+            return Err(GlbError::MissingJson);
+        }
+        offset += 8 + chunk_length;
+    }
+
+    Ok(Glb {
+        json: json.ok_or(GlbError::MissingJson)?,
+        bin,
+    })
+}
+
+/// Build a .glb file out of the gltf json and the data of its first, uri-less, buffer.
+///
+/// Both chunks are padded to 4 bytes: json with spaces, bin with zeroes.
+pub fn write(json: &str, bin: Option<&[u8]>) -> Vec<u8> {
+    let padded = |len: usize| (len + 3) & !3;
+
+    let json_length = padded(json.len());
+    let bin_length = bin.map_or(0, |bin| padded(bin.len()));
+    let length = 12 + 8 + json_length + bin.map_or(0, |_| 8 + bin_length);
+
+    let mut bytes = Vec::with_capacity(length);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&2u32.to_le_bytes());
+    bytes.extend_from_slice(&(length as u32).to_le_bytes());
+
+    bytes.extend_from_slice(&(json_length as u32).to_le_bytes());
+    bytes.extend_from_slice(&CHUNK_JSON.to_le_bytes());
+    bytes.extend_from_slice(json.as_bytes());
+    bytes.resize(20 + json_length, b' ');
+
+    if let Some(bin) = bin {
+        bytes.extend_from_slice(&(bin_length as u32).to_le_bytes());
+        bytes.extend_from_slice(&CHUNK_BIN.to_le_bytes());
+        bytes.extend_from_slice(bin);
+        bytes.resize(length, 0);
+    }
+
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let json = r#"{"asset":{"version":"2.0"}}"#;
+        let bin = [1, 2, 3, 4, 5];
+        let bytes = write(json, Some(&bin));
+        assert_eq!(bytes.len() % 4, 0);
+        let glb = parse(&bytes).unwrap();
+        assert_eq!(glb.json.trim_end(), json);
+        assert_eq!(glb.bin, Some(&[1, 2, 3, 4, 5, 0, 0, 0][..]));
+
+        let bytes = write(json, None);
+        let glb = parse(&bytes).unwrap();
+        assert_eq!(glb.json.trim_end(), json);
+        assert_eq!(glb.bin, None);
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = write("{}", Some(&[0; 16]));
+
+        // the header promises more bytes than the file has
+        let cut = &bytes[..bytes.len() - 4];
+        assert!(matches!(parse(cut), Err(GlbError::UnexpectedEof)));
+
+        // the header agrees with the file, but the BIN chunk does not fit
+        let mut cut = cut.to_vec();
+        let length = (cut.len() as u32).to_le_bytes();
+        cut[8..12].copy_from_slice(&length);
+        assert!(matches!(parse(&cut), Err(GlbError::UnexpectedEof)));
+
+        assert!(matches!(parse(&bytes[..10]), Err(GlbError::UnexpectedEof)));
+    }
+
+    #[test]
+    fn not_glb() {
+        assert!(matches!(parse(b"{\"asset\": {}}"), Err(GlbError::BadMagic)));
+
+        let mut bytes = write("{}", None);
+        bytes[4] = 1;
+        assert!(matches!(
+            parse(&bytes),
+            Err(GlbError::UnsupportedVersion(1))
+        ));
+    }
+}
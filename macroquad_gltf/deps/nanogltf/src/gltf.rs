@@ -1,8 +1,12 @@
 use std::fmt;
 
-use nanoserde::DeJson;
+use nanoserde::{
+    DeJson, DeJsonErr, DeJsonState, DeJsonTok, JsonInput, JsonValue, SerJson, SerJsonState,
+};
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::GltfError;
 
 // a helper for Debug impls
 fn debug_trim_string(uri: &str) -> String {
@@ -13,12 +17,12 @@ fn debug_trim_string(uri: &str) -> String {
     }
 }
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct Gltf {
     #[nserde(default)]
     pub accessors: Vec<Accessor>,
     #[nserde(default)]
-    pub assets: Vec<Asset>,
+    pub asset: Asset,
     #[nserde(default)]
     pub buffers: Vec<Buffer>,
     #[nserde(rename = "bufferViews")]
@@ -26,6 +30,7 @@ pub struct Gltf {
     pub buffer_views: Vec<BufferView>,
     #[nserde(default)]
     pub images: Vec<Image>,
+    pub scene: Option<usize>,
     #[nserde(default)]
     pub scenes: Vec<Scene>,
     #[nserde(default)]
@@ -34,6 +39,134 @@ pub struct Gltf {
     pub meshes: Vec<Mesh>,
     #[nserde(default)]
     pub nodes: Vec<Node>,
+    #[nserde(default)]
+    pub textures: Vec<Texture>,
+    #[nserde(default)]
+    pub samplers: Vec<Sampler>,
+    #[nserde(default)]
+    pub skins: Vec<Skin>,
+    #[nserde(default)]
+    pub animations: Vec<Animation>,
+    #[nserde(rename = "extensionsUsed")]
+    #[nserde(default)]
+    pub extensions_used: Vec<String>,
+    pub extensions: Option<Extensions>,
+    pub extras: Option<JsonValue>,
+}
+
+// Extension objects with a few known extensions, keeping the rest as they were in the file.
+macro_rules! extensions {
+    ($name:ident { $($field:ident: $ty:ty = $key:literal),* $(,)? }) => {
+        #[derive(PartialEq, Debug, Default)]
+        pub struct $name {
+            $(pub $field: Option<$ty>,)*
+            /// Extensions without a typed field here, by name.
+            pub others: BTreeMap<String, JsonValue>,
+        }
+
+        impl<'de> DeJson<'de> for $name {
+            fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<Self, DeJsonErr> {
+                let mut extensions = $name::default();
+                s.curly_open(i)?;
+                while s.tok != DeJsonTok::CurlyClose {
+                    let key = s.as_string()?;
+                    s.next_colon(i)?;
+                    match key.as_str() {
+                        $($key => extensions.$field = DeJson::de_json(s, i)?,)*
+                        _ => {
+                            extensions.others.insert(key, DeJson::de_json(s, i)?);
+                        }
+                    }
+                    s.eat_comma_curly(i)?;
+                }
+                s.curly_close(i)?;
+                Ok(extensions)
+            }
+        }
+
+        impl SerJson for $name {
+            fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+                s.st_pre();
+                let mut first = true;
+                $(if let Some(extension) = &self.$field {
+                    first = false;
+                    s.field(d + 1, $key);
+                    extension.ser_json(d + 1, s);
+                })*
+                for (key, extension) in &self.others {
+                    if !first {
+                        s.conl();
+                    }
+                    first = false;
+                    s.field(d + 1, key);
+                    extension.ser_json(d + 1, s);
+                }
+                s.st_post(d);
+            }
+        }
+    };
+}
+
+extensions!(Extensions {
+    khr_lights_punctual: LightsPunctual = "KHR_lights_punctual",
+});
+
+// Enums stored as a json number or string. Unknown values fail the parsing
+// through their `TryFrom` impl instead of panicking.
+macro_rules! json_enum {
+    ($name:ident, $repr:ty) => {
+        impl<'de> DeJson<'de> for $name {
+            fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<Self, DeJsonErr> {
+                let value: $repr = DeJson::de_json(s, i)?;
+                $name::try_from(&value).map_err(|err| s.err_parse(&err.to_string()))
+            }
+        }
+
+        impl SerJson for $name {
+            fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+                <$repr>::from(self).ser_json(d, s);
+            }
+        }
+    };
+}
+
+#[derive(DeJson, SerJson, PartialEq, Debug)]
+pub struct LightsPunctual {
+    #[nserde(default)]
+    pub lights: Vec<Light>,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
+}
+
+#[derive(DeJson, SerJson, PartialEq, Debug)]
+pub struct Light {
+    pub name: Option<String>,
+    #[nserde(default = "[1.0, 1.0, 1.0]")]
+    pub color: [f64; 3],
+    #[nserde(default = "1.0")]
+    pub intensity: f64,
+    #[nserde(rename = "type")]
+    pub type_: String,
+    pub range: Option<f64>,
+    pub spot: Option<LightSpot>,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
+}
+
+#[derive(DeJson, SerJson, PartialEq, Debug)]
+pub struct LightSpot {
+    #[nserde(rename = "innerConeAngle")]
+    #[nserde(default = "0.0")]
+    pub inner_cone_angle: f64,
+    #[nserde(rename = "outerConeAngle")]
+    #[nserde(default_with = "default_outer_cone_angle")]
+    pub outer_cone_angle: f64,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
+}
+
+fn default_outer_cone_angle() -> f64 {
+    core::f64::consts::FRAC_PI_4
 }
 
 #[derive(Debug, PartialEq)]
@@ -45,19 +178,26 @@ pub enum ComponentType {
     UnsignedInt,
     Float,
 }
-impl From<&u32> for ComponentType {
-    fn from(n: &u32) -> ComponentType {
+impl TryFrom<&u32> for ComponentType {
+    type Error = GltfError;
+
+    fn try_from(n: &u32) -> Result<ComponentType, GltfError> {
         match *n {
-            5120 => ComponentType::Byte,
-            5121 => ComponentType::UnsignedByte,
-            5122 => ComponentType::Short,
-            5123 => ComponentType::UnsignedShort,
-            5125 => ComponentType::UnsignedInt,
-            5126 => ComponentType::Float,
-            x => panic!("Not a ComponentType u32!: {x}"),
+            5120 => Ok(ComponentType::Byte),
+            5121 => Ok(ComponentType::UnsignedByte),
+            5122 => Ok(ComponentType::Short),
+            5123 => Ok(ComponentType::UnsignedShort),
+            5125 => Ok(ComponentType::UnsignedInt),
+            5126 => Ok(ComponentType::Float),
+            x => Err(GltfError::UnsupportedComponentType(x)),
         }
     }
 }
+impl From<&ComponentType> for u32 {
+    fn from(ty: &ComponentType) -> u32 {
+        ty.gl_enum()
+    }
+}
 
 impl ComponentType {
     pub fn byte_size(&self) -> usize {
@@ -70,9 +210,24 @@ impl ComponentType {
             Float => 4,
         }
     }
+
+    /// The GL enum value, as stored in the json.
+    pub fn gl_enum(&self) -> u32 {
+        use ComponentType::*;
+
+        match self {
+            Byte => 5120,
+            UnsignedByte => 5121,
+            Short => 5122,
+            UnsignedShort => 5123,
+            UnsignedInt => 5125,
+            Float => 5126,
+        }
+    }
 }
+json_enum!(ComponentType, u32);
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct Accessor {
     #[nserde(rename = "bufferView")]
     pub buffer_view: Option<usize>,
@@ -80,7 +235,6 @@ pub struct Accessor {
     #[nserde(default = 0)]
     pub byte_offset: usize,
     #[nserde(rename = "componentType")]
-    #[nserde(proxy = "u32")]
     pub component_type: ComponentType,
     #[nserde(default = "false")]
     pub normalized: bool,
@@ -91,55 +245,83 @@ pub struct Accessor {
     pub name: Option<String>,
     #[nserde(rename = "type")]
     pub type_: Option<String>,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
 }
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct SparseIndices {
     #[nserde(rename = "bufferView")]
     pub buffer_view: usize,
     #[nserde(rename = "byteOffset")]
-    pub byte_fffset: usize,
+    #[nserde(default = 0)]
+    pub byte_offset: usize,
     #[nserde(rename = "componentType")]
-    pub component_type: u32,
+    pub component_type: ComponentType,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
 }
 
-#[derive(DeJson, PartialEq, Debug)]
-struct SparseValues {
+#[derive(DeJson, SerJson, PartialEq, Debug)]
+pub struct SparseValues {
     #[nserde(rename = "bufferView")]
     pub buffer_view: usize,
     #[nserde(rename = "byteOffset")]
+    #[nserde(default = 0)]
     pub byte_offset: usize,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
 }
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct Sparse {
-    count: usize,
-    indices: SparseIndices,
-    values: SparseValues,
+    pub count: usize,
+    pub indices: SparseIndices,
+    pub values: SparseValues,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
 }
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct Asset {
     pub copyright: Option<String>,
     pub generator: Option<String>,
     pub version: String,
     #[nserde(rename = "minVersion")]
     pub min_version: Option<String>,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
+}
+impl Default for Asset {
+    fn default() -> Asset {
+        Asset {
+            copyright: None,
+            generator: None,
+            version: "2.0".to_string(),
+            min_version: None,
+            extensions: None,
+            extras: None,
+        }
+    }
 }
 
-#[derive(DeJson, PartialEq)]
+#[derive(DeJson, SerJson, PartialEq)]
 pub struct Buffer {
-    pub uri: String,
+    pub uri: Option<String>,
     #[nserde(rename = "byteLength")]
     pub byte_length: usize,
     pub name: Option<String>,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
 }
 impl fmt::Debug for Buffer {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Buffer")
-            .field("uri", &debug_trim_string(&self.uri))
+            .field("uri", &self.uri.as_ref().map(|s| debug_trim_string(s)))
             .field("byte_length", &self.byte_length)
             .field("name", &self.name)
+            .field("extensions", &self.extensions)
+            .field("extras", &self.extras)
             .finish()
     }
 }
@@ -149,17 +331,31 @@ pub enum BufferViewTarget {
     ArrayBuffer,
     ElementArrayBuffer,
 }
-impl From<&u32> for BufferViewTarget {
-    fn from(n: &u32) -> BufferViewTarget {
+impl TryFrom<&u32> for BufferViewTarget {
+    type Error = GltfError;
+
+    fn try_from(n: &u32) -> Result<BufferViewTarget, GltfError> {
         match *n {
-            34962 => BufferViewTarget::ArrayBuffer,
-            34963 => BufferViewTarget::ElementArrayBuffer,
-            x => panic!("Not a BufferViewTarget u32! {x}"),
+            34962 => Ok(BufferViewTarget::ArrayBuffer),
+            34963 => Ok(BufferViewTarget::ElementArrayBuffer),
+            x => Err(GltfError::UnknownEnumValue {
+                kind: "buffer view target",
+                value: x.to_string(),
+            }),
         }
     }
 }
+impl From<&BufferViewTarget> for u32 {
+    fn from(target: &BufferViewTarget) -> u32 {
+        match target {
+            BufferViewTarget::ArrayBuffer => 34962,
+            BufferViewTarget::ElementArrayBuffer => 34963,
+        }
+    }
+}
+json_enum!(BufferViewTarget, u32);
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct BufferView {
     pub buffer: usize,
     #[nserde(default)]
@@ -167,13 +363,15 @@ pub struct BufferView {
     pub byte_offset: usize,
     #[nserde(rename = "byteLength")]
     pub byte_length: usize,
-    pub stride: Option<usize>,
-    #[nserde(proxy = "u32")]
+    #[nserde(rename = "byteStride")]
+    pub byte_stride: Option<usize>,
     pub target: Option<BufferViewTarget>,
     pub name: Option<String>,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
 }
 
-#[derive(DeJson, PartialEq)]
+#[derive(DeJson, SerJson, PartialEq)]
 pub struct Image {
     pub uri: Option<String>,
     #[nserde(rename = "mimeType")]
@@ -181,6 +379,8 @@ pub struct Image {
     #[nserde(rename = "bufferView")]
     pub buffer_view: Option<usize>,
     pub name: Option<String>,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
 }
 
 impl fmt::Debug for Image {
@@ -188,12 +388,15 @@ impl fmt::Debug for Image {
         f.debug_struct("Image")
             .field("uri", &self.uri.as_ref().map(|s| debug_trim_string(s)))
             .field("mime_type", &self.mime_type)
+            .field("buffer_view", &self.buffer_view)
             .field("name", &self.name)
+            .field("extensions", &self.extensions)
+            .field("extras", &self.extras)
             .finish()
     }
 }
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct PBRMetallicRoughness {
     #[nserde(default = "[1.0, 1.0, 1.0, 1.0]")]
     #[nserde(rename = "baseColorFactor")]
@@ -208,17 +411,21 @@ pub struct PBRMetallicRoughness {
     pub roughness_factor: f64,
     #[nserde(rename = "metallicRoughnessTexture")]
     pub metallic_roughness_texture: Option<MetallicRoughnessTexture>,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
 }
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct BaseColorTexture {
     pub index: usize,
     #[nserde(rename = "texCoord")]
     #[nserde(default = 0)]
     pub tex_coord: usize,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
 }
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct NormalTexture {
     pub index: usize,
     #[nserde(rename = "texCoord")]
@@ -226,9 +433,11 @@ pub struct NormalTexture {
     pub tex_coord: usize,
     #[nserde(default = 1.0)]
     pub scale: f64,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
 }
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct OcclusionTexture {
     pub index: usize,
     #[nserde(rename = "texCoord")]
@@ -236,25 +445,31 @@ pub struct OcclusionTexture {
     pub tex_coord: usize,
     #[nserde(default = 1.0)]
     pub strength: f64,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
 }
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct EmissiveTexture {
     pub index: usize,
     #[nserde(rename = "texCoord")]
     #[nserde(default = 0)]
     pub tex_coord: usize,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
 }
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct MetallicRoughnessTexture {
     pub index: usize,
     #[nserde(rename = "texCoord")]
     #[nserde(default = 0)]
     pub tex_coord: usize,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
 }
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct Material {
     pub name: Option<String>,
     #[nserde(rename = "pbrMetallicRoughness")]
@@ -270,21 +485,59 @@ pub struct Material {
     pub emissive_factor: [f64; 3],
     #[nserde(rename = "alphaMode")]
     #[nserde(default)]
-    pub alpha_mode: String,
+    pub alpha_mode: AlphaMode,
     #[nserde(rename = "alphaCutoff")]
     #[nserde(default = "0.5")]
     pub alpha_cutoff: f64,
     #[nserde(rename = "doubleSided")]
     #[nserde(default = "false")]
     pub double_sided: bool,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum AlphaMode {
+    #[default]
+    Opaque,
+    Mask,
+    Blend,
+}
+impl TryFrom<&String> for AlphaMode {
+    type Error = GltfError;
+
+    fn try_from(s: &String) -> Result<AlphaMode, GltfError> {
+        match s.as_str() {
+            "OPAQUE" => Ok(AlphaMode::Opaque),
+            "MASK" => Ok(AlphaMode::Mask),
+            "BLEND" => Ok(AlphaMode::Blend),
+            x => Err(GltfError::UnknownEnumValue {
+                kind: "alphaMode",
+                value: x.to_string(),
+            }),
+        }
+    }
+}
+impl From<&AlphaMode> for String {
+    fn from(mode: &AlphaMode) -> String {
+        match mode {
+            AlphaMode::Opaque => "OPAQUE",
+            AlphaMode::Mask => "MASK",
+            AlphaMode::Blend => "BLEND",
+        }
+        .to_string()
+    }
 }
+json_enum!(AlphaMode, String);
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct Mesh {
     #[nserde(default)]
     pub primitives: Vec<Primitive>,
     pub weights: Option<Vec<f64>>,
     pub name: Option<String>,
+    pub extras: Option<JsonValue>,
+    pub extensions: Option<JsonValue>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -298,32 +551,54 @@ pub enum PrimitiveMode {
     TriangleStrip,
     TriangleFan,
 }
-impl From<&u32> for PrimitiveMode {
-    fn from(n: &u32) -> PrimitiveMode {
+impl TryFrom<&u32> for PrimitiveMode {
+    type Error = GltfError;
+
+    fn try_from(n: &u32) -> Result<PrimitiveMode, GltfError> {
         match *n {
-            0 => PrimitiveMode::Points,
-            1 => PrimitiveMode::Lines,
-            2 => PrimitiveMode::LineLoop,
-            3 => PrimitiveMode::LineStrip,
-            4 => PrimitiveMode::Triangles,
-            5 => PrimitiveMode::TriangleStrip,
-            6 => PrimitiveMode::TriangleFan,
-            x => panic!("Not a PrimitiveMode u32! {x}"),
+            0 => Ok(PrimitiveMode::Points),
+            1 => Ok(PrimitiveMode::Lines),
+            2 => Ok(PrimitiveMode::LineLoop),
+            3 => Ok(PrimitiveMode::LineStrip),
+            4 => Ok(PrimitiveMode::Triangles),
+            5 => Ok(PrimitiveMode::TriangleStrip),
+            6 => Ok(PrimitiveMode::TriangleFan),
+            x => Err(GltfError::UnknownEnumValue {
+                kind: "primitive mode",
+                value: x.to_string(),
+            }),
         }
     }
 }
-#[derive(DeJson, PartialEq, Debug)]
+impl From<&PrimitiveMode> for u32 {
+    fn from(mode: &PrimitiveMode) -> u32 {
+        match mode {
+            PrimitiveMode::Points => 0,
+            PrimitiveMode::Lines => 1,
+            PrimitiveMode::LineLoop => 2,
+            PrimitiveMode::LineStrip => 3,
+            PrimitiveMode::Triangles => 4,
+            PrimitiveMode::TriangleStrip => 5,
+            PrimitiveMode::TriangleFan => 6,
+        }
+    }
+}
+json_enum!(PrimitiveMode, u32);
+
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct Primitive {
     #[nserde(default)]
     pub attributes: HashMap<String, usize>,
     pub indices: Option<usize>,
     pub material: Option<usize>,
-    #[nserde(proxy = "u32")]
     pub mode: Option<PrimitiveMode>,
-    pub targets: Option<HashMap<String, usize>>,
+    #[nserde(default)]
+    pub targets: Vec<HashMap<String, usize>>,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
 }
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct Node {
     pub camera: Option<usize>,
     #[nserde(default)]
@@ -336,6 +611,19 @@ pub struct Node {
     pub translation: Option<[f64; 3]>,
     pub weights: Option<Vec<f64>>,
     pub name: Option<String>,
+    pub extensions: Option<NodeExtensions>,
+    pub extras: Option<JsonValue>,
+}
+
+extensions!(NodeExtensions {
+    khr_lights_punctual: NodeLight = "KHR_lights_punctual",
+});
+
+#[derive(DeJson, SerJson, PartialEq, Debug)]
+pub struct NodeLight {
+    pub light: usize,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -347,19 +635,37 @@ pub enum Filter {
     NearestMipmapLinear = 9986,
     LinearMipmapLinear = 9987,
 }
-impl From<&u32> for Filter {
-    fn from(n: &u32) -> Filter {
+impl TryFrom<&u32> for Filter {
+    type Error = GltfError;
+
+    fn try_from(n: &u32) -> Result<Filter, GltfError> {
         match *n {
-            9728 => Filter::Nearest,
-            9729 => Filter::Linear,
-            9984 => Filter::NearestMimpapNearest,
-            9985 => Filter::LinearMipmapNearest,
-            9986 => Filter::NearestMipmapLinear,
-            9987 => Filter::LinearMipmapLinear,
-            x => panic!("Not a Filter u32! {x}"),
+            9728 => Ok(Filter::Nearest),
+            9729 => Ok(Filter::Linear),
+            9984 => Ok(Filter::NearestMimpapNearest),
+            9985 => Ok(Filter::LinearMipmapNearest),
+            9986 => Ok(Filter::NearestMipmapLinear),
+            9987 => Ok(Filter::LinearMipmapLinear),
+            x => Err(GltfError::UnknownEnumValue {
+                kind: "sampler filter",
+                value: x.to_string(),
+            }),
         }
     }
 }
+impl From<&Filter> for u32 {
+    fn from(filter: &Filter) -> u32 {
+        match filter {
+            Filter::Nearest => 9728,
+            Filter::Linear => 9729,
+            Filter::NearestMimpapNearest => 9984,
+            Filter::LinearMipmapNearest => 9985,
+            Filter::NearestMipmapLinear => 9986,
+            Filter::LinearMipmapLinear => 9987,
+        }
+    }
+}
+json_enum!(Filter, u32);
 
 #[derive(Debug, PartialEq)]
 pub enum WrappingMode {
@@ -367,43 +673,283 @@ pub enum WrappingMode {
     MirroredRepeat = 33648,
     Repeat = 10497,
 }
-impl From<&u32> for WrappingMode {
-    fn from(n: &u32) -> WrappingMode {
+impl TryFrom<&u32> for WrappingMode {
+    type Error = GltfError;
+
+    fn try_from(n: &u32) -> Result<WrappingMode, GltfError> {
         match *n {
-            33071 => WrappingMode::ClampToEdge,
-            33648 => WrappingMode::MirroredRepeat,
-            10497 => WrappingMode::Repeat,
-            x => panic!("Not a WrappingMode u32! {x}"),
+            33071 => Ok(WrappingMode::ClampToEdge),
+            33648 => Ok(WrappingMode::MirroredRepeat),
+            10497 => Ok(WrappingMode::Repeat),
+            x => Err(GltfError::UnknownEnumValue {
+                kind: "wrapping mode",
+                value: x.to_string(),
+            }),
+        }
+    }
+}
+impl From<&WrappingMode> for u32 {
+    fn from(mode: &WrappingMode) -> u32 {
+        match mode {
+            WrappingMode::ClampToEdge => 33071,
+            WrappingMode::MirroredRepeat => 33648,
+            WrappingMode::Repeat => 10497,
         }
     }
 }
+json_enum!(WrappingMode, u32);
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct Sampler {
     #[nserde(rename = "magFilter")]
-    #[nserde(proxy = "u32")]
     pub mag_filter: Option<Filter>,
     #[nserde(rename = "minFilter")]
-    #[nserde(proxy = "u32")]
     pub min_filter: Option<Filter>,
     #[nserde(rename = "wrapS")]
-    #[nserde(proxy = "u32")]
     pub wrap_s: Option<WrappingMode>,
-    #[nserde(proxy = "u32")]
     #[nserde(rename = "wrapT")]
     pub wrap_t: Option<WrappingMode>,
     pub name: Option<String>,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
 }
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct Scene {
     pub nodes: Vec<usize>,
     pub name: Option<String>,
+    pub extras: Option<JsonValue>,
+    pub extensions: Option<JsonValue>,
 }
 
-#[derive(DeJson, PartialEq, Debug)]
+#[derive(DeJson, SerJson, PartialEq, Debug)]
 pub struct Texture {
     pub sampler: Option<usize>,
     pub source: Option<usize>,
     pub name: Option<String>,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
+}
+
+#[derive(DeJson, SerJson, PartialEq, Debug)]
+pub struct Skin {
+    #[nserde(rename = "inverseBindMatrices")]
+    pub inverse_bind_matrices: Option<usize>,
+    pub skeleton: Option<usize>,
+    pub joints: Vec<usize>,
+    pub name: Option<String>,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    Step,
+    CubicSpline,
+}
+impl TryFrom<&String> for Interpolation {
+    type Error = GltfError;
+
+    fn try_from(s: &String) -> Result<Interpolation, GltfError> {
+        match s.as_str() {
+            "LINEAR" => Ok(Interpolation::Linear),
+            "STEP" => Ok(Interpolation::Step),
+            "CUBICSPLINE" => Ok(Interpolation::CubicSpline),
+            x => Err(GltfError::UnknownEnumValue {
+                kind: "interpolation",
+                value: x.to_string(),
+            }),
+        }
+    }
+}
+impl From<&Interpolation> for String {
+    fn from(interpolation: &Interpolation) -> String {
+        match interpolation {
+            Interpolation::Linear => "LINEAR",
+            Interpolation::Step => "STEP",
+            Interpolation::CubicSpline => "CUBICSPLINE",
+        }
+        .to_string()
+    }
+}
+json_enum!(Interpolation, String);
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum TargetPath {
+    Translation,
+    Rotation,
+    Scale,
+    Weights,
+    /// A path added by an extension, like "pointer" of KHR_animation_pointer.
+    Unknown(String),
+}
+impl From<&String> for TargetPath {
+    fn from(s: &String) -> TargetPath {
+        match s.as_str() {
+            "translation" => TargetPath::Translation,
+            "rotation" => TargetPath::Rotation,
+            "scale" => TargetPath::Scale,
+            "weights" => TargetPath::Weights,
+            x => TargetPath::Unknown(x.to_string()),
+        }
+    }
+}
+impl From<&TargetPath> for String {
+    fn from(path: &TargetPath) -> String {
+        match path {
+            TargetPath::Translation => "translation",
+            TargetPath::Rotation => "rotation",
+            TargetPath::Scale => "scale",
+            TargetPath::Weights => "weights",
+            TargetPath::Unknown(path) => path,
+        }
+        .to_string()
+    }
+}
+
+#[derive(DeJson, SerJson, PartialEq, Debug)]
+pub struct AnimationSampler {
+    pub input: usize,
+    #[nserde(default)]
+    pub interpolation: Interpolation,
+    pub output: usize,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
+}
+
+#[derive(DeJson, SerJson, PartialEq, Debug)]
+pub struct ChannelTarget {
+    pub node: Option<usize>,
+    #[nserde(proxy = "String")]
+    pub path: TargetPath,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
+}
+
+#[derive(DeJson, SerJson, PartialEq, Debug)]
+pub struct Channel {
+    pub sampler: usize,
+    pub target: ChannelTarget,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
+}
+
+#[derive(DeJson, SerJson, PartialEq, Debug)]
+pub struct Animation {
+    pub channels: Vec<Channel>,
+    pub samplers: Vec<AnimationSampler>,
+    pub name: Option<String>,
+    pub extensions: Option<JsonValue>,
+    pub extras: Option<JsonValue>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn animation_enums() {
+        let animation: Animation = DeJson::deserialize_json(
+            r#"{
+                "channels": [
+                    {"sampler": 0, "target": {"node": 0, "path": "rotation"}},
+                    {"sampler": 0, "target": {"path": "pointer"}}
+                ],
+                "samplers": [{"input": 0, "output": 1, "interpolation": "STEP"}]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(animation.samplers[0].interpolation, Interpolation::Step);
+        assert_eq!(animation.channels[0].target.path, TargetPath::Rotation);
+        assert_eq!(
+            animation.channels[1].target.path,
+            TargetPath::Unknown("pointer".to_string())
+        );
+        let reparsed: Animation = DeJson::deserialize_json(&animation.serialize_json()).unwrap();
+        assert_eq!(reparsed, animation);
+
+        let sampler: Result<AnimationSampler, _> =
+            DeJson::deserialize_json(r#"{"input": 0, "output": 1, "interpolation": "CUBIC"}"#);
+        assert!(sampler.is_err());
+    }
+
+    #[test]
+    fn gl_enums() {
+        let sampler: Sampler = DeJson::deserialize_json(
+            r#"{"magFilter": 9729, "minFilter": 9987, "wrapS": 33648, "wrapT": 10497}"#,
+        )
+        .unwrap();
+        assert_eq!(sampler.mag_filter, Some(Filter::Linear));
+        assert_eq!(sampler.wrap_s, Some(WrappingMode::MirroredRepeat));
+        let reparsed: Sampler = DeJson::deserialize_json(&sampler.serialize_json()).unwrap();
+        assert_eq!(reparsed, sampler);
+
+        let accessor: Accessor =
+            DeJson::deserialize_json(r#"{"componentType": 5123, "count": 3, "type": "SCALAR"}"#)
+                .unwrap();
+        assert_eq!(accessor.component_type, ComponentType::UnsignedShort);
+
+        // unknown values are parse errors, not panics
+        assert!(Sampler::deserialize_json(r#"{"magFilter": 1}"#).is_err());
+        assert!(Sampler::deserialize_json(r#"{"wrapT": 1}"#).is_err());
+        assert!(Primitive::deserialize_json(r#"{"mode": 7}"#).is_err());
+        assert!(
+            BufferView::deserialize_json(r#"{"buffer": 0, "byteLength": 4, "target": 1}"#).is_err()
+        );
+        assert!(Accessor::deserialize_json(r#"{"componentType": 5124, "count": 1}"#).is_err());
+    }
+
+    #[test]
+    fn nested_extensions() {
+        let json = r#"{
+            "asset": {"version": "2.0", "extras": {"exporter": "test"}},
+            "materials": [{
+                "pbrMetallicRoughness": {
+                    "baseColorTexture": {
+                        "index": 0,
+                        "extensions": {
+                            "KHR_texture_transform": {"offset": [0.5, 0], "scale": [2, 2]}
+                        }
+                    }
+                }
+            }],
+            "accessors": [{"componentType": 5126, "count": 1, "type": "SCALAR", "extras": [1]}]
+        }"#;
+        let gltf = Gltf::from_json(json).unwrap();
+        let texture = gltf.materials[0]
+            .pbr_metallic_roughness
+            .base_color_texture
+            .as_ref()
+            .unwrap();
+        let transform = &texture.extensions.as_ref().unwrap()["KHR_texture_transform"];
+        assert_eq!(transform["offset"][0].as_f64(), Some(0.5));
+
+        let reparsed = Gltf::from_json(&gltf.to_json()).unwrap();
+        assert_eq!(reparsed.materials, gltf.materials);
+        assert_eq!(reparsed.asset, gltf.asset);
+        assert_eq!(reparsed.accessors, gltf.accessors);
+        assert!(reparsed.accessors[0].extras.is_some());
+    }
+
+    #[test]
+    fn alpha_mode() {
+        let material: Material = DeJson::deserialize_json(
+            r#"{"pbrMetallicRoughness": {}, "alphaMode": "MASK", "alphaCutoff": 0.25}"#,
+        )
+        .unwrap();
+        assert_eq!(material.alpha_mode, AlphaMode::Mask);
+        let reparsed: Material = DeJson::deserialize_json(&material.serialize_json()).unwrap();
+        assert_eq!(reparsed, material);
+
+        let material: Material =
+            DeJson::deserialize_json(r#"{"pbrMetallicRoughness": {}}"#).unwrap();
+        assert_eq!(material.alpha_mode, AlphaMode::Opaque);
+
+        let err =
+            Material::deserialize_json(r#"{"pbrMetallicRoughness": {}, "alphaMode": "ADDITIVE"}"#)
+                .unwrap_err();
+        assert!(err.msg.contains("ADDITIVE"), "{}", err.msg);
+    }
 }
@@ -1,53 +1,70 @@
 /// Raw json structs.
 pub mod gltf;
 
+/// Binary glTF container.
+pub mod glb;
+
 mod base64;
+mod error;
+mod reader;
 
+pub use error::GltfError;
 pub use gltf::*;
+pub use reader::AccessorReader;
 
 impl gltf::Gltf {
     pub fn from_json(json: &str) -> Result<gltf::Gltf, nanoserde::DeJsonErr> {
         nanoserde::DeJson::deserialize_json(json)
     }
+
+    pub fn to_json(&self) -> String {
+        nanoserde::SerJson::serialize_json(self)
+    }
 }
 
 /// A few optional helpers to extract the data out of the parsed gltf.
 pub mod utils {
-    use crate::{base64, gltf as ngltf, Gltf};
+    use crate::{base64, gltf as ngltf, Gltf, GltfError};
 
     /// Data encoded in the URI
     pub enum UriData {
+        /// No URI: the buffer data is in the BIN chunk of a .glb file.
+        Embedded,
         Bytes(Vec<u8>),
         RelativePath(String),
     }
 
-    /// Parse gltf's base64 string into a byte array or an external link.
-    pub fn parse_uri(uri: &str) -> UriData {
-        if !uri.starts_with("data:") {
-            return UriData::RelativePath(uri.to_string());
-        }
-
-        if uri.starts_with("data:application/octet-stream;base64") {
-            let uri = uri
-                .strip_prefix("data:application/octet-stream;base64,")
-                .unwrap();
-            let bytes = base64::decode(uri);
-            return UriData::Bytes(bytes);
+    /// Buffer's data location.
+    pub fn buffer_source(buffer: &ngltf::Buffer) -> Result<UriData, GltfError> {
+        match buffer.uri {
+            Some(ref uri) => parse_uri(uri),
+            None => Ok(UriData::Embedded),
         }
+    }
 
-        if uri.starts_with("data:image/jpeg;base64") {
-            let uri = uri.strip_prefix("data:image/jpeg;base64,").unwrap();
-            let bytes = base64::decode(uri);
-            return UriData::Bytes(bytes);
+    /// Parse gltf's base64 string into a byte array or an external link.
+    pub fn parse_uri(uri: &str) -> Result<UriData, GltfError> {
+        if !uri.starts_with("data:") {
+            return Ok(UriData::RelativePath(uri.to_string()));
         }
 
-        if uri.starts_with("data:image/png;base64") {
-            let uri = uri.strip_prefix("data:image/png;base64,").unwrap();
-            let bytes = base64::decode(uri);
-            return UriData::Bytes(bytes);
+        // data:[<mime type>];base64,<data>
+        // the mime type is not important, the data is the same base64 for
+        // application/octet-stream, application/gltf-buffer, image/png etc
+        match uri.split_once(";base64,") {
+            Some((_, data)) => Ok(UriData::Bytes(base64::decode(data)?)),
+            None => Err(GltfError::UnknownUriScheme(
+                uri.chars().take(40).collect::<String>(),
+            )),
         }
+    }
 
-        unimplemented!()
+    /// Embed the bytes into a base64 `data:` uri, for buffers of a .gltf without a .bin file.
+    pub fn data_uri(bytes: &[u8]) -> String {
+        format!(
+            "data:application/octet-stream;base64,{}",
+            base64::encode(bytes)
+        )
     }
 
     pub enum ImageSource {
@@ -67,45 +84,88 @@ pub mod utils {
     /// or
     /// `utils::attribute_bytes(&model, primitive.indices)`
     ///
-    /// Common attribute names are: TEXCOORD_*, POSITION, NORMAL
+    /// Common attribute names are: TEXCOORD_*, POSITION, NORMAL, JOINTS_0, WEIGHTS_0
     ///
-    /// Will panic if gltf have sparse accessors present.
-    /// (they are described here [glTF-Tutorials](https://github.com/KhronosGroup/glTF-Tutorials/blob/master/gltfTutorial/gltfTutorial_005_BuffersBufferViewsAccessors.md), but not yet implemented by nanogltf)
-    pub fn attribute_bytes(gltf: &Gltf, attribute: usize) -> (usize, usize, usize) {
-        let accessor = &gltf.accessors[attribute];
-        let buffer_view = accessor.buffer_view.unwrap();
-        let view = &gltf.buffer_views[buffer_view];
-
-        let k = match accessor.type_.as_ref().unwrap().as_str() {
-            "VEC3" => 3,
-            "VEC2" => 2,
-            "SCALAR" => 1,
-            _ => panic!(),
-        };
-
-        (
+    /// Only works for tightly packed accessors: interleaved views' `byte_stride`, sparse
+    /// accessors and the column padding of MAT2/MAT3 with 1 or 2 byte components
+    /// are not taken into account, use `AccessorReader` for those.
+    /// Will fail with `GltfError::MissingBufferView` on sparse accessors without a buffer view.
+    pub fn attribute_bytes(
+        gltf: &Gltf,
+        attribute: usize,
+    ) -> Result<(usize, usize, usize), GltfError> {
+        let accessor = gltf.accessors.get(attribute).ok_or(GltfError::BadIndex {
+            kind: "accessor",
+            index: attribute,
+        })?;
+        let buffer_view = accessor.buffer_view.ok_or(GltfError::MissingBufferView {
+            accessor: attribute,
+        })?;
+        let view = gltf
+            .buffer_views
+            .get(buffer_view)
+            .ok_or(GltfError::BadIndex {
+                kind: "buffer view",
+                index: buffer_view,
+            })?;
+
+        let k = components(accessor)?;
+
+        Ok((
             view.buffer,
             accessor.byte_offset + view.byte_offset,
             accessor.count * accessor.component_type.byte_size() * k,
-        )
+        ))
+    }
+
+    /// Amount of components in each element of the accessor.
+    pub fn components(accessor: &ngltf::Accessor) -> Result<usize, GltfError> {
+        match accessor.type_.as_deref().unwrap_or("") {
+            "MAT4" => Ok(16),
+            "MAT3" => Ok(9),
+            "MAT2" => Ok(4),
+            "VEC4" => Ok(4),
+            "VEC3" => Ok(3),
+            "VEC2" => Ok(2),
+            "SCALAR" => Ok(1),
+            ty => Err(GltfError::UnsupportedAccessorType(ty.to_string())),
+        }
+    }
+
+    /// Bounds checked `buffers[buffer][offset..offset + length]`.
+    pub fn buffer_slice(
+        buffers: &[Vec<u8>],
+        (buffer, offset, length): (usize, usize, usize),
+    ) -> Result<&[u8], GltfError> {
+        buffers
+            .get(buffer)
+            .and_then(|bytes| bytes.get(offset..offset.checked_add(length)?))
+            .ok_or(GltfError::BadBufferRange {
+                buffer,
+                offset,
+                length,
+            })
     }
 
     /// If uri is present - will parse the uri into a byte array. If not - will return the (buffer index, byte_offset, byte_length).
-    pub fn image_source(gltf: &Gltf, image: &ngltf::Image) -> ImageSource {
-        if image.uri.is_some() {
-            let uri = parse_uri(image.uri.as_ref().unwrap());
-            match uri {
-                UriData::Bytes(view) => ImageSource::Bytes(view),
-                UriData::RelativePath(uri) => ImageSource::RelativePath(uri),
+    pub fn image_source(gltf: &Gltf, image: &ngltf::Image) -> Result<ImageSource, GltfError> {
+        if let Some(ref uri) = image.uri {
+            match parse_uri(uri)? {
+                UriData::Bytes(view) => Ok(ImageSource::Bytes(view)),
+                UriData::RelativePath(uri) => Ok(ImageSource::RelativePath(uri)),
+                UriData::Embedded => unreachable!(),
             }
         } else {
-            let view = image.buffer_view.unwrap();
-            let view = &gltf.buffer_views[view];
-            ImageSource::Slice {
+            let view = image.buffer_view.ok_or(GltfError::MissingImageSource)?;
+            let view = gltf.buffer_views.get(view).ok_or(GltfError::BadIndex {
+                kind: "buffer view",
+                index: view,
+            })?;
+            Ok(ImageSource::Slice {
                 buffer: view.buffer,
                 offset: view.byte_offset,
                 length: view.byte_length,
-            }
+            })
         }
     }
 }
@@ -0,0 +1,431 @@
+use crate::{gltf::ComponentType, utils, Gltf, GltfError};
+
+/// Decodes accessors into plain arrays, whatever the component type, stride or sparse storage is.
+///
+/// ```ignore
+/// let reader = AccessorReader::new(&gltf, &buffers);
+/// let positions: Vec<[f32; 3]> = reader.read_f32(primitive.attributes["POSITION"])?;
+/// let indices: Vec<u32> = reader.read_indices(primitive.indices.unwrap())?;
+/// ```
+pub struct AccessorReader<'a> {
+    gltf: &'a Gltf,
+    buffers: &'a [Vec<u8>],
+}
+
+fn component_f32(component_type: &ComponentType, normalized: bool, c: &[u8]) -> f32 {
+    match component_type {
+        ComponentType::Float => f32::from_le_bytes([c[0], c[1], c[2], c[3]]),
+        ComponentType::UnsignedInt => u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as f32,
+        ComponentType::UnsignedShort if normalized => {
+            u16::from_le_bytes([c[0], c[1]]) as f32 / 65535.0
+        }
+        ComponentType::UnsignedShort => u16::from_le_bytes([c[0], c[1]]) as f32,
+        ComponentType::Short if normalized => {
+            (i16::from_le_bytes([c[0], c[1]]) as f32 / 32767.0).max(-1.0)
+        }
+        ComponentType::Short => i16::from_le_bytes([c[0], c[1]]) as f32,
+        ComponentType::UnsignedByte if normalized => c[0] as f32 / 255.0,
+        ComponentType::UnsignedByte => c[0] as f32,
+        ComponentType::Byte if normalized => (c[0] as i8 as f32 / 127.0).max(-1.0),
+        ComponentType::Byte => c[0] as i8 as f32,
+    }
+}
+
+fn component_u32(component_type: &ComponentType, _normalized: bool, c: &[u8]) -> u32 {
+    match component_type {
+        ComponentType::Float => f32::from_le_bytes([c[0], c[1], c[2], c[3]]) as u32,
+        ComponentType::UnsignedInt => u32::from_le_bytes([c[0], c[1], c[2], c[3]]),
+        ComponentType::UnsignedShort => u16::from_le_bytes([c[0], c[1]]) as u32,
+        ComponentType::Short => i16::from_le_bytes([c[0], c[1]]) as u32,
+        ComponentType::UnsignedByte => c[0] as u32,
+        ComponentType::Byte => c[0] as i8 as u32,
+    }
+}
+
+impl<'a> AccessorReader<'a> {
+    /// `buffers` are the loaded data of each of the `gltf.buffers`.
+    pub fn new(gltf: &'a Gltf, buffers: &'a [Vec<u8>]) -> AccessorReader<'a> {
+        AccessorReader { gltf, buffers }
+    }
+
+    /// Read an accessor with N components per element as floats.
+    /// Normalized integers are mapped to [0, 1] or [-1, 1], other integers are just converted.
+    pub fn read_f32<const N: usize>(&self, accessor: usize) -> Result<Vec<[f32; N]>, GltfError> {
+        self.read(accessor, Some(N), component_f32)
+            .map(|data| data.chunks(N).map(|c| c.try_into().unwrap()).collect())
+    }
+
+    /// Read an accessor with N integer components per element, like JOINTS_0.
+    pub fn read_u32<const N: usize>(&self, accessor: usize) -> Result<Vec<[u32; N]>, GltfError> {
+        self.read(accessor, Some(N), component_u32)
+            .map(|data| data.chunks(N).map(|c| c.try_into().unwrap()).collect())
+    }
+
+    /// Read a SCALAR indices accessor, any of the integer types.
+    pub fn read_indices(&self, accessor: usize) -> Result<Vec<u32>, GltfError> {
+        self.read(accessor, Some(1), component_u32)
+    }
+
+    /// Read all the components of all the elements into a single list,
+    /// for accessors with a variable amount of components, like morph target weights.
+    pub fn read_f32_flat(&self, accessor: usize) -> Result<Vec<f32>, GltfError> {
+        self.read(accessor, None, component_f32)
+    }
+
+    fn read<T: Copy + Default>(
+        &self,
+        accessor_ix: usize,
+        expected_components: Option<usize>,
+        convert: fn(&ComponentType, bool, &[u8]) -> T,
+    ) -> Result<Vec<T>, GltfError> {
+        let accessor = self
+            .gltf
+            .accessors
+            .get(accessor_ix)
+            .ok_or(GltfError::BadIndex {
+                kind: "accessor",
+                index: accessor_ix,
+            })?;
+        let components = utils::components(accessor)?;
+        if let Some(expected) = expected_components {
+            if expected != components {
+                return Err(GltfError::UnexpectedComponentsCount {
+                    accessor: accessor_ix,
+                    expected,
+                    found: components,
+                });
+            }
+        }
+        let component_type = &accessor.component_type;
+        let component_size = component_type.byte_size();
+        // matrix columns start at 4 byte boundaries, so MAT2 of bytes
+        // and MAT3 of bytes or shorts have padding after each column
+        let (columns, rows) = match accessor.type_.as_deref() {
+            Some("MAT2") => (2, 2),
+            Some("MAT3") => (3, 3),
+            Some("MAT4") => (4, 4),
+            _ => (1, components),
+        };
+        let column_size = rows * component_size;
+        let column_stride = if columns > 1 {
+            (column_size + 3) & !3
+        } else {
+            column_size
+        };
+        let element_size = columns * column_stride;
+
+        // the elements have to fit into the buffer view before anything is allocated for them
+        let view = accessor
+            .buffer_view
+            .map(|view| self.buffer_view(view))
+            .transpose()?;
+        let stride = view
+            .as_ref()
+            .and_then(|view| view.byte_stride)
+            .unwrap_or(element_size);
+        if let Some(view) = &view {
+            let length = elements_length(accessor.count, stride, element_size);
+            let end = length.and_then(|length| length.checked_add(accessor.byte_offset));
+            if end.is_none_or(|end| end > view.data.len()) {
+                return Err(GltfError::BadBufferRange {
+                    buffer: view.buffer,
+                    offset: view.offset.saturating_add(accessor.byte_offset),
+                    length: length.unwrap_or(usize::MAX),
+                });
+            }
+        }
+
+        // without a buffer view the count is not backed by any data
+        let too_large = || GltfError::AccessorTooLarge {
+            accessor: accessor_ix,
+            count: accessor.count,
+        };
+        let len = accessor
+            .count
+            .checked_mul(components)
+            .ok_or_else(too_large)?;
+        let mut res = Vec::new();
+        res.try_reserve_exact(len).map_err(|_| too_large())?;
+        res.resize(len, T::default());
+        let mut read_element = |element: usize, bytes: &[u8]| {
+            for (column, bytes) in bytes.chunks(column_stride).enumerate() {
+                for (row, c) in bytes[..column_size].chunks(component_size).enumerate() {
+                    res[element * components + column * rows + row] =
+                        convert(component_type, accessor.normalized, c);
+                }
+            }
+        };
+
+        // no buffer view: all zeroes, until replaced by sparse values
+        if let Some(view) = &view {
+            for element in 0..accessor.count {
+                let offset = accessor.byte_offset + element * stride;
+                read_element(element, &view.data[offset..offset + element_size]);
+            }
+        }
+
+        if let Some(ref sparse) = accessor.sparse {
+            let indices_view = self.buffer_view(sparse.indices.buffer_view)?;
+            let values_view = self.buffer_view(sparse.values.buffer_view)?;
+            let index_type = &sparse.indices.component_type;
+            let index_size = match index_type {
+                ComponentType::UnsignedByte
+                | ComponentType::UnsignedShort
+                | ComponentType::UnsignedInt => index_type.byte_size(),
+                _ => return Err(GltfError::UnsupportedComponentType(index_type.gl_enum())),
+            };
+            let sparse_data = |view: &View<'a>, byte_offset: usize, size: usize| {
+                let length = elements_length(sparse.count, size, size);
+                length
+                    .and_then(|length| view.data.get(byte_offset..)?.get(..length))
+                    .ok_or(GltfError::BadBufferRange {
+                        buffer: view.buffer,
+                        offset: view.offset.saturating_add(byte_offset),
+                        length: length.unwrap_or(usize::MAX),
+                    })
+            };
+            let indices = sparse_data(&indices_view, sparse.indices.byte_offset, index_size)?;
+            let values = sparse_data(&values_view, sparse.values.byte_offset, element_size)?;
+            for (index, value) in indices.chunks(index_size).zip(values.chunks(element_size)) {
+                let index = component_u32(index_type, false, index) as usize;
+                if index >= accessor.count {
+                    return Err(GltfError::BadIndex {
+                        kind: "sparse accessor element",
+                        index,
+                    });
+                }
+                read_element(index, value);
+            }
+        }
+
+        Ok(res)
+    }
+
+    fn buffer_view(&self, view: usize) -> Result<View<'a>, GltfError> {
+        let buffer_view = self
+            .gltf
+            .buffer_views
+            .get(view)
+            .ok_or(GltfError::BadIndex {
+                kind: "buffer view",
+                index: view,
+            })?;
+        // 0 would repeat the first element forever
+        if buffer_view
+            .byte_stride
+            .is_some_and(|stride| !(4..=252).contains(&stride))
+        {
+            return Err(GltfError::InvalidByteStride {
+                view,
+                stride: buffer_view.byte_stride.unwrap(),
+            });
+        }
+        let data = utils::buffer_slice(
+            self.buffers,
+            (
+                buffer_view.buffer,
+                buffer_view.byte_offset,
+                buffer_view.byte_length,
+            ),
+        )?;
+        Ok(View {
+            data,
+            buffer: buffer_view.buffer,
+            offset: buffer_view.byte_offset,
+            byte_stride: buffer_view.byte_stride,
+        })
+    }
+}
+
+/// Bytes from the start of the first of `count` elements, `stride` apart, to the end of the last.
+/// `None` if that doesn't fit in an usize.
+fn elements_length(count: usize, stride: usize, element_size: usize) -> Option<usize> {
+    match count {
+        0 => Some(0),
+        count => (count - 1).checked_mul(stride)?.checked_add(element_size),
+    }
+}
+
+struct View<'a> {
+    data: &'a [u8],
+    buffer: usize,
+    offset: usize,
+    byte_stride: Option<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn f32_bytes(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn strided() {
+        // VEC2 positions interleaved with a float of something else
+        let buffer = f32_bytes(&[1.0, 2.0, -1.0, 3.0, 4.0, -1.0, 5.0, 6.0, -1.0]);
+        let gltf = Gltf::from_json(
+            r#"{
+                "bufferViews": [{"buffer": 0, "byteLength": 36, "byteStride": 12}],
+                "accessors": [
+                    {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC2"},
+                    {"bufferView": 0, "byteOffset": 8, "componentType": 5126, "count": 3, "type": "SCALAR"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let buffers = [buffer];
+        let reader = AccessorReader::new(&gltf, &buffers);
+        assert_eq!(
+            reader.read_f32::<2>(0).unwrap(),
+            [[1.0, 2.0], [3.0, 4.0], [5.0, 6.0]]
+        );
+        assert_eq!(reader.read_f32_flat(1).unwrap(), [-1.0; 3]);
+        assert!(matches!(
+            reader.read_f32::<3>(0),
+            Err(GltfError::UnexpectedComponentsCount { .. })
+        ));
+    }
+
+    #[test]
+    fn sparse() {
+        // 4 u16 indices, then 2 u8 sparse indices, then 2 u16 sparse values
+        let buffer = vec![1, 0, 2, 0, 3, 0, 4, 0, 3, 1, 0, 0, 40, 0, 20, 0];
+        let gltf = Gltf::from_json(
+            r#"{
+                "bufferViews": [
+                    {"buffer": 0, "byteLength": 8},
+                    {"buffer": 0, "byteOffset": 8, "byteLength": 2},
+                    {"buffer": 0, "byteOffset": 12, "byteLength": 4}
+                ],
+                "accessors": [
+                    {"bufferView": 0, "componentType": 5123, "count": 4, "type": "SCALAR",
+                     "sparse": {"count": 2,
+                                "indices": {"bufferView": 1, "componentType": 5121},
+                                "values": {"bufferView": 2}}},
+                    {"componentType": 5123, "count": 4, "type": "SCALAR",
+                     "sparse": {"count": 2,
+                                "indices": {"bufferView": 1, "componentType": 5121},
+                                "values": {"bufferView": 2}}},
+                    {"componentType": 5123, "count": 2, "type": "SCALAR",
+                     "sparse": {"count": 2,
+                                "indices": {"bufferView": 1, "componentType": 5121},
+                                "values": {"bufferView": 2}}}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let buffers = [buffer];
+        let reader = AccessorReader::new(&gltf, &buffers);
+        assert_eq!(reader.read_indices(0).unwrap(), [1, 20, 3, 40]);
+        // no buffer view: zeroes under the sparse values
+        assert_eq!(reader.read_indices(1).unwrap(), [0, 20, 0, 40]);
+        assert!(matches!(
+            reader.read_indices(2),
+            Err(GltfError::BadIndex { index: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn normalized() {
+        let buffer = vec![
+            255, 0, 51, 255, // u8 VEC4
+            0x81, 0x80, 127, 0, // i8 VEC4: -127, -128, 127, 0
+            0xFF, 0xFF, 0, 0, // u16 VEC2
+        ];
+        let gltf = Gltf::from_json(
+            r#"{
+                "bufferViews": [{"buffer": 0, "byteLength": 12}],
+                "accessors": [
+                    {"bufferView": 0, "componentType": 5121, "normalized": true, "count": 1, "type": "VEC4"},
+                    {"bufferView": 0, "byteOffset": 4, "componentType": 5120, "normalized": true, "count": 1, "type": "VEC4"},
+                    {"bufferView": 0, "byteOffset": 8, "componentType": 5123, "normalized": true, "count": 1, "type": "VEC2"},
+                    {"bufferView": 0, "componentType": 5121, "count": 1, "type": "VEC4"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let buffers = [buffer];
+        let reader = AccessorReader::new(&gltf, &buffers);
+        assert_eq!(reader.read_f32::<4>(0).unwrap(), [[1.0, 0.0, 0.2, 1.0]]);
+        assert_eq!(reader.read_f32::<4>(1).unwrap(), [[-1.0, -1.0, 1.0, 0.0]]);
+        assert_eq!(reader.read_f32::<2>(2).unwrap(), [[1.0, 0.0]]);
+        // not normalized: plain conversion
+        assert_eq!(
+            reader.read_f32::<4>(3).unwrap(),
+            [[255.0, 0.0, 51.0, 255.0]]
+        );
+        assert_eq!(reader.read_u32::<4>(3).unwrap(), [[255, 0, 51, 255]]);
+    }
+
+    #[test]
+    fn huge_counts() {
+        // counts way past the 12 bytes of data fail before allocating anything
+        let gltf = Gltf::from_json(
+            r#"{
+                "bufferViews": [
+                    {"buffer": 0, "byteLength": 12},
+                    {"buffer": 0, "byteLength": 12, "byteStride": 0}
+                ],
+                "accessors": [
+                    {"bufferView": 0, "componentType": 5126, "count": 4000000000, "type": "VEC3"},
+                    {"bufferView": 0, "byteOffset": 4000000000000000000, "componentType": 5126, "count": 4000000000000000000, "type": "VEC3"},
+                    {"bufferView": 1, "componentType": 5126, "count": 4000000000, "type": "VEC3"},
+                    {"componentType": 5126, "count": 4000000000000000000, "type": "VEC3"},
+                    {"componentType": 5126, "count": 1, "type": "VEC3",
+                     "sparse": {"count": 4000000000000000000,
+                                "indices": {"bufferView": 0, "componentType": 5121},
+                                "values": {"bufferView": 0}}}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let buffers = [f32_bytes(&[1.0, 2.0, 3.0])];
+        let reader = AccessorReader::new(&gltf, &buffers);
+        assert!(matches!(
+            reader.read_f32::<3>(0),
+            Err(GltfError::BadBufferRange { offset: 0, .. })
+        ));
+        assert!(matches!(
+            reader.read_f32::<3>(1),
+            Err(GltfError::BadBufferRange {
+                length: usize::MAX,
+                ..
+            })
+        ));
+        assert!(matches!(
+            reader.read_f32::<3>(2),
+            Err(GltfError::InvalidByteStride { view: 1, stride: 0 })
+        ));
+        assert!(matches!(
+            reader.read_f32::<3>(3),
+            Err(GltfError::AccessorTooLarge { accessor: 3, .. })
+        ));
+        assert!(matches!(
+            reader.read_f32::<3>(4),
+            Err(GltfError::BadBufferRange { .. })
+        ));
+    }
+
+    #[test]
+    fn padded_matrix_columns() {
+        // MAT2 of bytes: 2 bytes of each column, then 2 bytes of padding
+        let buffer = vec![1, 2, 0, 0, 3, 4, 0, 0, 5, 6, 0, 0, 7, 8, 0, 0];
+        let gltf = Gltf::from_json(
+            r#"{
+                "bufferViews": [{"buffer": 0, "byteLength": 16}],
+                "accessors": [
+                    {"bufferView": 0, "componentType": 5121, "count": 2, "type": "MAT2"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let buffers = [buffer];
+        let reader = AccessorReader::new(&gltf, &buffers);
+        assert_eq!(
+            reader.read_u32::<4>(0).unwrap(),
+            [[1, 2, 3, 4], [5, 6, 7, 8]]
+        );
+    }
+}
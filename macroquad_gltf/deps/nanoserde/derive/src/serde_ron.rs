@@ -7,6 +7,7 @@ use crate::parse::{Attribute, Category, Enum, Field, Struct, Type};
 use proc_macro::TokenStream;
 
 use crate::shared;
+use crate::shared::{variant_kind, EnumTag, VariantKind};
 
 pub fn derive_ser_ron_proxy(proxy_type: &str, type_: &str) -> TokenStream {
     format!(
@@ -36,35 +37,63 @@ pub fn derive_de_ron_proxy(proxy_type: &str, type_: &str) -> TokenStream {
     .unwrap()
 }
 
-pub fn derive_ser_ron_struct(struct_: &Struct) -> TokenStream {
+/// Writes the named fields of a struct at indent level `d`,
+/// `access` turns a field's name into the expression reading it.
+fn ser_ron_fields(fields: &[Field], access: impl Fn(&str) -> String, d: &str) -> String {
     let mut s = String::new();
 
-    for field in &struct_.fields {
+    for field in fields {
         let struct_fieldname = field.field_name.clone().unwrap();
         let ron_fieldname =
             shared::attrs_rename(&field.attributes).unwrap_or_else(|| struct_fieldname.clone());
-        if field.ty.base() == "Option" {
+        let value = access(&struct_fieldname);
+        if shared::attrs_flatten(&field.attributes) {
+            if shared::is_map(&field.ty) {
+                l!(
+                    s,
+                    "for (key, value) in &{} {{
+                        s.field({}+1, key);
+                        value.ser_ron({}+1, s);
+                        s.conl();
+                    }}",
+                    value,
+                    d,
+                    d
+                );
+            } else {
+                l!(s, "s.flatten({}, &{});", d, value);
+            }
+        } else if field.ty.base() == "Option" {
             l!(
                 s,
-                "if let Some(t) = &self.{} {{
-                    s.field(d+1, \"{}\");
-                    t.ser_ron(d+1, s);
+                "if let Some(t) = &{} {{
+                    s.field({}+1, \"{}\");
+                    t.ser_ron({}+1, s);
                     s.conl();
                 }};",
-                struct_fieldname,
-                ron_fieldname
+                value,
+                d,
+                ron_fieldname,
+                d
             );
         } else {
             l!(
                 s,
-                "s.field(d+1,\"{}\");
-                self.{}.ser_ron(d+1, s);
+                "s.field({}+1,\"{}\");
+                {}.ser_ron({}+1, s);
                 s.conl();",
+                d,
                 ron_fieldname,
-                struct_fieldname
+                value,
+                d
             );
         }
     }
+    s
+}
+
+pub fn derive_ser_ron_struct(struct_: &Struct) -> TokenStream {
+    let s = ser_ron_fields(&struct_.fields, |name| format!("self.{}", name), "d");
 
     format!(
         "
@@ -115,14 +144,20 @@ pub fn derive_ser_ron_struct_unnamed(struct_: &Struct) -> TokenStream {
     .unwrap()
 }
 
+/// Parses the struct into `name { .. }`.
+///
+/// Flattened fields take the fields that didn't match any other field,
+/// or the ones in `flat_fields` when it's given, the fields `de_ron_flat` takes them from.
 pub fn derive_de_ron_named(
     name: &String,
     fields: &Vec<Field>,
     attributes: &Vec<Attribute>,
+    flat_fields: Option<&str>,
 ) -> String {
     let mut local_vars = Vec::new();
     let mut struct_field_names = Vec::new();
     let mut ron_field_names = Vec::new();
+    let mut flattened = Vec::new();
 
     let container_attr_default = shared::attrs_default(attributes).is_some();
 
@@ -157,6 +192,13 @@ pub fn derive_de_ron_named(
         let ron_fieldname =
             shared::attrs_rename(&field.attributes).unwrap_or(struct_fieldname.clone());
 
+        if shared::attrs_flatten(&field.attributes) {
+            unwraps.push(localvar.clone());
+            struct_field_names.push(struct_fieldname);
+            flattened.push((localvar, field));
+            continue;
+        }
+
         if field.ty.base() == "Option" {
             unwraps.push(format!(
                 "{{
@@ -204,8 +246,14 @@ pub fn derive_de_ron_named(
     for local in &local_vars {
         l!(local_lets, "let mut {} = None;", local)
     }
+    if !flattened.is_empty() {
+        l!(
+            local_lets,
+            "let mut _rest: nanoserde::RonFields = ::core::default::Default::default();"
+        );
+    }
 
-    let match_names = if ron_field_names.len() != 0 {
+    let match_names = if ron_field_names.len() != 0 || !flattened.is_empty() {
         let mut inner = String::new();
         for (ron_field_name, local_var) in ron_field_names.iter().zip(local_vars.iter()) {
             l!(
@@ -218,10 +266,25 @@ pub fn derive_de_ron_named(
                 local_var
             );
         }
+        if flattened.is_empty() {
+            l!(
+                inner,
+                "_ => return ::core::result::Result::Err(s.err_exp(&s.identbuf))"
+            );
+        } else {
+            l!(
+                inner,
+                "_ => {
+                    let key = ::core::mem::take(&mut s.identbuf);
+                    s.next_colon(i)?;
+                    let value = s.capture_value(i)?;
+                    _rest.push((key, value));
+                }"
+            );
+        }
         format!(
             "match s.identbuf.as_ref() {{
                 {}
-                _ => return ::core::result::Result::Err(s.err_exp(&s.identbuf))
             }}",
             inner
         )
@@ -229,6 +292,19 @@ pub fn derive_de_ron_named(
         String::new()
     };
 
+    // maps take everything left, so the structs go first
+    flattened.sort_by_key(|(_, field)| shared::is_map(&field.ty));
+    let mut flat_lets = String::new();
+    for (local_var, field) in &flattened {
+        l!(
+            flat_lets,
+            "let {} = <{} as DeRon>::de_ron_flat(s, {})?;",
+            local_var,
+            field.ty.full(),
+            flat_fields.unwrap_or("&mut _rest")
+        );
+    }
+
     let mut body = String::new();
 
     for (field_name, unwrap) in struct_field_names.iter().zip(unwraps.iter()) {
@@ -244,30 +320,47 @@ pub fn derive_de_ron_named(
                 s.eat_comma_paren(i)?;
             }};
             s.paren_close(i)?;
+            {}
             {} {{
                 {}
             }}
         }}",
-        local_lets, match_names, name, body
+        local_lets, match_names, flat_lets, name, body
     )
 }
 
 pub fn derive_de_ron_struct(struct_: &Struct) -> TokenStream {
-    let body = derive_de_ron_named(
-        &struct_
-            .name
-            .as_ref()
-            .expect("Cannot implement for anonymous struct"),
-        &struct_.fields,
-        &struct_.attributes,
-    );
+    let name = struct_
+        .name
+        .as_ref()
+        .expect("Cannot implement for anonymous struct");
+    let body = derive_de_ron_named(name, &struct_.fields, &struct_.attributes, None);
+    let flat_body = derive_de_ron_named(name, &struct_.fields, &struct_.attributes, Some("fields"));
+
+    // the fields a flattened struct takes out of its parent
+    let mut own_fields = String::new();
+    for field in &struct_.fields {
+        if !shared::attrs_flatten(&field.attributes) {
+            let name = field.field_name.clone().unwrap();
+            l!(
+                own_fields,
+                "\"{}\",",
+                shared::attrs_rename(&field.attributes).unwrap_or(name)
+            );
+        }
+    }
 
     format!(
         "impl DeRon for {} {{
             fn de_ron(s: &mut nanoserde::DeRonState, i: &mut core::str::Chars) -> ::core::result::Result<Self,nanoserde::DeRonErr> {{
                 ::core::result::Result::Ok({})
             }}
-        }}", struct_.name.as_ref().expect("Cannot implement for anonymous struct"), body)
+
+            fn de_ron_flat(s: &nanoserde::DeRonState, fields: &mut nanoserde::RonFields) -> ::core::result::Result<Self,nanoserde::DeRonErr> {{
+                let own = nanoserde::DeRonState::take_fields(fields, &[{}]);
+                s.de_buffered(&nanoserde::DeRonState::fields_text(&own), |s, i| ::core::result::Result::Ok({}))
+            }}
+        }}", name, body, own_fields, flat_body)
     .parse()
     .unwrap()
 }
@@ -299,6 +392,10 @@ pub fn derive_de_ron_struct_unnamed(struct_: &Struct) -> TokenStream {
 }
 
 pub fn derive_ser_ron_enum(enum_: &Enum) -> TokenStream {
+    let tag = shared::attrs_enum_tag(&enum_.attributes);
+    if !matches!(tag, EnumTag::External) {
+        return derive_ser_ron_enum_tagged(enum_, &tag);
+    }
     let mut body = String::new();
 
     for variant in &enum_.variants {
@@ -407,6 +504,10 @@ pub fn derive_ser_ron_enum(enum_: &Enum) -> TokenStream {
 }
 
 pub fn derive_de_ron_enum(enum_: &Enum) -> TokenStream {
+    let tag = shared::attrs_enum_tag(&enum_.attributes);
+    if !matches!(tag, EnumTag::External) {
+        return derive_de_ron_enum_tagged(enum_, &tag);
+    }
     let mut body = String::new();
     for variant in &enum_.variants {
         let ident = variant.field_name.clone().unwrap();
@@ -425,7 +526,7 @@ pub fn derive_de_ron_enum(enum_: &Enum) -> TokenStream {
                 ..
             } => {
                 let name = format!("{}::{}", enum_.name, ident);
-                let inner = derive_de_ron_named(&name, &contents.fields, &vec![]);
+                let inner = derive_de_ron_named(&name, &contents.fields, &vec![], None);
                 l!(body, "\"{}\" => {}", ident, inner);
             }
             Type {
@@ -475,3 +576,247 @@ pub fn derive_de_ron_enum(enum_: &Enum) -> TokenStream {
             }}
         }}", enum_.name, body).parse().unwrap()
 }
+
+fn derive_ser_ron_enum_tagged(enum_: &Enum, tag: &EnumTag) -> TokenStream {
+    let mut r = String::new();
+
+    for variant in &enum_.variants {
+        let ident = variant.field_name.clone().unwrap();
+        let ron_variant_name = shared::attrs_rename(&variant.attributes).unwrap_or(ident.clone());
+        let kind = variant_kind(&variant.ty);
+
+        let (pattern, names) = match kind {
+            VariantKind::Unit => (format!("Self::{}", ident), Vec::new()),
+            VariantKind::Struct(fields) => {
+                let names: Vec<String> = fields
+                    .iter()
+                    .map(|field| field.field_name.clone().unwrap())
+                    .collect();
+                (format!("Self::{} {{ {} }}", ident, names.join(",")), names)
+            }
+            VariantKind::Tuple(len) => {
+                let names: Vec<String> = (0..len).map(|index| format!("f{}", index)).collect();
+                (format!("Self::{}({})", ident, names.join(",")), names)
+            }
+        };
+        let fields = |d: &str| match kind {
+            VariantKind::Struct(fields) => ser_ron_fields(fields, |name| format!("(*{})", name), d),
+            _ => String::new(),
+        };
+        let tuple = |d: &str| {
+            let mut inner = String::new();
+            l!(inner, "s.out.push('(');");
+            for (index, name) in names.iter().enumerate() {
+                if index != 0 {
+                    l!(inner, "s.out.push_str(\", \");");
+                }
+                l!(inner, "{}.ser_ron({}, s);", name, d);
+            }
+            l!(inner, "s.out.push(')');");
+            inner
+        };
+
+        let mut body = String::new();
+        match tag {
+            EnumTag::Internal(tag) => {
+                l!(
+                    body,
+                    "s.st_pre(); s.field(d+1, \"{}\"); s.out.push_str(\"\\\"{}\\\"\"); s.conl();",
+                    tag,
+                    ron_variant_name
+                );
+                match kind {
+                    VariantKind::Unit => {}
+                    VariantKind::Struct(_) => body.push_str(&fields("d")),
+                    VariantKind::Tuple(1) => l!(body, "s.flatten(d, f0);"),
+                    VariantKind::Tuple(_) => panic!(
+                        "Internally tagged enums only support unit, struct and newtype variants, {}::{} is a tuple",
+                        enum_.name, ident
+                    ),
+                }
+                l!(body, "s.st_post(d);");
+            }
+            EnumTag::Adjacent(tag, content) => {
+                l!(
+                    body,
+                    "s.st_pre(); s.field(d+1, \"{}\"); s.out.push_str(\"\\\"{}\\\"\"); s.conl();",
+                    tag,
+                    ron_variant_name
+                );
+                if !matches!(kind, VariantKind::Unit) {
+                    l!(body, "s.field(d+1, \"{}\");", content);
+                }
+                match kind {
+                    VariantKind::Unit => {}
+                    VariantKind::Struct(_) => {
+                        l!(body, "s.st_pre();");
+                        body.push_str(&fields("(d+1)"));
+                        l!(body, "s.st_post(d+1); s.conl();");
+                    }
+                    VariantKind::Tuple(1) => l!(body, "f0.ser_ron(d+1, s); s.conl();"),
+                    VariantKind::Tuple(_) => {
+                        body.push_str(&tuple("d+1"));
+                        l!(body, "s.conl();");
+                    }
+                }
+                l!(body, "s.st_post(d);");
+            }
+            EnumTag::Untagged => match kind {
+                VariantKind::Unit => l!(body, "s.out.push_str(\"()\");"),
+                VariantKind::Struct(_) => {
+                    l!(body, "s.st_pre();");
+                    body.push_str(&fields("d"));
+                    l!(body, "s.st_post(d);");
+                }
+                VariantKind::Tuple(1) => l!(body, "f0.ser_ron(d, s);"),
+                VariantKind::Tuple(_) => body.push_str(&tuple("d")),
+            },
+            EnumTag::External => unreachable!(),
+        }
+
+        l!(r, "{} => {{ {} }}", pattern, body);
+    }
+
+    format!(
+        "
+        impl SerRon for {} {{
+            fn ser_ron(&self, d: usize, s: &mut nanoserde::SerRonState) {{
+                match self {{
+                    {}
+                }}
+            }}
+        }}",
+        enum_.name, r
+    )
+    .parse()
+    .unwrap()
+}
+
+fn derive_de_ron_enum_tagged(enum_: &Enum, tag: &EnumTag) -> TokenStream {
+    let mut r = String::new();
+
+    match tag {
+        EnumTag::Internal(tag) | EnumTag::Adjacent(tag, _) => {
+            l!(r, "let mut _fields = s.capture_fields(i)?;");
+            l!(
+                r,
+                "let _tag = match nanoserde::DeRonState::take_fields(&mut _fields, &[\"{}\"]).pop() {{
+                    Some((_, tag)) => s.de_buffered(&tag, |s, i| {{ let tag = s.as_string()?; s.next_tok(i)?; ::core::result::Result::Ok(tag) }})?,
+                    None => return ::core::result::Result::Err(s.err_nf(\"{}\")),
+                }};",
+                tag,
+                tag
+            );
+        }
+        EnumTag::Untagged => l!(r, "let _text = s.capture_value(i)?;"),
+        EnumTag::External => unreachable!(),
+    }
+    match tag {
+        EnumTag::Internal(_) => l!(
+            r,
+            "let _text = nanoserde::DeRonState::fields_text(&_fields);"
+        ),
+        EnumTag::Adjacent(_, content) => l!(
+            r,
+            "let _text = nanoserde::DeRonState::take_fields(&mut _fields, &[\"{}\"]).pop().map(|(_, content)| content).unwrap_or_default();",
+            content
+        ),
+        _ => {}
+    }
+
+    let mut r_variants = String::new();
+    for variant in &enum_.variants {
+        let ident = variant.field_name.clone().unwrap();
+        let ron_variant_name = shared::attrs_rename(&variant.attributes).unwrap_or(ident.clone());
+        let kind = variant_kind(&variant.ty);
+
+        // reads the variant's content out of `_text`
+        let parse = match kind {
+            VariantKind::Unit => format!(
+                "{{s.paren_open(i)?; s.paren_close(i)?; ::core::result::Result::Ok(Self::{})}}",
+                ident
+            ),
+            VariantKind::Struct(fields) => format!(
+                "::core::result::Result::Ok({})",
+                derive_de_ron_named(&format!("Self::{}", ident), &fields.to_vec(), &vec![], None)
+            ),
+            VariantKind::Tuple(1) => format!(
+                "::core::result::Result::Ok(Self::{}(DeRon::de_ron(s, i)?))",
+                ident
+            ),
+            VariantKind::Tuple(len) => {
+                if matches!(tag, EnumTag::Internal(_)) {
+                    panic!(
+                        "Internally tagged enums only support unit, struct and newtype variants, {}::{} is a tuple",
+                        enum_.name, ident
+                    );
+                }
+                let mut inner = String::new();
+                for _ in 0..len {
+                    l!(
+                        inner,
+                        "{
+                            let r = DeRon::de_ron(s, i)?;
+                            s.eat_comma_paren(i)?;
+                            r
+                        }, "
+                    )
+                }
+                format!(
+                    "{{s.paren_open(i)?; let r = Self::{}({}); s.paren_close(i)?; ::core::result::Result::Ok(r)}}",
+                    ident, inner
+                )
+            }
+        };
+
+        match (tag, kind) {
+            (EnumTag::Untagged, _) => l!(
+                r_variants,
+                "if let ::core::result::Result::Ok(r) = s.de_buffered(&_text, |s, i| {}) {{ return ::core::result::Result::Ok(r); }}",
+                parse
+            ),
+            (_, VariantKind::Unit) => l!(
+                r_variants,
+                "\"{}\" => Self::{},",
+                ron_variant_name,
+                ident
+            ),
+            _ => l!(
+                r_variants,
+                "\"{}\" => s.de_buffered(&_text, |s, i| {})?,",
+                ron_variant_name,
+                parse
+            ),
+        }
+    }
+
+    if matches!(tag, EnumTag::Untagged) {
+        l!(r, "{}", r_variants);
+        l!(
+            r,
+            "::core::result::Result::Err(s.err_parse(\"untagged enum {}\"))",
+            enum_.name
+        );
+    } else {
+        l!(
+            r,
+            "::core::result::Result::Ok(match _tag.as_str() {{
+                {}
+                _ => return ::core::result::Result::Err(s.err_enum(&_tag)),
+            }})",
+            r_variants
+        );
+    }
+
+    format!(
+        "
+        impl DeRon for {} {{
+            fn de_ron(s: &mut nanoserde::DeRonState, i: &mut core::str::Chars) -> ::core::result::Result<Self,nanoserde::DeRonErr> {{
+                {}
+            }}
+        }}",
+        enum_.name, r
+    )
+    .parse()
+    .unwrap()
+}
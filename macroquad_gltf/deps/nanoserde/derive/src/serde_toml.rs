@@ -0,0 +1,469 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::shared::{
+    enum_bounds_strings, ser_proxy_guard, struct_bounds_strings, variant_kind, VariantKind,
+};
+use crate::{
+    parse::{Enum, Field, Struct},
+    shared,
+};
+
+use proc_macro::TokenStream;
+
+pub fn derive_ser_toml_proxy(proxy_type: &str, type_: &str) -> TokenStream {
+    format!(
+        "impl SerToml for {} {{
+            fn ser_toml(&self) -> nanoserde::Toml {{
+                let proxy: {} = self.into();
+                proxy.ser_toml()
+            }}
+        }}",
+        type_, proxy_type
+    )
+    .parse()
+    .unwrap()
+}
+
+pub fn derive_de_toml_proxy(proxy_type: &str, type_: &str) -> TokenStream {
+    format!(
+        "impl DeToml for {} {{
+            fn de_toml(value: &nanoserde::Toml) -> ::core::result::Result<Self, nanoserde::DeTomlErr> {{
+                let proxy: {} = DeToml::de_toml(value)?;
+                ::core::result::Result::Ok(Into::into(&proxy))
+            }}
+        }}",
+        type_, proxy_type
+    )
+    .parse()
+    .unwrap()
+}
+
+fn no_flatten(field: &Field) {
+    if shared::attrs_flatten(&field.attributes) {
+        panic!("#[nserde(flatten)] isn't supported by the TOML derives");
+    }
+}
+
+/// Inserts the named fields into `table`, `access` turns a field's name into the expression
+/// reading it. `None` fields are left out, TOML has no null.
+fn ser_toml_fields(fields: &[Field], access: impl Fn(&str) -> String) -> String {
+    let mut s = String::new();
+
+    for field in fields {
+        no_flatten(field);
+        if shared::attrs_skip(&field.attributes) {
+            continue;
+        }
+        let struct_fieldname = field.field_name.clone().unwrap();
+        let toml_fieldname =
+            shared::attrs_rename(&field.attributes).unwrap_or_else(|| struct_fieldname.clone());
+        let proxied_field = ser_proxy_guard(&access(&struct_fieldname), field);
+
+        if field.ty.base() == "Option" {
+            l!(
+                s,
+                "if let Some(t) = &{} {{ table.insert(\"{}\".into(), SerToml::ser_toml(t)); }}",
+                proxied_field,
+                toml_fieldname
+            );
+        } else {
+            l!(
+                s,
+                "table.insert(\"{}\".into(), SerToml::ser_toml(&{}));",
+                toml_fieldname,
+                proxied_field
+            );
+        }
+    }
+    s
+}
+
+/// Reads `name { .. }` out of the table in `value`, unknown keys are skipped unless
+/// `deny_unknown_fields` is set.
+fn de_toml_named(
+    name: &str,
+    defaults: bool,
+    deny_unknown_fields: bool,
+    fields: &[Field],
+) -> String {
+    let mut r = String::new();
+    let mut lets = String::new();
+    let mut unwraps = String::new();
+    let mut known = String::new();
+
+    for field in fields {
+        no_flatten(field);
+        let struct_fieldname = field.field_name.clone().unwrap();
+        if shared::attrs_skip(&field.attributes) {
+            l!(unwraps, "{}: Default::default(),", struct_fieldname);
+            continue;
+        }
+        let localvar = format!("_{}", struct_fieldname);
+        let toml_fieldname =
+            shared::attrs_rename(&field.attributes).unwrap_or_else(|| struct_fieldname.clone());
+        let default_val = shared::attrs_default_value(field);
+
+        let proxified_t = match shared::attrs_proxy(&field.attributes) {
+            Some(proxy) if field.ty.base() == "Option" => {
+                format!("Some(From::<&{proxy}>::from(&t))")
+            }
+            Some(proxy) => format!("From::<&{proxy}>::from(&t)"),
+            None => "t".to_string(),
+        };
+        let otherwise = if field.ty.base() == "Option" {
+            default_val.unwrap_or_else(|| "None".to_string())
+        } else if defaults || default_val.is_some() {
+            default_val.unwrap_or_else(|| "Default::default()".to_string())
+        } else {
+            format!(
+                "return ::core::result::Result::Err(nanoserde::DeTomlErr::err_nf(\"{}\"))",
+                toml_fieldname
+            )
+        };
+
+        l!(
+            lets,
+            "let {} = DeToml::de_toml_field(table, \"{}\")?;",
+            localvar,
+            toml_fieldname
+        );
+        l!(
+            unwraps,
+            "{}: if let Some(t) = {} {{ {} }} else {{ {} }},",
+            struct_fieldname,
+            localvar,
+            proxified_t,
+            otherwise
+        );
+        l!(known, "\"{}\",", toml_fieldname);
+    }
+
+    l!(r, "let table = match value {");
+    l!(r, "nanoserde::Toml::Table(table) => table,");
+    l!(
+        r,
+        "_ => return ::core::result::Result::Err(nanoserde::DeTomlErr::err_type(\"table\", value)),"
+    );
+    l!(r, "};");
+    if deny_unknown_fields {
+        l!(r, "for key in table.keys() {");
+        l!(r, "if ![{}].contains(&key.as_str()) {{", known);
+        l!(
+            r,
+            "return ::core::result::Result::Err(nanoserde::DeTomlErr::err_unknown(key, &[{}]));",
+            known
+        );
+        l!(r, "}");
+        l!(r, "}");
+    }
+    r.push_str(&lets);
+    l!(r, "::core::result::Result::Ok({} {{ {} }})", name, unwraps);
+    r
+}
+
+pub fn derive_ser_toml_struct(struct_: &Struct) -> TokenStream {
+    let (generic_w_bounds, generic_no_bounds) = struct_bounds_strings(struct_, "SerToml");
+    let fields = ser_toml_fields(&struct_.fields, |name| format!("self.{name}"));
+
+    format!(
+        "
+        impl{} SerToml for {}{} {{
+            fn ser_toml(&self) -> nanoserde::Toml {{
+                let mut table = nanoserde::TomlTable::new();
+                {}
+                nanoserde::Toml::Table(table)
+            }}
+        }}
+    ",
+        generic_w_bounds,
+        struct_
+            .name
+            .as_ref()
+            .expect("Cannot implement for anonymous struct"),
+        generic_no_bounds,
+        fields
+    )
+    .parse()
+    .unwrap()
+}
+
+pub fn derive_de_toml_struct(struct_: &Struct) -> TokenStream {
+    let name = struct_
+        .name
+        .as_ref()
+        .expect("Cannot implement for anonymous struct");
+    let defaults = shared::attrs_default(&struct_.attributes).is_some()
+        || shared::attrs_default_with(&struct_.attributes).is_some();
+    let deny_unknown_fields = shared::attrs_deny_unknown_fields(&struct_.attributes);
+    let body = de_toml_named(name, defaults, deny_unknown_fields, &struct_.fields);
+    let (generic_w_bounds, generic_no_bounds) = struct_bounds_strings(struct_, "DeToml");
+
+    format!(
+        "impl{} DeToml for {}{} {{
+            fn de_toml(value: &nanoserde::Toml) -> ::core::result::Result<Self, nanoserde::DeTomlErr> {{
+                {}
+            }}
+        }}",
+        generic_w_bounds, name, generic_no_bounds, body
+    )
+    .parse()
+    .unwrap()
+}
+
+pub fn derive_ser_toml_struct_unnamed(struct_: &Struct) -> TokenStream {
+    let (generic_w_bounds, generic_no_bounds) = struct_bounds_strings(struct_, "SerToml");
+    let transparent = shared::attrs_transparent(&struct_.attributes);
+
+    let body = if struct_.fields.is_empty() {
+        "nanoserde::Toml::Table(nanoserde::TomlTable::new())".to_string()
+    }
+    // a transparent newtype struct is just its content
+    else if transparent && struct_.fields.len() == 1 {
+        "SerToml::ser_toml(&self.0)".to_string()
+    }
+    // otherwise an array
+    else {
+        let mut items = String::new();
+        for n in 0..struct_.fields.len() {
+            l!(items, "SerToml::ser_toml(&self.{}),", n);
+        }
+        format!("nanoserde::Toml::SimpleArray([{}].into())", items)
+    };
+
+    format!(
+        "
+        impl{} SerToml for {}{} {{
+            fn ser_toml(&self) -> nanoserde::Toml {{
+                {}
+            }}
+        }}",
+        generic_w_bounds,
+        struct_
+            .name
+            .as_ref()
+            .expect("Cannot implement for anonymous struct"),
+        generic_no_bounds,
+        body
+    )
+    .parse()
+    .unwrap()
+}
+
+/// Reads the items of the array in `value` into `name(..)`.
+fn de_toml_tuple(name: &str, len: usize) -> String {
+    let mut items = String::new();
+    for n in 0..len {
+        l!(
+            items,
+            "DeToml::de_toml(&items[{}]).map_err(|err| err.at_index({}))?,",
+            n,
+            n
+        );
+    }
+    format!(
+        "match value {{
+            nanoserde::Toml::SimpleArray(items) if items.len() == {len} => ::core::result::Result::Ok({name}({items})),
+            _ => ::core::result::Result::Err(nanoserde::DeTomlErr::err_type(\"array of {len}\", value)),
+        }}"
+    )
+}
+
+pub fn derive_de_toml_struct_unnamed(struct_: &Struct) -> TokenStream {
+    let (generic_w_bounds, generic_no_bounds) = struct_bounds_strings(struct_, "DeToml");
+    let transparent = shared::attrs_transparent(&struct_.attributes);
+
+    let body = if struct_.fields.is_empty() {
+        "::core::result::Result::Ok(Self)".to_string()
+    } else if transparent && struct_.fields.len() == 1 {
+        "::core::result::Result::Ok(Self(DeToml::de_toml(value)?))".to_string()
+    } else {
+        de_toml_tuple("Self", struct_.fields.len())
+    };
+
+    format!(
+        "impl{} DeToml for {}{} {{
+            fn de_toml(value: &nanoserde::Toml) -> ::core::result::Result<Self, nanoserde::DeTomlErr> {{
+                {}
+            }}
+        }}",
+        generic_w_bounds,
+        struct_
+            .name
+            .as_ref()
+            .expect("Cannot implement for anonymous struct"),
+        generic_no_bounds,
+        body
+    )
+    .parse()
+    .unwrap()
+}
+
+fn only_external_tags(enum_: &Enum) {
+    if enum_.attributes.iter().any(|attr| {
+        matches!(
+            attr.tokens.first().map(String::as_str),
+            Some("tag" | "content" | "untagged")
+        )
+    }) {
+        panic!("The TOML derives only support externally tagged enums");
+    }
+}
+
+/// Unit variants are strings, the others a table with the variant's name as its only key.
+pub fn derive_ser_toml_enum(enum_: &Enum) -> TokenStream {
+    only_external_tags(enum_);
+    let (generic_w_bounds, generic_no_bounds) = enum_bounds_strings(enum_, "SerToml");
+    let mut r = String::new();
+
+    for variant in &enum_.variants {
+        let ident = variant.field_name.clone().unwrap();
+        let toml_variant_name =
+            shared::attrs_rename(&variant.attributes).unwrap_or_else(|| ident.clone());
+
+        match variant_kind(&variant.ty) {
+            VariantKind::Unit => {
+                l!(
+                    r,
+                    "Self::{} => nanoserde::Toml::Str(\"{}\".into()),",
+                    ident,
+                    toml_variant_name
+                );
+            }
+            VariantKind::Struct(fields) => {
+                let names = fields
+                    .iter()
+                    .map(|field| field.field_name.clone().unwrap())
+                    .collect::<Vec<_>>();
+                let fields = ser_toml_fields(fields, |name| format!("(*{name})"));
+                l!(
+                    r,
+                    "#[allow(unused_variables)] Self::{} {{ {} }} => {{
+                        let mut table = nanoserde::TomlTable::new();
+                        {}
+                        nanoserde::Toml::Table(nanoserde::TomlTable::from([(\"{}\".into(), nanoserde::Toml::Table(table))]))
+                    }},",
+                    ident,
+                    names.join(","),
+                    fields,
+                    toml_variant_name
+                );
+            }
+            VariantKind::Tuple(len) => {
+                let names = (0..len).map(|n| format!("f{}", n)).collect::<Vec<_>>();
+                let content = if len == 1 {
+                    "SerToml::ser_toml(f0)".to_string()
+                } else {
+                    let items = names
+                        .iter()
+                        .map(|name| format!("SerToml::ser_toml({}),", name))
+                        .collect::<String>();
+                    format!("nanoserde::Toml::SimpleArray([{}].into())", items)
+                };
+                l!(
+                    r,
+                    "Self::{}({}) => nanoserde::Toml::Table(nanoserde::TomlTable::from([(\"{}\".into(), {})])),",
+                    ident,
+                    names.join(","),
+                    toml_variant_name,
+                    content
+                );
+            }
+        }
+    }
+
+    format!(
+        "
+        impl{} SerToml for {}{} {{
+            fn ser_toml(&self) -> nanoserde::Toml {{
+                match self {{
+                    {}
+                }}
+            }}
+        }}",
+        generic_w_bounds, enum_.name, generic_no_bounds, r
+    )
+    .parse()
+    .unwrap()
+}
+
+pub fn derive_de_toml_enum(enum_: &Enum) -> TokenStream {
+    only_external_tags(enum_);
+    let (generic_w_bounds, generic_no_bounds) = enum_bounds_strings(enum_, "DeToml");
+    let mut units = String::new();
+    let mut tables = String::new();
+
+    for variant in &enum_.variants {
+        let ident = variant.field_name.clone().unwrap();
+        let toml_variant_name =
+            shared::attrs_rename(&variant.attributes).unwrap_or_else(|| ident.clone());
+
+        match variant_kind(&variant.ty) {
+            VariantKind::Unit => {
+                l!(
+                    units,
+                    "\"{}\" => ::core::result::Result::Ok(Self::{}),",
+                    toml_variant_name,
+                    ident
+                );
+            }
+            VariantKind::Struct(fields) => {
+                let body = de_toml_named(&format!("Self::{}", ident), false, false, fields);
+                l!(tables, "\"{}\" => {{ {} }},", toml_variant_name, body);
+            }
+            VariantKind::Tuple(1) => {
+                l!(
+                    tables,
+                    "\"{}\" => ::core::result::Result::Ok(Self::{}(DeToml::de_toml(value)?)),",
+                    toml_variant_name,
+                    ident
+                );
+            }
+            VariantKind::Tuple(len) => {
+                let body = de_toml_tuple(&format!("Self::{}", ident), len);
+                l!(tables, "\"{}\" => {{ {} }},", toml_variant_name, body);
+            }
+        }
+    }
+
+    let mut r = String::new();
+    if !units.is_empty() {
+        l!(
+            r,
+            "nanoserde::Toml::Str(name) => match name.as_str() {{
+                {}
+                _ => ::core::result::Result::Err(nanoserde::DeTomlErr::err_enum(name)),
+            }},",
+            units
+        );
+    }
+    if !tables.is_empty() {
+        l!(
+            r,
+            "nanoserde::Toml::Table(table) if table.len() == 1 => {{
+                let (name, value) = table.iter().next().unwrap();
+                let variant: ::core::result::Result<Self, nanoserde::DeTomlErr> = match name.as_str() {{
+                    {}
+                    _ => ::core::result::Result::Err(nanoserde::DeTomlErr::err_enum(name)),
+                }};
+                variant.map_err(|err| err.in_field(name))
+            }},",
+            tables
+        );
+    }
+
+    format!(
+        "impl{} DeToml for {}{} {{
+            fn de_toml(value: &nanoserde::Toml) -> ::core::result::Result<Self, nanoserde::DeTomlErr> {{
+                match value {{
+                    {}
+                    _ => ::core::result::Result::Err(nanoserde::DeTomlErr::err_type(\"variant of {}\", value)),
+                }}
+            }}
+        }}",
+        generic_w_bounds, enum_.name, generic_no_bounds, r, enum_.name
+    )
+    .parse()
+    .unwrap()
+}
@@ -4,9 +4,12 @@ use alloc::{
     vec::Vec,
 };
 
-#[cfg(any(feature = "binary", feature = "json"))]
+#[cfg(any(feature = "binary", feature = "json", feature = "toml"))]
 use crate::parse::{Enum, Struct};
 
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+use crate::parse::{Category, Field, Type};
+
 macro_rules! l {
     ($target:ident, $line:expr) => {
         $target.push_str($line)
@@ -27,7 +30,7 @@ pub fn attrs_proxy(attributes: &[crate::parse::Attribute]) -> Option<String> {
     })
 }
 
-#[cfg(any(feature = "ron", feature = "json"))]
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
 pub fn attrs_rename(attributes: &[crate::parse::Attribute]) -> Option<String> {
     attributes.iter().find_map(|attr| {
         if attr.tokens.len() == 2 && attr.tokens[0] == "rename" {
@@ -38,7 +41,7 @@ pub fn attrs_rename(attributes: &[crate::parse::Attribute]) -> Option<String> {
     })
 }
 
-#[cfg(any(feature = "ron", feature = "json"))]
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
 pub fn attrs_default(attributes: &[crate::parse::Attribute]) -> Option<Option<String>> {
     attributes.iter().find_map(|attr| {
         if attr.tokens.len() == 1 && attr.tokens[0] == "default" {
@@ -51,7 +54,7 @@ pub fn attrs_default(attributes: &[crate::parse::Attribute]) -> Option<Option<St
     })
 }
 
-#[cfg(any(feature = "ron", feature = "json"))]
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
 pub fn attrs_default_with(attributes: &[crate::parse::Attribute]) -> Option<String> {
     attributes.iter().find_map(|attr| {
         if attr.tokens.len() == 2 && attr.tokens[0] == "default_with" {
@@ -62,21 +65,129 @@ pub fn attrs_default_with(attributes: &[crate::parse::Attribute]) -> Option<Stri
     })
 }
 
-#[cfg(feature = "json")]
+#[cfg(any(feature = "json", feature = "toml"))]
 pub fn attrs_transparent(attributes: &[crate::parse::Attribute]) -> bool {
     attributes
         .iter()
         .any(|attr| attr.tokens.len() == 1 && attr.tokens[0] == "transparent")
 }
 
-#[cfg(feature = "json")]
+#[cfg(any(feature = "json", feature = "toml"))]
+pub fn attrs_deny_unknown_fields(attributes: &[crate::parse::Attribute]) -> bool {
+    attributes
+        .iter()
+        .any(|attr| attr.tokens.len() == 1 && attr.tokens[0] == "deny_unknown_fields")
+}
+
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+pub fn attrs_flatten(attributes: &[crate::parse::Attribute]) -> bool {
+    attributes
+        .iter()
+        .any(|attr| attr.tokens.len() == 1 && attr.tokens[0] == "flatten")
+}
+
+/// How enum variants are told apart, serde's enum representations.
+#[cfg(any(feature = "ron", feature = "json"))]
+pub enum EnumTag {
+    /// `{"Variant": {..}}`, the default.
+    External,
+    /// `#[nserde(tag = "type")]`: `{"type": "Variant", ..}`.
+    Internal(String),
+    /// `#[nserde(tag = "t", content = "c")]`: `{"t": "Variant", "c": {..}}`.
+    Adjacent(String, String),
+    /// `#[nserde(untagged)]`: `{..}`, the first variant that parses.
+    Untagged,
+}
+
+#[cfg(any(feature = "ron", feature = "json"))]
+pub fn attrs_enum_tag(attributes: &[crate::parse::Attribute]) -> EnumTag {
+    let value = |name: &str| {
+        attributes.iter().find_map(|attr| {
+            if attr.tokens.len() == 2 && attr.tokens[0] == name {
+                Some(attr.tokens[1].clone())
+            } else {
+                None
+            }
+        })
+    };
+    let untagged = attributes
+        .iter()
+        .any(|attr| attr.tokens.len() == 1 && attr.tokens[0] == "untagged");
+
+    match (value("tag"), value("content")) {
+        _ if untagged => EnumTag::Untagged,
+        (Some(tag), Some(content)) => EnumTag::Adjacent(tag, content),
+        (Some(tag), None) => EnumTag::Internal(tag),
+        (None, Some(_)) => panic!("#[nserde(content)] needs #[nserde(tag)] as well"),
+        (None, None) => EnumTag::External,
+    }
+}
+
+/// Flattened maps take all the fields left, so they go after the flattened structs.
+#[cfg(any(feature = "ron", feature = "json"))]
+pub fn is_map(ty: &crate::parse::Type) -> bool {
+    let base = ty.base();
+    base.ends_with("HashMap") || base.ends_with("BTreeMap")
+}
+
+#[cfg(any(feature = "json", feature = "toml"))]
 pub fn attrs_skip(attributes: &[crate::parse::Attribute]) -> bool {
     attributes
         .iter()
         .any(|attr| attr.tokens.len() == 1 && attr.tokens[0] == "skip")
 }
 
-#[cfg(any(feature = "binary", feature = "json"))]
+/// The expression a missing field defaults to, from `#[nserde(default)]`,
+/// `#[nserde(default = "..")]` or `#[nserde(default_with = "..")]`.
+#[cfg(any(feature = "json", feature = "toml"))]
+pub(crate) fn attrs_default_value(field: &Field) -> Option<String> {
+    if let Some(v) = attrs_default(&field.attributes) {
+        if let Some(mut val) = v {
+            if field.ty.base() == "String"
+                || field
+                    .ty
+                    .wraps
+                    .as_ref()
+                    .is_some_and(|wrapped| wrapped.iter().any(|ty| ty.base() == "String"))
+            {
+                val = format!("\"{}\".to_string()", val)
+            }
+            if field.ty.base() == "Option" {
+                val = format!("Some({})", val);
+            }
+            Some(val)
+        } else {
+            if field.ty.base() != "Option" {
+                Some(String::from("Default::default()"))
+            } else {
+                Some(String::from("None"))
+            }
+        }
+    } else if let Some(mut v) = attrs_default_with(&field.attributes) {
+        v.push_str("()");
+        Some(v)
+    } else {
+        None
+    }
+}
+
+/// The expression a field is serialized from, through its `#[nserde(proxy = "..")]` if it has one.
+#[cfg(any(feature = "json", feature = "toml"))]
+pub(crate) fn ser_proxy_guard(fieldname: &str, field: &Field) -> String {
+    if let Some(proxy) = attrs_proxy(&field.attributes) {
+        if field.ty.base() == "Option" {
+            format!(
+                "{{{fieldname}.as_ref().map(|f| {{let proxy: {proxy} = Into::into(f);proxy}})}}"
+            )
+        } else {
+            format!("{{let proxy: {proxy} = Into::into(&{fieldname});proxy}}",)
+        }
+    } else {
+        fieldname.to_string()
+    }
+}
+
+#[cfg(any(feature = "binary", feature = "json", feature = "toml"))]
 pub(crate) fn struct_bounds_strings(struct_: &Struct, bound_name: &str) -> (String, String) {
     let generics: &Vec<_> = &struct_.generics;
 
@@ -101,7 +212,7 @@ pub(crate) fn struct_bounds_strings(struct_: &Struct, bound_name: &str) -> (Stri
     return (generic_w_bounds, generic_no_bounds);
 }
 
-#[cfg(any(feature = "binary", feature = "json"))]
+#[cfg(any(feature = "binary", feature = "json", feature = "toml"))]
 pub(crate) fn enum_bounds_strings(enum_: &Enum, bound_name: &str) -> (String, String) {
     let generics: &Vec<_> = &enum_.generics;
 
@@ -125,3 +236,32 @@ pub(crate) fn enum_bounds_strings(enum_: &Enum, bound_name: &str) -> (String, St
     generic_no_bounds += ">";
     return (generic_w_bounds, generic_no_bounds);
 }
+
+/// The shape of a variant, as the tagged enum derives see it.
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+#[derive(Clone, Copy)]
+pub(crate) enum VariantKind<'a> {
+    Unit,
+    Struct(&'a [Field]),
+    Tuple(usize),
+}
+
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+pub(crate) fn variant_kind(ty: &Type) -> VariantKind<'_> {
+    match ty {
+        Type {
+            wraps: None,
+            ident: Category::None,
+            ..
+        } => VariantKind::Unit,
+        Type {
+            ident: Category::AnonymousStruct { contents },
+            ..
+        } => VariantKind::Struct(&contents.fields),
+        Type {
+            ident: Category::Tuple { contents },
+            ..
+        } => VariantKind::Tuple(contents.len()),
+        v => unimplemented!("Unexpected type in enum: {:?}", v),
+    }
+}
@@ -42,6 +42,20 @@ impl SerRonState {
         self.indent(d);
         self.out.push(')');
     }
+
+    /// Write the fields of `value`'s struct into the struct being written, for `#[nserde(flatten)]`.
+    /// Values that aren't structs write nothing.
+    pub fn flatten<T: SerRon + ?Sized>(&mut self, d: usize, value: &T) {
+        let mut state = SerRonState { out: String::new() };
+        value.ser_ron(d, &mut state);
+        if let Some(fields) = state
+            .out
+            .strip_prefix("(\n")
+            .and_then(|out| out.trim_end_matches(' ').strip_suffix(')'))
+        {
+            self.out.push_str(fields.trim_end_matches(' '));
+        }
+    }
 }
 
 /// A trait for objects that can be serialized to the RON file format.
@@ -68,6 +82,9 @@ pub trait SerRon {
     fn ser_ron(&self, indent_level: usize, state: &mut SerRonState);
 }
 
+/// The fields of a struct that were read ahead, as their names and the source text of their values.
+pub type RonFields = Vec<(String, String)>;
+
 /// A trait for objects that can be deserialized from the RON file format.
 ///
 /// [Specification](https://github.com/ron-rs/ron).
@@ -95,6 +112,24 @@ pub trait DeRon: Sized {
     /// assert_eq!(out, 42);
     /// ```
     fn de_ron(state: &mut DeRonState, input: &mut Chars) -> Result<Self, DeRonErr>;
+
+    /// Parse Self out of the fields of the struct it's flattened into with `#[nserde(flatten)]`,
+    /// taking the fields it uses out of `fields`, the names and the source text of the values.
+    ///
+    /// Takes all the fields as a map by default, derived structs only take their own.
+    fn de_ron_flat(state: &DeRonState, fields: &mut RonFields) -> Result<Self, DeRonErr> {
+        let mut map = String::from("{");
+        for (name, value) in fields.drain(..) {
+            let mut key = SerRonState { out: String::new() };
+            name.ser_ron(0, &mut key);
+            map.push_str(&key.out);
+            map.push(':');
+            map.push_str(&value);
+            map.push(',');
+        }
+        map.push('}');
+        state.de_buffered(&map, |s, i| DeRon::de_ron(s, i))
+    }
 }
 
 /// A RON parsed token.
@@ -236,6 +271,134 @@ impl DeRonState {
         }
     }
 
+    /// Read over the current value without parsing it and return its source text,
+    /// for the values that have to be read ahead, like untagged enums.
+    pub fn capture_value(&mut self, i: &mut Chars) -> Result<String, DeRonErr> {
+        let mut out = match self.tok {
+            DeRonTok::Ident => self.identbuf.clone(),
+            DeRonTok::Str => {
+                let mut s = SerRonState { out: String::new() };
+                self.strbuf.ser_ron(0, &mut s);
+                s.out
+            }
+            DeRonTok::U64(_) | DeRonTok::I64(_) | DeRonTok::F64(_) => self.numbuf.clone(),
+            DeRonTok::Bool(value) => value.to_string(),
+            DeRonTok::Char(c @ ('\'' | '\\')) => format!("'\\{}'", c),
+            DeRonTok::Char(c) => format!("'{}'", c),
+            DeRonTok::ParenOpen => "(".to_string(),
+            DeRonTok::BlockOpen => "[".to_string(),
+            DeRonTok::CurlyOpen => "{".to_string(),
+            _ => return Err(self.err_token("value")),
+        };
+        // the text after the current token, the tokenizer already took its next char
+        let start = self.cur;
+        let rest = i.as_str();
+
+        if self.tok == DeRonTok::Ident {
+            // `Some(..)` or a variant with fields
+            self.next_tok(i)?;
+            if self.tok != DeRonTok::ParenOpen {
+                return Ok(out);
+            }
+        } else if let DeRonTok::ParenOpen | DeRonTok::BlockOpen | DeRonTok::CurlyOpen = self.tok {
+        } else {
+            self.next_tok(i)?;
+            return Ok(out);
+        }
+
+        let mut depth = 0;
+        loop {
+            match self.tok {
+                DeRonTok::ParenOpen | DeRonTok::BlockOpen | DeRonTok::CurlyOpen => depth += 1,
+                DeRonTok::ParenClose | DeRonTok::BlockClose | DeRonTok::CurlyClose => depth -= 1,
+                DeRonTok::Eof => return Err(self.err_token("end of the value")),
+                _ => (),
+            }
+            if depth == 0 {
+                break;
+            }
+            self.next_tok(i)?;
+        }
+        let text = &rest[..rest.len() - i.as_str().len()];
+        let text = if self.cur != '\0' {
+            &text[..text.len() - self.cur.len_utf8()]
+        } else {
+            text
+        };
+        if start != '\0' {
+            out.push(start);
+        }
+        out.push_str(text);
+        self.next_tok(i)?;
+        Ok(out)
+    }
+
+    /// Read over the current struct, `(name: value, ..)`, returning the names and the source
+    /// text of its fields.
+    pub fn capture_fields(&mut self, i: &mut Chars) -> Result<RonFields, DeRonErr> {
+        let mut fields = Vec::new();
+        self.paren_open(i)?;
+        while self.next_ident().is_some() {
+            let name = self.identbuf.clone();
+            self.next_colon(i)?;
+            fields.push((name, self.capture_value(i)?));
+            self.eat_comma_paren(i)?;
+        }
+        self.paren_close(i)?;
+        Ok(fields)
+    }
+
+    /// Remove the named fields out of the captured ones.
+    pub fn take_fields(fields: &mut RonFields, names: &[&str]) -> RonFields {
+        let mut taken = Vec::new();
+        fields.retain(|field| {
+            if names.contains(&field.0.as_str()) {
+                taken.push(field.clone());
+                false
+            } else {
+                true
+            }
+        });
+        taken
+    }
+
+    /// The source text of a struct with the captured fields.
+    pub fn fields_text(fields: &[(String, String)]) -> String {
+        let mut out = String::from("(");
+        for (name, value) in fields {
+            out.push_str(name);
+            out.push(':');
+            out.push_str(value);
+            out.push(',');
+        }
+        out.push(')');
+        out
+    }
+
+    /// Parse a value that had to be read ahead, like the struct of an internally tagged enum,
+    /// out of its source text. Errors are reported at the current position.
+    pub fn de_buffered<T>(
+        &self,
+        input: &str,
+        f: impl FnOnce(&mut DeRonState, &mut Chars) -> Result<T, DeRonErr>,
+    ) -> Result<T, DeRonErr> {
+        let mut state = DeRonState::default();
+        let mut chars = input.chars();
+        state.next(&mut chars);
+        let result = state
+            .next_tok(&mut chars)
+            .and_then(|_| f(&mut state, &mut chars));
+        let result = match result {
+            Ok(_) if state.tok != DeRonTok::Eof => Err(state.err_token("end of the value")),
+            result => result,
+        };
+        result.map_err(|err| DeRonErr {
+            msg: err.msg,
+            line: self.line,
+            col: self.col,
+        })
+    }
+
     pub fn eat_comma_paren(&mut self, i: &mut Chars) -> Result<(), DeRonErr> {
         match self.tok {
             DeRonTok::Comma => {
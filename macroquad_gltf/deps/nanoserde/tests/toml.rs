@@ -3,8 +3,7 @@
 extern crate alloc;
 
 use alloc::collections::BTreeMap;
-use nanoserde::Toml;
-use nanoserde::TomlParser;
+use nanoserde::{DeToml, SerToml, Toml, TomlDatetime, TomlParser, TomlTime};
 
 #[test]
 fn de_toml() {
@@ -64,12 +63,16 @@ fn key_without_value() {
 fn assert_specific_toml_types() {
     let data = r#"
     num = 3.14
+    exp = -2.5e-3
+    big = 1E6
     str = "quoth the raven"
     simple_arr = [1, 2, 3, 4]
     boolean = false
     date = 1979-05-27
     "#;
     assert_eq!(TomlParser::parse(data).unwrap()["num"].num(), 3.14);
+    assert_eq!(TomlParser::parse(data).unwrap()["exp"].num(), -2.5e-3);
+    assert_eq!(TomlParser::parse(data).unwrap()["big"].num(), 1e6);
     assert_eq!(
         TomlParser::parse(data).unwrap()["str"].str(),
         "quoth the raven"
@@ -113,3 +116,194 @@ fn toml_key_chars() {
         ])
     );
 }
+
+#[test]
+fn inline_tables() {
+    let data = r#"
+    point = { x = 1, y = 2, "with space" = "a\tb" }
+    empty = {}
+    nested = { inner = { flag = true }, list = [1, 2] }
+    "#;
+
+    let toml = TomlParser::parse(data).unwrap();
+    let point = toml["point"].as_table().unwrap();
+    assert_eq!(point["x"], Toml::Num(1.));
+    assert_eq!(point["y"], Toml::Num(2.));
+    assert_eq!(point["with space"].str(), "a\tb");
+    assert_eq!(toml["empty"], Toml::Table(BTreeMap::new()));
+    let nested = toml["nested"].as_table().unwrap();
+    assert_eq!(
+        nested["inner"].as_table().unwrap()["flag"],
+        Toml::Bool(true)
+    );
+    assert_eq!(nested["list"].simple_arr().len(), 2);
+}
+
+#[test]
+fn datetimes() {
+    let data = r#"
+    offset = 1979-05-27T07:32:00Z
+    offset_fraction = 1979-05-27 00:32:00.999999-07:00
+    local = 1979-05-27T07:32:00
+    date = 1979-05-27
+    time = 00:32:00.5
+    "#;
+
+    let toml = TomlParser::parse(data).unwrap();
+    let datetime = |key: &str| toml[key].date().parse::<TomlDatetime>().unwrap();
+
+    assert_eq!(datetime("offset").offset, Some(0));
+    assert_eq!(datetime("offset").to_string(), "1979-05-27T07:32:00Z");
+    let fraction = datetime("offset_fraction");
+    assert_eq!(fraction.time.unwrap().nanosecond, 999_999_000);
+    assert_eq!(fraction.offset, Some(-7 * 60));
+    assert_eq!(fraction.to_string(), "1979-05-27T00:32:00.999999-07:00");
+    assert_eq!(datetime("local").offset, None);
+    assert!(datetime("date").time.is_none());
+    assert_eq!(
+        datetime("time").time,
+        Some(TomlTime {
+            hour: 0,
+            minute: 32,
+            second: 0,
+            nanosecond: 500_000_000
+        })
+    );
+    assert!("1979-13-27".parse::<TomlDatetime>().is_err());
+    assert!("07:32:00Z".parse::<TomlDatetime>().is_err());
+}
+
+#[test]
+fn derive_round_trip() {
+    #[derive(DeToml, SerToml, PartialEq, Debug)]
+    struct Config {
+        title: String,
+        #[nserde(rename = "release-date")]
+        released: TomlDatetime,
+        window: Window,
+        plugins: Vec<Plugin>,
+        tags: Vec<String>,
+        #[nserde(default = "0.5")]
+        volume: f32,
+        theme: Option<String>,
+        #[nserde(skip)]
+        cache: Vec<u8>,
+    }
+
+    #[derive(DeToml, SerToml, PartialEq, Debug)]
+    struct Window {
+        size: Size,
+        position: Option<[i32; 2]>,
+        mode: Mode,
+    }
+
+    #[derive(DeToml, SerToml, PartialEq, Debug)]
+    struct Size(u32, u32);
+
+    #[derive(DeToml, SerToml, PartialEq, Debug)]
+    enum Mode {
+        Windowed,
+        Fullscreen { monitor: u8 },
+    }
+
+    #[derive(DeToml, SerToml, PartialEq, Debug)]
+    struct Plugin {
+        name: String,
+        #[nserde(default)]
+        enabled: bool,
+        settings: BTreeMap<String, i64>,
+    }
+
+    let data = r#"
+    title = "Helmet viewer"
+    release-date = 2024-03-01T10:00:00+01:00
+    tags = ["gltf", "pbr"]
+
+    [window]
+    size = [1280, 720]
+    mode = { Fullscreen = { monitor = 1 } }
+
+    [[plugins]]
+    name = "shadows"
+    enabled = true
+    settings = { cascades = 4, resolution = 2048 }
+
+    [[plugins]]
+    name = "ssao"
+
+    [plugins.settings]
+    samples = 16
+    "#;
+
+    let config: Config = DeToml::deserialize_toml(data).unwrap();
+    assert_eq!(config.title, "Helmet viewer");
+    assert_eq!(config.released.offset, Some(60));
+    assert_eq!(config.window.size, Size(1280, 720));
+    assert_eq!(config.window.position, None);
+    assert_eq!(config.window.mode, Mode::Fullscreen { monitor: 1 });
+    assert_eq!(config.plugins.len(), 2);
+    assert!(config.plugins[0].enabled);
+    assert_eq!(config.plugins[0].settings["resolution"], 2048);
+    assert!(!config.plugins[1].enabled);
+    assert_eq!(config.plugins[1].settings["samples"], 16);
+    assert_eq!(config.tags, vec!["gltf", "pbr"]);
+    assert_eq!(config.volume, 0.5);
+    assert_eq!(config.theme, None);
+
+    let config = Config {
+        window: Window {
+            position: Some([10, -20]),
+            mode: Mode::Windowed,
+            ..config.window
+        },
+        theme: Some("dark \"blue\"".to_string()),
+        cache: vec![1, 2, 3],
+        ..config
+    };
+    let serialized = config.serialize_toml();
+    let deserialized: Config = DeToml::deserialize_toml(&serialized).unwrap();
+    assert_eq!(
+        deserialized,
+        Config {
+            cache: vec![],
+            ..config
+        }
+    );
+    assert!(serialized.contains("[[plugins]]"));
+    assert!(serialized.contains("[window]"));
+}
+
+#[test]
+fn derive_errors() {
+    #[derive(DeToml, Debug)]
+    #[nserde(deny_unknown_fields)]
+    struct Material {
+        name: String,
+        factors: Vec<f32>,
+        textures: Vec<Texture>,
+    }
+
+    #[derive(DeToml, Debug)]
+    struct Texture {
+        index: u8,
+    }
+
+    let err = Material::deserialize_toml("name = 1").unwrap_err();
+    assert_eq!(err.path, "name");
+    assert_eq!(err.msg, "Expected string, found number");
+
+    let err = Material::deserialize_toml("name = \"a\"\nfactors = [1, \"2\"]").unwrap_err();
+    assert_eq!(err.path, "factors[1]");
+
+    let data = "name = \"a\"\nfactors = []\n[[textures]]\nindex = 1\n[[textures]]\nindex = 300";
+    let err = Material::deserialize_toml(data).unwrap_err();
+    assert_eq!(err.path, "textures[1].index");
+    assert_eq!(err.msg, "Value out of range for u8");
+
+    let err = Material::deserialize_toml("name = \"a\"\nfactors = []").unwrap_err();
+    assert_eq!(err.msg, "Key not found textures");
+
+    let err = Material::deserialize_toml("nmae = \"a\"").unwrap_err();
+    assert_eq!(err.msg, "Unexpected key nmae, did you mean name?");
+    assert_eq!(err.path, "nmae");
+}
@@ -1,6 +1,13 @@
 //! 2D and 3D camera.
 
-use crate::{color::Color, cubemap::Cubemap, material::Material, texture::RenderTarget};
+use crate::{
+    color::Color,
+    cubemap::Cubemap,
+    post_processing::PostEffect,
+    prepass::Ssao,
+    scene::{raycast::Ray, Sphere},
+    texture::RenderTarget,
+};
 use glam::{vec2, vec3, Mat4, Vec2, Vec3};
 
 #[derive(Clone, Debug)]
@@ -41,9 +48,45 @@ pub struct Camera {
     pub environment: Environment,
     pub z_near: f32,
     pub z_far: f32,
+
+    /// Effects applied in order to the scene drawn in HDR, the last one
+    /// drawing into `render_target` or the screen. Empty draws the scene directly.
+    pub post_processing: Vec<PostEffect>,
+    /// Draw the depth and normals of the opaque geometry before the main pass,
+    /// for the shaders reading them. See the `prepass` module.
+    pub prepass: bool,
+    /// Darken the ambient light in creases, needs the prepass and enables it.
+    pub ssao: Option<Ssao>,
 }
 
 impl Camera {
+    /// Move the camera along its view direction, so the whole sphere is in the view,
+    /// and look at the sphere's center. `z_far` is extended if the sphere doesn't fit.
+    pub fn frame(&mut self, sphere: Sphere) {
+        let direction = (self.target - self.position).normalize_or_zero();
+        let direction = if direction == Vec3::ZERO {
+            -Vec3::Z
+        } else {
+            direction
+        };
+        let (w, h) = miniquad::window::screen_size();
+        let aspect = self.aspect.unwrap_or(w / h);
+        let distance = match self.projection {
+            Projection::Perspective => {
+                // the narrowest of the vertical and the horizontal half-angles
+                let tan = (self.fovy / 2.0).tan().abs() * aspect.min(1.0);
+                sphere.radius * (1.0 + 1.0 / (tan * tan)).sqrt()
+            }
+            Projection::Orthographic => {
+                self.fovy = sphere.radius * 2.0 / aspect.min(1.0);
+                sphere.radius + self.z_near
+            }
+        };
+        self.target = sphere.center;
+        self.position = sphere.center - direction * distance;
+        self.z_far = self.z_far.max(distance + sphere.radius);
+    }
+
     pub fn proj_view(&self) -> (Mat4, Mat4) {
         //     // gleaned from https://github.com/raysan5/raylib/blob/master/src/core.c#L1528
 
@@ -90,6 +133,26 @@ impl Camera {
             }
         }
     }
+
+    /// Ray from the camera through the point in window pixels, like the mouse position.
+    /// With a viewport, the point is mapped into it, the viewport's origin being
+    /// at the bottom left, like in `apply_viewport`.
+    pub fn screen_to_ray(&self, point: Vec2) -> Ray {
+        let (w, h) = miniquad::window::screen_size();
+        let (x, y, width, height) = self.viewport.map_or((0.0, 0.0, w, h), |(x, y, w, h)| {
+            (x as f32, y as f32, w as f32, h as f32)
+        });
+        let ndc = vec2(
+            (point.x - x) / width * 2.0 - 1.0,
+            (h - point.y - y) / height * 2.0 - 1.0,
+        );
+
+        let (proj, view) = self.proj_view();
+        let inverse = (proj * view).inverse();
+        let near = inverse.project_point3(ndc.extend(-1.0));
+        let far = inverse.project_point3(ndc.extend(1.0));
+        Ray::new(near, far - near)
+    }
 }
 
 //     pub fn fixed_height(height: f32) -> Camera {
@@ -151,6 +214,9 @@ impl Default for Camera {
             z_near: 3.0,
             aspect: None,
             fovy: 45.,
+            post_processing: vec![],
+            prepass: false,
+            ssao: None,
         }
     }
 }
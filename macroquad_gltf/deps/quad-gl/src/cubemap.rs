@@ -4,6 +4,8 @@ use glam::{vec3, Mat3, Mat4};
 
 use crate::image;
 
+pub mod ibl;
+
 #[derive(Clone, Debug)]
 pub struct Cubemap {
     display_pipeline: Pipeline,
@@ -11,30 +13,98 @@ pub struct Cubemap {
     rx: f32,
     ry: f32,
     pub texture: TextureId,
+    /// Ambient lighting computed from this cubemap, used by the scene shader.
+    pub ibl: ibl::Ibl,
+}
+
+/// Linear floats of a Radiance .hdr or of any image `image::decode` supports.
+fn decode_linear(
+    bytes: &[u8],
+) -> Result<(usize, usize, Vec<[f32; 3]>), Box<dyn std::error::Error>> {
+    let (width, height, data) = if image::is_hdr(bytes) {
+        let image = image::decode_hdr(bytes)?;
+        (image.width, image.height, image.data)
+    } else {
+        let image = image::decode(bytes)?;
+        let data = image
+            .data
+            .chunks(4)
+            .map(|c| {
+                [
+                    c[0] as f32 / 255.0,
+                    c[1] as f32 / 255.0,
+                    c[2] as f32 / 255.0,
+                ]
+            })
+            .collect();
+        (image.width, image.height, data)
+    };
+    if width == 0 || height == 0 {
+        return Err("Empty cubemap image".into());
+    }
+    Ok((width, height, data))
 }
 
 impl Cubemap {
-    pub fn new(ctx: &mut dyn RenderingBackend, bytes: &[&[u8]]) -> Cubemap {
-        let texture0 = image::decode(&bytes[0]).unwrap();
-        let texture1 = image::decode(&bytes[1]).unwrap();
-        let texture2 = image::decode(&bytes[2]).unwrap();
-        let texture3 = image::decode(&bytes[3]).unwrap();
-        let texture4 = image::decode(&bytes[4]).unwrap();
-        let texture5 = image::decode(&bytes[5]).unwrap();
+    /// Six square images of the same size, the faces in the +X, -X, +Y, -Y, +Z, -Z order,
+    /// .hdr files included.
+    ///
+    /// The background is clamped to the displayable range, while the lighting is computed
+    /// from the full range of the .hdr values.
+    pub fn new(
+        ctx: &mut dyn RenderingBackend,
+        bytes: &[&[u8]],
+    ) -> Result<Cubemap, Box<dyn std::error::Error>> {
+        if bytes.len() != 6 {
+            return Err(format!("A cubemap needs 6 faces, got {}", bytes.len()).into());
+        }
+        let faces = bytes
+            .iter()
+            .map(|bytes| decode_linear(bytes))
+            .collect::<Result<Vec<_>, _>>()?;
+        let size = faces[0].0;
+        for (i, (width, height, _)) in faces.iter().enumerate() {
+            if *width != size || *height != size {
+                return Err(format!(
+                    "Cubemap face {} is {}x{}, all the faces must be {}x{}",
+                    i, width, height, size, size
+                )
+                .into());
+            }
+        }
+        let cpu = ibl::CpuCubemap::from_faces(size, std::array::from_fn(|i| &faces[i].2[..]));
+
+        Ok(Self::from_cpu(ctx, &cpu))
+    }
+
+    /// A single latitude-longitude panorama, .hdr or a regular image.
+    pub fn from_equirectangular(
+        ctx: &mut dyn RenderingBackend,
+        bytes: &[u8],
+    ) -> Result<Cubemap, Box<dyn std::error::Error>> {
+        let (width, height, data) = decode_linear(bytes)?;
+        let size = (width / 4).clamp(1, 1024);
+        let cpu = ibl::CpuCubemap::from_equirectangular(width, height, &data, size);
+
+        Ok(Self::from_cpu(ctx, &cpu))
+    }
+
+    fn from_cpu(ctx: &mut dyn RenderingBackend, cpu: &ibl::CpuCubemap) -> Cubemap {
+        let faces = cpu.to_rgba8();
         let color_img = ctx.new_texture(
             TextureAccess::Static,
             TextureSource::Array(&[
-                &[&texture0.data],
-                &[&texture1.data],
-                &[&texture2.data],
-                &[&texture3.data],
-                &[&texture4.data],
-                &[&texture5.data],
+                &[&faces[0]],
+                &[&faces[1]],
+                &[&faces[2]],
+                &[&faces[3]],
+                &[&faces[4]],
+                &[&faces[5]],
             ]),
             TextureParams {
                 kind: TextureKind::CubeMap,
-                width: texture0.width as _,
-                height: texture0.height as _,
+                width: cpu.size as _,
+                height: cpu.size as _,
                 format: TextureFormat::RGBA8,
                 ..Default::default()
             },
@@ -106,7 +176,7 @@ impl Cubemap {
         let default_shader = ctx
             .new_shader(
                 match info.backend {
-                    Backend::OpenGl => ShaderSource::Glsl {
+                    Backend::OpenGl | Backend::Software => ShaderSource::Glsl {
                         vertex: display_shader::VERTEX,
                         fragment: display_shader::FRAGMENT,
                     },
@@ -146,12 +216,21 @@ impl Cubemap {
             rx: 0.,
             ry: 0.,
             texture: color_img,
+            ibl: ibl::Ibl::new(ctx, cpu),
         }
     }
 }
 
 impl Cubemap {
-    pub fn draw(&self, ctx: &mut dyn RenderingBackend, proj: &Mat4, view: &Mat4) {
+    /// Draw into the pass, the screen if `None`, clearing it first with the action.
+    pub fn draw(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        pass: Option<RenderPass>,
+        action: PassAction,
+        proj: &Mat4,
+        view: &Mat4,
+    ) {
         let (width, height) = window::screen_size();
 
         let view_proj = *proj * Mat4::from_mat3(Mat3::from_mat4(*view));
@@ -160,7 +239,7 @@ impl Cubemap {
 
         let vs_params = display_shader::Uniforms { mvp: view_proj };
 
-        ctx.begin_default_pass(PassAction::Nothing);
+        ctx.begin_pass(pass, action);
         ctx.apply_pipeline(&self.display_pipeline);
         ctx.apply_bindings(&self.display_bind);
         ctx.apply_uniforms(UniformsSource::table(&vs_params));
@@ -254,3 +333,46 @@ mod display_shader {
         pub mvp: glam::Mat4,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Flat Radiance .hdr of a single color.
+    fn hdr(width: usize, height: usize, rgbe: [u8; 4]) -> Vec<u8> {
+        let mut bytes = format!(
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+            height, width
+        )
+        .into_bytes();
+        for _ in 0..width * height {
+            bytes.extend(rgbe);
+        }
+        bytes
+    }
+
+    #[test]
+    fn cubemap_faces() {
+        let mut ctx = SoftwareContext::new(1, 1);
+        let face = hdr(2, 2, [128, 128, 128, 131]);
+        let faces = [&face[..]; 6];
+        assert!(Cubemap::new(&mut ctx, &faces).is_ok());
+
+        assert!(Cubemap::new(&mut ctx, &faces[..5]).is_err());
+
+        let not_square = hdr(2, 1, [128, 128, 128, 131]);
+        let mut faces = [&face[..]; 6];
+        faces[3] = &not_square;
+        assert!(Cubemap::new(&mut ctx, &faces).is_err());
+
+        let smaller = hdr(1, 1, [128, 128, 128, 131]);
+        faces[3] = &smaller;
+        assert!(Cubemap::new(&mut ctx, &faces).is_err());
+
+        faces[3] = b"not an image";
+        assert!(Cubemap::new(&mut ctx, &faces).is_err());
+
+        assert!(Cubemap::from_equirectangular(&mut ctx, &hdr(8, 4, [128, 128, 128, 131])).is_ok());
+        assert!(Cubemap::from_equirectangular(&mut ctx, &face[..face.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,434 @@
+//! Image based lighting: the environment cubemap convolved on the CPU into
+//! a diffuse irradiance cubemap, a specular cubemap prefiltered for each roughness
+//! in its mip levels, and the split-sum BRDF lookup table.
+//!
+//! Everything is computed in floats and the cubemaps are stored in half float textures,
+//! so bright .hdr sources light the scene properly.
+
+use miniquad::*;
+
+use glam::{vec2, vec3, Vec2, Vec3};
+
+use std::f32::consts::PI;
+
+/// Face size of the specular cubemap's first, mirror-like, mip level.
+pub const SPECULAR_SIZE: usize = 64;
+/// Mip levels of the specular cubemap, from roughness 0 to roughness 1.
+/// The default fragment shader relies on it being 5.
+pub const SPECULAR_LEVELS: usize = 5;
+pub const IRRADIANCE_SIZE: usize = 16;
+pub const BRDF_LUT_SIZE: usize = 32;
+
+const SAMPLES: u32 = 64;
+const BRDF_SAMPLES: u32 = 128;
+
+/// Textures used by the default scene shader for the ambient lighting.
+#[derive(Clone, Debug)]
+pub struct Ibl {
+    pub irradiance: TextureId,
+    pub specular: TextureId,
+    pub brdf_lut: TextureId,
+}
+
+impl Ibl {
+    pub(crate) fn new(ctx: &mut dyn RenderingBackend, environment: &CpuCubemap) -> Ibl {
+        let base = environment.resized(environment.size.min(128));
+        let mut chain = vec![base];
+        while chain.last().unwrap().size > 1 {
+            let next = chain
+                .last()
+                .unwrap()
+                .resized(chain.last().unwrap().size / 2);
+            chain.push(next);
+        }
+
+        let irradiance = irradiance(&chain);
+        let specular = (0..SPECULAR_LEVELS)
+            .map(|level| {
+                let roughness = level as f32 / (SPECULAR_LEVELS - 1) as f32;
+                prefiltered(&chain, SPECULAR_SIZE >> level, roughness)
+            })
+            .collect::<Vec<_>>();
+
+        Ibl {
+            irradiance: upload_cubemap(ctx, &[irradiance]),
+            specular: upload_cubemap(ctx, &specular),
+            brdf_lut: brdf_lut(ctx),
+        }
+    }
+
+    /// The same light coming from every direction, for scenes without a skybox.
+    pub(crate) fn uniform(ctx: &mut dyn RenderingBackend, color: Vec3) -> Ibl {
+        let cubemap = CpuCubemap {
+            size: 1,
+            faces: std::array::from_fn(|_| vec![color]),
+        };
+        Ibl {
+            irradiance: upload_cubemap(ctx, &[cubemap.clone()]),
+            specular: upload_cubemap(ctx, &[cubemap]),
+            brdf_lut: brdf_lut(ctx),
+        }
+    }
+}
+
+/// Linear color cubemap, faces in the GL order: +X, -X, +Y, -Y, +Z, -Z.
+#[derive(Clone, Debug)]
+pub(crate) struct CpuCubemap {
+    pub size: usize,
+    pub faces: [Vec<Vec3>; 6],
+}
+
+impl CpuCubemap {
+    pub fn from_faces(size: usize, faces: [&[[f32; 3]]; 6]) -> CpuCubemap {
+        CpuCubemap {
+            size,
+            faces: faces.map(|face| face.iter().cloned().map(Vec3::from).collect()),
+        }
+    }
+
+    /// Project a latitude-longitude panorama onto the cube, with bilinear filtering.
+    pub fn from_equirectangular(
+        width: usize,
+        height: usize,
+        data: &[[f32; 3]],
+        size: usize,
+    ) -> CpuCubemap {
+        let texel = |x: isize, y: isize| {
+            let x = x.rem_euclid(width as isize) as usize;
+            let y = y.clamp(0, height as isize - 1) as usize;
+            Vec3::from(data[y * width + x])
+        };
+        let faces = std::array::from_fn(|face| {
+            let mut pixels = Vec::with_capacity(size * size);
+            for y in 0..size {
+                for x in 0..size {
+                    let dir = direction(face, x, y, size);
+                    let u = 0.5 + dir.x.atan2(-dir.z) / (2.0 * PI);
+                    let v = dir.y.clamp(-1.0, 1.0).acos() / PI;
+                    let fx = u * width as f32 - 0.5;
+                    let fy = v * height as f32 - 0.5;
+                    let (x0, y0) = (fx.floor() as isize, fy.floor() as isize);
+                    let (tx, ty) = (fx - fx.floor(), fy - fy.floor());
+                    let top = texel(x0, y0).lerp(texel(x0 + 1, y0), tx);
+                    let bottom = texel(x0, y0 + 1).lerp(texel(x0 + 1, y0 + 1), tx);
+                    pixels.push(top.lerp(bottom, ty));
+                }
+            }
+            pixels
+        });
+        CpuCubemap { size, faces }
+    }
+
+    pub fn to_rgba8(&self) -> [Vec<u8>; 6] {
+        self.faces.clone().map(|face| {
+            face.iter()
+                .flat_map(|c| {
+                    let c = c.clamp(Vec3::ZERO, Vec3::ONE) * 255.0 + 0.5;
+                    [c.x as u8, c.y as u8, c.z as u8, 255]
+                })
+                .collect()
+        })
+    }
+
+    /// Little endian half floats, for `TextureFormat::RGBA16F`.
+    pub fn to_rgba16f(&self) -> [Vec<u8>; 6] {
+        self.faces.clone().map(|face| {
+            face.iter()
+                .flat_map(|c| [c.x, c.y, c.z, 1.0])
+                .flat_map(|x| f32_to_half(x).to_le_bytes())
+                .collect()
+        })
+    }
+
+    /// Box filter into a new face size, any sizes work.
+    fn resized(&self, size: usize) -> CpuCubemap {
+        let src = self.size;
+        let faces = std::array::from_fn(|face| {
+            let mut pixels = Vec::with_capacity(size * size);
+            for y in 0..size {
+                for x in 0..size {
+                    let (x0, x1) = (
+                        x * src / size,
+                        ((x + 1) * src / size).max(x * src / size + 1),
+                    );
+                    let (y0, y1) = (
+                        y * src / size,
+                        ((y + 1) * src / size).max(y * src / size + 1),
+                    );
+                    let mut sum = Vec3::ZERO;
+                    for sy in y0..y1 {
+                        for sx in x0..x1 {
+                            sum += self.faces[face][sy * src + sx];
+                        }
+                    }
+                    pixels.push(sum / ((x1 - x0) * (y1 - y0)) as f32);
+                }
+            }
+            pixels
+        });
+        CpuCubemap { size, faces }
+    }
+
+    fn sample(&self, dir: Vec3) -> Vec3 {
+        let (face, uv) = face_uv(dir);
+        let x = (((uv.x + 1.0) * 0.5 * self.size as f32) as usize).min(self.size - 1);
+        let y = (((uv.y + 1.0) * 0.5 * self.size as f32) as usize).min(self.size - 1);
+        self.faces[face][y * self.size + x]
+    }
+}
+
+/// Half float bits of a non-negative color component, too bright values saturate
+/// to the largest half float and tiny ones flush to zero.
+fn f32_to_half(x: f32) -> u16 {
+    let x = x.clamp(0.0, 65504.0);
+    let exponent = (x.to_bits() >> 23) as i32 - 127 + 15;
+    if exponent <= 0 {
+        return 0;
+    }
+    ((exponent as u16) << 10) | ((x.to_bits() >> 13) & 0x3ff) as u16
+}
+
+/// Direction through the center of the texel, as OpenGL defines the cube faces.
+fn direction(face: usize, x: usize, y: usize, size: usize) -> Vec3 {
+    let u = 2.0 * (x as f32 + 0.5) / size as f32 - 1.0;
+    let v = 2.0 * (y as f32 + 0.5) / size as f32 - 1.0;
+    match face {
+        0 => vec3(1.0, -v, -u),
+        1 => vec3(-1.0, -v, u),
+        2 => vec3(u, 1.0, v),
+        3 => vec3(u, -1.0, -v),
+        4 => vec3(u, -v, 1.0),
+        _ => vec3(-u, -v, -1.0),
+    }
+    .normalize()
+}
+
+/// Inverse of `direction`: the face and [-1, 1] coordinates on it.
+fn face_uv(dir: Vec3) -> (usize, Vec2) {
+    let abs = dir.abs();
+    if abs.x >= abs.y && abs.x >= abs.z {
+        if dir.x > 0.0 {
+            (0, vec2(-dir.z, -dir.y) / abs.x)
+        } else {
+            (1, vec2(dir.z, -dir.y) / abs.x)
+        }
+    } else if abs.y >= abs.z {
+        if dir.y > 0.0 {
+            (2, vec2(dir.x, dir.z) / abs.y)
+        } else {
+            (3, vec2(dir.x, -dir.z) / abs.y)
+        }
+    } else if dir.z > 0.0 {
+        (4, vec2(dir.x, -dir.y) / abs.z)
+    } else {
+        (5, vec2(-dir.x, -dir.y) / abs.z)
+    }
+}
+
+fn hammersley(i: u32, n: u32) -> Vec2 {
+    vec2(i as f32 / n as f32, i.reverse_bits() as f32 / 4294967296.0)
+}
+
+/// GGX distributed half vector around +Z.
+fn importance_sample_ggx(xi: Vec2, alpha: f32) -> Vec3 {
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = ((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    vec3(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta)
+}
+
+fn tangent_to_world(v: Vec3, n: Vec3) -> Vec3 {
+    let up = if n.z.abs() < 0.999 { Vec3::Z } else { Vec3::X };
+    let tangent = up.cross(n).normalize();
+    let bitangent = n.cross(tangent);
+    tangent * v.x + bitangent * v.y + n * v.z
+}
+
+/// Cosine weighted average of the whole environment, brute forced over a small mip.
+fn irradiance(chain: &[CpuCubemap]) -> CpuCubemap {
+    let source = chain.iter().find(|mip| mip.size <= 8).unwrap();
+    let mut texels = vec![];
+    for face in 0..6 {
+        for y in 0..source.size {
+            for x in 0..source.size {
+                let u = 2.0 * (x as f32 + 0.5) / source.size as f32 - 1.0;
+                let v = 2.0 * (y as f32 + 0.5) / source.size as f32 - 1.0;
+                let solid_angle = 1.0 / (1.0 + u * u + v * v).powf(1.5);
+                let color = source.faces[face][y * source.size + x];
+                texels.push((direction(face, x, y, source.size), color, solid_angle));
+            }
+        }
+    }
+
+    let size = IRRADIANCE_SIZE;
+    let faces = std::array::from_fn(|face| {
+        let mut pixels = Vec::with_capacity(size * size);
+        for y in 0..size {
+            for x in 0..size {
+                let n = direction(face, x, y, size);
+                let mut sum = Vec3::ZERO;
+                let mut weight = 0.0;
+                for (dir, color, solid_angle) in &texels {
+                    let cos = n.dot(*dir);
+                    if cos > 0.0 {
+                        sum += *color * cos * *solid_angle;
+                        weight += cos * *solid_angle;
+                    }
+                }
+                pixels.push(sum / weight.max(1e-6));
+            }
+        }
+        pixels
+    });
+    CpuCubemap { size, faces }
+}
+
+/// GGX lobe convolution with the N = V = R assumption, importance sampled from
+/// the mip level matching each sample's footprint.
+fn prefiltered(chain: &[CpuCubemap], size: usize, roughness: f32) -> CpuCubemap {
+    if roughness == 0.0 {
+        return chain[0].resized(size);
+    }
+
+    let alpha = roughness * roughness;
+    let base_size = chain[0].size as f32;
+    let texel_solid_angle = 4.0 * PI / (6.0 * base_size * base_size);
+    let faces = std::array::from_fn(|face| {
+        let mut pixels = Vec::with_capacity(size * size);
+        for y in 0..size {
+            for x in 0..size {
+                let n = direction(face, x, y, size);
+                let mut sum = Vec3::ZERO;
+                let mut weight = 0.0;
+                for i in 0..SAMPLES {
+                    let h =
+                        tangent_to_world(importance_sample_ggx(hammersley(i, SAMPLES), alpha), n);
+                    let n_dot_h = n.dot(h);
+                    let l = 2.0 * n_dot_h * h - n;
+                    let n_dot_l = n.dot(l);
+                    if n_dot_l <= 0.0 {
+                        continue;
+                    }
+                    let a2 = alpha * alpha;
+                    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+                    let pdf = a2 / (PI * d * d) / 4.0;
+                    let sample_solid_angle = 1.0 / (SAMPLES as f32 * pdf + 1e-4);
+                    let mip = 0.5 * (sample_solid_angle / texel_solid_angle).log2() + 1.0;
+                    let mip = (mip.round().max(0.0) as usize).min(chain.len() - 1);
+                    sum += chain[mip].sample(l) * n_dot_l;
+                    weight += n_dot_l;
+                }
+                pixels.push(sum / weight.max(1e-6));
+            }
+        }
+        pixels
+    });
+    CpuCubemap { size, faces }
+}
+
+/// Split-sum scale (R) and bias (G) applied to F0, by NdotV (U) and roughness (V).
+fn brdf_lut(ctx: &mut dyn RenderingBackend) -> TextureId {
+    let size = BRDF_LUT_SIZE;
+    let mut data = Vec::with_capacity(size * size * 4);
+    for y in 0..size {
+        let roughness = (y as f32 + 0.5) / size as f32;
+        let alpha = roughness * roughness;
+        let k = alpha / 2.0;
+        for x in 0..size {
+            let n_dot_v = (x as f32 + 0.5) / size as f32;
+            let v = vec3((1.0 - n_dot_v * n_dot_v).sqrt(), 0.0, n_dot_v);
+            let mut scale = 0.0;
+            let mut bias = 0.0;
+            for i in 0..BRDF_SAMPLES {
+                let h = importance_sample_ggx(hammersley(i, BRDF_SAMPLES), alpha);
+                let v_dot_h = v.dot(h);
+                let l = 2.0 * v_dot_h * h - v;
+                let n_dot_l = l.z;
+                if n_dot_l <= 0.0 {
+                    continue;
+                }
+                let n_dot_h = h.z.max(0.0);
+                let v_dot_h = v_dot_h.max(0.0);
+                let g = n_dot_l / (n_dot_l * (1.0 - k) + k) * n_dot_v / (n_dot_v * (1.0 - k) + k);
+                let g_vis = g * v_dot_h / (n_dot_h * n_dot_v).max(1e-6);
+                let fc = (1.0 - v_dot_h).powi(5);
+                scale += (1.0 - fc) * g_vis;
+                bias += fc * g_vis;
+            }
+            let to_u8 = |x: f32| ((x / BRDF_SAMPLES as f32).clamp(0.0, 1.0) * 255.0) as u8;
+            data.extend_from_slice(&[to_u8(scale), to_u8(bias), 0, 255]);
+        }
+    }
+
+    ctx.new_texture(
+        TextureAccess::Static,
+        TextureSource::Bytes(&data),
+        TextureParams {
+            kind: TextureKind::Texture2D,
+            width: size as _,
+            height: size as _,
+            format: TextureFormat::RGBA8,
+            wrap: TextureWrap::Clamp,
+            min_filter: FilterMode::Linear,
+            mag_filter: FilterMode::Linear,
+            ..Default::default()
+        },
+    )
+}
+
+/// Cubemap texture with the given mip levels, each half the size of the previous one.
+fn upload_cubemap(ctx: &mut dyn RenderingBackend, levels: &[CpuCubemap]) -> TextureId {
+    let size = levels[0].size;
+    let mipmapped = levels.len() > 1;
+    let levels: Vec<[Vec<u8>; 6]> = levels.iter().map(CpuCubemap::to_rgba16f).collect();
+    let faces: Vec<Vec<&[u8]>> = (0..6)
+        .map(|face| levels.iter().map(|level| &level[face][..]).collect())
+        .collect();
+    let faces: Vec<&[&[u8]]> = faces.iter().map(|mips| &mips[..]).collect();
+
+    ctx.new_texture(
+        TextureAccess::Static,
+        TextureSource::Array(&faces),
+        TextureParams {
+            kind: TextureKind::CubeMap,
+            width: size as _,
+            height: size as _,
+            format: TextureFormat::RGBA16F,
+            wrap: TextureWrap::Clamp,
+            min_filter: FilterMode::Linear,
+            mag_filter: FilterMode::Linear,
+            mipmap_filter: if mipmapped {
+                MipmapFilterMode::Linear
+            } else {
+                MipmapFilterMode::None
+            },
+            allocate_mipmaps: mipmapped,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_floats() {
+        assert_eq!(f32_to_half(0.0), 0);
+        assert_eq!(f32_to_half(1.0), 0x3c00);
+        assert_eq!(f32_to_half(0.5), 0x3800);
+        assert_eq!(f32_to_half(24.0), 0x4e00);
+        assert_eq!(f32_to_half(1e9), 0x7bff);
+        assert_eq!(f32_to_half(-1.0), 0);
+        assert_eq!(f32_to_half(1e-9), 0);
+
+        // bright texels are not clamped to 1
+        let cubemap = CpuCubemap {
+            size: 1,
+            faces: std::array::from_fn(|_| vec![vec3(4.0, 1.0, 0.25)]),
+        };
+        assert_eq!(
+            cubemap.to_rgba16f()[0],
+            [0x00, 0x44, 0x00, 0x3c, 0x00, 0x34, 0x00, 0x3c]
+        );
+    }
+}
@@ -207,7 +207,7 @@ impl MagicSnapshotter {
         let shader = ctx
             .new_shader(
                 match info.backend {
-                    Backend::OpenGl => ShaderSource::Glsl {
+                    Backend::OpenGl | Backend::Software => ShaderSource::Glsl {
                         vertex: snapshotter_shader::VERTEX,
                         fragment: snapshotter_shader::FRAGMENT,
                     },
@@ -361,7 +361,7 @@ impl PipelinesStorage {
         let shader = ctx
             .new_shader(
                 match info.backend {
-                    Backend::OpenGl => ShaderSource::Glsl {
+                    Backend::OpenGl | Backend::Software => ShaderSource::Glsl {
                         vertex: shader::VERTEX,
                         fragment: shader::FRAGMENT,
                     },
@@ -1,38 +1,221 @@
 varying vec2 out_uv;
 varying vec3 out_pos;
 varying vec3 out_normal;
-varying vec4 out_shadow[4];
-varying float out_clip_z;
+varying vec4 out_tint;
+varying vec4 out_screen;
 
 uniform sampler2D Albedo;
 uniform sampler2D Emissive;
 uniform sampler2D Occlusion;
 uniform sampler2D Normal;
 uniform sampler2D MetallicRoughness;
-uniform sampler2D ShadowMap0;
-uniform sampler2D ShadowMap1;
-uniform sampler2D ShadowMap2;
-uniform sampler2D ShadowMap3;
+uniform sampler2D ShadowMap;
+uniform sampler2D SceneNormals;
+uniform sampler2D SceneDepth;
+uniform sampler2D AmbientOcclusion;
 uniform vec3 CameraPosition;
+uniform vec3 CameraForward;
 uniform vec4 Material;
 uniform vec4 Color;
-uniform vec4 ShadowCascades;
+// prefiltered specular, roughness 0..1 in the mip levels 0..4
 uniform samplerCube Environment;
+uniform samplerCube Irradiance;
+uniform sampler2D BrdfLut;
+// x - directional shadow casters
 uniform ivec4 ShadowCasters;
+// world to the atlas, per shadow map
+uniform mat4 ShadowProjection[16];
+// x - first map, y - cascades, per caster
+uniform vec4 ShadowCasterMaps[4];
+// distances from the camera where the cascades end, per caster
+uniform vec4 CascadeSplits[4];
+// x, y - maps per row and column, z, w - texel size
+uniform vec4 ShadowAtlas;
+// x - depth bias, y - normal offset, z - cascade blend
+uniform vec4 ShadowParams;
+// x - 0 hard, 1 PCF, 2 PCSS, y - kernel radius, z - light size in texels
+uniform vec4 ShadowFilter;
+uniform int LightsCount;
+uniform vec4 LightPositions[8];
+uniform vec4 LightDirections[8];
+uniform vec4 LightColors[8];
+uniform vec4 LightCones[8];
 
-float ShadowMap(int ix, vec2 uv, vec2 offset, float z) {
-    float d = 0.0002;
-    if (ix == 0) return ((texture2D(ShadowMap0, uv + offset * d).z < z - 0.001) ? 1.0 : 0.0);
-    if (ix == 1) return ((texture2D(ShadowMap1, uv + offset * d).z < z - 0.001) ? 1.0 : 0.0);
-    if (ix == 2) return ((texture2D(ShadowMap2, uv + offset * d).z < z - 0.001) ? 1.0 : 0.0);
-    if (ix == 3) return ((texture2D(ShadowMap3, uv + offset * d).z < z - 0.001) ? 1.0 : 0.0);
+const float PI = 3.14159265;
+
+// GLSL 100 only indexes the arrays with constants and loop counters
+mat4 ShadowProjectionAt(int map) {
+    for (int i = 0; i < 16; i++) {
+        if (i == map) {
+            return ShadowProjection[i];
+        }
+    }
+    return ShadowProjection[0];
+}
+
+float CascadeSplit(int caster, int cascade) {
+    vec4 splits = CascadeSplits[0];
+    for (int i = 1; i < 4; i++) {
+        if (i == caster) {
+            splits = CascadeSplits[i];
+        }
+    }
+    if (cascade == 0) return splits.x;
+    if (cascade == 1) return splits.y;
+    if (cascade == 2) return splits.z;
+    return splits.w;
 }
 
-float ShadowCascade(int ix) {
-    if (ix == 0) return ShadowCascades.x;
-    if (ix == 1) return ShadowCascades.y;
-    if (ix == 2) return ShadowCascades.z;
-    if (ix == 3) return ShadowCascades.a;
+// The map's part of the atlas, half a texel in, so the filters don't read the neighbours.
+vec4 ShadowRect(int map) {
+    float column = mod(float(map), ShadowAtlas.x);
+    float row = floor(float(map) / ShadowAtlas.x);
+    vec2 size = 1.0 / ShadowAtlas.xy;
+    vec2 start = vec2(column, row) * size;
+    return vec4(start + ShadowAtlas.zw * 0.5, start + size - ShadowAtlas.zw * 0.5);
+}
+
+float ShadowLit(vec2 uv, vec4 rect, float z) {
+    return texture2D(ShadowMap, clamp(uv, rect.xy, rect.zw)).z < z ? 0.0 : 1.0;
+}
+
+// 1 in the light, 0 in the shadow of the map.
+float ShadowVisibility(int map, vec3 pos, vec3 normal) {
+    vec4 coord = ShadowProjectionAt(map) * vec4(pos + normal * ShadowParams.y, 1.0);
+    coord.xyz /= coord.w;
+    vec4 rect = ShadowRect(map);
+    if (coord.w <= 0.0 || coord.z >= 1.0 || clamp(coord.xy, rect.xy, rect.zw) != coord.xy) {
+        return 1.0;
+    }
+    float z = coord.z - ShadowParams.x;
+    if (ShadowFilter.x < 0.5) {
+        return ShadowLit(coord.xy, rect, z);
+    }
+
+    float radius = ShadowFilter.y;
+    float spread = 1.0;
+    if (ShadowFilter.x > 1.5) {
+        // PCSS: the average depth of the blockers gives the penumbra's width
+        float blockers = 0.0;
+        float blocker_depth = 0.0;
+        for (int x = -2; x <= 2; x++) {
+            for (int y = -2; y <= 2; y++) {
+                vec2 offset = vec2(float(x), float(y)) * 0.5 * ShadowFilter.z * ShadowAtlas.zw;
+                float d = texture2D(ShadowMap, clamp(coord.xy + offset, rect.xy, rect.zw)).z;
+                if (d < z) {
+                    blockers += 1.0;
+                    blocker_depth += d;
+                }
+            }
+        }
+        if (blockers == 0.0) {
+            return 1.0;
+        }
+        blocker_depth /= blockers;
+        float penumbra = ShadowFilter.z * (z - blocker_depth) / max(blocker_depth, 0.0001);
+        spread = max(penumbra / max(radius, 1.0), 1.0);
+    }
+
+    float lit = 0.0;
+    float taps = 0.0;
+    for (int x = -4; x <= 4; x++) {
+        for (int y = -4; y <= 4; y++) {
+            if (abs(float(x)) <= radius && abs(float(y)) <= radius) {
+                vec2 offset = vec2(float(x), float(y)) * spread * ShadowAtlas.zw;
+                lit += ShadowLit(coord.xy + offset, rect, z);
+                taps += 1.0;
+            }
+        }
+    }
+    return lit / taps;
+}
+
+// Cascades of all the directional shadow casters, darkening everything by up to a half.
+float DirectionalShadows(vec3 pos, vec3 normal, float depth) {
+    float visibility = 1.0;
+    for (int n = 0; n < 4; n++) {
+        if (n >= ShadowCasters.x) {
+            break;
+        }
+        int first = int(ShadowCasterMaps[n].x + 0.5);
+        int cascades = int(ShadowCasterMaps[n].y + 0.5);
+        float start = 0.0;
+        for (int i = 0; i < 4; i++) {
+            if (i >= cascades) {
+                break;
+            }
+            float end = CascadeSplit(n, i);
+            if (depth <= end) {
+                float lit = ShadowVisibility(first + i, pos, normal);
+                // fade into the next cascade over the last part of this one
+                float blend_start = end - (end - start) * ShadowParams.z;
+                if (i + 1 < cascades && depth > blend_start) {
+                    float next = ShadowVisibility(first + i + 1, pos, normal);
+                    lit = mix(lit, next, (depth - blend_start) / max(end - blend_start, 0.0001));
+                }
+                visibility *= 1.0 - 0.5 * (1.0 - lit);
+                break;
+            }
+            start = end;
+        }
+    }
+    return visibility;
+}
+
+// KHR_lights_punctual recommended falloff: inverse square, smoothly windowed by the range.
+float LightAttenuation(float distance, float range) {
+    float attenuation = 1.0 / max(distance * distance, 0.0001);
+    if (range > 0.0) {
+        attenuation *= clamp(1.0 - pow(distance / range, 4.0), 0.0, 1.0);
+    }
+    return attenuation;
+}
+
+// Lambert diffuse and GGX specular of all the lights culled for this draw.
+vec3 PunctualLights(vec3 N, vec3 V, vec3 albedo, float metallic, float roughness) {
+    vec3 result = vec3(0.0);
+    float alpha = max(roughness * roughness, 0.002);
+    float a2 = alpha * alpha;
+    float k = alpha / 2.0;
+    float NdotV = max(dot(N, V), 0.0001);
+    vec3 f0 = mix(vec3(0.04), albedo, metallic);
+
+    for (int i = 0; i < 8; i++) {
+        if (i >= LightsCount) {
+            break;
+        }
+        vec4 position = LightPositions[i];
+        vec4 direction = LightDirections[i];
+        vec3 L = -direction.xyz;
+        float attenuation = 1.0;
+        if (position.w > 0.5) {
+            vec3 to_light = position.xyz - out_pos;
+            float distance = length(to_light);
+            L = to_light / distance;
+            attenuation = LightAttenuation(distance, direction.w);
+        }
+        if (position.w > 1.5) {
+            float cone = clamp(dot(direction.xyz, -L) * LightCones[i].x + LightCones[i].y, 0.0, 1.0);
+            attenuation *= cone * cone;
+            if (LightCones[i].z > -0.5) {
+                attenuation *= ShadowVisibility(int(LightCones[i].z + 0.5), out_pos, N);
+            }
+        }
+        float NdotL = max(dot(N, L), 0.0);
+
+        vec3 H = normalize(L + V);
+        float NdotH = max(dot(N, H), 0.0);
+        float VdotH = max(dot(V, H), 0.0);
+        vec3 F = f0 + (vec3(1.0) - f0) * pow(1.0 - VdotH, 5.0);
+        float d = NdotH * NdotH * (a2 - 1.0) + 1.0;
+        float D = a2 / (PI * d * d);
+        float G = NdotL / (NdotL * (1.0 - k) + k) * NdotV / (NdotV * (1.0 - k) + k);
+        vec3 specular = F * D * G / max(4.0 * NdotL * NdotV, 0.0001);
+        vec3 diffuse = (vec3(1.0) - F) * (1.0 - metallic) * albedo / PI;
+
+        result += (diffuse + specular) * LightColors[i].rgb * NdotL * attenuation;
+    }
+    return result;
 }
 
 #if HAS_NORMAL_MAP
@@ -69,6 +252,14 @@ void main() {
     vec3 R = reflect(I, N);
 #endif
 
+#if DOUBLE_SIDED
+    // back faces are lit as the other side of the surface
+    if (dot(out_normal, I) > 0.0) {
+        N = -N;
+        R = reflect(I, N);
+    }
+#endif
+
 #if HAS_METALLIC_ROUGHNESS_MAP
     float roughness = texture2D(MetallicRoughness, out_uv).g * Material.y;
 #else
@@ -83,39 +274,32 @@ void main() {
 
     vec4 o = texture2D(Occlusion, out_uv);
     vec4 occlusion = vec4(o.r, o.r, o.r, 1.0);
-    vec4 base_color = texture2D(Albedo, out_uv) * Color;
-
-    float specular = 0.0;
-    // vec3 light_dir = vec3(1.0, -2.0, -1.0);
-
-    // if (dot(N, light_dir) > 0.0) {
-    //     specular = pow(dot(reflect(-light_dir, N), I), 5.0) * 0.05;
-    // }
+    vec4 base_color = texture2D(Albedo, out_uv) * Color * out_tint;
+#if ALPHA_MASK
+    if (base_color.a < Material.z) {
+        discard;
+    }
+    base_color.a = 1.0;
+#endif
 
-    vec4 environment = textureCubeLod(Environment, R, sm_level((1.0 - metallic) * 5.0));
-    vec4 reflection = vec4(roughness) + environment * vec4((1.0 - roughness) + specular * (1.0 - roughness + metallic * 0.2));
+    // split-sum image based lighting
+    float NdotV = max(dot(N, -I), 0.0001);
+    vec3 f0 = mix(vec3(0.04), base_color.rgb, metallic);
+    vec3 F = f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - NdotV, 5.0);
+    vec3 kd = (vec3(1.0) - F) * (1.0 - metallic);
+    vec3 irradiance = textureCube(Irradiance, N).rgb;
+    vec3 prefiltered = textureCubeLod(Environment, R, sm_level(roughness * 4.0)).rgb;
+    vec2 brdf = texture2D(BrdfLut, vec2(NdotV, roughness)).rg;
+    vec4 ambient = vec4(kd * irradiance * base_color.rgb + prefiltered * (F * brdf.x + brdf.y), base_color.a);
+    // white without the camera's SSAO
+    float ssao = texture2D(AmbientOcclusion, out_screen.xy / out_screen.w * 0.5 + 0.5).r;
+    ambient = vec4(ambient.rgb * ssao, ambient.a);
 
-    float visibility = 1.0;
+    float visibility = DirectionalShadows(out_pos, N, dot(out_pos - CameraPosition, CameraForward));
 
-    for (int n = 0; n < ShadowCasters.x; n++) {
-        for (int i = 0; i < ShadowCasters.y; i++) {
-            if (out_clip_z <= ShadowCascade(i)) {
-                float s = ShadowMap(i, out_shadow[i].xy, vec2(0.0, 0.0), out_shadow[i].z)
-                    + ShadowMap(i, out_shadow[i].xy, vec2(1, 0.0), out_shadow[i].z)
-                    + ShadowMap(i, out_shadow[i].xy, vec2(1, 1), out_shadow[i].z)
-                    + ShadowMap(i, out_shadow[i].xy, vec2(0.0, 1), out_shadow[i].z)
-                    + ShadowMap(i, out_shadow[i].xy, vec2(-1, 1), out_shadow[i].z)
-                    + ShadowMap(i, out_shadow[i].xy, vec2(-1, 0.0), out_shadow[i].z)
-                    + ShadowMap(i, out_shadow[i].xy, vec2(-1, -1), out_shadow[i].z)
-                    + ShadowMap(i, out_shadow[i].xy, vec2(0.0, -1), out_shadow[i].z);
-                s /= 8.0;
-                visibility = 1.0 - 0.5 * s;
-                break;
-            }
-        }
-    }
+    vec4 lights = vec4(PunctualLights(N, -I, base_color.rgb, metallic, roughness), 0.0);
 
-    gl_FragColor = (reflection * occlusion * base_color  + texture2D(Emissive, out_uv)) * vec4(visibility, visibility, visibility, 1.);
+    gl_FragColor = ((ambient + lights) * occlusion + texture2D(Emissive, out_uv)) * vec4(visibility, visibility, visibility, 1.);
     //gl_FragColor = (reflection * occlusion * base_color  + texture2D(Emissive, out_uv)) * vec4(visibility, visibility, visibility, 1.);
     //gl_FragColor = metallic;
     //gl_FragColor = vec4(metallic, 0.0, 0.0, 1.0);
@@ -16,7 +16,7 @@ pub fn decode(bytes: &[u8]) -> Result<RGBA8Buffer, Box<dyn std::error::Error>> {
             .set_strict_mode(false);
         let mut decoder = JpegDecoder::new_with_options(bytes, options);
         decoder.decode_headers()?;
-        let info = decoder.info().unwrap();
+        let info = decoder.info().ok_or("Jpeg without image info")?;
         let pixels = decoder.decode()?;
         Ok(RGBA8Buffer {
             width: info.width as _,
@@ -24,37 +24,235 @@ pub fn decode(bytes: &[u8]) -> Result<RGBA8Buffer, Box<dyn std::error::Error>> {
             data: pixels,
         })
     } else if bytes.len() >= 8 && bytes[0..8] == [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A] {
+        use zune_core::bit_depth::{BitDepth, ByteEndian};
+        use zune_core::colorspace::ColorSpace;
         use zune_core::options::DecoderOptions;
         use zune_png::PngDecoder;
+        // RGB and palette images get an alpha channel, gray ones become gray and alpha
         let options = DecoderOptions::default()
             .png_set_add_alpha_channel(true)
+            .set_byte_endian(ByteEndian::BE)
             .set_strict_mode(false);
         let mut decoder = PngDecoder::new_with_options(bytes, options);
+        let invalid = |err| format!("Invalid png: {:?}", err);
 
-        decoder.decode_headers().unwrap();
-        let (width, height) = decoder.get_dimensions().unwrap();
-
-        let pixels = decoder.decode_raw().unwrap();
-        if pixels.len() == width * height * 2 {
-            Ok(RGBA8Buffer {
-                width: width as _,
-                height: height as _,
-                data: pixels
-                    .chunks(2)
-                    .map(|v| [v[0], v[0], v[0], v[1]])
-                    .flatten()
-                    .collect::<Vec<_>>(),
-            })
-        } else if pixels.len() == width * height * 4 {
-            Ok(RGBA8Buffer {
-                width: width as _,
-                height: height as _,
-                data: pixels,
-            })
-        } else {
-            unimplemented!()
+        decoder.decode_headers().map_err(invalid)?;
+        let (width, height) = decoder.get_dimensions().ok_or("Png without dimensions")?;
+        let depth = decoder.get_depth().ok_or("Png without bit depth")?;
+        let colorspace = decoder.get_colorspace().ok_or("Png without colorspace")?;
+
+        let mut pixels = decoder.decode_raw().map_err(invalid)?;
+        if depth == BitDepth::Sixteen {
+            // keep the high byte of the big endian samples
+            pixels = pixels.chunks(2).map(|v| v[0]).collect();
         }
+        let data = match colorspace {
+            ColorSpace::LumaA => pixels
+                .chunks(2)
+                .map(|v| [v[0], v[0], v[0], v[1]])
+                .flatten()
+                .collect::<Vec<_>>(),
+            ColorSpace::RGBA => pixels,
+            colorspace => return Err(format!("Unsupported png layout: {:?}", colorspace).into()),
+        };
+        if data.len() != width * height * 4 {
+            return Err("Invalid png: wrong pixel count".into());
+        }
+        Ok(RGBA8Buffer {
+            width: width as _,
+            height: height as _,
+            data,
+        })
     } else {
-        panic!()
+        Err("Unsupported image format, only png and jpeg are supported".into())
+    }
+}
+
+/// Linear RGB floats, for high dynamic range images.
+#[derive(Default)]
+pub struct RGBF32Buffer {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<[f32; 3]>,
+}
+
+/// Radiance .hdr files start with "#?RADIANCE" or "#?RGBE".
+pub fn is_hdr(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"#?")
+}
+
+/// Decode a Radiance .hdr (RGBE) image, flat or with the new style run length encoding.
+pub fn decode_hdr(bytes: &[u8]) -> Result<RGBF32Buffer, Box<dyn std::error::Error>> {
+    let mut lines = bytes.split(|b| *b == b'\n');
+    let mut offset = 0;
+    let mut next_line = || {
+        let line = lines.next().ok_or("Unexpected end of the hdr header")?;
+        offset += line.len() + 1;
+        Ok::<_, Box<dyn std::error::Error>>(std::str::from_utf8(line)?.trim())
+    };
+
+    let mut line = next_line()?;
+    if !line.starts_with("#?") {
+        return Err("Not a Radiance hdr file".into());
+    }
+    while !line.is_empty() {
+        if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+            return Err(format!("Unsupported hdr format: {}", line).into());
+        }
+        line = next_line()?;
+    }
+    // only the standard orientation is supported: "-Y height +X width"
+    let resolution = next_line()?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (height.parse::<usize>()?, width.parse::<usize>()?),
+        _ => return Err(format!("Unsupported hdr orientation: {}", resolution).into()),
+    };
+    if width == 0 || height == 0 {
+        return Err(format!("Invalid hdr resolution: {}", resolution).into());
+    }
+
+    let mut data = bytes
+        .get(offset..)
+        .ok_or("Unexpected end of the hdr file")?;
+    let mut byte = || {
+        let (first, rest) = data.split_first().ok_or("Unexpected end of the hdr file")?;
+        data = rest;
+        Ok::<_, Box<dyn std::error::Error>>(*first)
+    };
+
+    let mut rgbe = vec![];
+    let mut pixels = vec![];
+    width
+        .checked_mul(height)
+        .and_then(|len| {
+            rgbe.try_reserve_exact(width)
+                .and(pixels.try_reserve_exact(len))
+                .ok()
+        })
+        .ok_or_else(|| format!("Hdr image too large: {}", resolution))?;
+    rgbe.resize(width, [0u8; 4]);
+    for _ in 0..height {
+        let header = [byte()?, byte()?, byte()?, byte()?];
+        let rle = (8..0x8000).contains(&width)
+            && header[0] == 2
+            && header[1] == 2
+            && ((header[2] as usize) << 8 | header[3] as usize) == width;
+        if rle {
+            // each of the 4 channels is encoded separately
+            for channel in 0..4 {
+                let mut x = 0;
+                while x < width {
+                    let count = byte()? as usize;
+                    if count > 128 {
+                        if x + count - 128 > width {
+                            return Err("Malformed hdr scanline".into());
+                        }
+                        let value = byte()?;
+                        for _ in 0..count - 128 {
+                            rgbe[x][channel] = value;
+                            x += 1;
+                        }
+                    } else {
+                        if count == 0 || x + count > width {
+                            return Err("Malformed hdr scanline".into());
+                        }
+                        for _ in 0..count {
+                            rgbe[x][channel] = byte()?;
+                            x += 1;
+                        }
+                    }
+                }
+            }
+        } else {
+            rgbe[0] = header;
+            for pixel in &mut rgbe[1..] {
+                *pixel = [byte()?, byte()?, byte()?, byte()?];
+            }
+        }
+
+        pixels.extend(rgbe.iter().map(|[r, g, b, e]| {
+            if *e == 0 {
+                return [0.0; 3];
+            }
+            let scale = 2.0f32.powi(*e as i32 - 136);
+            [
+                (*r as f32 + 0.5) * scale,
+                (*g as f32 + 0.5) * scale,
+                (*b as f32 + 0.5) * scale,
+            ]
+        }));
+    }
+
+    Ok(RGBF32Buffer {
+        width,
+        height,
+        data: pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_layouts() {
+        // 2x1, 16 bit RGB: #1234_5678_9abc and #ffff_0000_8000
+        let rgb16 = [
+            0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48,
+            0x44, 0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01, 0x10, 0x02, 0x00, 0x00,
+            0x00, 0x2b, 0xd0, 0x34, 0x9e, 0x00, 0x00, 0x00, 0x15, 0x49, 0x44, 0x41, 0x54, 0x78,
+            0x9c, 0x63, 0x10, 0x32, 0x09, 0xab, 0x98, 0xb5, 0xe7, 0xff, 0x7f, 0x06, 0x86, 0x06,
+            0x06, 0x00, 0x20, 0x9e, 0x04, 0xe9, 0x5a, 0x4f, 0xd4, 0x79, 0x00, 0x00, 0x00, 0x00,
+            0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+        ];
+        let image = decode(&rgb16).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.data, [0x12, 0x56, 0x9a, 0xff, 0xff, 0x00, 0x80, 0xff]);
+
+        // 1x1, 8 bit gray #40
+        let gray = [
+            0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48,
+            0x44, 0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x00, 0x00, 0x00,
+            0x00, 0x3a, 0x7e, 0x9b, 0x55, 0x00, 0x00, 0x00, 0x0a, 0x49, 0x44, 0x41, 0x54, 0x78,
+            0x9c, 0x63, 0x70, 0x00, 0x00, 0x00, 0x42, 0x00, 0x41, 0x29, 0x37, 0xf4, 0xef, 0x00,
+            0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+        ];
+        assert_eq!(decode(&gray).unwrap().data, [0x40, 0x40, 0x40, 0xff]);
+
+        assert!(decode(&rgb16[..40]).is_err());
+        assert!(decode(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn hdr_scanlines() {
+        let header = |resolution: &str| {
+            format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{}\n", resolution).into_bytes()
+        };
+
+        // flat 2x1: 1.0 and 0.5 gray
+        let mut flat = header("-Y 1 +X 2");
+        flat.extend([127, 127, 127, 129, 127, 127, 127, 128]);
+        let image = decode_hdr(&flat).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.data, [[1.0 - 1.0 / 256.0; 3], [0.5 - 1.0 / 512.0; 3]]);
+
+        // run length encoded 8x1, one run of 8 for every channel
+        let mut rle = header("-Y 1 +X 8");
+        rle.extend([2, 2, 0, 8, 136, 127, 136, 127, 136, 127, 136, 130]);
+        let image = decode_hdr(&rle).unwrap();
+        assert_eq!(image.data, [[2.0 - 2.0 / 256.0; 3]; 8]);
+
+        // a run past the end of the scanline
+        let len = rle.len();
+        rle[len - 2] = 137;
+        assert!(decode_hdr(&rle).is_err());
+        // a literal run past the end of the scanline
+        rle[len - 2] = 9;
+        assert!(decode_hdr(&rle).is_err());
+
+        assert!(decode_hdr(&header("-Y 1 +X 0")).is_err());
+        assert!(decode_hdr(&header("-Y 0 +X 1")).is_err());
+        assert!(decode_hdr(&header("-Y 99999999999 +X 99999999999")).is_err());
+        assert!(decode_hdr(&flat[..flat.len() - 1]).is_err());
     }
 }
@@ -11,6 +11,8 @@ pub mod color;
 pub mod material;
 pub mod math;
 pub mod models;
+pub mod post_processing;
+pub mod prepass;
 pub mod shapes;
 pub mod text;
 pub mod texture;
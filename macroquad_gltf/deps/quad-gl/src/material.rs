@@ -179,11 +179,8 @@ impl Material {
                 )
             });
 
-        // *self
-        //     .textures_data
-        //     .entry(name.to_owned())
-        //     .or_insert(texture.texture) = texture.texture;
-        unimplemented!()
+        self.textures_data
+            .insert(name.to_owned(), texture.raw_miniquad_id());
     }
 
     /// Delete this material. Using deleted material for either rendering
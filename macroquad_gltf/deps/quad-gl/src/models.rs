@@ -2,13 +2,24 @@
 
 use crate::{
     math::{vec2, vec3, Vec2, Vec3},
-    scene::{self, Model, Node, NodeData, Scene, Transform},
+    scene::{self, instancing::Instance, Model, Node, NodeData, Scene, Transform},
     texture::Texture2D,
 };
 use miniquad::*;
 
 pub struct CpuMesh(pub Vec<Vec3>, pub Vec<Vec2>, pub Vec<Vec3>, pub Vec<u16>);
 
+impl From<&CpuMesh> for scene::MeshData {
+    fn from(CpuMesh(vertices, uvs, normals, indices): &CpuMesh) -> scene::MeshData {
+        scene::MeshData {
+            positions: vertices.clone(),
+            uvs: uvs.clone(),
+            normals: normals.clone(),
+            indices: indices.iter().map(|ix| *ix as u32).collect(),
+        }
+    }
+}
+
 pub fn sphere(radius: f32, rings: u32, slices: u32) -> CpuMesh {
     let scale = vec3(radius, radius, radius);
     let mut vertices = vec![];
@@ -133,11 +144,9 @@ pub fn square() -> CpuMesh {
 }
 
 impl crate::QuadGl {
-    pub fn mesh(
-        &self,
-        CpuMesh(vertices, uvs, normals, indices): CpuMesh,
-        texture: Option<Texture2D>,
-    ) -> Model {
+    pub fn mesh(&self, mesh: CpuMesh, texture: Option<Texture2D>) -> Model {
+        let mesh_data = std::sync::Arc::new(scene::MeshData::from(&mesh));
+        let CpuMesh(vertices, uvs, normals, indices) = mesh;
         let mut quad_ctx = self.quad_ctx.lock().unwrap();
 
         let vertex_buffer = quad_ctx.new_buffer(
@@ -183,17 +192,17 @@ impl crate::QuadGl {
                 BufferLayout::default(),
                 BufferLayout::default(),
                 BufferLayout::default(),
-                BufferLayout {
-                    step_func: VertexStep::PerInstance,
-                    ..Default::default()
-                },
+                Instance::buffer_layout(),
             ],
             &[
-                VertexAttribute::with_buffer("in_position", VertexFormat::Float3, 0),
-                VertexAttribute::with_buffer("in_uv", VertexFormat::Float2, 1),
-                VertexAttribute::with_buffer("in_normal", VertexFormat::Float3, 2),
-                VertexAttribute::with_buffer("in_inst", VertexFormat::Float3, 3),
-            ],
+                &[
+                    VertexAttribute::with_buffer("in_position", VertexFormat::Float3, 0),
+                    VertexAttribute::with_buffer("in_uv", VertexFormat::Float2, 1),
+                    VertexAttribute::with_buffer("in_normal", VertexFormat::Float3, 2),
+                ][..],
+                &Instance::attributes(),
+            ]
+            .concat(),
             shader,
             PipelineParams {
                 depth_test: Comparison::LessOrEqual,
@@ -207,16 +216,14 @@ impl crate::QuadGl {
             },
         );
 
-        let instancing = vec![vec3(0.0, 0.0, 0.0)];
-        let instancing_buffer =
-            quad_ctx.new_buffer(BufferType::VertexBuffer, BufferUsage::Immutable, unsafe {
-                BufferSource::slice(&instancing[..])
-            });
+        let instancing_buffer = Instance::default_buffer(quad_ctx.as_mut());
 
         let data = NodeData {
             vertex_buffers: vec![vertex_buffer, uvs_buffer, normals_buffer, instancing_buffer],
             index_buffer,
-
+            index_type: scene::IndexType::U16,
+            morph_targets: None,
+            mesh: mesh_data,
         };
         let material = scene::Material2 {
             color: [1.0, 1.0, 1.0, 1.0],
@@ -227,13 +234,21 @@ impl crate::QuadGl {
             metallic_roughness_texture: None,
             metallic: 0.01,
             roughness: 0.8,
-            shader: scene::Shader::default(quad_ctx.as_mut()),
+            alpha_cutoff: 0.5,
+            // procedural meshes, like `square`, are often meant to be seen from both sides
+            shader: scene::Shader::with_features(
+                quad_ctx.as_mut(),
+                vec![],
+                None,
+                None,
+                scene::ShaderFeatures {
+                    double_sided: true,
+                    ..Default::default()
+                },
+            ),
         };
 
-        let mut aabb = crate::scene::AABB {
-            min: vec3(std::f32::MAX, std::f32::MAX, std::f32::MAX),
-            max: vec3(-std::f32::MAX, -std::f32::MAX, -std::f32::MAX),
-        };
+        let mut aabb = crate::scene::AABB::EMPTY;
         for vertex in &vertices {
             aabb.min = aabb.min.min(*vertex);
             aabb.max = aabb.max.max(*vertex);
@@ -244,8 +259,15 @@ impl crate::QuadGl {
                 data: vec![data],
                 materials: vec![material],
                 transform: Transform::default(),
+                parent: None,
+                children: vec![],
+                skin: None,
+                weights: vec![],
+                light: None,
             }],
             aabb,
+            skins: vec![],
+            animations: vec![],
         }
     }
 }
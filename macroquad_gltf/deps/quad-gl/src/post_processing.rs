@@ -0,0 +1,648 @@
+//! HDR post-processing.
+//!
+//! A camera with a non-empty `post_processing` stack has the scene drawn into
+//! a float render target. Each effect then draws a fullscreen quad sampling
+//! the previous effect's output, the last one drawing into the camera's
+//! `render_target` or the screen.
+
+use crate::{
+    draw_calls_batcher::Vertex,
+    material::Material,
+    math::{vec2, vec4, Mat4, Vec2, Vec4},
+    texture::Texture2D,
+};
+
+use miniquad::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tonemapper {
+    /// Just the exposure, clamped to 0..1.
+    Linear,
+    /// `c / (1 + c)`
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve.
+    Aces,
+}
+
+#[derive(Debug, Clone)]
+pub enum PostEffect {
+    /// Map the HDR colors into 0..1, the effects after it see the LDR image.
+    Tonemap {
+        tonemapper: Tonemapper,
+        /// Colors are multiplied by it first.
+        exposure: f32,
+    },
+    /// Colors brighter than the threshold bleed into their surroundings.
+    Bloom {
+        threshold: f32,
+        intensity: f32,
+        /// Blur passes over the half resolution bright image, each one wider.
+        iterations: u32,
+    },
+    /// Fast approximate antialiasing, for the LDR image after tonemapping.
+    Fxaa,
+    /// Color grading with a lookup table, for the LDR image after tonemapping.
+    ColorGrading {
+        /// A 3D table as a strip of `size` slices, each `size` x `size`, like the
+        /// common 256x16 LUT images. Red goes right in each slice, green goes down
+        /// and blue picks the slice.
+        lut: Texture2D,
+        size: u32,
+        /// 0 keeps the original colors, 1 uses the table's ones.
+        strength: f32,
+    },
+    /// Darken the image towards the corners.
+    Vignette {
+        intensity: f32,
+        /// Distance from the center, as a part of the image height, where the darkening starts.
+        radius: f32,
+        /// How far from the radius the darkening is complete.
+        smoothness: f32,
+    },
+    /// A fullscreen quad drawn with a material from `custom_material`.
+    Custom(Material),
+}
+
+/// Material for a `PostEffect::Custom`.
+///
+/// The fragment shader gets the quad's `varying vec2 uv`, the previous effect's output
+/// as "Texture" and the scene's HDR image as "_ScreenTexture". With the camera's
+/// prepass, "_NormalTexture", "_DepthTexture" and "_OcclusionTexture" are the
+/// `prepass::PrepassTextures`, black, black and white without it. Other textures are
+/// set with `Material::set_texture`, uniforms with `Material::set_uniform`.
+/// The shader is GLSL 100 without the `#version`, like the scene's shaders.
+pub fn custom_material(
+    ctx: &mut Context,
+    fragment: &str,
+    uniforms: Vec<(String, UniformType)>,
+    textures: Vec<String>,
+) -> Result<Material, ShaderError> {
+    let mut meta = ShaderMeta {
+        images: vec![
+            "Texture".to_string(),
+            "_ScreenTexture".to_string(),
+            "_NormalTexture".to_string(),
+            "_DepthTexture".to_string(),
+            "_OcclusionTexture".to_string(),
+        ],
+        uniforms: UniformBlockLayout {
+            uniforms: vec![
+                UniformDesc::new("Projection", UniformType::Mat4),
+                UniformDesc::new("Model", UniformType::Mat4),
+                UniformDesc::new("_Time", UniformType::Float4),
+            ],
+        },
+    };
+    meta.images.extend(textures.iter().cloned());
+    for (name, uniform_type) in &uniforms {
+        meta.uniforms
+            .uniforms
+            .push(UniformDesc::new(name, *uniform_type));
+    }
+    let shader = new_shader(ctx, fragment, meta)?;
+    Material::new2(ctx, shader, PipelineParams::default(), uniforms, textures)
+}
+
+pub(crate) fn new_shader(
+    ctx: &mut Context,
+    fragment: &str,
+    meta: ShaderMeta,
+) -> Result<ShaderId, ShaderError> {
+    let shader = shadermagic::transform(
+        fragment,
+        shader::VERTEX,
+        &meta,
+        &shadermagic::Options::default(),
+    )
+    .unwrap();
+    let shader = shadermagic::choose_appropriate_shader(&shader, &ctx.info());
+    ctx.new_shader(shader, meta)
+}
+
+/// Pipeline drawing a `quad_bindings` quad with a fragment shader over the quad's `uv`.
+pub(crate) fn quad_pipeline(ctx: &mut Context, fragment: &str, meta: ShaderMeta) -> Pipeline {
+    let shader =
+        new_shader(ctx, fragment, meta).unwrap_or_else(|e| panic!("Failed to load shader: {}", e));
+    ctx.new_pipeline(
+        &[BufferLayout::default()],
+        &[
+            VertexAttribute::new("position", VertexFormat::Float3),
+            VertexAttribute::new("texcoord", VertexFormat::Float2),
+            VertexAttribute::new("color0", VertexFormat::Byte4),
+        ],
+        shader,
+        PipelineParams::default(),
+    )
+}
+
+/// Fullscreen quad.
+pub(crate) fn quad_bindings(ctx: &mut Context) -> Bindings {
+    #[rustfmt::skip]
+    let vertices = [
+        Vertex::new(-1.0, -1.0, 0.0, 0.0, 0.0, crate::color::WHITE),
+        Vertex::new( 1.0, -1.0, 0.0, 1.0, 0.0, crate::color::WHITE),
+        Vertex::new( 1.0,  1.0, 0.0, 1.0, 1.0, crate::color::WHITE),
+        Vertex::new(-1.0,  1.0, 0.0, 0.0, 1.0, crate::color::WHITE),
+    ];
+    let indices: [u16; 6] = [0, 1, 2, 0, 2, 3];
+    Bindings {
+        vertex_buffers: vec![ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&vertices),
+        )],
+        index_buffer: ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&indices),
+        ),
+        images: vec![],
+    }
+}
+
+/// Color texture with its render pass.
+pub(crate) struct Target {
+    pub texture: TextureId,
+    pub pass: RenderPass,
+}
+
+impl Target {
+    pub fn new(ctx: &mut Context, width: u32, height: u32, depth: bool) -> Target {
+        let texture = ctx.new_render_texture(TextureParams {
+            width,
+            height,
+            format: TextureFormat::RGBA16F,
+            ..Default::default()
+        });
+        let depth = depth.then(|| {
+            ctx.new_render_texture(TextureParams {
+                width,
+                height,
+                format: TextureFormat::Depth,
+                ..Default::default()
+            })
+        });
+        Target {
+            texture,
+            pass: ctx.new_render_pass(texture, depth),
+        }
+    }
+}
+
+#[repr(C)]
+struct Uniforms {
+    /// Effect specific, see the shaders.
+    params: Vec4,
+    /// Of the sampled texture.
+    texel_size: Vec2,
+}
+
+/// GPU side of the post-processing, shared by all the cameras of a scene.
+pub(crate) struct PostProcessor {
+    bindings: Bindings,
+    tonemap: Pipeline,
+    bright: Pipeline,
+    blur: Pipeline,
+    bloom: Pipeline,
+    fxaa: Pipeline,
+    lut: Pipeline,
+    vignette: Pipeline,
+    size: (u32, u32),
+    /// The scene is drawn here.
+    hdr: Target,
+    /// Outputs of the effects before the last one.
+    ping_pong: [Target; 2],
+    /// Half resolution, for bloom.
+    half: [Target; 2],
+}
+
+impl PostProcessor {
+    pub fn new(ctx: &mut Context) -> PostProcessor {
+        let bindings = quad_bindings(ctx);
+
+        let mut pipeline = |fragment: &str| {
+            let meta = ShaderMeta {
+                images: vec!["Texture".to_string(), "Texture2".to_string()],
+                uniforms: UniformBlockLayout {
+                    uniforms: vec![
+                        UniformDesc::new("Params", UniformType::Float4),
+                        UniformDesc::new("TexelSize", UniformType::Float2),
+                    ],
+                },
+            };
+            quad_pipeline(ctx, fragment, meta)
+        };
+        let tonemap = pipeline(shader::TONEMAP);
+        let bright = pipeline(shader::BRIGHT);
+        let blur = pipeline(shader::BLUR);
+        let bloom = pipeline(shader::BLOOM);
+        let fxaa = pipeline(shader::FXAA);
+        let lut = pipeline(shader::LUT);
+        let vignette = pipeline(shader::VIGNETTE);
+
+        PostProcessor {
+            bindings,
+            tonemap,
+            bright,
+            blur,
+            bloom,
+            fxaa,
+            lut,
+            vignette,
+            size: (1, 1),
+            hdr: Target::new(ctx, 1, 1, true),
+            ping_pong: [Target::new(ctx, 1, 1, false), Target::new(ctx, 1, 1, false)],
+            half: [Target::new(ctx, 1, 1, false), Target::new(ctx, 1, 1, false)],
+        }
+    }
+
+    /// Pass to draw the scene into, resized to the camera's output.
+    pub fn hdr_pass(&mut self, ctx: &mut Context, width: u32, height: u32) -> RenderPass {
+        if self.size != (width, height) {
+            let targets = std::iter::once(&self.hdr)
+                .chain(&self.ping_pong)
+                .chain(&self.half);
+            for target in targets {
+                ctx.delete_render_pass(target.pass);
+            }
+            let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+            self.size = (width, height);
+            self.hdr = Target::new(ctx, width, height, true);
+            self.ping_pong = [
+                Target::new(ctx, width, height, false),
+                Target::new(ctx, width, height, false),
+            ];
+            self.half = [
+                Target::new(ctx, half_width, half_height, false),
+                Target::new(ctx, half_width, half_height, false),
+            ];
+        }
+        self.hdr.pass
+    }
+
+    /// Run the effects over the scene drawn into the `hdr_pass`,
+    /// the last one draws into the output, the screen if `None`.
+    /// `prepass` are the normals, depth and occlusion for the custom materials.
+    pub fn apply(
+        &mut self,
+        ctx: &mut Context,
+        effects: &[PostEffect],
+        output: Option<RenderPass>,
+        prepass: [TextureId; 3],
+    ) {
+        let (width, height) = self.size;
+        let texel_size = vec2(1.0 / width as f32, 1.0 / height as f32);
+        let mut source = self.hdr.texture;
+        for (ix, effect) in effects.iter().enumerate() {
+            let target = if ix + 1 == effects.len() {
+                output
+            } else {
+                Some(self.ping_pong[ix % 2].pass)
+            };
+            let draw = |ctx: &mut Context, pipeline: &Pipeline, images: &[TextureId], params| {
+                self.draw(ctx, target, pipeline, images, params, texel_size);
+            };
+            match effect {
+                PostEffect::Tonemap {
+                    tonemapper,
+                    exposure,
+                } => {
+                    let tonemapper = match tonemapper {
+                        Tonemapper::Linear => 0.0,
+                        Tonemapper::Reinhard => 1.0,
+                        Tonemapper::Aces => 2.0,
+                    };
+                    draw(
+                        ctx,
+                        &self.tonemap,
+                        &[source],
+                        vec4(*exposure, tonemapper, 0.0, 0.0),
+                    );
+                }
+                PostEffect::Bloom {
+                    threshold,
+                    intensity,
+                    iterations,
+                } => {
+                    let bloom = self.blur_bright(ctx, source, *threshold, *iterations);
+                    draw(
+                        ctx,
+                        &self.bloom,
+                        &[source, bloom],
+                        vec4(*intensity, 0.0, 0.0, 0.0),
+                    );
+                }
+                PostEffect::Fxaa => {
+                    draw(ctx, &self.fxaa, &[source], Vec4::ZERO);
+                }
+                PostEffect::ColorGrading {
+                    lut,
+                    size,
+                    strength,
+                } => {
+                    let images = [source, lut.raw_miniquad_id()];
+                    draw(
+                        ctx,
+                        &self.lut,
+                        &images,
+                        vec4(*size as f32, *strength, 0.0, 0.0),
+                    );
+                }
+                PostEffect::Vignette {
+                    intensity,
+                    radius,
+                    smoothness,
+                } => {
+                    let aspect = width as f32 / height as f32;
+                    let params = vec4(*intensity, *radius, *smoothness, aspect);
+                    draw(ctx, &self.vignette, &[source], params);
+                }
+                PostEffect::Custom(material) => {
+                    self.draw_material(ctx, target, material, source, prepass);
+                }
+            }
+            source = self.ping_pong[ix % 2].texture;
+        }
+    }
+
+    /// Bright parts of the image, blurred at half resolution.
+    fn blur_bright(
+        &self,
+        ctx: &mut Context,
+        source: TextureId,
+        threshold: f32,
+        iterations: u32,
+    ) -> TextureId {
+        let (width, height) = self.size;
+        let texel_size = vec2(1.0 / width as f32, 1.0 / height as f32);
+        let half_texel_size = texel_size * 2.0;
+        let params = vec4(threshold, 0.0, 0.0, 0.0);
+        self.draw(
+            ctx,
+            Some(self.half[0].pass),
+            &self.bright,
+            &[source],
+            params,
+            texel_size,
+        );
+        for i in 0..iterations {
+            let spread = 1.0 + i as f32;
+            let [a, b] = &self.half;
+            let horizontal = vec4(spread, 0.0, 0.0, 0.0);
+            self.draw(
+                ctx,
+                Some(b.pass),
+                &self.blur,
+                &[a.texture],
+                horizontal,
+                half_texel_size,
+            );
+            let vertical = vec4(0.0, spread, 0.0, 0.0);
+            self.draw(
+                ctx,
+                Some(a.pass),
+                &self.blur,
+                &[b.texture],
+                vertical,
+                half_texel_size,
+            );
+        }
+        self.half[0].texture
+    }
+
+    fn begin(&self, ctx: &mut Context, target: Option<RenderPass>) {
+        match target {
+            Some(pass) => ctx.begin_pass(Some(pass), PassAction::Nothing),
+            None => ctx.begin_default_pass(PassAction::Nothing),
+        }
+    }
+
+    fn draw(
+        &self,
+        ctx: &mut Context,
+        target: Option<RenderPass>,
+        pipeline: &Pipeline,
+        images: &[TextureId],
+        params: Vec4,
+        texel_size: Vec2,
+    ) {
+        self.begin(ctx, target);
+        ctx.apply_pipeline(pipeline);
+        // both samplers have to be bound
+        let images = [images[0], *images.get(1).unwrap_or(&images[0])];
+        ctx.apply_bindings_from_slice(
+            &self.bindings.vertex_buffers,
+            self.bindings.index_buffer,
+            &images,
+        );
+        ctx.apply_uniforms(UniformsSource::table(&Uniforms { params, texel_size }));
+        ctx.draw(0, 6, 1);
+        ctx.end_render_pass();
+    }
+
+    fn draw_material(
+        &self,
+        ctx: &mut Context,
+        target: Option<RenderPass>,
+        material: &Material,
+        source: TextureId,
+        prepass: [TextureId; 3],
+    ) {
+        let mut material = material.clone();
+        let mut images = vec![source, self.hdr.texture];
+        images.extend(prepass);
+        for name in &material.textures {
+            images.push(material.textures_data.get(name).copied().unwrap_or(source));
+        }
+        let time = miniquad::date::now() as f32;
+        material.set_uniform("Projection", Mat4::IDENTITY);
+        material.set_uniform("Model", Mat4::IDENTITY);
+        material.set_uniform("_Time", vec4(time, time.sin(), time.cos(), 0.0));
+
+        self.begin(ctx, target);
+        ctx.apply_pipeline(&material.pipeline_2d);
+        ctx.apply_bindings_from_slice(
+            &self.bindings.vertex_buffers,
+            self.bindings.index_buffer,
+            &images,
+        );
+        ctx.apply_uniforms_from_bytes(
+            material.uniforms_data.as_ptr(),
+            material.uniforms_data.len(),
+        );
+        ctx.draw(0, 6, 1);
+        ctx.end_render_pass();
+    }
+}
+
+mod shader {
+    pub const VERTEX: &str = r#"
+attribute vec3 position; // [[attribute(0)]]
+attribute vec2 texcoord; // [[attribute(1)]]
+
+varying vec2 uv; // [[user(locn0)]]
+
+void main() {
+    gl_Position = vec4(position, 1.0);
+    uv = texcoord;
+}
+"#;
+
+    /// Params: exposure, tonemapper (0 linear, 1 Reinhard, 2 ACES).
+    pub const TONEMAP: &str = r#"
+varying vec2 uv;
+
+uniform sampler2D Texture;
+uniform vec4 Params;
+
+vec3 aces(vec3 x) {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+void main() {
+    vec4 color = texture2D(Texture, uv);
+    vec3 c = color.rgb * Params.x;
+    if (Params.y > 1.5) {
+        c = aces(c);
+    } else if (Params.y > 0.5) {
+        c = c / (1.0 + c);
+    }
+    gl_FragColor = vec4(clamp(c, 0.0, 1.0), color.a);
+}
+"#;
+
+    /// Params: threshold. Downsamples with 4 taps.
+    pub const BRIGHT: &str = r#"
+varying vec2 uv;
+
+uniform sampler2D Texture;
+uniform vec4 Params;
+uniform vec2 TexelSize;
+
+void main() {
+    vec3 c = (texture2D(Texture, uv + TexelSize * vec2(-0.5, -0.5)).rgb
+        + texture2D(Texture, uv + TexelSize * vec2(0.5, -0.5)).rgb
+        + texture2D(Texture, uv + TexelSize * vec2(-0.5, 0.5)).rgb
+        + texture2D(Texture, uv + TexelSize * vec2(0.5, 0.5)).rgb) * 0.25;
+    float brightness = max(c.r, max(c.g, c.b));
+    c *= max(brightness - Params.x, 0.0) / max(brightness, 0.0001);
+    gl_FragColor = vec4(c, 1.0);
+}
+"#;
+
+    /// Params: direction in texels. 9 taps gaussian.
+    pub const BLUR: &str = r#"
+varying vec2 uv;
+
+uniform sampler2D Texture;
+uniform vec4 Params;
+uniform vec2 TexelSize;
+
+void main() {
+    vec2 offset = Params.xy * TexelSize;
+    vec3 c = texture2D(Texture, uv).rgb * 0.227027;
+    c += texture2D(Texture, uv + offset).rgb * 0.1945946;
+    c += texture2D(Texture, uv - offset).rgb * 0.1945946;
+    c += texture2D(Texture, uv + offset * 2.0).rgb * 0.1216216;
+    c += texture2D(Texture, uv - offset * 2.0).rgb * 0.1216216;
+    c += texture2D(Texture, uv + offset * 3.0).rgb * 0.054054;
+    c += texture2D(Texture, uv - offset * 3.0).rgb * 0.054054;
+    c += texture2D(Texture, uv + offset * 4.0).rgb * 0.016216;
+    c += texture2D(Texture, uv - offset * 4.0).rgb * 0.016216;
+    gl_FragColor = vec4(c, 1.0);
+}
+"#;
+
+    /// Params: intensity. Texture2 is the blurred bright image.
+    pub const BLOOM: &str = r#"
+varying vec2 uv;
+
+uniform sampler2D Texture;
+uniform sampler2D Texture2;
+uniform vec4 Params;
+
+void main() {
+    vec4 color = texture2D(Texture, uv);
+    gl_FragColor = vec4(color.rgb + texture2D(Texture2, uv).rgb * Params.x, color.a);
+}
+"#;
+
+    /// FXAA 3.11 console-like variant, luma from the LDR color.
+    pub const FXAA: &str = r#"
+varying vec2 uv;
+
+uniform sampler2D Texture;
+uniform vec2 TexelSize;
+
+float luma(vec3 c) {
+    return dot(c, vec3(0.299, 0.587, 0.114));
+}
+
+void main() {
+    vec4 color = texture2D(Texture, uv);
+    float nw = luma(texture2D(Texture, uv + vec2(-1.0, -1.0) * TexelSize).rgb);
+    float ne = luma(texture2D(Texture, uv + vec2(1.0, -1.0) * TexelSize).rgb);
+    float sw = luma(texture2D(Texture, uv + vec2(-1.0, 1.0) * TexelSize).rgb);
+    float se = luma(texture2D(Texture, uv + vec2(1.0, 1.0) * TexelSize).rgb);
+    float m = luma(color.rgb);
+    float lo = min(m, min(min(nw, ne), min(sw, se)));
+    float hi = max(m, max(max(nw, ne), max(sw, se)));
+
+    vec2 dir = vec2(-((nw + ne) - (sw + se)), (nw + sw) - (ne + se));
+    float reduce = max((nw + ne + sw + se) * 0.25 * 0.125, 1.0 / 128.0);
+    float scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + reduce);
+    dir = clamp(dir * scale, vec2(-8.0), vec2(8.0)) * TexelSize;
+
+    vec3 a = 0.5 * (texture2D(Texture, uv + dir * (1.0 / 3.0 - 0.5)).rgb
+        + texture2D(Texture, uv + dir * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 b = a * 0.5 + 0.25 * (texture2D(Texture, uv - dir * 0.5).rgb
+        + texture2D(Texture, uv + dir * 0.5).rgb);
+    float lb = luma(b);
+    gl_FragColor = vec4((lb < lo || lb > hi) ? a : b, color.a);
+}
+"#;
+
+    /// Params: table size, strength. Texture2 is the table.
+    pub const LUT: &str = r#"
+varying vec2 uv;
+
+uniform sampler2D Texture;
+uniform sampler2D Texture2;
+uniform vec4 Params;
+
+vec3 lookup(vec2 rg, float slice) {
+    float size = Params.x;
+    return texture2D(Texture2, vec2((slice + rg.x) / size, rg.y)).rgb;
+}
+
+void main() {
+    vec4 color = texture2D(Texture, uv);
+    vec3 c = clamp(color.rgb, 0.0, 1.0);
+    float size = Params.x;
+    // texel centers of a slice
+    vec2 rg = c.rg * (size - 1.0) / size + 0.5 / size;
+    float blue = c.b * (size - 1.0);
+    float slice = floor(blue);
+    vec3 graded = mix(
+        lookup(rg, slice),
+        lookup(rg, min(slice + 1.0, size - 1.0)),
+        blue - slice);
+    gl_FragColor = vec4(mix(c, graded, Params.y), color.a);
+}
+"#;
+
+    /// Params: intensity, radius, smoothness, aspect.
+    pub const VIGNETTE: &str = r#"
+varying vec2 uv;
+
+uniform sampler2D Texture;
+uniform vec4 Params;
+
+void main() {
+    vec4 color = texture2D(Texture, uv);
+    float d = length((uv - 0.5) * vec2(Params.w, 1.0));
+    float vignette = smoothstep(Params.y + Params.z, Params.y, d);
+    gl_FragColor = vec4(color.rgb * mix(1.0, vignette, Params.x), color.a);
+}
+"#;
+}
@@ -0,0 +1,581 @@
+//! Depth and normals prepass and screen-space ambient occlusion.
+//!
+//! A camera with `prepass` or `ssao` has the opaque geometry drawn once more before
+//! the main pass, into two float render targets at the camera's resolution: view space
+//! normals and linear depth. SSAO compares the depth around each pixel with a
+//! hemisphere of samples around its normal, the main pass multiplies the ambient
+//! light by the result.
+//!
+//! The scene's shaders get the three textures as "SceneNormals", "SceneDepth" and
+//! "AmbientOcclusion", sampled with `out_screen.xy / out_screen.w * 0.5 + 0.5`.
+//! Without a prepass they are black, black and white.
+
+use miniquad::*;
+
+use glam::{vec3, vec4, Mat4, Vec2, Vec4};
+use slotmap::SlotMap;
+
+use crate::{
+    camera::Camera,
+    post_processing::{quad_bindings, quad_pipeline, Target},
+    scene::{animation::MAX_JOINTS, instancing::Instance, AlphaMode, Model2, ModelHandle},
+    texture::Texture2D,
+};
+
+/// Should match the size of the `Kernel` array in the SSAO shader.
+pub const MAX_SSAO_SAMPLES: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ssao {
+    /// Of the sampled hemisphere, in world units.
+    pub radius: f32,
+    /// Power of the unoccluded part, 1 is the plain occlusion.
+    pub intensity: f32,
+    /// Depth difference ignored, against flat surfaces occluding themselves.
+    pub bias: f32,
+    /// Per pixel, up to `MAX_SSAO_SAMPLES`.
+    pub samples: usize,
+    /// Smooth out the noise of the per-pixel rotated samples.
+    pub blur: bool,
+}
+
+impl Default for Ssao {
+    fn default() -> Ssao {
+        Ssao {
+            radius: 0.5,
+            intensity: 1.0,
+            bias: 0.025,
+            samples: 16,
+            blur: true,
+        }
+    }
+}
+
+/// Outputs of the last camera drawn with a prepass.
+/// Valid until a camera with a prepass and a different resolution is drawn.
+#[derive(Debug)]
+pub struct PrepassTextures {
+    /// View space normals in rgb, alpha is 0 where nothing was drawn.
+    pub normals: Texture2D,
+    /// Linear distance from the camera plane in r, 0 where nothing was drawn.
+    pub depth: Texture2D,
+    /// Ambient light visibility in r, if the camera has `ssao`.
+    pub occlusion: Option<Texture2D>,
+}
+
+/// GPU side of the prepass, shared by all the cameras of a scene.
+pub(crate) struct Prepass {
+    /// By skinning and double-sidedness.
+    pipelines: [[Pipeline; 2]; 2],
+    quad: Bindings,
+    ssao_pipeline: Pipeline,
+    blur_pipeline: Pipeline,
+    size: (u32, u32),
+    pass: RenderPass,
+    normals: TextureId,
+    depth: TextureId,
+    /// Raw and blurred occlusion.
+    occlusion: [Target; 2],
+    /// Which one of the `occlusion` the last camera with SSAO left, if the last camera had it.
+    pub occlusion_drawn: Option<usize>,
+}
+
+impl Prepass {
+    pub fn new(ctx: &mut Context) -> Prepass {
+        let pipeline = |ctx: &mut Context, skinned: bool, double_sided: bool| {
+            let mut meta = shader::meta();
+            let mut defines = vec![];
+            let mut buffer_layouts = vec![
+                BufferLayout::default(),
+                BufferLayout::default(),
+                BufferLayout::default(),
+                Instance::buffer_layout(),
+            ];
+            let mut attributes = vec![
+                VertexAttribute::with_buffer("in_position", VertexFormat::Float3, 0),
+                VertexAttribute::with_buffer("in_uv", VertexFormat::Float2, 1),
+                VertexAttribute::with_buffer("in_normal", VertexFormat::Float3, 2),
+            ];
+            attributes.extend(Instance::attributes());
+            if skinned {
+                meta.uniforms.uniforms.push(UniformDesc::array(
+                    UniformDesc::new("Joints", UniformType::Mat4),
+                    MAX_JOINTS,
+                ));
+                defines.push("HAS_SKIN".to_string());
+                buffer_layouts.push(BufferLayout::default());
+                buffer_layouts.push(BufferLayout::default());
+                attributes.push(VertexAttribute::with_buffer(
+                    "in_joints",
+                    VertexFormat::Float4,
+                    4,
+                ));
+                attributes.push(VertexAttribute::with_buffer(
+                    "in_weights",
+                    VertexFormat::Float4,
+                    5,
+                ));
+            }
+            let source = shadermagic::transform(
+                shader::FRAGMENT,
+                shader::VERTEX,
+                &meta,
+                &shadermagic::Options {
+                    defines,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            let source = shadermagic::choose_appropriate_shader(&source, &ctx.info());
+            let shader = ctx
+                .new_shader(source, meta)
+                .unwrap_or_else(|e| panic!("Failed to load shader: {}", e));
+            ctx.new_pipeline(
+                &buffer_layouts,
+                &attributes,
+                shader,
+                PipelineParams {
+                    cull_face: if double_sided {
+                        CullFace::Nothing
+                    } else {
+                        CullFace::Back
+                    },
+                    depth_test: Comparison::LessOrEqual,
+                    depth_write: true,
+                    ..Default::default()
+                },
+            )
+        };
+        let pipelines = [
+            [pipeline(ctx, false, false), pipeline(ctx, false, true)],
+            [pipeline(ctx, true, false), pipeline(ctx, true, true)],
+        ];
+        let ssao_pipeline = quad_pipeline(
+            ctx,
+            shader::SSAO,
+            shader::ssao_meta(&["SceneNormals", "SceneDepth"]),
+        );
+        let blur_pipeline = quad_pipeline(ctx, shader::BLUR, shader::ssao_meta(&["Occlusion"]));
+        let (pass, normals, depth) = Prepass::targets(ctx, 1, 1);
+
+        Prepass {
+            pipelines,
+            quad: quad_bindings(ctx),
+            ssao_pipeline,
+            blur_pipeline,
+            size: (1, 1),
+            pass,
+            normals,
+            depth,
+            occlusion: [Target::new(ctx, 1, 1, false), Target::new(ctx, 1, 1, false)],
+            occlusion_drawn: None,
+        }
+    }
+
+    fn targets(ctx: &mut Context, width: u32, height: u32) -> (RenderPass, TextureId, TextureId) {
+        let color = |ctx: &mut Context| {
+            ctx.new_render_texture(TextureParams {
+                width,
+                height,
+                format: TextureFormat::RGBA16F,
+                ..Default::default()
+            })
+        };
+        let normals = color(ctx);
+        let depth = color(ctx);
+        let depth_buffer = ctx.new_render_texture(TextureParams {
+            width,
+            height,
+            format: TextureFormat::Depth,
+            ..Default::default()
+        });
+        let pass = ctx.new_render_pass_mrt(&[normals, depth], Some(depth_buffer));
+        (pass, normals, depth)
+    }
+
+    fn resize(&mut self, ctx: &mut Context, width: u32, height: u32) {
+        if self.size == (width, height) {
+            return;
+        }
+        ctx.delete_render_pass(self.pass);
+        for target in &self.occlusion {
+            ctx.delete_render_pass(target.pass);
+        }
+        self.size = (width, height);
+        (self.pass, self.normals, self.depth) = Prepass::targets(ctx, width, height);
+        self.occlusion = [
+            Target::new(ctx, width, height, false),
+            Target::new(ctx, width, height, false),
+        ];
+    }
+
+    /// Draw the normals and the depth of the opaque and alpha tested primitives.
+    /// The camera's instances should be already uploaded.
+    pub fn draw(
+        &mut self,
+        ctx: &mut Context,
+        models: &SlotMap<ModelHandle, Model2>,
+        drawn: &[ModelHandle],
+        camera: &Camera,
+        (width, height): (u32, u32),
+        white_texture: TextureId,
+    ) {
+        self.resize(ctx, width, height);
+        self.occlusion_drawn = None;
+        let (proj, view) = camera.proj_view();
+
+        ctx.begin_pass(Some(self.pass), PassAction::clear_color(0.0, 0.0, 0.0, 0.0));
+        for h in drawn {
+            let model = &models[*h];
+            let instances = model.drawn_instances();
+            if instances == 0 {
+                continue;
+            }
+            for (node_ix, node) in model.model.nodes.iter().enumerate() {
+                let model_view = view * model.node_matrix(node_ix);
+                let normal_matrix = model_view.inverse().transpose();
+                for (bindings, material) in node.data.iter().zip(&node.materials) {
+                    let features = material.shader.features;
+                    if features.alpha_mode == AlphaMode::Blend {
+                        continue;
+                    }
+                    let skin = node
+                        .skin
+                        .filter(|_| features.skinning)
+                        .map(|skin| &model.joint_matrices[skin]);
+                    let pipeline =
+                        &self.pipelines[skin.is_some() as usize][features.double_sided as usize];
+                    let albedo = material
+                        .base_color_texture
+                        .as_ref()
+                        .map_or(white_texture, |t| t.raw_miniquad_id());
+                    let alpha_cutoff = match features.alpha_mode {
+                        AlphaMode::Mask => material.alpha_cutoff,
+                        _ => 0.0,
+                    };
+                    let uniforms = shader::Uniforms {
+                        projection: proj,
+                        model_view,
+                        normal_matrix,
+                        color: material.color.into(),
+                        material: vec4(alpha_cutoff, 0.0, 0.0, 0.0),
+                    };
+
+                    ctx.apply_pipeline(pipeline);
+                    ctx.apply_bindings_from_slice(
+                        &bindings.vertex_buffers,
+                        bindings.index_buffer,
+                        &[albedo],
+                    );
+                    if let Some(joints) = skin {
+                        let mut uniforms = shader::SkinnedUniforms {
+                            uniforms,
+                            joints: [Mat4::IDENTITY; MAX_JOINTS],
+                        };
+                        let count = joints.len().min(MAX_JOINTS);
+                        uniforms.joints[..count].copy_from_slice(&joints[..count]);
+                        ctx.apply_uniforms(UniformsSource::table(&uniforms));
+                    } else {
+                        ctx.apply_uniforms(UniformsSource::table(&uniforms));
+                    }
+                    let len = ctx.buffer_size(bindings.index_buffer) / bindings.index_type.size();
+                    ctx.draw(0, len as _, instances as _);
+                }
+            }
+        }
+        ctx.end_render_pass();
+    }
+
+    /// Ambient occlusion from the last `draw`.
+    pub fn ssao(&mut self, ctx: &mut Context, ssao: &Ssao, proj: Mat4) {
+        let (width, height) = self.size;
+        let samples = ssao.samples.clamp(1, MAX_SSAO_SAMPLES);
+        let uniforms = shader::SsaoUniforms {
+            projection: proj,
+            kernel: kernel(samples),
+            params: vec4(ssao.radius, ssao.intensity, ssao.bias, samples as f32),
+            texel_size: Vec2::new(1.0 / width as f32, 1.0 / height as f32),
+        };
+        self.draw_quad(
+            ctx,
+            self.occlusion[0].pass,
+            &self.ssao_pipeline,
+            &[self.normals, self.depth],
+            &uniforms,
+        );
+        self.occlusion_drawn = Some(0);
+        if ssao.blur {
+            self.draw_quad(
+                ctx,
+                self.occlusion[1].pass,
+                &self.blur_pipeline,
+                &[self.occlusion[0].texture],
+                &uniforms,
+            );
+            self.occlusion_drawn = Some(1);
+        }
+    }
+
+    fn draw_quad(
+        &self,
+        ctx: &mut Context,
+        pass: RenderPass,
+        pipeline: &Pipeline,
+        images: &[TextureId],
+        uniforms: &shader::SsaoUniforms,
+    ) {
+        ctx.begin_pass(Some(pass), PassAction::Nothing);
+        ctx.apply_pipeline(pipeline);
+        ctx.apply_bindings_from_slice(&self.quad.vertex_buffers, self.quad.index_buffer, images);
+        ctx.apply_uniforms(UniformsSource::table(uniforms));
+        ctx.draw(0, 6, 1);
+        ctx.end_render_pass();
+    }
+
+    /// Normals, depth and occlusion of the last camera, if it had SSAO.
+    pub fn textures(&self) -> (TextureId, TextureId, Option<TextureId>) {
+        let occlusion = self.occlusion_drawn.map(|ix| self.occlusion[ix].texture);
+        (self.normals, self.depth, occlusion)
+    }
+}
+
+/// Sample offsets in the hemisphere around +Z, more of them close to the center.
+/// Every prefix is spread over the whole hemisphere.
+fn kernel(samples: usize) -> [Vec4; MAX_SSAO_SAMPLES] {
+    const GOLDEN_ANGLE: f32 = 2.399_963;
+    let mut kernel = [Vec4::ZERO; MAX_SSAO_SAMPLES];
+    for (i, offset) in kernel.iter_mut().take(samples).enumerate() {
+        // van der Corput sequence
+        let u = (i as u32).reverse_bits() as f32 / 4_294_967_296.0;
+        let phi = i as f32 * GOLDEN_ANGLE;
+        // cosine weighted
+        let r = u.sqrt();
+        let direction = vec3(r * phi.cos(), r * phi.sin(), (1.0 - u).sqrt());
+        let t = (i + 1) as f32 / samples as f32;
+        *offset = (direction * (0.1 + 0.9 * t * t)).extend(0.0);
+    }
+    kernel
+}
+
+mod shader {
+    use glam::{Mat4, Vec2, Vec4};
+    use miniquad::*;
+
+    use crate::scene::animation::MAX_JOINTS;
+
+    use super::MAX_SSAO_SAMPLES;
+
+    pub const VERTEX: &str = r#"
+attribute vec3 in_position; // [[attribute(0)]]
+attribute vec2 in_uv; // [[attribute(1)]]
+attribute vec3 in_normal; // [[attribute(2)]]
+attribute vec4 in_inst0; // [[attribute(3)]]
+attribute vec4 in_inst1; // [[attribute(4)]]
+attribute vec4 in_inst2; // [[attribute(5)]]
+attribute vec4 in_inst3; // [[attribute(6)]]
+attribute vec4 in_tint; // [[attribute(7)]]
+#ifdef HAS_SKIN
+attribute vec4 in_joints; // [[attribute(8)]]
+attribute vec4 in_weights; // [[attribute(9)]]
+#endif
+
+varying vec2 out_uv; // [[user(locn0)]]
+varying vec3 out_view_pos; // [[user(locn1)]]
+varying vec3 out_normal; // [[user(locn2)]]
+varying vec4 out_tint; // [[user(locn3)]]
+
+uniform mat4 Projection;
+uniform mat4 ModelView;
+uniform mat4 NormalMatrix;
+#ifdef HAS_SKIN
+uniform mat4 Joints[64];
+#endif
+
+void main() {
+    vec3 p = in_position;
+    vec3 normal = in_normal;
+#ifdef HAS_SKIN
+    mat4 skin = in_weights.x * Joints[int(in_joints.x)]
+        + in_weights.y * Joints[int(in_joints.y)]
+        + in_weights.z * Joints[int(in_joints.z)]
+        + in_weights.w * Joints[int(in_joints.w)];
+    p = vec3(skin * vec4(p, 1.0));
+    normal = mat3(skin) * normal;
+#endif
+    mat4 inst = mat4(in_inst0, in_inst1, in_inst2, in_inst3);
+    p = vec3(inst * vec4(p, 1.0));
+    vec3 c0 = cross(inst[1].xyz, inst[2].xyz);
+    mat3 inst_normal = mat3(c0, cross(inst[2].xyz, inst[0].xyz), cross(inst[0].xyz, inst[1].xyz));
+    normal = inst_normal * normal * sign(dot(inst[0].xyz, c0));
+    vec4 view_pos = ModelView * vec4(p, 1.0);
+    gl_Position = Projection * view_pos;
+    out_uv = in_uv;
+    out_tint = in_tint;
+    out_view_pos = view_pos.xyz;
+    out_normal = mat3(NormalMatrix) * normal;
+}
+"#;
+
+    /// Material: alpha cutoff, 0 for the opaque primitives.
+    pub const FRAGMENT: &str = r#"
+varying vec2 out_uv;
+varying vec3 out_view_pos;
+varying vec3 out_normal;
+varying vec4 out_tint;
+
+uniform sampler2D Albedo;
+uniform vec4 Color;
+uniform vec4 Material;
+
+layout(location = 0) out vec4 prepass_normal;
+layout(location = 1) out vec4 prepass_depth;
+
+void main() {
+    if (texture2D(Albedo, out_uv).a * Color.a * out_tint.a < Material.x) {
+        discard;
+    }
+    vec3 n = normalize(out_normal);
+    // back faces of the double sided primitives
+    if (dot(n, out_view_pos) > 0.0) {
+        n = -n;
+    }
+    prepass_normal = vec4(n, 1.0);
+    prepass_depth = vec4(-out_view_pos.z, 0.0, 0.0, 1.0);
+}
+"#;
+
+    /// Params: radius, intensity, bias, samples.
+    pub const SSAO: &str = r#"
+varying vec2 uv;
+
+uniform sampler2D SceneNormals;
+uniform sampler2D SceneDepth;
+uniform mat4 Projection;
+uniform vec4 Kernel[32];
+uniform vec4 Params;
+uniform vec2 TexelSize;
+
+vec3 view_position(vec2 p, float depth, mat4 proj) {
+    vec2 ndc = p * 2.0 - 1.0;
+    vec2 scale = vec2(proj[0][0], proj[1][1]);
+    // perspective divides by -z
+    if (proj[2][3] < -0.5) {
+        return vec3((ndc + proj[2].xy) * depth / scale, -depth);
+    }
+    return vec3((ndc - proj[3].xy) / scale, -depth);
+}
+
+float hash(vec2 p) {
+    return fract(sin(dot(p, vec2(12.9898, 78.233))) * 43758.5453);
+}
+
+void main() {
+    float depth = texture2D(SceneDepth, uv).r;
+    float occlusion = 0.0;
+    if (depth > 0.0) {
+        vec3 p = view_position(uv, depth, Projection);
+        vec3 n = texture2D(SceneNormals, uv).xyz;
+        // the kernel is rotated around the normal per pixel, the blur hides the pattern
+        float angle = hash(floor(uv / TexelSize)) * 6.2831853;
+        vec3 random = vec3(cos(angle), sin(angle), 0.0);
+        vec3 t = random - n * dot(random, n);
+        if (dot(t, t) < 0.0001) {
+            t = cross(n, vec3(0.0, 0.0, 1.0));
+        }
+        t = normalize(t);
+        mat3 tbn = mat3(t, cross(n, t), n);
+        float radius = Params.x;
+        int samples = int(Params.w);
+        for (int i = 0; i < 32; i++) {
+            if (i >= samples) {
+                break;
+            }
+            vec3 s = p + tbn * Kernel[i].xyz * radius;
+            vec4 clip = Projection * vec4(s, 1.0);
+            float sample_depth = texture2D(SceneDepth, clip.xy / clip.w * 0.5 + 0.5).r;
+            // occluders far in front of the surface are not around it
+            float range = smoothstep(0.0, 1.0, radius / max(abs(depth - sample_depth), 0.0001));
+            if (sample_depth > 0.0 && sample_depth <= -s.z - Params.z) {
+                occlusion += range;
+            }
+        }
+        occlusion /= float(samples);
+    }
+    gl_FragColor = vec4(vec3(pow(1.0 - occlusion, Params.y)), 1.0);
+}
+"#;
+
+    /// 4x4 box, the size of the noise pattern.
+    pub const BLUR: &str = r#"
+varying vec2 uv;
+
+uniform sampler2D Occlusion;
+uniform vec2 TexelSize;
+
+void main() {
+    float result = 0.0;
+    for (int x = -2; x < 2; x++) {
+        for (int y = -2; y < 2; y++) {
+            vec2 offset = (vec2(float(x), float(y)) + 0.5) * TexelSize;
+            result += texture2D(Occlusion, uv + offset).r;
+        }
+    }
+    gl_FragColor = vec4(vec3(result / 16.0), 1.0);
+}
+"#;
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec!["Albedo".to_string()],
+            uniforms: UniformBlockLayout {
+                uniforms: vec![
+                    UniformDesc::new("Projection", UniformType::Mat4),
+                    UniformDesc::new("ModelView", UniformType::Mat4),
+                    UniformDesc::new("NormalMatrix", UniformType::Mat4),
+                    UniformDesc::new("Color", UniformType::Float4),
+                    UniformDesc::new("Material", UniformType::Float4),
+                ],
+            },
+        }
+    }
+
+    pub fn ssao_meta(images: &[&str]) -> ShaderMeta {
+        ShaderMeta {
+            images: images.iter().map(|image| image.to_string()).collect(),
+            uniforms: UniformBlockLayout {
+                uniforms: vec![
+                    UniformDesc::new("Projection", UniformType::Mat4),
+                    UniformDesc::array(
+                        UniformDesc::new("Kernel", UniformType::Float4),
+                        MAX_SSAO_SAMPLES,
+                    ),
+                    UniformDesc::new("Params", UniformType::Float4),
+                    UniformDesc::new("TexelSize", UniformType::Float2),
+                ],
+            },
+        }
+    }
+
+    #[repr(C)]
+    pub struct Uniforms {
+        pub projection: Mat4,
+        pub model_view: Mat4,
+        pub normal_matrix: Mat4,
+        pub color: Vec4,
+        pub material: Vec4,
+    }
+
+    #[repr(C)]
+    pub struct SkinnedUniforms {
+        pub uniforms: Uniforms,
+        pub joints: [Mat4; MAX_JOINTS],
+    }
+
+    #[repr(C)]
+    pub struct SsaoUniforms {
+        pub projection: Mat4,
+        pub kernel: [Vec4; MAX_SSAO_SAMPLES],
+        pub params: Vec4,
+        pub texel_size: Vec2,
+    }
+}
@@ -5,6 +5,9 @@ use crate::{
     image,
     material::Material,
     math::{vec2, vec3, Mat4, Quat, Vec2, Vec3},
+    post_processing::PostProcessor,
+    prepass::{Prepass, PrepassTextures},
+    shadowmap::{ShadowSettings, ShadowUniforms},
     telemetry, text,
     texture::Texture2D,
     tobytes::ToBytes,
@@ -15,14 +18,71 @@ use crate::material::shaders::{preprocess_shader, PreprocessorConfig};
 
 use miniquad::*;
 
+use slotmap::{SecondaryMap, SlotMap};
+
 use std::sync::{Arc, Mutex};
 
+pub mod animation;
+pub(crate) mod bvh;
 pub mod frustum;
+pub mod instancing;
+pub mod light;
+pub mod morph;
+pub mod raycast;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IndexType {
+    U16,
+    U32,
+}
+
+impl IndexType {
+    pub fn size(&self) -> usize {
+        match self {
+            IndexType::U16 => 2,
+            IndexType::U32 => 4,
+        }
+    }
+}
+
+/// CPU copy of the primitive's vertices, GPU buffers can't be read back.
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    pub uvs: Vec<Vec2>,
+    pub normals: Vec<Vec3>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    /// Center of the positions' bounding box.
+    pub fn center(&self) -> Vec3 {
+        if self.positions.is_empty() {
+            return Vec3::ZERO;
+        }
+        let min = self
+            .positions
+            .iter()
+            .fold(Vec3::splat(f32::MAX), |a, b| a.min(*b));
+        let max = self
+            .positions
+            .iter()
+            .fold(Vec3::splat(-f32::MAX), |a, b| a.max(*b));
+        (min + max) * 0.5
+    }
+}
 
 #[derive(Clone)]
 pub struct NodeData {
     pub vertex_buffers: Vec<miniquad::BufferId>,
     pub index_buffer: miniquad::BufferId,
+    /// Element type of the `index_buffer`.
+    pub index_type: IndexType,
+    /// With morph targets, position and normal buffers are re-uploaded
+    /// each time the node's weights change.
+    pub morph_targets: Option<Arc<morph::MorphTargets>>,
+    /// Rest pose geometry, used for exporting and ray casting.
+    pub mesh: Arc<MeshData>,
 }
 
 #[derive(Clone, Debug)]
@@ -33,24 +93,64 @@ pub struct Uniform {
     byte_size: usize,
 }
 
+/// How the base color's alpha is used, glTF's `alphaMode`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AlphaMode {
+    /// Alpha is ignored.
+    #[default]
+    Opaque,
+    /// Fragments with alpha below `Material2::alpha_cutoff` are discarded, the rest are opaque.
+    Mask,
+    /// Blended over the geometry behind. Drawn after everything opaque,
+    /// back to front and without writing depth.
+    Blend,
+}
+
+/// Optional parts of the default vertex layout and shader.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ShaderFeatures {
+    /// JOINTS_0 and WEIGHTS_0 vertex buffers and a "Joints" matrices uniform.
+    pub skinning: bool,
+    pub alpha_mode: AlphaMode,
+    /// Back faces are not culled and are lit with a flipped normal.
+    pub double_sided: bool,
+}
+
 #[derive(Clone)]
 pub struct Shader {
     pub shader: miniquad::ShaderId,
     pub pipeline: miniquad::Pipeline,
     pub uniforms: Vec<Uniform>,
     pub uniforms_data: Vec<u8>,
+    pub features: ShaderFeatures,
 }
 
 impl Shader {
     pub fn new(
+        ctx: &mut miniquad::Context,
+        uniforms: Vec<(String, UniformType, usize)>,
+        fragment: Option<&str>,
+        vertex: Option<&str>,
+    ) -> Shader {
+        Self::with_features(ctx, uniforms, fragment, vertex, ShaderFeatures::default())
+    }
+
+    pub fn with_features(
         ctx: &mut miniquad::Context,
         mut uniforms: Vec<(String, UniformType, usize)>,
         fragment: Option<&str>,
         vertex: Option<&str>,
+        features: ShaderFeatures,
     ) -> Shader {
-        let mut max_offset = 0;
+        let mut base_meta = shader::meta();
+        if features.skinning {
+            base_meta.uniforms.uniforms.push(UniformDesc::array(
+                UniformDesc::new("Joints", UniformType::Mat4),
+                animation::MAX_JOINTS,
+            ));
+        }
 
-        let mut meta = shader::meta().clone();
+        let mut meta = base_meta.clone();
         for uniform in &uniforms {
             meta.uniforms
                 .uniforms
@@ -61,7 +161,7 @@ impl Shader {
             name,
             uniform_type,
             array_count,
-        } in shader::meta().uniforms.uniforms.into_iter().rev()
+        } in base_meta.uniforms.uniforms.into_iter().rev()
         {
             uniforms.insert(0, (name.to_owned(), uniform_type, array_count));
         }
@@ -92,10 +192,19 @@ impl Shader {
                 )],
             },
         );
-        let defines = vec![
+        let mut defines = vec![
             "HAS_METALLIC_ROUGHNESS_MAP".to_string(),
             "HAS_NORMAL_MAP".to_string(),
         ];
+        if features.skinning {
+            defines.push("HAS_SKIN".to_string());
+        }
+        if features.alpha_mode == AlphaMode::Mask {
+            defines.push("ALPHA_MASK".to_string());
+        }
+        if features.double_sided {
+            defines.push("DOUBLE_SIDED".to_string());
+        }
         let shader = shadermagic::transform(
             fragment.unwrap_or(shader::FRAGMENT),
             &vertex,
@@ -114,32 +223,52 @@ impl Shader {
             .new_shader(shader, meta)
             .unwrap_or_else(|e| panic!("Failed to load shader: {}", e));
 
+        let mut buffer_layouts = vec![
+            BufferLayout::default(),
+            BufferLayout::default(),
+            BufferLayout::default(),
+            instancing::Instance::buffer_layout(),
+        ];
+        let mut attributes = vec![
+            VertexAttribute::with_buffer("in_position", VertexFormat::Float3, 0),
+            VertexAttribute::with_buffer("in_uv", VertexFormat::Float2, 1),
+            VertexAttribute::with_buffer("in_normal", VertexFormat::Float3, 2),
+        ];
+        attributes.extend(instancing::Instance::attributes());
+        if features.skinning {
+            buffer_layouts.push(BufferLayout::default());
+            buffer_layouts.push(BufferLayout::default());
+            attributes.push(VertexAttribute::with_buffer(
+                "in_joints",
+                VertexFormat::Float4,
+                4,
+            ));
+            attributes.push(VertexAttribute::with_buffer(
+                "in_weights",
+                VertexFormat::Float4,
+                5,
+            ));
+        }
+
         let pipeline = ctx.new_pipeline(
-            &[
-                BufferLayout::default(),
-                BufferLayout::default(),
-                BufferLayout::default(),
-                BufferLayout {
-                    step_func: VertexStep::PerInstance,
-                    ..Default::default()
-                },
-            ],
-            &[
-                VertexAttribute::with_buffer("in_position", VertexFormat::Float3, 0),
-                VertexAttribute::with_buffer("in_uv", VertexFormat::Float2, 1),
-                VertexAttribute::with_buffer("in_normal", VertexFormat::Float3, 2),
-                VertexAttribute::with_buffer("in_inst", VertexFormat::Float3, 3),
-            ],
+            &buffer_layouts,
+            &attributes,
             shader,
             PipelineParams {
+                cull_face: if features.double_sided {
+                    CullFace::Nothing
+                } else {
+                    CullFace::Back
+                },
                 depth_test: Comparison::LessOrEqual,
-                depth_write: true,
-                color_blend: Some(BlendState::new(
-                    Equation::Add,
-                    BlendFactor::Value(BlendValue::SourceAlpha),
-                    BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
-                )),
-
+                depth_write: features.alpha_mode != AlphaMode::Blend,
+                color_blend: (features.alpha_mode == AlphaMode::Blend).then(|| {
+                    BlendState::new(
+                        Equation::Add,
+                        BlendFactor::Value(BlendValue::SourceAlpha),
+                        BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+                    )
+                }),
                 ..Default::default()
             },
         );
@@ -149,6 +278,7 @@ impl Shader {
             pipeline,
             uniforms,
             uniforms_data: vec![0; max_offset],
+            features,
         }
     }
 
@@ -174,17 +304,18 @@ impl Shader {
         let uniform_byte_size = uniform_meta.byte_size;
         let uniform_byte_offset = uniform_meta.byte_offset;
 
-        if uniform_byte_size != uniform_byte_size {
+        let data: &[u8] = uniform.to_bytes().as_ref();
+        if data.len() > uniform_byte_size {
             eprintln!(
                 "Trying to set uniform {} sized {} bytes value of {} bytes",
                 name,
-                std::mem::size_of::<T>(),
-                uniform_byte_size
+                uniform_byte_size,
+                data.len()
             );
             return;
         }
-        let data: &[u8] = uniform.to_bytes().as_ref();
-        for i in 0..uniform_byte_size {
+        // arrays could be set partially, the tail keeps its old value
+        for i in 0..data.len() {
             self.uniforms_data[uniform_byte_offset + i] = data[i];
         }
     }
@@ -200,6 +331,9 @@ pub struct Material2 {
     pub metallic_roughness_texture: Option<Texture2D>,
     pub metallic: f32,
     pub roughness: f32,
+    /// Only used with `AlphaMode::Mask`.
+    pub alpha_cutoff: f32,
+    /// Blending, alpha test and face culling are in `shader.features`.
     pub shader: Shader,
 }
 #[derive(Clone)]
@@ -208,6 +342,16 @@ pub struct Node {
     pub data: Vec<NodeData>,
     pub materials: Vec<Material2>,
     pub transform: Transform,
+    /// Index in `Model::nodes`. Parents always come before their children.
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    /// Index in `Model::skins`. Skinned nodes ignore their own and parents transforms,
+    /// the vertices are placed by the skin's joints.
+    pub skin: Option<usize>,
+    /// Morph target weights, shared by all the node's primitives.
+    pub weights: Vec<f32>,
+    /// A light attached to the node, in the node's space: at its origin, shining down -Z.
+    pub light: Option<light::Light>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -216,16 +360,218 @@ pub struct AABB {
     pub max: Vec3,
 }
 
+impl AABB {
+    /// Bounds of nothing, like a model without meshes. The union with any box is that box.
+    pub const EMPTY: AABB = AABB {
+        min: Vec3::splat(f32::MAX),
+        max: Vec3::splat(-f32::MAX),
+    };
+
+    /// No points inside, `EMPTY` or any box with a min past its max.
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    /// The smallest box containing both.
+    pub fn union(&self, other: &AABB) -> AABB {
+        AABB {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// The box containing all 8 transformed corners, empty boxes stay empty.
+    pub fn transformed(&self, matrix: Mat4) -> AABB {
+        if self.is_empty() {
+            return AABB::EMPTY;
+        }
+        let mut min = Vec3::splat(f32::MAX);
+        let mut max = Vec3::splat(-f32::MAX);
+        for corner in 0..8 {
+            let corner = vec3(
+                if corner & 1 == 0 {
+                    self.min.x
+                } else {
+                    self.max.x
+                },
+                if corner & 2 == 0 {
+                    self.min.y
+                } else {
+                    self.max.y
+                },
+                if corner & 4 == 0 {
+                    self.min.z
+                } else {
+                    self.max.z
+                },
+            );
+            let corner = matrix.transform_point3(corner);
+            min = min.min(corner);
+            max = max.max(corner);
+        }
+        AABB { min, max }
+    }
+
+    /// Sphere through the corners, a zero radius one at the origin for an empty box.
+    pub fn bounding_sphere(&self) -> Sphere {
+        if self.is_empty() {
+            return Sphere {
+                center: Vec3::ZERO,
+                radius: 0.0,
+            };
+        }
+        Sphere {
+            center: self.center(),
+            radius: self.size().length() * 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl Sphere {
+    /// Scaling is applied by the largest axis scale, so the sphere stays a sphere.
+    pub fn transformed(&self, matrix: Mat4) -> Sphere {
+        let scale = matrix
+            .x_axis
+            .truncate()
+            .length()
+            .max(matrix.y_axis.truncate().length())
+            .max(matrix.z_axis.truncate().length());
+        Sphere {
+            center: matrix.transform_point3(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Model {
     pub nodes: Vec<Node>,
+    /// Model space bounds of the nodes in their rest pose, `AABB::EMPTY` without meshes.
     pub aabb: AABB,
+    pub skins: Vec<animation::Skin>,
+    pub animations: Vec<animation::AnimationClip>,
+}
+
+impl Model {
+    /// A node by its name, or by a "/" separated path of names from the root,
+    /// like "body/arm/hand".
+    pub fn find_node(&self, name_or_path: &str) -> Option<usize> {
+        if name_or_path.contains('/') {
+            let mut node: Option<usize> = None;
+            for name in name_or_path.split('/') {
+                let child = self
+                    .nodes
+                    .iter()
+                    .position(|child| child.parent == node && child.name == name)?;
+                node = Some(child);
+            }
+            node
+        } else {
+            self.nodes.iter().position(|node| node.name == name_or_path)
+        }
+    }
+
+    /// "/" separated path of names from the root to the node.
+    pub fn node_path(&self, node: usize) -> String {
+        let mut names = vec![self.nodes[node].name.as_str()];
+        let mut parent = self.nodes[node].parent;
+        while let Some(ix) = parent {
+            names.push(&self.nodes[ix].name);
+            parent = self.nodes[ix].parent;
+        }
+        names.reverse();
+        names.join("/")
+    }
+
+    /// Model-space transform of each node, local transforms composed with all the parents.
+    pub fn global_transforms(&self, out: &mut Vec<Mat4>) {
+        out.clear();
+        for node in &self.nodes {
+            let local = node.transform.matrix();
+            let global = match node.parent {
+                Some(parent) => out[parent] * local,
+                None => local,
+            };
+            out.push(global);
+        }
+    }
+
+    /// Nodes matching `find_node`'s path, or all the nodes with the given name.
+    fn matching_nodes(&self, name_or_path: &str) -> Vec<usize> {
+        if name_or_path.contains('/') {
+            self.find_node(name_or_path).into_iter().collect()
+        } else {
+            (0..self.nodes.len())
+                .filter(|ix| self.nodes[*ix].name == name_or_path)
+                .collect()
+        }
+    }
 }
 
 pub struct Model2 {
     pub model: Model,
     pub transform: Transform,
     pub world_aabb: AABB,
+    /// Model bounds placed into the world, the sphere is often tighter than the box
+    /// of a rotated model. Neither follows the animations.
+    pub world_sphere: Sphere,
+    pub animator: animation::Animator,
+    pub(crate) rest_nodes: Vec<Transform>,
+    pub(crate) rest_weights: Vec<Vec<f32>>,
+    pub(crate) skin_poses: Vec<Vec<Transform>>,
+    pub(crate) joint_matrices: Vec<Vec<Mat4>>,
+    /// Weights currently uploaded to the morphed buffers, per node.
+    pub(crate) applied_weights: Vec<Vec<f32>>,
+    /// Model-space node transforms, updated before each draw.
+    pub(crate) node_globals: Vec<Mat4>,
+    /// Node-space bounding box centers of each node's primitives,
+    /// to sort the blended ones by distance.
+    pub(crate) centers: Vec<Vec<Vec3>>,
+    /// `transform` composed with the parents' world transforms.
+    pub(crate) world: Mat4,
+    pub(crate) visible: bool,
+    pub(crate) parent: Option<ModelHandle>,
+    pub(crate) children: Vec<ModelHandle>,
+    /// Leaf of the scene's `bvh`, models with empty bounds are left out of it.
+    pub(crate) proxy: Option<usize>,
+    /// Copies of the model drawn instead of the model itself.
+    pub(crate) instances: Option<instancing::Instances>,
+}
+
+/// Parameters shared by all the draw calls of a `Scene::draw`.
+pub(crate) struct DrawParams<'a> {
+    pub camera: &'a Camera,
+    pub white_texture: TextureId,
+    pub black_texture: TextureId,
+    /// Ambient light for cameras without a skybox.
+    pub default_ibl: &'a crate::cubemap::ibl::Ibl,
+    pub shadows: &'a ShadowUniforms,
+    pub shadowmap: TextureId,
+    /// Normals, depth and ambient occlusion, black, black and white without them.
+    pub prepass: [TextureId; 3],
+}
+
+/// A primitive with `AlphaMode::Blend`, waiting for the transparent pass.
+pub(crate) struct BlendedPrimitive {
+    /// Squared distance from the camera to the primitive's center.
+    pub distance: f32,
+    pub model: ModelHandle,
+    pub node: usize,
+    pub primitive: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -246,13 +592,24 @@ pub struct Scene {
     pub(crate) fonts_storage: Arc<Mutex<text::FontsStorage>>,
 
     pub(crate) cameras: Vec<camera::Camera>,
-    pub(crate) models: Vec<Model2>,
+    pub(crate) models: SlotMap<ModelHandle, Model2>,
+    /// Models by their `world_aabb`s.
+    pub(crate) bvh: bvh::Bvh<ModelHandle>,
+    /// Directional shadows, up to `MAX_SHADOW_CASTERS`.
     pub(crate) shadow_casters: Vec<ShadowCaster>,
+    pub(crate) shadow_settings: ShadowSettings,
+    pub(crate) lights: SlotMap<LightHandle, light::Light>,
 
     pub(crate) white_texture: miniquad::TextureId,
     pub(crate) black_texture: miniquad::TextureId,
+    /// Ambient light for cameras without a skybox.
+    pub(crate) default_ibl: crate::cubemap::ibl::Ibl,
 
     pub(crate) shadowmap: crate::shadowmap::ShadowMap,
+    /// Created by the first camera with post-processing.
+    pub(crate) post_processor: Option<PostProcessor>,
+    /// Created by the first camera with a prepass.
+    pub(crate) prepass: Option<Prepass>,
     //pub(crate) default_material: Material,
 }
 
@@ -286,42 +643,211 @@ impl Transform {
 }
 
 impl Model2 {
+    fn new(ctx: &mut miniquad::Context, model: &Model) -> Model2 {
+        let skin_poses: Vec<_> = model.skins.iter().map(|skin| skin.rest_pose()).collect();
+        let mut joint_matrices = vec![vec![]; model.skins.len()];
+        for (skin, (pose, matrices)) in model
+            .skins
+            .iter()
+            .zip(skin_poses.iter().zip(joint_matrices.iter_mut()))
+        {
+            skin.joint_matrices(pose, matrices);
+        }
+
+        // Morphed buffers are modified in place, so each scene model needs its own copy.
+        let mut model = model.clone();
+        for node in &mut model.nodes {
+            for data in &mut node.data {
+                if let Some(ref morph_targets) = data.morph_targets {
+                    data.vertex_buffers[0] = ctx.new_buffer(
+                        BufferType::VertexBuffer,
+                        BufferUsage::Stream,
+                        BufferSource::slice(&morph_targets.positions[..]),
+                    );
+                    data.vertex_buffers[2] = ctx.new_buffer(
+                        BufferType::VertexBuffer,
+                        BufferUsage::Stream,
+                        BufferSource::slice(&morph_targets.normals[..]),
+                    );
+                }
+            }
+        }
+
+        Model2 {
+            transform: Transform::default(),
+            world_aabb: model.aabb,
+            world_sphere: model.aabb.bounding_sphere(),
+            animator: Default::default(),
+            rest_nodes: model.nodes.iter().map(|n| n.transform.clone()).collect(),
+            rest_weights: model.nodes.iter().map(|n| n.weights.clone()).collect(),
+            skin_poses,
+            joint_matrices,
+            applied_weights: model
+                .nodes
+                .iter()
+                .map(|n| vec![0.0; n.weights.len()])
+                .collect(),
+            node_globals: vec![],
+            world: Mat4::IDENTITY,
+            visible: true,
+            parent: None,
+            children: vec![],
+            proxy: None,
+            instances: None,
+            centers: model
+                .nodes
+                .iter()
+                .map(|node| node.data.iter().map(|data| data.mesh.center()).collect())
+                .collect(),
+            model,
+        }
+    }
+
+    fn clip(&self, name: &str) -> Option<usize> {
+        let clip = self.model.animations.iter().position(|a| a.name == name);
+        if clip.is_none() {
+            eprintln!("Trying to play non-existing animation: {}", name);
+        }
+        clip
+    }
+
+    fn update_pose(&mut self) {
+        let Model2 {
+            model,
+            animator,
+            rest_nodes,
+            rest_weights,
+            skin_poses,
+            joint_matrices,
+            ..
+        } = self;
+        animation::evaluate(
+            animator,
+            &model.animations,
+            &model.skins,
+            animation::Rest {
+                nodes: rest_nodes,
+                weights: rest_weights,
+            },
+            animation::Pose {
+                nodes: &mut model.nodes,
+                skins: skin_poses,
+            },
+        );
+        for ((skin, pose), matrices) in model
+            .skins
+            .iter()
+            .zip(skin_poses.iter())
+            .zip(joint_matrices.iter_mut())
+        {
+            skin.joint_matrices(pose, matrices);
+        }
+    }
+
+    /// Re-upload morphed vertices of the nodes whose weights changed since the last draw.
+    fn update_morphs(&mut self, ctx: &mut miniquad::Context) {
+        let mut positions = vec![];
+        let mut normals = vec![];
+        for (node, applied) in self.model.nodes.iter().zip(&mut self.applied_weights) {
+            if node.weights == *applied {
+                continue;
+            }
+            for data in &node.data {
+                if let Some(ref morph_targets) = data.morph_targets {
+                    morph_targets.apply(&node.weights, &mut positions, &mut normals);
+                    ctx.buffer_update(data.vertex_buffers[0], BufferSource::slice(&positions[..]));
+                    ctx.buffer_update(data.vertex_buffers[2], BufferSource::slice(&normals[..]));
+                }
+            }
+            applied.clone_from(&node.weights);
+        }
+    }
+
+    fn update_globals(&mut self) {
+        self.model.global_transforms(&mut self.node_globals);
+    }
+
     fn update_aabb(&mut self) {
-        let aabb = self.model.aabb;
-        let min = self.transform.matrix().transform_point3(aabb.min);
-        let max = self.transform.matrix().transform_point3(aabb.max);
-        self.world_aabb = AABB { min, max };
+        let Some(ref mut instances) = self.instances else {
+            self.world_aabb = self.model.aabb.transformed(self.world);
+            self.world_sphere = self.model.aabb.bounding_sphere().transformed(self.world);
+            return;
+        };
+        let (aabb, world) = (self.model.aabb, self.world);
+        instances.aabbs = instances
+            .matrices
+            .iter()
+            .map(|matrix| aabb.transformed(world * *matrix))
+            .collect();
+        // without instances nothing is drawn
+        self.world_aabb = instances.aabbs.iter().fold(AABB::EMPTY, |a, b| a.union(b));
+        self.world_sphere = self.world_aabb.bounding_sphere();
+    }
+
+    /// Buffers created by `Model2::new` and the instance buffers,
+    /// the rest are shared with the `Model`.
+    fn delete_buffers(&mut self, ctx: &mut miniquad::Context) {
+        if let Some(ref mut instances) = self.instances {
+            instances.delete_buffers(ctx);
+        }
+        for node in &self.model.nodes {
+            for data in &node.data {
+                if data.morph_targets.is_some() {
+                    ctx.delete_buffer(data.vertex_buffers[0]);
+                    ctx.delete_buffer(data.vertex_buffers[2]);
+                }
+            }
+        }
     }
 }
-#[derive(Clone)]
-pub struct ModelHandle(usize);
+
+slotmap::new_key_type! {
+    /// Stays valid until the model is removed, after that it doesn't refer to any
+    /// other model, even if the scene reuses the slot.
+    pub struct ModelHandle;
+
+    pub struct LightHandle;
+}
 
 impl Scene {
+    /// World space bounds of the model.
     pub fn aabb(&self, h: &ModelHandle) -> AABB {
-        self.models[h.0].world_aabb
+        self.models[*h].world_aabb
+    }
+    pub fn bounding_sphere(&self, h: &ModelHandle) -> Sphere {
+        self.models[*h].world_sphere
+    }
+    /// Bounds of all the visible models, `None` if there are none or all of them are empty.
+    pub fn scene_aabb(&self) -> Option<AABB> {
+        self.models
+            .iter()
+            .filter(|(h, _)| self.is_drawn(*h))
+            .map(|(_, model)| model.world_aabb)
+            .filter(|aabb| !aabb.is_empty())
+            .reduce(|a, b| a.union(&b))
     }
     pub fn set_translation(&mut self, h: &ModelHandle, pos: Vec3) {
-        self.models[h.0].transform.translation = pos;
-        self.models[h.0].update_aabb();
+        self.models[*h].transform.translation = pos;
+        self.update_world(*h);
     }
     pub fn set_rotation(&mut self, h: &ModelHandle, rotation: Quat) {
-        self.models[h.0].transform.rotation = rotation;
-        self.models[h.0].update_aabb();
+        self.models[*h].transform.rotation = rotation;
+        self.update_world(*h);
     }
     pub fn set_scale(&mut self, h: &ModelHandle, scale: Vec3) {
-        self.models[h.0].transform.scale = scale;
-        self.models[h.0].update_aabb();
+        self.models[*h].transform.scale = scale;
+        self.update_world(*h);
     }
 
     pub fn translation(&self, h: &ModelHandle) -> Vec3 {
-        self.models[h.0].transform.translation
+        self.models[*h].transform.translation
     }
     pub fn rotation(&self, h: &ModelHandle) -> Quat {
-        self.models[h.0].transform.rotation
+        self.models[*h].transform.rotation
     }
 
     pub fn materials(&mut self, h: &ModelHandle) -> impl Iterator<Item = &mut Material2> {
-        self.models[h.0]
+        self.models[*h]
             .model
             .nodes
             .iter_mut()
@@ -329,31 +855,169 @@ impl Scene {
             .flatten()
     }
 
-    pub fn update_multi_positions(&mut self, h: &ModelHandle, positions: &[Vec3]) {
-        let mut model = &mut self.models[h.0];
+    /// Draw the model once per transform instead, each placed relative to the model.
+    /// The colors of the first instances are kept, the new ones are white.
+    pub fn set_instances(&mut self, h: &ModelHandle, instances: &[Transform]) {
+        let model = &mut self.models[*h];
+        let matrices: Vec<Mat4> = instances.iter().map(Transform::matrix).collect();
+        let nodes = model.model.nodes.len();
         let mut ctx = self.quad_ctx.lock().unwrap();
-        for mut child in &mut model.model.nodes {
-            for mut bindings in &mut child.data {
-                let old_vec_size = ctx.buffer_size(bindings.vertex_buffers[3]) as i32 / 12;
-                let new_vec_size = positions.len();
-                if old_vec_size != new_vec_size as i32 {
-                    bindings.vertex_buffers[3] =
-                        ctx.new_buffer(BufferType::VertexBuffer, BufferUsage::Stream, unsafe {
-                            BufferSource::slice(positions)
-                        });
-                } else {
-                    ctx.buffer_update(bindings.vertex_buffers[3], BufferSource::slice(positions));
+        match model.instances {
+            Some(ref mut instances) => {
+                instances.colors.resize(matrices.len(), crate::color::WHITE);
+                instances.matrices = matrices;
+                if instances.reserve(ctx.as_mut(), nodes) {
+                    model.bind_instances();
                 }
             }
+            None => {
+                model.instances = Some(instancing::Instances::new(ctx.as_mut(), nodes, matrices));
+                model.bind_instances();
+            }
         }
+        drop(ctx);
+        self.update_world(*h);
+    }
+
+    /// Tint of one of the instances set by `set_instances`, multiplies the material colors.
+    pub fn set_instance_color(&mut self, h: &ModelHandle, instance: usize, color: Color) {
+        let Some(instances) = self.models[*h].instances.as_mut() else {
+            eprintln!("Trying to set instance color on a model without instances");
+            return;
+        };
+        let Some(tint) = instances.colors.get_mut(instance) else {
+            eprintln!(
+                "Trying to set color of instance {} out of {}",
+                instance,
+                instances.colors.len()
+            );
+            return;
+        };
+        *tint = color;
+    }
+
+    /// Instances set by `set_instances`, 1 for the models drawn once.
+    pub fn instance_count(&self, h: &ModelHandle) -> usize {
+        self.models[*h]
+            .instances
+            .as_ref()
+            .map_or(1, |instances| instances.matrices.len())
     }
 
+    /// Modify local transform of the child nodes with the given name,
+    /// or of the single node with the given "/" separated path, like "body/arm/hand".
     pub fn update_child(&mut self, h: &ModelHandle, name: &str, f: impl Fn(&mut Transform)) {
-        let model = &mut self.models[h.0];
-        for child in &mut model.model.nodes {
-            if child.name == name {
-                f(&mut child.transform)
+        let model = &mut self.models[*h].model;
+        for ix in model.matching_nodes(name) {
+            f(&mut model.nodes[ix].transform)
+        }
+    }
+
+    /// Local transform of the child node, by name or "/" separated path.
+    pub fn child_transform(&self, h: &ModelHandle, name: &str) -> Option<Transform> {
+        let model = &self.models[*h].model;
+        model
+            .find_node(name)
+            .map(|ix| model.nodes[ix].transform.clone())
+    }
+
+    /// World transform of the child node, by name or "/" separated path:
+    /// the model transform composed with all the node's parents.
+    pub fn child_world_transform(&self, h: &ModelHandle, name: &str) -> Option<Mat4> {
+        let model = &self.models[*h];
+        let ix = model.model.find_node(name)?;
+        let mut globals = vec![];
+        model.model.global_transforms(&mut globals);
+        Some(model.world * globals[ix])
+    }
+
+    /// Morph target weights of the child node, by name or "/" separated path.
+    pub fn morph_weights(&self, h: &ModelHandle, name: &str) -> Option<&[f32]> {
+        let model = &self.models[*h].model;
+        model.find_node(name).map(|ix| &model.nodes[ix].weights[..])
+    }
+
+    /// Set morph target weights of the child nodes, matched like in `update_child`.
+    /// Extra weights are ignored, missing ones keep their current values.
+    pub fn set_morph_weights(&mut self, h: &ModelHandle, name: &str, weights: &[f32]) {
+        let model = &mut self.models[*h].model;
+        for ix in model.matching_nodes(name) {
+            for (weight, new_weight) in model.nodes[ix].weights.iter_mut().zip(weights) {
+                *weight = *new_weight;
+            }
+        }
+    }
+
+    /// Names of all the animation clips of the model.
+    pub fn animations(&self, h: &ModelHandle) -> impl Iterator<Item = &str> {
+        self.models[*h]
+            .model
+            .animations
+            .iter()
+            .map(|a| a.name.as_str())
+    }
+
+    /// Stop all the playing clips and play the given one with full weight.
+    pub fn play_animation(&mut self, h: &ModelHandle, name: &str, looping: bool) {
+        let model = &mut self.models[*h];
+        if let Some(clip) = model.clip(name) {
+            model.animator.play(clip, looping);
+        }
+    }
+
+    /// Play the clip together with already playing ones.
+    /// Clips are blended proportionally to their weights, if total weight is less than 1.0
+    /// the rest pose takes the remaining part.
+    pub fn blend_animation(&mut self, h: &ModelHandle, name: &str, weight: f32, looping: bool) {
+        let model = &mut self.models[*h];
+        if let Some(clip) = model.clip(name) {
+            model.animator.blend(clip, weight, looping);
+        }
+    }
+
+    /// Fade all the playing clips out and the given clip in, over `duration` seconds.
+    pub fn cross_fade_animation(
+        &mut self,
+        h: &ModelHandle,
+        name: &str,
+        duration: f32,
+        looping: bool,
+    ) {
+        let model = &mut self.models[*h];
+        if let Some(clip) = model.clip(name) {
+            model.animator.cross_fade(clip, duration, looping);
+        }
+    }
+
+    /// Stop all the clips and put the model back into its rest pose.
+    pub fn stop_animations(&mut self, h: &ModelHandle) {
+        let model = &mut self.models[*h];
+        model.animator.stop();
+        for (node, rest) in model.model.nodes.iter_mut().zip(&model.rest_nodes) {
+            node.transform = rest.clone();
+        }
+        for (node, rest) in model.model.nodes.iter_mut().zip(&model.rest_weights) {
+            node.weights.clone_from(rest);
+        }
+        for (pose, skin) in model.skin_poses.iter_mut().zip(&model.model.skins) {
+            *pose = skin.rest_pose();
+        }
+        model.update_pose();
+    }
+
+    /// Direct access to the playing layers: playback speed, time, weights.
+    pub fn animator(&mut self, h: &ModelHandle) -> &mut animation::Animator {
+        &mut self.models[*h].animator
+    }
+
+    /// Advance all the playing animations by `dt` seconds.
+    pub fn update_animations(&mut self, dt: f32) {
+        for model in self.models.values_mut() {
+            if !model.animator.is_playing() {
+                continue;
             }
+            model.animator.advance(&model.model.animations, dt);
+            model.update_pose();
         }
     }
 }
@@ -391,13 +1055,19 @@ impl Scene {
         Scene {
             white_texture: ctx.new_texture_from_rgba8(1, 1, &[255, 255, 255, 255]),
             black_texture: ctx.new_texture_from_rgba8(1, 1, &[0, 0, 0, 0]),
+            default_ibl: crate::cubemap::ibl::Ibl::uniform(ctx.as_mut(), Vec3::ONE),
             fonts_storage: fonts_storage.clone(),
 
             cameras: vec![],
-            models: vec![],
+            models: SlotMap::with_key(),
+            bvh: bvh::Bvh::new(),
             shadow_casters: vec![],
+            shadow_settings: ShadowSettings::default(),
+            lights: SlotMap::with_key(),
 
             shadowmap: crate::shadowmap::ShadowMap::new(ctx.as_mut()),
+            post_processor: None,
+            prepass: None,
             //default_material,
             quad_ctx,
         }
@@ -414,17 +1084,234 @@ impl Scene {
         self.shadow_casters.push(shadow_caster);
     }
 
+    pub fn shadow_settings(&self) -> &ShadowSettings {
+        &self.shadow_settings
+    }
+
+    /// Shadow maps are resized with the next `draw`.
+    pub fn set_shadow_settings(&mut self, settings: ShadowSettings) {
+        self.shadow_settings = settings;
+    }
+
+    /// A world space light. Lights of the models' nodes are added with the models.
+    pub fn add_light(&mut self, light: light::Light) -> LightHandle {
+        self.lights.insert(light)
+    }
+
+    pub fn remove_light(&mut self, h: &LightHandle) {
+        self.lights.remove(*h);
+    }
+
+    pub fn light(&self, h: &LightHandle) -> &light::Light {
+        &self.lights[*h]
+    }
+
+    pub fn light_mut(&mut self, h: &LightHandle) -> &mut light::Light {
+        &mut self.lights[*h]
+    }
+
     pub fn add_model(&mut self, model: &Model) -> ModelHandle {
-        self.models.push(Model2 {
-            model: model.clone(),
-            transform: Transform {
-                translation: vec3(0.0, 0.0, 0.0),
-                scale: vec3(1., 1., 1.),
-                rotation: Quat::IDENTITY,
-            },
-            world_aabb: model.aabb,
+        let mut ctx = self.quad_ctx.lock().unwrap();
+        let h = self.models.insert(Model2::new(ctx.as_mut(), model));
+        drop(ctx);
+        self.update_world(h);
+        h
+    }
+
+    /// Add a model drawn once per transform, with a single draw call per primitive.
+    /// Instances are culled one by one, both for the camera and the shadow maps.
+    pub fn add_instanced_model(&mut self, model: &Model, instances: &[Transform]) -> ModelHandle {
+        let h = self.add_model(model);
+        self.set_instances(&h, instances);
+        h
+    }
+
+    /// Remove the model together with all its children.
+    /// Does nothing if the model was already removed.
+    pub fn remove_model(&mut self, h: &ModelHandle) {
+        let Some(mut model) = self.models.remove(*h) else {
+            return;
+        };
+        if let Some(parent) = model.parent {
+            self.models[parent].children.retain(|child| child != h);
+        }
+        if let Some(proxy) = model.proxy {
+            self.bvh.remove(proxy);
+        }
+        model.delete_buffers(self.quad_ctx.lock().unwrap().as_mut());
+        for child in &model.children {
+            // already detached, don't touch the removed parent
+            self.models[*child].parent = None;
+            self.remove_model(child);
+        }
+    }
+
+    /// `false` once the model is removed.
+    pub fn contains_model(&self, h: &ModelHandle) -> bool {
+        self.models.contains_key(*h)
+    }
+
+    /// All the models in the scene, in no particular order.
+    pub fn models(&self) -> impl Iterator<Item = (ModelHandle, &Model2)> {
+        self.models.iter()
+    }
+
+    /// Hidden models, and all their children, are not drawn and don't cast shadows.
+    /// Their animations and lights keep working.
+    pub fn set_visible(&mut self, h: &ModelHandle, visible: bool) {
+        self.models[*h].visible = visible;
+    }
+
+    pub fn is_visible(&self, h: &ModelHandle) -> bool {
+        self.models[*h].visible
+    }
+
+    /// Attach the model to another one, or detach it with `None`.
+    /// The model's transform becomes relative to the parent's one.
+    /// Panics if the model is the parent itself or one of its ancestors.
+    pub fn set_parent(&mut self, h: &ModelHandle, parent: Option<&ModelHandle>) {
+        let mut ancestor = parent.copied();
+        while let Some(ix) = ancestor {
+            assert!(ix != *h, "a model can't be its own ancestor");
+            ancestor = self.models[ix].parent;
+        }
+
+        if let Some(old) = self.models[*h].parent {
+            self.models[old].children.retain(|child| child != h);
+        }
+        if let Some(parent) = parent {
+            self.models[*parent].children.push(*h);
+        }
+        self.models[*h].parent = parent.copied();
+        self.update_world(*h);
+    }
+
+    pub fn parent(&self, h: &ModelHandle) -> Option<ModelHandle> {
+        self.models[*h].parent
+    }
+
+    pub fn children(&self, h: &ModelHandle) -> &[ModelHandle] {
+        &self.models[*h].children
+    }
+
+    /// Recompute the world transform and bounds of the model and all its children.
+    fn update_world(&mut self, h: ModelHandle) {
+        let parent = self.models[h]
+            .parent
+            .map_or(Mat4::IDENTITY, |p| self.models[p].world);
+        let model = &mut self.models[h];
+        model.world = parent * model.transform.matrix();
+        model.update_aabb();
+        match (model.proxy, model.world_aabb.is_empty()) {
+            (Some(proxy), false) => self.bvh.update(proxy, model.world_aabb),
+            (Some(proxy), true) => {
+                self.bvh.remove(proxy);
+                model.proxy = None;
+            }
+            (None, false) => model.proxy = Some(self.bvh.insert(model.world_aabb, h)),
+            (None, true) => {}
+        }
+        for ix in 0..model.children.len() {
+            let child = self.models[h].children[ix];
+            self.update_world(child);
+        }
+    }
+
+    fn is_drawn(&self, h: ModelHandle) -> bool {
+        is_drawn(&self.models, h)
+    }
+
+    /// Models whose bounds overlap the box, visible or not.
+    pub fn query_aabb(&self, aabb: &AABB) -> Vec<ModelHandle> {
+        let overlaps =
+            |other: &AABB| aabb.min.cmple(other.max).all() && aabb.max.cmpge(other.min).all();
+        let mut models = vec![];
+        self.bvh.query(overlaps, |h| {
+            if overlaps(&self.models[h].world_aabb) {
+                models.push(h);
+            }
+        });
+        models
+    }
+
+    /// Models whose bounding boxes overlap the sphere, visible or not.
+    pub fn query_sphere(&self, sphere: &Sphere) -> Vec<ModelHandle> {
+        let overlaps = |aabb: &AABB| {
+            let closest = sphere.center.clamp(aabb.min, aabb.max);
+            closest.distance_squared(sphere.center) <= sphere.radius * sphere.radius
+        };
+        let mut models = vec![];
+        self.bvh.query(overlaps, |h| {
+            if overlaps(&self.models[h].world_aabb) {
+                models.push(h);
+            }
         });
-        ModelHandle(self.models.len() - 1)
+        models
+    }
+
+    /// Visible models in the camera's view.
+    pub fn query_frustum(&self, camera: &Camera) -> Vec<ModelHandle> {
+        visible_in(&self.models, &self.bvh, &frustum::projection_planes(camera))
+    }
+
+    /// The closest triangle of the visible models hit by the ray.
+    /// Models are tested in their current node transforms, but skinned and morphed
+    /// geometry in its rest pose.
+    pub fn raycast(&self, ray: &raycast::Ray) -> Option<raycast::Hit> {
+        let mut candidates: Vec<(f32, ModelHandle)> = vec![];
+        self.bvh.query(
+            |aabb| ray.intersect_aabb(aabb).is_some(),
+            |h| {
+                if let Some(distance) = ray.intersect_aabb(&self.models[h].world_aabb) {
+                    if self.is_drawn(h) {
+                        candidates.push((distance, h));
+                    }
+                }
+            },
+        );
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut closest: Option<raycast::Hit> = None;
+        let mut globals = vec![];
+        for (distance, h) in candidates {
+            if closest
+                .as_ref()
+                .map_or(false, |hit| hit.distance < distance)
+            {
+                break;
+            }
+            let model = &self.models[h];
+            model.model.global_transforms(&mut globals);
+            let placements = model.placements(|aabb| ray.intersect_aabb(aabb).is_some());
+            for (instance, placement) in placements {
+                for (node_ix, node) in model.model.nodes.iter().enumerate() {
+                    let matrix = match node.skin {
+                        Some(_) => placement,
+                        None => placement * globals[node_ix],
+                    };
+                    for (primitive, data) in node.data.iter().enumerate() {
+                        let Some((distance, triangle, normal)) =
+                            raycast::intersect_mesh(ray, &data.mesh, matrix)
+                        else {
+                            continue;
+                        };
+                        if closest.as_ref().map_or(true, |hit| distance < hit.distance) {
+                            closest = Some(raycast::Hit {
+                                model: h,
+                                instance,
+                                node: node_ix,
+                                primitive,
+                                triangle,
+                                distance,
+                                point: ray.point(distance),
+                                normal,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        closest
     }
 
     // pub fn add_multi_model(&mut self, model: &Model, multi_position: Vec<Vec3>) -> ModelHandle {
@@ -506,108 +1393,46 @@ impl Scene {
         unimplemented!()
     }
 
+    /// Draw the model's opaque and alpha tested primitives, blended ones are only collected
+    /// into `blended` to be sorted and drawn once everything opaque is in the depth buffer.
+    /// Returns the lights affecting the model, `None` if it is out of the frustum.
     pub(crate) fn draw_model(
         ctx: &mut miniquad::Context,
-        white_texture: TextureId,
-        black_texture: TextureId,
+        params: &DrawParams,
+        model_ix: ModelHandle,
         model: &mut Model2,
-        camera: &camera::Camera,
-        shadow_proj: [Mat4; 4],
-        shadow_cascades: [f32; 4],
-        shadowmap: [TextureId; 4],
-        shadow_casters: [i32; 4],
         clipping_planes: [frustum::Plane; 6],
-    ) {
+        lights: &[light::Light],
+        blended: &mut Vec<BlendedPrimitive>,
+    ) -> Option<light::LightUniforms> {
         // unsafe {
         //     miniquad::gl::glPolygonMode(miniquad::gl::GL_FRONT_AND_BACK, miniquad::gl::GL_LINE);
         // }
 
-        let transform = model.transform.matrix();
         let aabb = model.world_aabb;
-        let m = &model;
-        let model = &mut model.model;
         if clipping_planes.iter().any(|p| !p.clip(aabb)) {
-            return;
+            return None;
         }
-        for node in &mut model.nodes {
-            for (bindings, material) in node.data.iter_mut().zip(node.materials.iter_mut()) {
-                let cubemap = match camera.environment {
-                    crate::camera::Environment::Skybox(ref cubemap) => Some(cubemap.texture),
-                    _ => None,
-                };
-                let or_white = |t: &Option<Texture2D>| {
-                    t.as_ref().map_or(white_texture, |t| t.raw_miniquad_id())
-                };
-                let or_black = |t: &Option<Texture2D>| {
-                    t.as_ref().map_or(black_texture, |t| t.raw_miniquad_id())
-                };
-                let images = [
-                    or_white(&material.base_color_texture),
-                    or_black(&material.emissive_texture),
-                    or_white(&material.occlusion_texture),
-                    or_white(&material.normal_texture),
-                    or_white(&material.metallic_roughness_texture),
-                    cubemap.unwrap_or(white_texture),
-                    shadowmap[0],
-                    shadowmap[1],
-                    shadowmap[2],
-                    shadowmap[3],
-                ];
-                ctx.apply_pipeline(&material.shader.pipeline);
-                assert_eq!(bindings.vertex_buffers.len(), 4);
-                ctx.apply_bindings_from_slice(
-                    &bindings.vertex_buffers,
-                    bindings.index_buffer,
-                    &images,
-                );
-
-                let (proj, view) = camera.proj_view();
-
-                let projection = proj * view;
-                let time = (miniquad::date::now()) as f32;
-                let time = glam::vec4(time, time.sin(), time.cos(), 0.);
-
-                let model_matrix = transform * node.transform.matrix();
-                let model_matrix_inverse = model_matrix.inverse();
-                // ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
-                //     projection,
-                //     shadow_projection: shadow_proj,
-                //     model: model_matrix,
-                //     model_inverse: model_matrix_inverse,
-                //     color: material.color,
-                //     shadow_cascades,
-                //     shadow_casters,
-                //     material: [material.metallic, material.roughness, 0.0, 0.0],
-                //     camera_pos: camera.position,
-                // }));
-                material.shader.set_uniform("Projection", projection);
-                // TODO: implement the array thing
-                material
-                    .shader
-                    .set_uniform("ShadowProjection", &shadow_proj[..]);
-                material.shader.set_uniform("Model", model_matrix);
-                material
-                    .shader
-                    .set_uniform("ModelInverse", model_matrix_inverse);
-                material.shader.set_uniform("Color", material.color);
-                material
-                    .shader
-                    .set_uniform("ShadowCascades", shadow_cascades);
-                material.shader.set_uniform("ShadowCasters", shadow_casters);
-                material.shader.set_uniform(
-                    "Material",
-                    [material.metallic, material.roughness, 0.0, 0.0],
-                );
-                material
-                    .shader
-                    .set_uniform("CameraPosition", camera.position);
-                ctx.apply_uniforms_from_bytes(
-                    material.shader.uniforms_data.as_ptr(),
-                    material.shader.uniforms_data.len(),
-                );
-                let buffer_size = ctx.buffer_size(bindings.index_buffer) as i32 / 2;
-                let multi_size = ctx.buffer_size(bindings.vertex_buffers[3]) as i32 / 12;
-                ctx.draw(0, buffer_size, multi_size);
+        let lights = light::cull(lights, &params.shadows.light_maps, &aabb);
+        let transform = model.world;
+        for node_ix in 0..model.model.nodes.len() {
+            for primitive in 0..model.model.nodes[node_ix].data.len() {
+                let node = &model.model.nodes[node_ix];
+                if node.materials[primitive].shader.features.alpha_mode == AlphaMode::Blend {
+                    let model_matrix = match node.skin {
+                        Some(_) => transform,
+                        None => transform * model.node_globals[node_ix],
+                    };
+                    let center = model_matrix.transform_point3(model.centers[node_ix][primitive]);
+                    blended.push(BlendedPrimitive {
+                        distance: center.distance_squared(params.camera.position),
+                        model: model_ix,
+                        node: node_ix,
+                        primitive,
+                    });
+                    continue;
+                }
+                Scene::draw_primitive(ctx, params, model, node_ix, primitive, &lights);
             }
         }
 
@@ -615,6 +1440,138 @@ impl Scene {
         //     use miniquad::gl;
         //     gl::glPolygonMode(gl::GL_FRONT_AND_BACK, gl::GL_FILL);
         // }
+        Some(lights)
+    }
+
+    fn draw_primitive(
+        ctx: &mut miniquad::Context,
+        params: &DrawParams,
+        model: &mut Model2,
+        node_ix: usize,
+        primitive: usize,
+        lights: &light::LightUniforms,
+    ) {
+        let instances = model.drawn_instances();
+        if instances == 0 {
+            return;
+        }
+        let model_matrix = model.node_matrix(node_ix);
+        let joint_matrices = &model.joint_matrices;
+        let node = &mut model.model.nodes[node_ix];
+        let bindings = &node.data[primitive];
+        let material = &mut node.materials[primitive];
+        let camera = params.camera;
+
+        let ibl = match camera.environment {
+            crate::camera::Environment::Skybox(ref cubemap) => &cubemap.ibl,
+            _ => params.default_ibl,
+        };
+        let or_white = |t: &Option<Texture2D>| {
+            t.as_ref()
+                .map_or(params.white_texture, |t| t.raw_miniquad_id())
+        };
+        let or_black = |t: &Option<Texture2D>| {
+            t.as_ref()
+                .map_or(params.black_texture, |t| t.raw_miniquad_id())
+        };
+        let images = [
+            or_white(&material.base_color_texture),
+            or_black(&material.emissive_texture),
+            or_white(&material.occlusion_texture),
+            or_white(&material.normal_texture),
+            or_white(&material.metallic_roughness_texture),
+            ibl.specular,
+            ibl.irradiance,
+            ibl.brdf_lut,
+            params.shadowmap,
+            params.prepass[0],
+            params.prepass[1],
+            params.prepass[2],
+        ];
+        ctx.apply_pipeline(&material.shader.pipeline);
+        assert!(bindings.vertex_buffers.len() >= 4);
+        ctx.apply_bindings_from_slice(&bindings.vertex_buffers, bindings.index_buffer, &images);
+
+        let (proj, view) = camera.proj_view();
+
+        let projection = proj * view;
+        let time = (miniquad::date::now()) as f32;
+        let time = glam::vec4(time, time.sin(), time.cos(), 0.);
+
+        let model_matrix_inverse = model_matrix.inverse();
+        // ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
+        //     projection,
+        //     shadow_projection: shadow_proj,
+        //     model: model_matrix,
+        //     model_inverse: model_matrix_inverse,
+        //     color: material.color,
+        //     shadow_cascades,
+        //     shadow_casters,
+        //     material: [material.metallic, material.roughness, 0.0, 0.0],
+        //     camera_pos: camera.position,
+        // }));
+        material.shader.set_uniform("Projection", projection);
+        let shadows = params.shadows;
+        material
+            .shader
+            .set_uniform("ShadowProjection", &shadows.projections[..]);
+        material.shader.set_uniform("Model", model_matrix);
+        material
+            .shader
+            .set_uniform("ModelInverse", model_matrix_inverse);
+        material.shader.set_uniform("Color", material.color);
+        material
+            .shader
+            .set_uniform("ShadowCasters", [shadows.casters_count, 0, 0, 0]);
+        material
+            .shader
+            .set_uniform("ShadowCasterMaps", &shadows.casters[..]);
+        material
+            .shader
+            .set_uniform("CascadeSplits", &shadows.splits[..]);
+        material.shader.set_uniform("ShadowAtlas", shadows.atlas);
+        material.shader.set_uniform("ShadowParams", shadows.params);
+        material.shader.set_uniform("ShadowFilter", shadows.filter);
+        material.shader.set_uniform(
+            "Material",
+            [
+                material.metallic,
+                material.roughness,
+                material.alpha_cutoff,
+                0.0,
+            ],
+        );
+        material
+            .shader
+            .set_uniform("CameraPosition", camera.position);
+        material.shader.set_uniform(
+            "CameraForward",
+            (camera.target - camera.position).normalize(),
+        );
+        material.shader.set_uniform("LightsCount", lights.count);
+        material
+            .shader
+            .set_uniform("LightPositions", &lights.positions);
+        material
+            .shader
+            .set_uniform("LightDirections", &lights.directions);
+        material.shader.set_uniform("LightColors", &lights.colors);
+        material.shader.set_uniform("LightCones", &lights.cones);
+        if let Some(skin) = node.skin {
+            if material.shader.features.skinning {
+                let joints = &joint_matrices[skin];
+                // the glTF loader rejects bigger skins, this only guards hand-built ones
+                let count = joints.len().min(animation::MAX_JOINTS);
+                material.shader.set_uniform("Joints", &joints[..count]);
+            }
+        }
+        ctx.apply_uniforms_from_bytes(
+            material.shader.uniforms_data.as_ptr(),
+            material.shader.uniforms_data.len(),
+        );
+        let buffer_size =
+            (ctx.buffer_size(bindings.index_buffer) / bindings.index_type.size()) as i32;
+        ctx.draw(0, buffer_size, instances as i32);
     }
 
     // pub fn set_transform(&self, model: usize, transform: Mat4) {
@@ -627,82 +1584,171 @@ impl Scene {
         let clipping_planes = frustum::projection_planes(camera);
         let (proj, view) = camera.proj_view();
         let mut clear_action = PassAction::Nothing;
+        let mut ctx = self.quad_ctx.lock().unwrap();
+
+        let (width, height) = match camera.render_target {
+            Some(ref rt) => ctx.texture_size(rt.texture.raw_miniquad_id()),
+            None => {
+                let (width, height) = window::screen_size();
+                (width as u32, height as u32)
+            }
+        };
+        // with post-processing, the scene is drawn into the HDR target first
+        let output = camera.render_target.as_ref().map(|rt| rt.render_pass);
+        let post_processing = !camera.post_processing.is_empty();
+        let scene_pass = if post_processing {
+            let post_processor = self
+                .post_processor
+                .get_or_insert_with(|| PostProcessor::new(ctx.as_mut()));
+            Some(post_processor.hdr_pass(ctx.as_mut(), width, height))
+        } else {
+            output
+        };
+
         {
             let _z = telemetry::ZoneGuard::new("environment");
 
             if let crate::camera::Environment::Skybox(ref cubemap) = camera.environment {
-                cubemap.draw(&mut **self.quad_ctx.lock().unwrap(), &proj, &view);
+                // nothing else clears the HDR target
+                let action = if post_processing {
+                    PassAction::clear_color(0.0, 0.0, 0.0, 1.0)
+                } else {
+                    PassAction::Nothing
+                };
+                cubemap.draw(ctx.as_mut(), scene_pass, action, &proj, &view);
             }
 
             if let crate::camera::Environment::SolidColor(color) = camera.environment {
                 clear_action = PassAction::clear_color(color.r, color.g, color.b, color.a);
             }
 
-            unsafe {
-                miniquad::gl::glFlush();
-                miniquad::gl::glFinish();
+            gl_finish(ctx.as_ref());
+        }
+
+        for model in self.models.values_mut() {
+            model.update_globals();
+            model.update_morphs(ctx.as_mut());
+        }
+
+        let drawn = visible_in(&self.models, &self.bvh, &clipping_planes);
+
+        let mut lights: Vec<light::Light> = self.lights.values().cloned().collect();
+        for model in self.models.values() {
+            let transform = model.world;
+            for (node, global) in model.model.nodes.iter().zip(&model.node_globals) {
+                if let Some(ref light) = node.light {
+                    lights.push(light.transformed(transform * *global));
+                }
             }
         }
-        let mut ctx = self.quad_ctx.lock().unwrap();
 
-        let mut shadow_proj = Default::default();
-        let mut cascade_clips = Default::default();
-        let casters_count = self.shadow_casters.len();
-        let mut split_count = 0;
-        if let Some(shadow_caster) = self.shadow_casters.get(0) {
-            split_count = match shadow_caster.split {
-                ShadowSplit::Orthogonal => 1,
-                ShadowSplit::PSSM2 => 2,
-                ShadowSplit::PSSM4 => 4,
-            };
+        let shadows = {
             let _z = telemetry::ZoneGuard::new("shadows");
-            (shadow_proj, cascade_clips) = self.shadowmap.draw_shadow_pass(
+            let shadows = self.shadowmap.draw_shadow_pass(
                 ctx.as_mut(),
-                &self.models[..],
+                &mut self.models,
+                &self.bvh,
                 &camera,
-                shadow_caster,
-                clipping_planes,
+                &self.shadow_casters,
+                &lights,
+                &self.shadow_settings,
             );
 
-            unsafe {
-                miniquad::gl::glFlush();
-                miniquad::gl::glFinish();
-            }
+            gl_finish(ctx.as_ref());
+            shadows
+        };
+
+        for h in &drawn {
+            self.models[*h].upload_instances(ctx.as_mut(), |aabb| {
+                clipping_planes.iter().all(|p| p.clip(*aabb))
+            });
         }
 
-        if let Some(pass) = camera.render_target.as_ref().map(|rt| rt.render_pass) {
-            ctx.begin_pass(Some(pass), clear_action);
-        } else {
-            ctx.begin_default_pass(clear_action);
+        let mut prepass = [self.black_texture, self.black_texture, self.white_texture];
+        if camera.prepass || camera.ssao.is_some() {
+            let _z = telemetry::ZoneGuard::new("prepass");
+            let pass = self
+                .prepass
+                .get_or_insert_with(|| Prepass::new(ctx.as_mut()));
+            pass.draw(
+                ctx.as_mut(),
+                &self.models,
+                &drawn,
+                camera,
+                (width, height),
+                self.white_texture,
+            );
+            if let Some(ref ssao) = camera.ssao {
+                pass.ssao(ctx.as_mut(), ssao, proj);
+            }
+            let (normals, depth, occlusion) = pass.textures();
+            prepass = [normals, depth, occlusion.unwrap_or(self.white_texture)];
+
+            gl_finish(ctx.as_ref());
         }
 
+        ctx.begin_pass(scene_pass, clear_action);
+
+        let params = DrawParams {
+            camera,
+            white_texture: self.white_texture,
+            black_texture: self.black_texture,
+            default_ibl: &self.default_ibl,
+            shadows: &shadows,
+            shadowmap: self.shadowmap.depth_img,
+            prepass,
+        };
+        let mut model_lights = SecondaryMap::new();
+        let mut blended = vec![];
         {
             let _z = telemetry::ZoneGuard::new("models");
-            for model in &mut self.models {
-                Scene::draw_model(
+            for h in drawn {
+                if let Some(lights) = Scene::draw_model(
                     ctx.as_mut(),
-                    self.white_texture,
-                    self.black_texture,
-                    model,
-                    camera,
-                    shadow_proj,
-                    cascade_clips,
-                    [
-                        self.shadowmap.depth_img[0],
-                        self.shadowmap.depth_img[1],
-                        self.shadowmap.depth_img[2],
-                        self.shadowmap.depth_img[3],
-                    ],
-                    [casters_count as _, split_count as _, 0, 0],
+                    &params,
+                    h,
+                    &mut self.models[h],
                     clipping_planes,
-                );
+                    &lights,
+                    &mut blended,
+                ) {
+                    model_lights.insert(h, lights);
+                }
             }
-            unsafe {
-                miniquad::gl::glFlush();
-                miniquad::gl::glFinish();
+        }
+        {
+            let _z = telemetry::ZoneGuard::new("transparent");
+            // back to front, so each blended surface is drawn over the ones behind it
+            blended.sort_by(|a, b| b.distance.total_cmp(&a.distance));
+            for primitive in &blended {
+                let lights = &model_lights[primitive.model];
+                Scene::draw_primitive(
+                    ctx.as_mut(),
+                    &params,
+                    &mut self.models[primitive.model],
+                    primitive.node,
+                    primitive.primitive,
+                    lights,
+                );
             }
+            gl_finish(ctx.as_ref());
         }
         ctx.end_render_pass();
+
+        if let Some(post_processor) = self.post_processor.as_mut().filter(|_| post_processing) {
+            let _z = telemetry::ZoneGuard::new("post-processing");
+            post_processor.apply(ctx.as_mut(), &camera.post_processing, output, prepass);
+        }
+    }
+
+    /// Depth, normals and ambient occlusion of the last camera drawn with a prepass.
+    pub fn prepass_textures(&self) -> Option<PrepassTextures> {
+        let (normals, depth, occlusion) = self.prepass.as_ref()?.textures();
+        Some(PrepassTextures {
+            normals: Texture2D::unmanaged(normals),
+            depth: Texture2D::unmanaged(depth),
+            occlusion: occlusion.map(Texture2D::unmanaged),
+        })
     }
 
     pub fn draw_shadow_debug(&mut self) {
@@ -710,12 +1756,53 @@ impl Scene {
 
         self.shadowmap
             .dbg
-            .draw(ctx.as_mut(), &self.shadowmap.depth_img[..]);
+            .draw(ctx.as_mut(), &[self.shadowmap.depth_img]);
+    }
+}
+
+/// Visible and not under a hidden parent.
+pub(crate) fn is_drawn(models: &SlotMap<ModelHandle, Model2>, h: ModelHandle) -> bool {
+    let mut ancestor = Some(h);
+    while let Some(ix) = ancestor {
+        if !models[ix].visible {
+            return false;
+        }
+        ancestor = models[ix].parent;
+    }
+    true
+}
+
+/// Visible models with the bounds inside all the planes.
+pub(crate) fn visible_in(
+    models: &SlotMap<ModelHandle, Model2>,
+    bvh: &bvh::Bvh<ModelHandle>,
+    planes: &[frustum::Plane],
+) -> Vec<ModelHandle> {
+    let inside = |aabb: &AABB| planes.iter().all(|p| p.clip(*aabb));
+    let mut visible = vec![];
+    bvh.query(inside, |h| {
+        if inside(&models[h].world_aabb) && is_drawn(models, h) {
+            visible.push(h);
+        }
+    });
+    visible
+}
+
+/// Wait for the GPU, so the telemetry zones measure the actual rendering.
+/// Only the GL backend gives access to the raw calls.
+fn gl_finish(ctx: &miniquad::Context) {
+    if ctx.info().backend == Backend::OpenGl {
+        unsafe {
+            miniquad::gl::glFlush();
+            miniquad::gl::glFinish();
+        }
     }
 }
 
 pub mod shader {
+    use super::light::MAX_LIGHTS;
     use crate::math::Vec3;
+    use crate::shadowmap::{MAX_SHADOW_CASTERS, MAX_SHADOW_MAPS};
     use miniquad::{ShaderMeta, UniformBlockLayout, UniformDesc, UniformType};
 
     pub const VERTEX: &str = include_str!("vertex.glsl");
@@ -729,22 +1816,55 @@ pub mod shader {
                 "Normal".to_string(),
                 "MetallicRoughness".to_string(),
                 "Environment".to_string(),
-                "ShadowMap0".to_string(),
-                "ShadowMap1".to_string(),
-                "ShadowMap2".to_string(),
-                "ShadowMap3".to_string(),
+                "Irradiance".to_string(),
+                "BrdfLut".to_string(),
+                "ShadowMap".to_string(),
+                "SceneNormals".to_string(),
+                "SceneDepth".to_string(),
+                "AmbientOcclusion".to_string(),
             ],
             uniforms: UniformBlockLayout {
                 uniforms: vec![
                     UniformDesc::new("Projection", UniformType::Mat4),
-                    UniformDesc::array(UniformDesc::new("ShadowProjection", UniformType::Mat4), 4),
+                    UniformDesc::array(
+                        UniformDesc::new("ShadowProjection", UniformType::Mat4),
+                        MAX_SHADOW_MAPS,
+                    ),
                     UniformDesc::new("Model", UniformType::Mat4),
                     UniformDesc::new("ModelInverse", UniformType::Mat4),
                     UniformDesc::new("Color", UniformType::Float4),
-                    UniformDesc::new("ShadowCascades", UniformType::Float4),
                     UniformDesc::new("ShadowCasters", UniformType::Int4),
+                    UniformDesc::array(
+                        UniformDesc::new("ShadowCasterMaps", UniformType::Float4),
+                        MAX_SHADOW_CASTERS,
+                    ),
+                    UniformDesc::array(
+                        UniformDesc::new("CascadeSplits", UniformType::Float4),
+                        MAX_SHADOW_CASTERS,
+                    ),
+                    UniformDesc::new("ShadowAtlas", UniformType::Float4),
+                    UniformDesc::new("ShadowParams", UniformType::Float4),
+                    UniformDesc::new("ShadowFilter", UniformType::Float4),
                     UniformDesc::new("Material", UniformType::Float4),
                     UniformDesc::new("CameraPosition", UniformType::Float3),
+                    UniformDesc::new("CameraForward", UniformType::Float3),
+                    UniformDesc::new("LightsCount", UniformType::Int1),
+                    UniformDesc::array(
+                        UniformDesc::new("LightPositions", UniformType::Float4),
+                        MAX_LIGHTS,
+                    ),
+                    UniformDesc::array(
+                        UniformDesc::new("LightDirections", UniformType::Float4),
+                        MAX_LIGHTS,
+                    ),
+                    UniformDesc::array(
+                        UniformDesc::new("LightColors", UniformType::Float4),
+                        MAX_LIGHTS,
+                    ),
+                    UniformDesc::array(
+                        UniformDesc::new("LightCones", UniformType::Float4),
+                        MAX_LIGHTS,
+                    ),
                 ],
             },
         }
@@ -763,3 +1883,122 @@ pub mod shader {
     //     pub camera_pos: glam::Vec3,
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!(a.abs_diff_eq(b, 1e-5), "{} != {}", a, b);
+    }
+
+    fn unit_box() -> AABB {
+        AABB {
+            min: Vec3::splat(-1.0),
+            max: Vec3::splat(1.0),
+        }
+    }
+
+    #[test]
+    fn aabb_transformed() {
+        let aabb = unit_box().transformed(Mat4::IDENTITY);
+        assert_close(aabb.min, Vec3::splat(-1.0));
+        assert_close(aabb.max, Vec3::splat(1.0));
+
+        let aabb = unit_box().transformed(Mat4::from_scale_rotation_translation(
+            vec3(2.0, 1.0, 1.0),
+            Quat::IDENTITY,
+            vec3(10.0, 0.0, -5.0),
+        ));
+        assert_close(aabb.min, vec3(8.0, -1.0, -6.0));
+        assert_close(aabb.max, vec3(12.0, 1.0, -4.0));
+
+        // rotated corners stick out further than the rotated min and max
+        let aabb = unit_box().transformed(Mat4::from_rotation_y(std::f32::consts::FRAC_PI_4));
+        let half = 2.0f32.sqrt();
+        assert_close(aabb.min, vec3(-half, -1.0, -half));
+        assert_close(aabb.max, vec3(half, 1.0, half));
+
+        // a mirroring matrix keeps min below max
+        let aabb = AABB {
+            min: vec3(1.0, 2.0, 3.0),
+            max: vec3(2.0, 3.0, 4.0),
+        }
+        .transformed(Mat4::from_scale(vec3(-1.0, 1.0, 1.0)));
+        assert_close(aabb.min, vec3(-2.0, 2.0, 3.0));
+        assert_close(aabb.max, vec3(-1.0, 3.0, 4.0));
+    }
+
+    #[test]
+    fn sphere_transformed() {
+        let sphere = unit_box().bounding_sphere();
+        assert_close(sphere.center, Vec3::ZERO);
+        assert!((sphere.radius - 3.0f32.sqrt()).abs() < 1e-5);
+
+        let sphere = sphere.transformed(Mat4::from_scale_rotation_translation(
+            vec3(1.0, 3.0, 2.0),
+            Quat::from_rotation_x(1.0),
+            vec3(0.0, 1.0, 0.0),
+        ));
+        assert_close(sphere.center, vec3(0.0, 1.0, 0.0));
+        assert!((sphere.radius - 3.0 * 3.0f32.sqrt()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn aabb_empty() {
+        assert!(AABB::EMPTY.is_empty());
+        assert!(!unit_box().is_empty());
+        // a single point isn't empty
+        assert!(!AABB {
+            min: Vec3::ONE,
+            max: Vec3::ONE,
+        }
+        .is_empty());
+
+        let aabb = AABB::EMPTY.transformed(Mat4::from_scale_rotation_translation(
+            vec3(-2.0, 1.0, 1.0),
+            Quat::from_rotation_z(1.0),
+            vec3(1.0, 2.0, 3.0),
+        ));
+        assert!(aabb.is_empty());
+        assert!(!aabb.min.is_nan() && !aabb.max.is_nan());
+
+        let sphere = AABB::EMPTY.bounding_sphere();
+        assert_eq!(sphere.radius, 0.0);
+        assert!(sphere.center.is_finite());
+
+        let aabb = AABB::EMPTY.union(&unit_box());
+        assert_close(aabb.min, Vec3::splat(-1.0));
+        assert_close(aabb.max, Vec3::splat(1.0));
+    }
+
+    #[test]
+    fn empty_models_outside_bvh() {
+        let ctx: Box<miniquad::Context> = Box::new(miniquad::SoftwareContext::new(1, 1));
+        let quad_gl = crate::QuadGl::new(Arc::new(Mutex::new(ctx)));
+        let mut scene = quad_gl.new_scene();
+        let model = |aabb| Model {
+            nodes: vec![],
+            aabb,
+            skins: vec![],
+            animations: vec![],
+        };
+
+        let empty = scene.add_model(&model(AABB::EMPTY));
+        scene.set_translation(&empty, vec3(5.0, 0.0, 0.0));
+        assert!(scene.bvh.bounds().is_none());
+        assert!(scene.scene_aabb().is_none());
+        assert!(scene.query_aabb(&unit_box()).is_empty());
+
+        let full = scene.add_model(&model(unit_box()));
+        // the bvh fattens its leaves
+        let aabb = scene.bvh.bounds().unwrap();
+        assert!(aabb.min.cmple(Vec3::splat(-1.0)).all() && aabb.max.cmpge(Vec3::ONE).all());
+        assert!(aabb.size().length() < 5.0);
+        assert_eq!(scene.query_aabb(&unit_box()), vec![full]);
+
+        scene.remove_model(&empty);
+        scene.remove_model(&full);
+        assert!(scene.bvh.bounds().is_none());
+    }
+}
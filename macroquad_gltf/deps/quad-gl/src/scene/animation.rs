@@ -0,0 +1,521 @@
+//! Skeletons and keyframe animation clips, plus the state needed to
+//! play, blend and cross-fade them on a scene model.
+
+use crate::{
+    math::{Mat4, Quat, Vec4},
+    scene::{Node, Transform},
+};
+
+/// Maximum amount of joints a single skin could upload to the shader.
+/// Should match the size of the `Joints` array in the scene and shadow shaders.
+/// Skins with more joints are rejected by the glTF loader.
+///
+/// 64 matrices take 256 vertex uniform vectors, twice the 128 that GLES2 and WebGL1
+/// guarantee. Skinned shaders fail to compile on devices at that minimum.
+pub const MAX_JOINTS: usize = 64;
+
+/// A node of the skeleton hierarchy.
+/// Not every bone is a joint: bones also include all the joint's ancestors,
+/// so the whole chain up to the model root could be animated.
+#[derive(Clone, Debug)]
+pub struct Bone {
+    pub name: String,
+    /// Index of the parent bone. Parents always come before their children.
+    pub parent: Option<usize>,
+    /// Rest pose, relative to the parent bone.
+    pub transform: Transform,
+}
+
+#[derive(Clone, Debug)]
+pub struct Skin {
+    pub bones: Vec<Bone>,
+    /// Bone index for each joint referenced by the JOINTS_0 attribute.
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+impl Skin {
+    /// Model-space joint matrices for the given bone local transforms.
+    pub fn joint_matrices(&self, pose: &[Transform], out: &mut Vec<Mat4>) {
+        let mut globals: Vec<Mat4> = Vec::with_capacity(self.bones.len());
+        for (bone, local) in self.bones.iter().zip(pose) {
+            let global = match bone.parent {
+                Some(parent) => globals[parent] * local.matrix(),
+                None => local.matrix(),
+            };
+            globals.push(global);
+        }
+
+        out.clear();
+        for (joint, inverse_bind_matrix) in self.joints.iter().zip(&self.inverse_bind_matrices) {
+            out.push(globals[*joint] * *inverse_bind_matrix);
+        }
+    }
+
+    pub fn rest_pose(&self) -> Vec<Transform> {
+        self.bones.iter().map(|b| b.transform.clone()).collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    Step,
+    CubicSpline,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Property {
+    Translation,
+    Rotation,
+    Scale,
+    /// Morph target weights, only for `AnimationTarget::Node`.
+    Weights,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AnimationTarget {
+    /// Index in `Model::nodes`.
+    Node(usize),
+    /// Bone of one of the `Model::skins`.
+    Bone { skin: usize, bone: usize },
+}
+
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub target: AnimationTarget,
+    pub property: Property,
+    pub interpolation: Interpolation,
+    /// Keyframe times, in seconds.
+    pub times: Vec<f32>,
+    /// Tightly packed keyframe values, `components` floats per value.
+    /// CUBICSPLINE stores (in-tangent, value, out-tangent) triplets.
+    pub values: Vec<f32>,
+    /// 3 for translation and scale, 4 for rotation, amount of morph targets for weights.
+    pub components: usize,
+}
+
+impl Channel {
+    /// Sample the channel at `time`, writing `components` floats into `out`.
+    pub fn sample(&self, time: f32, out: &mut [f32]) {
+        let n = self.components;
+        let stride = match self.interpolation {
+            Interpolation::CubicSpline => 3 * n,
+            _ => n,
+        };
+        // the actual value inside a cubic spline triplet is the middle one
+        let value_offset = match self.interpolation {
+            Interpolation::CubicSpline => n,
+            _ => 0,
+        };
+        let value = |key: usize| &self.values[key * stride + value_offset..][..n];
+
+        if self.times.is_empty() {
+            return;
+        }
+        let last = self.times.len() - 1;
+        if time <= self.times[0] {
+            out[..n].copy_from_slice(value(0));
+            return;
+        }
+        if time >= self.times[last] {
+            out[..n].copy_from_slice(value(last));
+            return;
+        }
+
+        let next = self.times.partition_point(|t| *t <= time);
+        let prev = next - 1;
+        let dt = self.times[next] - self.times[prev];
+        let t = if dt > 0.0 {
+            (time - self.times[prev]) / dt
+        } else {
+            0.0
+        };
+
+        match self.interpolation {
+            Interpolation::Step => out[..n].copy_from_slice(value(prev)),
+            Interpolation::Linear => {
+                if self.property == Property::Rotation {
+                    let a = Quat::from_slice(value(prev));
+                    let b = Quat::from_slice(value(next));
+                    a.slerp(b, t).write_to_slice(out);
+                } else {
+                    for i in 0..n {
+                        out[i] = value(prev)[i] * (1.0 - t) + value(next)[i] * t;
+                    }
+                }
+            }
+            Interpolation::CubicSpline => {
+                let out_tangent = &self.values[prev * stride + 2 * n..][..n];
+                let in_tangent = &self.values[next * stride..][..n];
+                let t2 = t * t;
+                let t3 = t2 * t;
+                for i in 0..n {
+                    out[i] = (2.0 * t3 - 3.0 * t2 + 1.0) * value(prev)[i]
+                        + (t3 - 2.0 * t2 + t) * dt * out_tangent[i]
+                        + (-2.0 * t3 + 3.0 * t2) * value(next)[i]
+                        + (t3 - t2) * dt * in_tangent[i];
+                }
+                if self.property == Property::Rotation {
+                    Quat::from_slice(out).normalize().write_to_slice(out);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name: String,
+    /// Length of the clip in seconds, the time of the last keyframe.
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+#[derive(Clone, Debug)]
+struct Fade {
+    from: f32,
+    to: f32,
+    duration: f32,
+    elapsed: f32,
+}
+
+/// A clip being played on a model.
+#[derive(Clone, Debug)]
+pub struct AnimationLayer {
+    /// Index in `Model::animations`.
+    pub clip: usize,
+    /// Playback position, in seconds.
+    pub time: f32,
+    pub speed: f32,
+    pub weight: f32,
+    pub looping: bool,
+    fade: Option<Fade>,
+}
+
+impl AnimationLayer {
+    pub fn new(clip: usize, looping: bool) -> AnimationLayer {
+        AnimationLayer {
+            clip,
+            time: 0.0,
+            speed: 1.0,
+            weight: 1.0,
+            looping,
+            fade: None,
+        }
+    }
+
+    fn fade_to(&mut self, to: f32, duration: f32) {
+        self.fade = Some(Fade {
+            from: self.weight,
+            to,
+            duration,
+            elapsed: 0.0,
+        });
+    }
+}
+
+/// Playback state of all the animation layers of a single model.
+#[derive(Clone, Debug, Default)]
+pub struct Animator {
+    pub layers: Vec<AnimationLayer>,
+}
+
+impl Animator {
+    /// Stop everything and play only the given clip.
+    pub fn play(&mut self, clip: usize, looping: bool) {
+        self.layers.clear();
+        self.layers.push(AnimationLayer::new(clip, looping));
+    }
+
+    /// Play the clip on top of the already playing ones, with the given weight.
+    /// If the clip is already playing, only its weight is changed.
+    pub fn blend(&mut self, clip: usize, weight: f32, looping: bool) {
+        if let Some(layer) = self.layers.iter_mut().find(|l| l.clip == clip) {
+            layer.weight = weight;
+            layer.fade = None;
+            return;
+        }
+        let mut layer = AnimationLayer::new(clip, looping);
+        layer.weight = weight;
+        self.layers.push(layer);
+    }
+
+    /// Smoothly fade out all the playing clips while fading in the new one.
+    pub fn cross_fade(&mut self, clip: usize, duration: f32, looping: bool) {
+        if duration <= 0.0 {
+            self.play(clip, looping);
+            return;
+        }
+        for layer in &mut self.layers {
+            if layer.clip != clip {
+                layer.fade_to(0.0, duration);
+            }
+        }
+        match self.layers.iter_mut().find(|l| l.clip == clip) {
+            Some(layer) => {
+                layer.looping = looping;
+                layer.fade_to(1.0, duration);
+            }
+            None => {
+                let mut layer = AnimationLayer::new(clip, looping);
+                layer.weight = 0.0;
+                layer.fade_to(1.0, duration);
+                self.layers.push(layer);
+            }
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.layers.clear();
+    }
+
+    pub fn is_playing(&self) -> bool {
+        !self.layers.is_empty()
+    }
+
+    /// Advance time and fades. Layers with no weight left and no fade in progress are removed.
+    pub fn advance(&mut self, clips: &[AnimationClip], dt: f32) {
+        for layer in &mut self.layers {
+            let duration = clips[layer.clip].duration;
+            layer.time += dt * layer.speed;
+            if layer.looping && duration > 0.0 {
+                layer.time = layer.time.rem_euclid(duration);
+            } else {
+                layer.time = layer.time.clamp(0.0, duration);
+            }
+
+            if let Some(fade) = &mut layer.fade {
+                fade.elapsed += dt;
+                let k = (fade.elapsed / fade.duration).min(1.0);
+                layer.weight = fade.from + (fade.to - fade.from) * k;
+                if k >= 1.0 {
+                    layer.fade = None;
+                }
+            }
+        }
+        self.layers
+            .retain(|layer| layer.weight > 0.0 || layer.fade.is_some());
+    }
+}
+
+/// Weighted sum of the sampled channel values for a single animated property.
+#[derive(Clone, Copy)]
+struct Accumulator {
+    value: Vec4,
+    weight: f32,
+}
+
+/// Local transforms of everything a set of animation layers could touch.
+pub struct Pose<'a> {
+    pub nodes: &'a mut [Node],
+    pub skins: &'a mut [Vec<Transform>],
+}
+
+/// Model nodes state before any animation was applied.
+pub struct Rest<'a> {
+    pub nodes: &'a [Transform],
+    pub weights: &'a [Vec<f32>],
+}
+
+/// Blend all the layers of the animator into the pose.
+/// Where the total weight of the layers is below 1.0, the rest pose is blended in:
+/// `rest` for the model nodes and bones' own transforms for the skins.
+pub fn evaluate(
+    animator: &Animator,
+    clips: &[AnimationClip],
+    skins: &[Skin],
+    rest: Rest,
+    pose: Pose,
+) {
+    use std::collections::HashMap;
+
+    let mut accumulators: HashMap<(AnimationTarget, Property), Accumulator> = HashMap::new();
+    // morph weights are not limited to 4 components, so they are accumulated separately
+    let mut weights: HashMap<usize, (Vec<f32>, f32)> = HashMap::new();
+    let mut sample = vec![];
+
+    for layer in &animator.layers {
+        if layer.weight <= 0.0 {
+            continue;
+        }
+        for channel in &clips[layer.clip].channels {
+            sample.resize(channel.components.max(4), 0.0);
+            channel.sample(layer.time, &mut sample);
+
+            if channel.property == Property::Weights {
+                if let AnimationTarget::Node(node) = channel.target {
+                    let (acc, acc_weight) = weights
+                        .entry(node)
+                        .or_insert((vec![0.0; channel.components], 0.0));
+                    for (sum, value) in acc.iter_mut().zip(&sample) {
+                        *sum += value * layer.weight;
+                    }
+                    *acc_weight += layer.weight;
+                }
+                continue;
+            }
+
+            let mut value = Vec4::from_slice(&sample);
+            let acc = accumulators
+                .entry((channel.target, channel.property))
+                .or_insert(Accumulator {
+                    value: Vec4::ZERO,
+                    weight: 0.0,
+                });
+            // keep all the blended quaternions in the same hemisphere
+            if channel.property == Property::Rotation && acc.value.dot(value) < 0.0 {
+                value = -value;
+            }
+            acc.value += value * layer.weight;
+            acc.weight += layer.weight;
+        }
+    }
+
+    for (node, (acc, acc_weight)) in weights {
+        let rest_weight = (1.0 - acc_weight).max(0.0);
+        let total = acc_weight + rest_weight;
+        let node_weights = &mut pose.nodes[node].weights;
+        for (i, weight) in node_weights.iter_mut().enumerate() {
+            let value = acc.get(i).cloned().unwrap_or(0.0);
+            let rest_value = rest.weights[node].get(i).cloned().unwrap_or(0.0);
+            *weight = (value + rest_value * rest_weight) / total;
+        }
+    }
+
+    for ((target, property), acc) in accumulators {
+        let (transform, rest) = match target {
+            AnimationTarget::Node(node) => (&mut pose.nodes[node].transform, &rest.nodes[node]),
+            AnimationTarget::Bone { skin, bone } => (
+                &mut pose.skins[skin][bone],
+                &skins[skin].bones[bone].transform,
+            ),
+        };
+        let rest_weight = (1.0 - acc.weight).max(0.0);
+        let total = acc.weight + rest_weight;
+
+        match property {
+            Property::Translation => {
+                let value = acc.value.truncate() + rest.translation * rest_weight;
+                transform.translation = value / total;
+            }
+            Property::Scale => {
+                let value = acc.value.truncate() + rest.scale * rest_weight;
+                transform.scale = value / total;
+            }
+            Property::Rotation => {
+                let mut rest_rotation = Vec4::from(rest.rotation);
+                if rest_rotation.dot(acc.value) < 0.0 {
+                    rest_rotation = -rest_rotation;
+                }
+                let value = acc.value + rest_rotation * rest_weight;
+                transform.rotation = Quat::from_vec4(value).normalize();
+            }
+            Property::Weights => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(
+        property: Property,
+        interpolation: Interpolation,
+        times: &[f32],
+        values: &[f32],
+    ) -> Channel {
+        Channel {
+            target: AnimationTarget::Node(0),
+            property,
+            interpolation,
+            times: times.to_vec(),
+            values: values.to_vec(),
+            components: match property {
+                Property::Rotation => 4,
+                Property::Weights => 1,
+                _ => 3,
+            },
+        }
+    }
+
+    fn sample(channel: &Channel, time: f32) -> Vec<f32> {
+        let mut out = vec![0.0; channel.components];
+        channel.sample(time, &mut out);
+        out
+    }
+
+    fn assert_close(a: &[f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b) {
+            assert!((a - b).abs() < 1e-5, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn step() {
+        let channel = channel(
+            Property::Weights,
+            Interpolation::Step,
+            &[0.0, 1.0, 2.0],
+            &[1.0, 2.0, 3.0],
+        );
+        assert_eq!(sample(&channel, -1.0), [1.0]);
+        assert_eq!(sample(&channel, 0.99), [1.0]);
+        assert_eq!(sample(&channel, 1.0), [2.0]);
+        assert_eq!(sample(&channel, 1.5), [2.0]);
+        assert_eq!(sample(&channel, 5.0), [3.0]);
+    }
+
+    #[test]
+    fn linear() {
+        let channel = channel(
+            Property::Translation,
+            Interpolation::Linear,
+            &[0.0, 2.0],
+            &[0.0, 0.0, 0.0, 2.0, 4.0, 6.0],
+        );
+        assert_close(&sample(&channel, 0.5), &[0.5, 1.0, 1.5]);
+        assert_close(&sample(&channel, 2.0), &[2.0, 4.0, 6.0]);
+    }
+
+    #[test]
+    fn linear_rotation() {
+        let a = Quat::IDENTITY;
+        let b = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+        let mut values = a.to_array().to_vec();
+        values.extend_from_slice(&b.to_array());
+        let channel = channel(
+            Property::Rotation,
+            Interpolation::Linear,
+            &[0.0, 1.0],
+            &values,
+        );
+        let expected = Quat::from_rotation_z(std::f32::consts::FRAC_PI_4);
+        assert_close(&sample(&channel, 0.5), &expected.to_array());
+    }
+
+    #[test]
+    fn cubic_spline() {
+        // (in-tangent, value, out-tangent) per key
+        let flat = channel(
+            Property::Weights,
+            Interpolation::CubicSpline,
+            &[0.0, 1.0],
+            &[0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+        );
+        assert_close(&sample(&flat, 0.0), &[0.0]);
+        assert_close(&sample(&flat, 0.5), &[0.5]);
+        assert_close(&sample(&flat, 1.0), &[1.0]);
+
+        // the first key leaves with a slope of 1, scaled by the keys' distance
+        let sloped = channel(
+            Property::Weights,
+            Interpolation::CubicSpline,
+            &[0.0, 2.0],
+            &[0.0, 0.0, 1.0, 0.0, 1.0, 0.0],
+        );
+        assert_close(&sample(&sloped, 1.0), &[0.75]);
+    }
+}
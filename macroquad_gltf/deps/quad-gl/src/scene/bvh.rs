@@ -0,0 +1,385 @@
+//! Dynamic bounding volume hierarchy over the models' world boxes.
+//!
+//! Leaves keep their boxes a bit enlarged, so small moves don't touch the tree.
+//! Insertion picks the sibling growing the surface area the least, and the tree
+//! is kept balanced with rotations, like Box2D's dynamic tree.
+
+use glam::Vec3;
+
+use crate::scene::AABB;
+
+const NONE: usize = usize::MAX;
+
+/// Leaves are enlarged by this part of their size on each side.
+const MARGIN: f32 = 0.1;
+
+struct Node<T> {
+    aabb: AABB,
+    parent: usize,
+    /// `NONE` for leaves.
+    children: [usize; 2],
+    /// 0 for leaves, -1 for free nodes.
+    height: i32,
+    item: Option<T>,
+}
+
+pub(crate) struct Bvh<T> {
+    nodes: Vec<Node<T>>,
+    free: Vec<usize>,
+    root: usize,
+}
+
+fn area(aabb: &AABB) -> f32 {
+    let size = aabb.size();
+    2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+}
+
+fn contains(outer: &AABB, inner: &AABB) -> bool {
+    outer.min.cmple(inner.min).all() && outer.max.cmpge(inner.max).all()
+}
+
+fn enlarged(aabb: &AABB) -> AABB {
+    let margin = Vec3::splat(aabb.size().max_element() * MARGIN);
+    AABB {
+        min: aabb.min - margin,
+        max: aabb.max + margin,
+    }
+}
+
+impl<T: Copy> Bvh<T> {
+    pub fn new() -> Bvh<T> {
+        Bvh {
+            nodes: vec![],
+            free: vec![],
+            root: NONE,
+        }
+    }
+
+    /// Returns the leaf, to update or remove the item later.
+    pub fn insert(&mut self, aabb: AABB, item: T) -> usize {
+        let leaf = self.allocate(Node {
+            aabb: enlarged(&aabb),
+            parent: NONE,
+            children: [NONE; 2],
+            height: 0,
+            item: Some(item),
+        });
+        self.insert_leaf(leaf);
+        leaf
+    }
+
+    pub fn remove(&mut self, leaf: usize) {
+        self.remove_leaf(leaf);
+        self.nodes[leaf].height = -1;
+        self.nodes[leaf].item = None;
+        self.free.push(leaf);
+    }
+
+    /// Move the leaf to the new box, the tree is only modified when the box
+    /// leaves the enlarged one.
+    pub fn update(&mut self, leaf: usize, aabb: AABB) {
+        if contains(&self.nodes[leaf].aabb, &aabb) {
+            return;
+        }
+        self.remove_leaf(leaf);
+        self.nodes[leaf].aabb = enlarged(&aabb);
+        self.insert_leaf(leaf);
+    }
+
+    /// Box around all the items, a bit enlarged.
+    pub fn bounds(&self) -> Option<AABB> {
+        (self.root != NONE).then(|| self.nodes[self.root].aabb)
+    }
+
+    /// Visit the items of all the leaves whose boxes pass the test.
+    /// Boxes of the internal nodes contain all their children's ones,
+    /// so the test should accept any box overlapping the queried volume.
+    pub fn query(&self, mut test: impl FnMut(&AABB) -> bool, mut f: impl FnMut(T)) {
+        if self.root == NONE {
+            return;
+        }
+        let mut stack = vec![self.root];
+        while let Some(ix) = stack.pop() {
+            let node = &self.nodes[ix];
+            if !test(&node.aabb) {
+                continue;
+            }
+            match node.item {
+                Some(item) => f(item),
+                None => stack.extend_from_slice(&node.children),
+            }
+        }
+    }
+
+    fn allocate(&mut self, node: Node<T>) -> usize {
+        match self.free.pop() {
+            Some(ix) => {
+                self.nodes[ix] = node;
+                ix
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn is_leaf(&self, ix: usize) -> bool {
+        self.nodes[ix].children[0] == NONE
+    }
+
+    /// Point the parent, or the root, to the new child instead of the old one.
+    fn replace_child(&mut self, parent: usize, old: usize, new: usize) {
+        if parent == NONE {
+            self.root = new;
+        } else if self.nodes[parent].children[0] == old {
+            self.nodes[parent].children[0] = new;
+        } else {
+            self.nodes[parent].children[1] = new;
+        }
+    }
+
+    fn insert_leaf(&mut self, leaf: usize) {
+        if self.root == NONE {
+            self.root = leaf;
+            self.nodes[leaf].parent = NONE;
+            return;
+        }
+
+        let aabb = self.nodes[leaf].aabb;
+        let mut sibling = self.root;
+        while !self.is_leaf(sibling) {
+            let node = &self.nodes[sibling];
+            let combined = area(&node.aabb.union(&aabb));
+            // cost of a new parent for this node and the leaf
+            let cost = 2.0 * combined;
+            // cost of pushing the leaf further down, all the ancestors grow
+            let inheritance = 2.0 * (combined - area(&node.aabb));
+            let child_cost = |child: usize| {
+                let child = &self.nodes[child];
+                let union = area(&child.aabb.union(&aabb));
+                match child.item {
+                    Some(_) => union + inheritance,
+                    None => union - area(&child.aabb) + inheritance,
+                }
+            };
+            let [left, right] = node.children;
+            let (left_cost, right_cost) = (child_cost(left), child_cost(right));
+            if cost < left_cost && cost < right_cost {
+                break;
+            }
+            sibling = if left_cost < right_cost { left } else { right };
+        }
+
+        let old_parent = self.nodes[sibling].parent;
+        let parent = self.allocate(Node {
+            aabb: self.nodes[sibling].aabb.union(&aabb),
+            parent: old_parent,
+            children: [sibling, leaf],
+            height: self.nodes[sibling].height + 1,
+            item: None,
+        });
+        self.replace_child(old_parent, sibling, parent);
+        self.nodes[sibling].parent = parent;
+        self.nodes[leaf].parent = parent;
+
+        self.refit(parent);
+    }
+
+    fn remove_leaf(&mut self, leaf: usize) {
+        if leaf == self.root {
+            self.root = NONE;
+            return;
+        }
+
+        let parent = self.nodes[leaf].parent;
+        let grandparent = self.nodes[parent].parent;
+        let [left, right] = self.nodes[parent].children;
+        let sibling = if left == leaf { right } else { left };
+
+        self.replace_child(grandparent, parent, sibling);
+        self.nodes[sibling].parent = grandparent;
+        self.nodes[parent].height = -1;
+        self.free.push(parent);
+
+        if grandparent != NONE {
+            self.refit(grandparent);
+        }
+    }
+
+    /// Rebalance and recompute boxes and heights from the node up to the root.
+    fn refit(&mut self, mut ix: usize) {
+        while ix != NONE {
+            ix = self.balance(ix);
+            let [left, right] = self.nodes[ix].children;
+            self.nodes[ix].height = 1 + self.nodes[left].height.max(self.nodes[right].height);
+            self.nodes[ix].aabb = self.nodes[left].aabb.union(&self.nodes[right].aabb);
+            ix = self.nodes[ix].parent;
+        }
+    }
+
+    /// If one child of `a` is more than one level taller than the other,
+    /// rotate it up. Returns the node now in `a`'s place.
+    fn balance(&mut self, a: usize) -> usize {
+        if self.is_leaf(a) || self.nodes[a].height < 2 {
+            return a;
+        }
+        let [b, c] = self.nodes[a].children;
+        let balance = self.nodes[c].height - self.nodes[b].height;
+        if balance > 1 {
+            self.rotate(a, 1)
+        } else if balance < -1 {
+            self.rotate(a, 0)
+        } else {
+            a
+        }
+    }
+
+    /// Replace `a` with its child on the `side`, `a` takes the child's shorter subtree.
+    fn rotate(&mut self, a: usize, side: usize) -> usize {
+        let up = self.nodes[a].children[side];
+        let other = self.nodes[a].children[1 - side];
+        let [f, g] = self.nodes[up].children;
+
+        let parent = self.nodes[a].parent;
+        self.nodes[up].children[0] = a;
+        self.nodes[up].parent = parent;
+        self.nodes[a].parent = up;
+        self.replace_child(parent, a, up);
+
+        // the taller grandchild stays with `up`, the shorter one goes down to `a`
+        let (keep, give) = if self.nodes[f].height > self.nodes[g].height {
+            (f, g)
+        } else {
+            (g, f)
+        };
+        self.nodes[up].children[1] = keep;
+        self.nodes[a].children[side] = give;
+        self.nodes[give].parent = a;
+
+        self.nodes[a].aabb = self.nodes[other].aabb.union(&self.nodes[give].aabb);
+        self.nodes[a].height = 1 + self.nodes[other].height.max(self.nodes[give].height);
+        self.nodes[up].aabb = self.nodes[a].aabb.union(&self.nodes[keep].aabb);
+        self.nodes[up].height = 1 + self.nodes[a].height.max(self.nodes[keep].height);
+        up
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::vec3;
+
+    fn cube(center: Vec3, half: f32) -> AABB {
+        AABB {
+            min: center - Vec3::splat(half),
+            max: center + Vec3::splat(half),
+        }
+    }
+
+    fn overlaps(a: &AABB, b: &AABB) -> bool {
+        a.min.cmple(b.max).all() && b.min.cmple(a.max).all()
+    }
+
+    fn query(bvh: &Bvh<usize>, volume: &AABB) -> Vec<usize> {
+        let mut items = vec![];
+        bvh.query(|aabb| overlaps(aabb, volume), |item| items.push(item));
+        items.sort();
+        items
+    }
+
+    /// Items of the boxes overlapping the volume, without the tree.
+    fn brute_force(boxes: &[Option<AABB>], volume: &AABB) -> Vec<usize> {
+        (0..boxes.len())
+            .filter(|ix| boxes[*ix].map_or(false, |aabb| overlaps(&aabb, volume)))
+            .collect()
+    }
+
+    fn grid() -> (Bvh<usize>, Vec<usize>, Vec<Option<AABB>>) {
+        let mut bvh = Bvh::new();
+        let mut leaves = vec![];
+        let mut boxes = vec![];
+        for i in 0..100 {
+            let aabb = cube(vec3((i % 10) as f32 * 3.0, 0.0, (i / 10) as f32 * 3.0), 0.5);
+            leaves.push(bvh.insert(aabb, i));
+            boxes.push(Some(aabb));
+        }
+        (bvh, leaves, boxes)
+    }
+
+    #[test]
+    fn empty() {
+        let bvh: Bvh<usize> = Bvh::new();
+        assert!(bvh.bounds().is_none());
+        assert!(query(&bvh, &cube(Vec3::ZERO, 100.0)).is_empty());
+    }
+
+    #[test]
+    fn insert_and_query() {
+        let (bvh, _, boxes) = grid();
+        let bounds = bvh.bounds().unwrap();
+        for aabb in boxes.iter().flatten() {
+            assert!(contains(&bounds, aabb));
+        }
+        for volume in [
+            cube(Vec3::ZERO, 0.1),
+            cube(vec3(4.5, 0.0, 4.5), 2.0),
+            cube(vec3(13.5, 0.0, 13.5), 5.0),
+            cube(vec3(1.5, 0.0, 1.5), 0.2),
+            cube(Vec3::ZERO, 100.0),
+        ] {
+            // the volumes keep clear of the leaves' margins, so the results are exact
+            assert_eq!(query(&bvh, &volume), brute_force(&boxes, &volume));
+        }
+        assert_eq!(query(&bvh, &cube(Vec3::ZERO, 100.0)).len(), 100);
+    }
+
+    #[test]
+    fn remove_and_update() {
+        let (mut bvh, leaves, mut boxes) = grid();
+        for i in (0..100).step_by(3) {
+            bvh.remove(leaves[i]);
+            boxes[i] = None;
+        }
+        // move a few far away, and a few just a bit, within the leaves' margin
+        for i in (1..100).step_by(7) {
+            if let Some(aabb) = boxes[i] {
+                let offset = if i % 2 == 0 {
+                    vec3(100.0, 0.0, 0.0)
+                } else {
+                    vec3(0.01, 0.0, 0.0)
+                };
+                let moved = AABB {
+                    min: aabb.min + offset,
+                    max: aabb.max + offset,
+                };
+                bvh.update(leaves[i], moved);
+                boxes[i] = Some(moved);
+            }
+        }
+        for volume in [
+            cube(Vec3::ZERO, 0.1),
+            cube(vec3(4.5, 0.0, 4.5), 2.0),
+            cube(vec3(100.0, 0.0, 10.0), 20.0),
+            cube(Vec3::ZERO, 200.0),
+        ] {
+            // boxes are enlarged in the tree, so allow the items close to the volume
+            let found = query(&bvh, &volume);
+            let expected = brute_force(&boxes, &volume);
+            for item in &expected {
+                assert!(found.contains(item), "{} is missing", item);
+            }
+            for item in &found {
+                assert!(boxes[*item].is_some(), "{} was removed", item);
+                let close = cube(volume.center(), volume.size().x * 0.5 + 1.0);
+                assert!(overlaps(&boxes[*item].unwrap(), &close));
+            }
+        }
+
+        // freed nodes are reused
+        let nodes = bvh.nodes.len();
+        bvh.insert(cube(Vec3::ZERO, 1.0), 1000);
+        assert_eq!(bvh.nodes.len(), nodes);
+        assert!(query(&bvh, &cube(Vec3::ZERO, 0.1)).contains(&1000));
+    }
+}
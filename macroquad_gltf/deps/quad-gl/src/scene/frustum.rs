@@ -79,3 +79,21 @@ pub fn projection_planes(camera: &Camera) -> [Plane; 6] {
 
     planes
 }
+
+/// Planes of the volume a view-projection matrix maps into the clip space cube,
+/// facing inside.
+pub fn matrix_planes(view_proj: Mat4) -> [Plane; 6] {
+    let rows = [0, 1, 2, 3].map(|ix| view_proj.row(ix));
+    [
+        rows[3] + rows[0],
+        rows[3] - rows[0],
+        rows[3] + rows[1],
+        rows[3] - rows[1],
+        rows[3] + rows[2],
+        rows[3] - rows[2],
+    ]
+    .map(|plane| {
+        let length = plane.xyz().length();
+        Plane::new(plane.xyz() / length, -plane.w / length)
+    })
+}
@@ -0,0 +1,199 @@
+//! Many copies of one model, drawn with a single instanced draw call per primitive.
+//!
+//! Every primitive reads a matrix and a tint per instance from `vertex_buffers[3]`,
+//! plain models have a single identity instance there.
+//! An instanced model gets a buffer per node instead, filled before each pass with
+//! the instances that pass the pass's culling, each composed with the node's
+//! model-space transform. So the "Model" uniform of an instanced model is just
+//! its world matrix, and instances are placed relative to the model.
+
+use miniquad::*;
+
+use crate::{
+    color::{Color, WHITE},
+    math::Mat4,
+    scene::{Model2, AABB},
+};
+
+/// Per-instance vertex data.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Instance {
+    pub matrix: Mat4,
+    /// Multiplies the material color.
+    pub color: Color,
+}
+
+impl Default for Instance {
+    fn default() -> Instance {
+        Instance {
+            matrix: Mat4::IDENTITY,
+            color: WHITE,
+        }
+    }
+}
+
+impl Instance {
+    /// `vertex_buffers[3]` with a single identity instance, for the primitives
+    /// of a freshly loaded model.
+    pub fn default_buffer(ctx: &mut Context) -> BufferId {
+        ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&[Instance::default()]),
+        )
+    }
+
+    pub fn buffer_layout() -> BufferLayout {
+        BufferLayout {
+            step_func: VertexStep::PerInstance,
+            ..Default::default()
+        }
+    }
+
+    /// Matrix columns and the tint, all from the buffer 3.
+    pub fn attributes() -> [VertexAttribute; 5] {
+        [
+            VertexAttribute::with_buffer("in_inst0", VertexFormat::Float4, 3),
+            VertexAttribute::with_buffer("in_inst1", VertexFormat::Float4, 3),
+            VertexAttribute::with_buffer("in_inst2", VertexFormat::Float4, 3),
+            VertexAttribute::with_buffer("in_inst3", VertexFormat::Float4, 3),
+            VertexAttribute::with_buffer("in_tint", VertexFormat::Float4, 3),
+        ]
+    }
+}
+
+pub(crate) struct Instances {
+    /// Relative to the model.
+    pub matrices: Vec<Mat4>,
+    pub colors: Vec<Color>,
+    /// Model bounds placed by each instance, in the world.
+    pub aabbs: Vec<AABB>,
+    /// Per node, shared by its primitives.
+    pub buffers: Vec<BufferId>,
+    /// Instances the buffers have space for.
+    pub capacity: usize,
+    /// Instances uploaded for the current pass.
+    pub count: usize,
+    staging: Vec<Instance>,
+}
+
+impl Instances {
+    pub fn new(ctx: &mut Context, nodes: usize, matrices: Vec<Mat4>) -> Instances {
+        let mut instances = Instances {
+            colors: vec![WHITE; matrices.len()],
+            aabbs: vec![],
+            buffers: vec![],
+            capacity: 0,
+            count: 0,
+            staging: vec![],
+            matrices,
+        };
+        instances.reserve(ctx, nodes);
+        instances
+    }
+
+    /// Make the buffers big enough for all the instances.
+    /// Returns true if the buffers were recreated.
+    pub fn reserve(&mut self, ctx: &mut Context, nodes: usize) -> bool {
+        if self.buffers.len() == nodes && self.capacity >= self.matrices.len() {
+            return false;
+        }
+        self.delete_buffers(ctx);
+        // empty buffers are not allowed
+        self.capacity = self.matrices.len().max(1);
+        self.buffers = (0..nodes)
+            .map(|_| {
+                ctx.new_buffer(
+                    BufferType::VertexBuffer,
+                    BufferUsage::Stream,
+                    BufferSource::empty::<Instance>(self.capacity),
+                )
+            })
+            .collect();
+        true
+    }
+
+    pub fn delete_buffers(&mut self, ctx: &mut Context) {
+        for buffer in self.buffers.drain(..) {
+            ctx.delete_buffer(buffer);
+        }
+    }
+}
+
+impl Model2 {
+    /// Point the primitives of every node to the node's instance buffer.
+    pub(crate) fn bind_instances(&mut self) {
+        let Some(ref instances) = self.instances else {
+            return;
+        };
+        for (node, buffer) in self.model.nodes.iter_mut().zip(&instances.buffers) {
+            for data in &mut node.data {
+                data.vertex_buffers[3] = *buffer;
+            }
+        }
+    }
+
+    /// Upload the instances whose bounds pass the test, to be drawn by the next pass.
+    /// Uses the current `node_globals`.
+    pub(crate) fn upload_instances(&mut self, ctx: &mut Context, inside: impl Fn(&AABB) -> bool) {
+        let Some(ref mut instances) = self.instances else {
+            return;
+        };
+        let visible: Vec<usize> = (0..instances.matrices.len())
+            .filter(|ix| inside(&instances.aabbs[*ix]))
+            .collect();
+        instances.count = visible.len();
+        if visible.is_empty() {
+            return;
+        }
+        for (node_ix, node) in self.model.nodes.iter().enumerate() {
+            // skinned vertices are already in the model space
+            let node_global = match node.skin {
+                Some(_) => Mat4::IDENTITY,
+                None => self.node_globals[node_ix],
+            };
+            instances.staging.clear();
+            instances.staging.extend(visible.iter().map(|ix| Instance {
+                matrix: instances.matrices[*ix] * node_global,
+                color: instances.colors[*ix],
+            }));
+            ctx.buffer_update(
+                instances.buffers[node_ix],
+                BufferSource::slice(&instances.staging[..]),
+            );
+        }
+    }
+
+    /// "Model" matrix of the node's primitives. Instance matrices already include
+    /// the node transform and skinned vertices are already in the model space.
+    pub(crate) fn node_matrix(&self, node_ix: usize) -> Mat4 {
+        match self.model.nodes[node_ix].skin {
+            None if self.instances.is_none() => self.world * self.node_globals[node_ix],
+            _ => self.world,
+        }
+    }
+
+    /// Instances to draw in the current pass, 1 for the models that are not instanced.
+    pub(crate) fn drawn_instances(&self) -> usize {
+        self.instances
+            .as_ref()
+            .map_or(1, |instances| instances.count)
+    }
+
+    /// Model to world matrices of the instances whose bounds pass the test,
+    /// with their indices. Just the model's one for the models that are not instanced.
+    pub(crate) fn placements(&self, inside: impl Fn(&AABB) -> bool) -> Vec<(usize, Mat4)> {
+        match self.instances {
+            Some(ref instances) => instances
+                .matrices
+                .iter()
+                .zip(&instances.aabbs)
+                .enumerate()
+                .filter(|(_, (_, aabb))| inside(aabb))
+                .map(|(ix, (matrix, _))| (ix, self.world * *matrix))
+                .collect(),
+            None => vec![(0, self.world)],
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Punctual lights, following the KHR_lights_punctual model:
+//! point lights radiate in all directions, spot lights along a cone,
+//! directional lights are infinitely far away.
+
+use crate::{
+    color::Color,
+    math::{vec3, Mat4, Vec3, Vec4},
+    scene::AABB,
+};
+
+/// Lights evaluated for a single draw call, the closest ones win.
+pub const MAX_LIGHTS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    Directional,
+    Point,
+    /// Angles in radians from the spot direction: full intensity inside the inner cone,
+    /// fading to zero at the outer cone.
+    Spot {
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Color,
+    /// Candela for point and spot lights, lux for directional lights.
+    pub intensity: f32,
+    /// Distance at which the light is cut off. `None` is infinite: only the inverse square
+    /// falloff is applied, and the light affects every model.
+    pub range: Option<f32>,
+    /// Ignored by directional lights.
+    pub position: Vec3,
+    /// Ignored by point lights.
+    pub direction: Vec3,
+    /// Spot lights only, drawn into a perspective shadow map. Directional shadows
+    /// are `ShadowCaster`s.
+    pub shadows: bool,
+}
+
+impl Light {
+    pub fn directional(direction: Vec3, color: Color, intensity: f32) -> Light {
+        Light {
+            kind: LightKind::Directional,
+            color,
+            intensity,
+            range: None,
+            position: Vec3::ZERO,
+            direction: direction.normalize(),
+            shadows: false,
+        }
+    }
+
+    pub fn point(position: Vec3, color: Color, intensity: f32, range: Option<f32>) -> Light {
+        Light {
+            kind: LightKind::Point,
+            color,
+            intensity,
+            range,
+            position,
+            direction: vec3(0.0, 0.0, -1.0),
+            shadows: false,
+        }
+    }
+
+    pub fn spot(
+        position: Vec3,
+        direction: Vec3,
+        color: Color,
+        intensity: f32,
+        range: Option<f32>,
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    ) -> Light {
+        Light {
+            kind: LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            },
+            color,
+            intensity,
+            range,
+            position,
+            direction: direction.normalize(),
+            shadows: false,
+        }
+    }
+
+    /// The light attached to a node is positioned at the node's origin and shines down its -Z.
+    pub(crate) fn transformed(&self, matrix: Mat4) -> Light {
+        Light {
+            position: matrix.transform_point3(self.position),
+            direction: matrix.transform_vector3(self.direction).normalize_or_zero(),
+            ..self.clone()
+        }
+    }
+
+    /// Squared distance from the light to the box, 0 for directional lights
+    /// and lights inside the box.
+    fn distance_squared(&self, aabb: &AABB) -> f32 {
+        match self.kind {
+            LightKind::Directional => 0.0,
+            _ => {
+                let closest = self.position.clamp(aabb.min, aabb.max);
+                closest.distance_squared(self.position)
+            }
+        }
+    }
+
+    fn affects(&self, aabb: &AABB) -> bool {
+        match self.range {
+            Some(range) => self.distance_squared(aabb) <= range * range,
+            None => true,
+        }
+    }
+}
+
+/// Lights data, packed the way the default fragment shader expects it.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LightUniforms {
+    pub count: i32,
+    /// xyz - position, w - kind: 0 directional, 1 point, 2 spot.
+    pub positions: [Vec4; MAX_LIGHTS],
+    /// xyz - direction the light travels to, w - range, 0 for infinite.
+    pub directions: [Vec4; MAX_LIGHTS],
+    /// rgb - color multiplied by intensity.
+    pub colors: [Vec4; MAX_LIGHTS],
+    /// x, y - spot cone attenuation scale and offset, z - shadow map, -1 without.
+    pub cones: [Vec4; MAX_LIGHTS],
+}
+
+/// Pick the lights reaching the box, closest first, up to `MAX_LIGHTS`.
+/// `shadow_maps` has the shadow map of each light, -1 without.
+pub(crate) fn cull(lights: &[Light], shadow_maps: &[i32], aabb: &AABB) -> LightUniforms {
+    let mut affecting: Vec<(f32, &Light, i32)> = lights
+        .iter()
+        .zip(shadow_maps)
+        .filter(|(light, _)| light.affects(aabb))
+        .map(|(light, map)| (light.distance_squared(aabb), light, *map))
+        .collect();
+    affecting.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut uniforms = LightUniforms::default();
+    for (ix, (_, light, shadow_map)) in affecting.iter().take(MAX_LIGHTS).enumerate() {
+        let kind = match light.kind {
+            LightKind::Directional => 0.0,
+            LightKind::Point => 1.0,
+            LightKind::Spot { .. } => 2.0,
+        };
+        uniforms.positions[ix] = light.position.extend(kind);
+        uniforms.directions[ix] = light.direction.extend(light.range.unwrap_or(0.0));
+        uniforms.colors[ix] =
+            vec3(light.color.r, light.color.g, light.color.b).extend(0.0) * light.intensity;
+        if let LightKind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        } = light.kind
+        {
+            // from the KHR_lights_punctual reference implementation
+            let inner = inner_cone_angle.cos();
+            let outer = outer_cone_angle.cos();
+            let scale = 1.0 / (inner - outer).max(0.001);
+            uniforms.cones[ix] = Vec4::new(scale, -outer * scale, 0.0, 0.0);
+        }
+        uniforms.cones[ix].z = *shadow_map as f32;
+        uniforms.count += 1;
+    }
+    uniforms
+}
@@ -0,0 +1,49 @@
+//! Morph targets (blend shapes): per-vertex deltas, blended on the CPU
+//! and uploaded into the node's position and normal buffers.
+//!
+//! TANGENT deltas are not needed: tangents are reconstructed in the fragment shader
+//! from the screen-space derivatives of the already morphed positions.
+
+use crate::math::Vec3;
+
+#[derive(Clone, Debug, Default)]
+pub struct MorphTarget {
+    /// POSITION deltas, empty if the target does not move the vertices.
+    pub positions: Vec<Vec3>,
+    /// NORMAL deltas, empty if the target does not change the normals.
+    pub normals: Vec<Vec3>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MorphTargets {
+    /// Base mesh, before any target is applied.
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub targets: Vec<MorphTarget>,
+}
+
+impl MorphTargets {
+    /// Blend the targets with the given weights on top of the base mesh.
+    /// Missing weights are treated as zeroes.
+    pub fn apply(&self, weights: &[f32], positions: &mut Vec<Vec3>, normals: &mut Vec<Vec3>) {
+        positions.clear();
+        positions.extend_from_slice(&self.positions);
+        normals.clear();
+        normals.extend_from_slice(&self.normals);
+
+        for (target, weight) in self.targets.iter().zip(weights) {
+            if *weight == 0.0 {
+                continue;
+            }
+            for (position, delta) in positions.iter_mut().zip(&target.positions) {
+                *position += *delta * *weight;
+            }
+            for (normal, delta) in normals.iter_mut().zip(&target.normals) {
+                *normal += *delta * *weight;
+            }
+        }
+        for normal in normals.iter_mut() {
+            *normal = normal.normalize_or_zero();
+        }
+    }
+}
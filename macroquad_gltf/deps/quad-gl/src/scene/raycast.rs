@@ -0,0 +1,217 @@
+//! Rays against bounding boxes and the CPU copies of the models' triangles.
+
+use glam::{Mat4, Vec3};
+
+use crate::scene::{MeshData, ModelHandle, AABB};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Vec3,
+    /// Normalized.
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn point(&self, distance: f32) -> Vec3 {
+        self.origin + self.direction * distance
+    }
+
+    /// Distance to the box, 0 if the origin is inside, `None` if the ray misses it.
+    pub fn intersect_aabb(&self, aabb: &AABB) -> Option<f32> {
+        let inv = self.direction.recip();
+        let t0 = (aabb.min - self.origin) * inv;
+        let t1 = (aabb.max - self.origin) * inv;
+        let near = t0.min(t1).max_element();
+        let far = t0.max(t1).min_element();
+        // NaNs from 0 * inf, the origin on a slab boundary, fail the comparison
+        if far >= near.max(0.0) {
+            Some(near.max(0.0))
+        } else {
+            None
+        }
+    }
+
+    /// Möller–Trumbore, both sides of the triangle are hit.
+    /// Returns the distance in units of `direction`, which doesn't have to be normalized.
+    pub(crate) fn intersect_triangle(&self, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+        let ab = b - a;
+        let ac = c - a;
+        let p = self.direction.cross(ac);
+        let det = ab.dot(p);
+        if det.abs() < f32::EPSILON * ab.length_squared().max(ac.length_squared()) {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let s = self.origin - a;
+        let u = s.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(ab);
+        let v = self.direction.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = ac.dot(q) * inv_det;
+        (t >= 0.0).then_some(t)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub model: ModelHandle,
+    /// Index in the transforms given to `Scene::set_instances`, 0 for the models drawn once.
+    pub instance: usize,
+    /// Index in `Model::nodes`.
+    pub node: usize,
+    /// Index in the node's `data`.
+    pub primitive: usize,
+    /// Index of the triangle's first vertex in the primitive's indices, divided by 3.
+    pub triangle: usize,
+    pub distance: f32,
+    pub point: Vec3,
+    /// World space normal of the triangle, on the side of its counter-clockwise winding.
+    pub normal: Vec3,
+}
+
+/// Closest triangle of the mesh placed with the matrix, as
+/// (distance, triangle, world space normal).
+pub(crate) fn intersect_mesh(
+    ray: &Ray,
+    mesh: &MeshData,
+    matrix: Mat4,
+) -> Option<(f32, usize, Vec3)> {
+    // The ray goes to the mesh space instead of every vertex to the world.
+    // Its direction is not normalized there, so the distances stay in world units.
+    let inverse = matrix.inverse();
+    let local = Ray {
+        origin: inverse.transform_point3(ray.origin),
+        direction: inverse.transform_vector3(ray.direction),
+    };
+
+    let mut closest: Option<(f32, usize)> = None;
+    for (triangle, indices) in mesh.indices.chunks_exact(3).enumerate() {
+        let [a, b, c] = [indices[0], indices[1], indices[2]].map(|ix| mesh.positions[ix as usize]);
+        if let Some(distance) = local.intersect_triangle(a, b, c) {
+            if closest.map_or(true, |(closest, _)| distance < closest) {
+                closest = Some((distance, triangle));
+            }
+        }
+    }
+
+    closest.map(|(distance, triangle)| {
+        let indices = &mesh.indices[triangle * 3..triangle * 3 + 3];
+        let [a, b, c] = [indices[0], indices[1], indices[2]].map(|ix| mesh.positions[ix as usize]);
+        let normal = inverse
+            .transpose()
+            .transform_vector3((b - a).cross(c - a))
+            .normalize_or_zero();
+        (distance, triangle, normal)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::{vec2, vec3, Quat};
+
+    /// A 2x2 square at z = 0, facing +Z.
+    fn square() -> MeshData {
+        MeshData {
+            positions: vec![
+                vec3(-1.0, -1.0, 0.0),
+                vec3(1.0, -1.0, 0.0),
+                vec3(1.0, 1.0, 0.0),
+                vec3(-1.0, 1.0, 0.0),
+            ],
+            uvs: vec![vec2(0.0, 0.0); 4],
+            normals: vec![Vec3::Z; 4],
+            indices: vec![0, 1, 2, 0, 2, 3],
+        }
+    }
+
+    #[test]
+    fn ray_aabb() {
+        let aabb = AABB {
+            min: Vec3::splat(-1.0),
+            max: Vec3::splat(1.0),
+        };
+        let ray = Ray::new(vec3(0.0, 0.0, 5.0), -Vec3::Z);
+        assert_eq!(ray.intersect_aabb(&aabb), Some(4.0));
+        // inside
+        let ray = Ray::new(Vec3::ZERO, Vec3::X);
+        assert_eq!(ray.intersect_aabb(&aabb), Some(0.0));
+        // pointing away and passing by
+        let ray = Ray::new(vec3(0.0, 0.0, 5.0), Vec3::Z);
+        assert_eq!(ray.intersect_aabb(&aabb), None);
+        let ray = Ray::new(vec3(0.0, 2.0, 5.0), -Vec3::Z);
+        assert_eq!(ray.intersect_aabb(&aabb), None);
+        // axis aligned direction, so 0 * inf on the other axes
+        let ray = Ray::new(vec3(-5.0, 0.5, 0.5), Vec3::X);
+        assert_eq!(ray.intersect_aabb(&aabb), Some(4.0));
+    }
+
+    #[test]
+    fn ray_mesh() {
+        let mesh = square();
+        let ray = Ray::new(vec3(0.5, -0.5, 5.0), -Vec3::Z);
+        let (distance, triangle, normal) = intersect_mesh(&ray, &mesh, Mat4::IDENTITY).unwrap();
+        assert_eq!(distance, 5.0);
+        assert_eq!(triangle, 0);
+        assert!(normal.abs_diff_eq(Vec3::Z, 1e-6));
+
+        let ray = Ray::new(vec3(-0.5, 0.5, 5.0), -Vec3::Z);
+        let (_, triangle, _) = intersect_mesh(&ray, &mesh, Mat4::IDENTITY).unwrap();
+        assert_eq!(triangle, 1);
+
+        // from behind: still a hit, the normal keeps the winding's side
+        let ray = Ray::new(vec3(0.5, -0.5, -5.0), Vec3::Z);
+        let (distance, _, normal) = intersect_mesh(&ray, &mesh, Mat4::IDENTITY).unwrap();
+        assert_eq!(distance, 5.0);
+        assert!(normal.abs_diff_eq(Vec3::Z, 1e-6));
+
+        let ray = Ray::new(vec3(1.5, 0.0, 5.0), -Vec3::Z);
+        assert!(intersect_mesh(&ray, &mesh, Mat4::IDENTITY).is_none());
+    }
+
+    #[test]
+    fn ray_transformed_mesh() {
+        let mesh = square();
+        // scaled up, turned to face +X and moved away
+        let matrix = Mat4::from_scale_rotation_translation(
+            Vec3::splat(3.0),
+            Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+            vec3(10.0, 0.0, 0.0),
+        );
+        // outside the unit square, inside the scaled one
+        let ray = Ray::new(vec3(20.0, 2.0, 2.0), -Vec3::X);
+        let (distance, _, normal) = intersect_mesh(&ray, &mesh, matrix).unwrap();
+        // world units, not the mesh's ones
+        assert!((distance - 10.0).abs() < 1e-4);
+        assert!((ray.point(distance) - vec3(10.0, 2.0, 2.0)).length() < 1e-4);
+        assert!(normal.abs_diff_eq(Vec3::X, 1e-5));
+
+        let ray = Ray::new(vec3(20.0, 4.0, 0.0), -Vec3::X);
+        assert!(intersect_mesh(&ray, &mesh, matrix).is_none());
+    }
+
+    #[test]
+    fn ray_closest_triangle() {
+        // two squares along the ray, the closest one wins whatever the order
+        let mut mesh = square();
+        mesh.positions
+            .extend(square().positions.iter().map(|p| *p + Vec3::Z));
+        mesh.indices = vec![0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7];
+        let ray = Ray::new(vec3(0.5, -0.5, 5.0), -Vec3::Z);
+        let (distance, triangle, _) = intersect_mesh(&ray, &mesh, Mat4::IDENTITY).unwrap();
+        assert_eq!(distance, 4.0);
+        assert_eq!(triangle, 2);
+    }
+}
@@ -2,7 +2,17 @@ use miniquad::*;
 
 use glam::{vec3, vec4, Mat4, Vec3, Vec4, Vec4Swizzles};
 
-use crate::{camera::Projection, scene::ShadowSplit};
+use crate::scene::{
+    self,
+    animation::MAX_JOINTS,
+    bvh::Bvh,
+    frustum,
+    instancing::Instance,
+    light::{Light, LightKind},
+    Model2, ModelHandle, ShadowSplit, AABB,
+};
+
+use slotmap::SlotMap;
 
 mod debugquad {
     use miniquad::*;
@@ -60,7 +70,7 @@ mod debugquad {
                 images: vec![texture],
             };
             let source = match ctx.info().backend {
-                Backend::OpenGl => ShaderSource::Glsl {
+                Backend::OpenGl | Backend::Software => ShaderSource::Glsl {
                     vertex: shader::VERTEX,
                     fragment: shader::FRAGMENT,
                 },
@@ -85,7 +95,7 @@ mod debugquad {
         }
 
         pub fn draw(&mut self, ctx: &mut Context, texture: &[TextureId]) {
-            for i in 0..4 {
+            for i in 0..texture.len() {
                 ctx.begin_default_pass(PassAction::Nothing);
                 self.bindings.images[0] = texture[i];
                 ctx.apply_pipeline(&self.pipeline);
@@ -179,41 +189,97 @@ mod debugquad {
     }
 }
 
+/// Depth maps drawn for a camera: cascades of the `ShadowCaster`s and shadowed spot lights.
+/// Casters and lights that don't fit are drawn without shadows.
+pub const MAX_SHADOW_MAPS: usize = 16;
+/// Directional shadow casters used by a scene, the rest are ignored.
+pub const MAX_SHADOW_CASTERS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShadowFilter {
+    /// A single depth comparison, aliased edges.
+    Hard,
+    /// Percentage-closer filtering of `(2 * radius + 1)^2` texels, radius up to 4.
+    Pcf { radius: u32 },
+    /// Percentage-closer soft shadows: the penumbra widens with the distance between
+    /// the occluder and the receiver. `light_size` is in shadow map texels.
+    Pcss { light_size: f32, radius: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShadowSettings {
+    /// Width and height of each depth map. All the maps share one texture,
+    /// so a few of them at high resolution can hit the GPU's texture size limit.
+    pub resolution: u32,
+    /// Subtracted from the receiver's depth, in the map's 0..1 depth range.
+    pub depth_bias: f32,
+    /// Receivers are moved along their normal by this distance before the lookup,
+    /// against the acne on surfaces at grazing angles to the light.
+    pub normal_offset: f32,
+    pub filter: ShadowFilter,
+    /// Where the cascades split, between uniform (0) and logarithmic (1) distances.
+    pub split_lambda: f32,
+    /// Directional shadows end at this distance from the camera, or at its `z_far`.
+    pub max_distance: f32,
+    /// Part of each cascade faded into the next one, 0 switches sharply.
+    pub cascade_blend: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> ShadowSettings {
+        ShadowSettings {
+            resolution: 2048,
+            depth_bias: 0.001,
+            normal_offset: 0.02,
+            filter: ShadowFilter::Pcf { radius: 1 },
+            split_lambda: 0.75,
+            max_distance: 100.0,
+            cascade_blend: 0.1,
+        }
+    }
+}
+
+/// Shadows data, packed the way the default fragment shader expects it.
+#[derive(Clone, Debug)]
+pub(crate) struct ShadowUniforms {
+    /// World space to the map's part of the atlas, xy - uv, z - depth.
+    pub projections: [Mat4; MAX_SHADOW_MAPS],
+    pub casters_count: i32,
+    /// Per directional caster: x - first map, y - cascades count.
+    pub casters: [Vec4; MAX_SHADOW_CASTERS],
+    /// Per directional caster: distances from the camera where its cascades end.
+    pub splits: [Vec4; MAX_SHADOW_CASTERS],
+    /// x, y - maps per row and column, z, w - texel size in the atlas.
+    pub atlas: Vec4,
+    /// x - depth bias, y - normal offset, z - cascade blend.
+    pub params: Vec4,
+    /// x - 0 hard, 1 PCF, 2 PCSS, y - kernel radius, z - light size.
+    pub filter: Vec4,
+    /// Map of each of the drawn lights, -1 without shadows.
+    pub light_maps: Vec<i32>,
+}
+
+/// All the depth maps of a camera, in a grid in one texture.
 pub struct ShadowMap {
     pub shadow_pipeline: Pipeline,
-    pub shadow_pass: Vec<RenderPass>,
+    pub skinned_shadow_pipeline: Pipeline,
+    pub shadow_pass: RenderPass,
 
-    pub color_img: Vec<TextureId>,
-    pub depth_img: Vec<TextureId>,
+    pub color_img: TextureId,
+    pub depth_img: TextureId,
+    /// Maps per row and column of the atlas.
+    pub grid: (u32, u32),
+    /// Of each map.
+    pub resolution: u32,
 
     pub dbg: debugquad::DebugQuad,
 }
 
 impl ShadowMap {
     pub fn new(ctx: &mut Context) -> ShadowMap {
-        let mut color_img = vec![];
-        let mut depth_img = vec![];
-        let mut shadow_pass = vec![];
-        for i in 0..4 {
-            let c = ctx.new_render_texture(TextureParams {
-                width: 4096,
-                height: 4096,
-                format: TextureFormat::RGBA8,
-                ..Default::default()
-            });
-            let d = ctx.new_render_texture(TextureParams {
-                width: 4096,
-                height: 4096,
-                format: TextureFormat::Depth32,
-                ..Default::default()
-            });
-
-            color_img.push(c);
-            depth_img.push(d);
-            shadow_pass.push(ctx.new_render_pass(c, Some(d)));
-        }
+        let (shadow_pass, color_img, depth_img) = ShadowMap::atlas(ctx, 1, 1);
         let source = match ctx.info().backend {
-            Backend::OpenGl => ShaderSource::Glsl {
+            Backend::OpenGl | Backend::Software => ShaderSource::Glsl {
                 vertex: offscreen_shader::VERTEX,
                 fragment: offscreen_shader::FRAGMENT,
             },
@@ -222,18 +288,35 @@ impl ShadowMap {
             },
         };
         let offscreen_shader = ctx.new_shader(source, offscreen_shader::meta()).unwrap();
+        let source = match ctx.info().backend {
+            Backend::OpenGl | Backend::Software => ShaderSource::Glsl {
+                vertex: offscreen_shader::SKINNED_VERTEX,
+                fragment: offscreen_shader::FRAGMENT,
+            },
+            Backend::Metal => ShaderSource::Msl {
+                program: offscreen_shader::METAL,
+            },
+        };
+        let skinned_offscreen_shader = ctx
+            .new_shader(source, offscreen_shader::skinned_meta())
+            .unwrap();
 
         let shadow_pipeline = ctx.new_pipeline(
             &[
                 BufferLayout::default(),
                 BufferLayout::default(),
                 BufferLayout::default(),
+                Instance::buffer_layout(),
             ],
             &[
-                VertexAttribute::with_buffer("in_pos", VertexFormat::Float3, 0),
-                VertexAttribute::with_buffer("in_uv", VertexFormat::Float2, 1),
-                VertexAttribute::with_buffer("in_normal", VertexFormat::Float3, 2),
-            ],
+                &[
+                    VertexAttribute::with_buffer("in_pos", VertexFormat::Float3, 0),
+                    VertexAttribute::with_buffer("in_uv", VertexFormat::Float2, 1),
+                    VertexAttribute::with_buffer("in_normal", VertexFormat::Float3, 2),
+                ][..],
+                &Instance::attributes(),
+            ]
+            .concat(),
             offscreen_shader,
             PipelineParams {
                 depth_test: Comparison::LessOrEqual,
@@ -242,77 +325,249 @@ impl ShadowMap {
             },
         );
 
+        let skinned_shadow_pipeline = ctx.new_pipeline(
+            &[
+                BufferLayout::default(),
+                BufferLayout::default(),
+                BufferLayout::default(),
+                Instance::buffer_layout(),
+                BufferLayout::default(),
+                BufferLayout::default(),
+            ],
+            &[
+                &[
+                    VertexAttribute::with_buffer("in_pos", VertexFormat::Float3, 0),
+                    VertexAttribute::with_buffer("in_joints", VertexFormat::Float4, 4),
+                    VertexAttribute::with_buffer("in_weights", VertexFormat::Float4, 5),
+                ][..],
+                &Instance::attributes(),
+            ]
+            .concat(),
+            skinned_offscreen_shader,
+            PipelineParams {
+                depth_test: Comparison::LessOrEqual,
+                depth_write: true,
+                ..Default::default()
+            },
+        );
+
         ShadowMap {
             dbg: debugquad::DebugQuad::new(ctx),
             shadow_pass,
             shadow_pipeline,
+            skinned_shadow_pipeline,
             color_img,
             depth_img,
+            grid: (1, 1),
+            resolution: 1,
         }
     }
 
-    pub fn draw_shadow_pass(
+    fn atlas(ctx: &mut Context, width: u32, height: u32) -> (RenderPass, TextureId, TextureId) {
+        let color_img = ctx.new_render_texture(TextureParams {
+            width,
+            height,
+            format: TextureFormat::RGBA8,
+            ..Default::default()
+        });
+        let depth_img = ctx.new_render_texture(TextureParams {
+            width,
+            height,
+            format: TextureFormat::Depth32,
+            ..Default::default()
+        });
+        let pass = ctx.new_render_pass(color_img, Some(depth_img));
+        (pass, color_img, depth_img)
+    }
+
+    /// Make space for the maps, the atlas only grows.
+    fn reserve(&mut self, ctx: &mut Context, maps: usize, resolution: u32) {
+        let (columns, rows) = self.grid;
+        if (columns * rows) as usize >= maps && self.resolution == resolution {
+            return;
+        }
+        let columns = (maps as f32).sqrt().ceil() as u32;
+        let rows = (maps as u32 + columns - 1) / columns;
+        ctx.delete_render_pass(self.shadow_pass);
+        (self.shadow_pass, self.color_img, self.depth_img) =
+            ShadowMap::atlas(ctx, columns * resolution, rows * resolution);
+        self.grid = (columns, rows);
+        self.resolution = resolution;
+    }
+
+    /// Draw the cascades of the directional casters and the maps of the spot lights
+    /// with `shadows`, as seen by the camera.
+    pub(crate) fn draw_shadow_pass(
         &mut self,
         ctx: &mut Context,
-        models: &[crate::scene::Model2],
+        models: &mut SlotMap<ModelHandle, Model2>,
+        bvh: &Bvh<ModelHandle>,
         camera: &crate::camera::Camera,
-        shadow_caster: &crate::scene::ShadowCaster,
-        clipping_planes: [crate::scene::frustum::Plane; 6],
-    ) -> ([Mat4; 4], [f32; 4]) {
-        let (width, height) = window::screen_size();
-
-        let split_count = match shadow_caster.split {
-            ShadowSplit::Orthogonal => 1,
-            ShadowSplit::PSSM2 => 2,
-            ShadowSplit::PSSM4 => 4,
+        shadow_casters: &[crate::scene::ShadowCaster],
+        lights: &[Light],
+        settings: &ShadowSettings,
+    ) -> ShadowUniforms {
+        let mut maps = vec![];
+        let mut uniforms = ShadowUniforms {
+            projections: [Mat4::IDENTITY; MAX_SHADOW_MAPS],
+            casters_count: 0,
+            casters: [Vec4::ZERO; MAX_SHADOW_CASTERS],
+            splits: [Vec4::ZERO; MAX_SHADOW_CASTERS],
+            atlas: Vec4::ZERO,
+            params: vec4(
+                settings.depth_bias,
+                settings.normal_offset,
+                settings.cascade_blend,
+                0.0,
+            ),
+            filter: match settings.filter {
+                ShadowFilter::Hard => Vec4::ZERO,
+                ShadowFilter::Pcf { radius } => vec4(1.0, radius.min(4) as f32, 0.0, 0.0),
+                ShadowFilter::Pcss { light_size, radius } => {
+                    vec4(2.0, radius.min(4) as f32, light_size, 0.0)
+                }
+            },
+            light_maps: vec![-1; lights.len()],
         };
-        let matrices =
-            light_view_porijection_matrices(split_count, &camera, shadow_caster.direction);
 
-        for i in 0..split_count {
-            ctx.begin_pass(
-                Some(self.shadow_pass[i]),
-                PassAction::clear_color(1.0, 1.0, 1.0, 1.0),
-            );
+        let bounds = bvh.bounds();
+        for shadow_caster in shadow_casters.iter().take(MAX_SHADOW_CASTERS) {
+            let split_count = match shadow_caster.split {
+                ShadowSplit::Orthogonal => 1,
+                ShadowSplit::PSSM2 => 2,
+                ShadowSplit::PSSM4 => 4,
+            };
+            if maps.len() + split_count > MAX_SHADOW_MAPS {
+                break;
+            }
+            let splits = cascade_splits(camera, split_count, settings);
+            let caster = uniforms.casters_count as usize;
+            uniforms.casters[caster] = vec4(maps.len() as f32, split_count as f32, 0.0, 0.0);
+            uniforms.splits[caster] = Vec4::from(splits);
+            uniforms.casters_count += 1;
+            let mut near = camera.z_near;
+            for far in &splits[..split_count] {
+                maps.push(cascade_matrix(
+                    camera,
+                    shadow_caster.direction,
+                    (near, *far),
+                    bounds,
+                    settings.resolution,
+                ));
+                near = *far;
+            }
+        }
+        for (ix, light) in lights.iter().enumerate() {
+            if !light.shadows || maps.len() == MAX_SHADOW_MAPS {
+                continue;
+            }
+            if let Some(matrix) = spot_matrix(light, settings) {
+                uniforms.light_maps[ix] = maps.len() as i32;
+                maps.push(matrix);
+            }
+        }
+        if maps.is_empty() {
+            return uniforms;
+        }
 
-            let depth_view_proj = matrices[i];
-            for crate::scene::Model2 {
-                model,
-                transform,
-                world_aabb,
-            } in models
-            {
-                if clipping_planes.iter().any(|p| !p.clip(*world_aabb)) {
+        self.reserve(ctx, maps.len(), settings.resolution);
+        let (columns, rows) = self.grid;
+        let resolution = self.resolution as i32;
+        uniforms.atlas = vec4(
+            columns as f32,
+            rows as f32,
+            1.0 / (columns * self.resolution) as f32,
+            1.0 / (rows * self.resolution) as f32,
+        );
+
+        ctx.begin_pass(
+            Some(self.shadow_pass),
+            PassAction::clear_color(1.0, 1.0, 1.0, 1.0),
+        );
+        for (map, depth_view_proj) in maps.iter().enumerate() {
+            let (column, row) = (map as u32 % columns, map as u32 / columns);
+            ctx.apply_viewport(
+                column as i32 * resolution,
+                row as i32 * resolution,
+                resolution,
+                resolution,
+            );
+            // from the clip space to the map's part of the atlas, and the depth into 0..1
+            let tile = Mat4::from_translation(vec3(
+                column as f32 / columns as f32,
+                row as f32 / rows as f32,
+                0.0,
+            )) * Mat4::from_scale(vec3(1.0 / columns as f32, 1.0 / rows as f32, 1.0))
+                * Mat4::from_translation(Vec3::splat(0.5))
+                * Mat4::from_scale(Vec3::splat(0.5));
+            uniforms.projections[map] = tile * *depth_view_proj;
+
+            // only what the map sees casts shadows into it
+            let planes = frustum::matrix_planes(*depth_view_proj);
+            for h in scene::visible_in(models, bvh, &planes) {
+                models[h].upload_instances(ctx, |aabb| planes.iter().all(|p| p.clip(*aabb)));
+                let instances = models[h].drawn_instances();
+                if instances == 0 {
                     continue;
                 }
-
-                for node in &model.nodes {
+                let Model2 {
+                    model,
+                    world,
+                    joint_matrices,
+                    node_globals,
+                    instances: instanced,
+                    ..
+                } = &models[h];
+
+                for (node, node_global) in model.nodes.iter().zip(node_globals) {
                     for bindings in &node.data {
-                        let model = transform.matrix() * node.transform.matrix();
-
-                        ctx.apply_pipeline(&self.shadow_pipeline);
-                        ctx.apply_bindings_from_slice(
-                            &bindings.vertex_buffers,
-                            bindings.index_buffer,
-                            &[],
-                        );
-                        ctx.apply_uniforms(UniformsSource::table(&offscreen_shader::Uniforms {
-                            mvp: depth_view_proj.0 * model,
-                        }));
-                        let len = ctx.buffer_size(bindings.index_buffer) / 2;
-                        // TODO: dont skip multi_position
-                        ctx.draw(0, len as _, 1);
+                        let skin = node
+                            .skin
+                            .filter(|_| bindings.vertex_buffers.len() >= 6)
+                            .map(|skin| &joint_matrices[skin]);
+                        if let Some(joints) = skin {
+                            ctx.apply_pipeline(&self.skinned_shadow_pipeline);
+                            ctx.apply_bindings_from_slice(
+                                &bindings.vertex_buffers,
+                                bindings.index_buffer,
+                                &[],
+                            );
+                            let mut skinned = offscreen_shader::SkinnedUniforms {
+                                mvp: *depth_view_proj * *world,
+                                joints: [Mat4::IDENTITY; MAX_JOINTS],
+                            };
+                            let count = joints.len().min(MAX_JOINTS);
+                            skinned.joints[..count].copy_from_slice(&joints[..count]);
+                            ctx.apply_uniforms(UniformsSource::table(&skinned));
+                        } else {
+                            // instance matrices already include the node transform
+                            let model = match instanced {
+                                Some(_) => *world,
+                                None => *world * *node_global,
+                            };
+
+                            ctx.apply_pipeline(&self.shadow_pipeline);
+                            ctx.apply_bindings_from_slice(
+                                &bindings.vertex_buffers,
+                                bindings.index_buffer,
+                                &[],
+                            );
+                            ctx.apply_uniforms(UniformsSource::table(
+                                &offscreen_shader::Uniforms {
+                                    mvp: *depth_view_proj * model,
+                                },
+                            ));
+                        }
+                        let len =
+                            ctx.buffer_size(bindings.index_buffer) / bindings.index_type.size();
+                        ctx.draw(0, len as _, instances as _);
                     }
                 }
             }
-
-            ctx.end_render_pass();
         }
+        ctx.end_render_pass();
 
-        (
-            [matrices[0].0, matrices[1].0, matrices[2].0, matrices[3].0],
-            [matrices[0].2, matrices[1].2, matrices[2].2, matrices[3].2],
-        )
+        uniforms
     }
 }
 
@@ -323,11 +578,38 @@ mod offscreen_shader {
     attribute vec3 in_pos;
     attribute vec2 in_uv;
     attribute vec3 in_normal;
+    attribute vec4 in_inst0;
+    attribute vec4 in_inst1;
+    attribute vec4 in_inst2;
+    attribute vec4 in_inst3;
+
+    uniform mat4 mvp;
+
+    void main() {
+        mat4 inst = mat4(in_inst0, in_inst1, in_inst2, in_inst3);
+        gl_Position = mvp * inst * vec4(in_pos, 1.0);
+    }
+    "#;
+
+    pub const SKINNED_VERTEX: &str = r#"#version 100
+    attribute vec3 in_pos;
+    attribute vec4 in_joints;
+    attribute vec4 in_weights;
+    attribute vec4 in_inst0;
+    attribute vec4 in_inst1;
+    attribute vec4 in_inst2;
+    attribute vec4 in_inst3;
 
     uniform mat4 mvp;
+    uniform mat4 Joints[64];
 
     void main() {
-        gl_Position = mvp * vec4(in_pos, 1.0);
+        mat4 skin = in_weights.x * Joints[int(in_joints.x)]
+            + in_weights.y * Joints[int(in_joints.y)]
+            + in_weights.z * Joints[int(in_joints.z)]
+            + in_weights.w * Joints[int(in_joints.w)];
+        mat4 inst = mat4(in_inst0, in_inst1, in_inst2, in_inst3);
+        gl_Position = mvp * inst * skin * vec4(in_pos, 1.0);
     }
     "#;
 
@@ -349,30 +631,76 @@ mod offscreen_shader {
         }
     }
 
+    pub fn skinned_meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec![],
+            uniforms: UniformBlockLayout {
+                uniforms: vec![
+                    UniformDesc::new("mvp", UniformType::Mat4),
+                    UniformDesc::array(
+                        UniformDesc::new("Joints", UniformType::Mat4),
+                        crate::scene::animation::MAX_JOINTS,
+                    ),
+                ],
+            },
+        }
+    }
+
     #[repr(C)]
     pub struct Uniforms {
         pub mvp: glam::Mat4,
     }
+
+    #[repr(C)]
+    pub struct SkinnedUniforms {
+        pub mvp: glam::Mat4,
+        pub joints: [glam::Mat4; crate::scene::animation::MAX_JOINTS],
+    }
 }
 
-pub fn light_view_porijection_matrices(
+/// Distances from the camera where the cascades end, mixing the uniform and the
+/// logarithmic split schemes by `split_lambda`.
+fn cascade_splits(
+    camera: &crate::camera::Camera,
     num_cascades: usize,
+    settings: &ShadowSettings,
+) -> [f32; 4] {
+    let near = camera.z_near;
+    let far = camera.z_far.min(settings.max_distance).max(near);
+    // the logarithmic scheme needs a positive near plane
+    let log_near = near.max(0.01);
+    let mut splits = [far; 4];
+    for (cascade, split) in splits.iter_mut().take(num_cascades).enumerate() {
+        let t = (cascade + 1) as f32 / num_cascades as f32;
+        let uniform = near + (far - near) * t;
+        let logarithmic = log_near * (far.max(log_near) / log_near).powf(t);
+        *split = uniform + (logarithmic - uniform) * settings.split_lambda;
+    }
+    splits
+}
+
+/// Orthographic light view projection covering the part of the camera's frustum between
+/// the distances. Sized by the slice's bounding sphere and snapped to the map's texels,
+/// so it doesn't change and shimmer as the camera turns.
+/// The depth range is extended towards the light to the scene's bounds,
+/// to include the casters outside of the camera's view.
+fn cascade_matrix(
     camera: &crate::camera::Camera,
     light_dir: Vec3,
-) -> Vec<(Mat4, [Vec4; 8], f32)> {
-    let (screen_width, screen_height) = miniquad::window::screen_size();
-    let aspect = screen_width / screen_height;
-    let mut res = vec![Default::default(); 4];
-
-    let light_view = Mat4::look_at_rh(
-        light_dir,           // + middle,
-        vec3(0.0, 0.0, 0.0), // + middle,
-        vec3(0.0, 1.0, 0.0),
-    );
+    (near, far): (f32, f32),
+    bounds: Option<AABB>,
+    resolution: u32,
+) -> Mat4 {
+    let up = if light_dir.normalize().y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+    let light_view = Mat4::look_at_rh(light_dir, Vec3::ZERO, up);
 
     let (proj, view) = camera.proj_view();
     let inv = (proj * view).inverse();
-    let mut ndc = [
+    let corners = [
         vec4(-1.0, -1.0, -1.0, 1.0),
         vec4(1.0, -1.0, -1.0, 1.0),
         vec4(-1.0, 1.0, -1.0, 1.0),
@@ -381,70 +709,67 @@ pub fn light_view_porijection_matrices(
         vec4(1.0, -1.0, 1.0, 1.0),
         vec4(-1.0, 1.0, 1.0, 1.0),
         vec4(1.0, 1.0, 1.0, 1.0),
-    ];
-    let mut ndc_debug = ndc.clone();
-    for ndc in &mut ndc_debug {
-        *ndc = inv * *ndc;
-        *ndc /= ndc.w;
+    ]
+    .map(|ndc| {
+        let world = inv * ndc;
+        world.xyz() / world.w
+    });
+
+    // points on the rays from the near to the far plane move linearly with the view depth
+    let depth = (camera.z_far - camera.z_near).max(f32::EPSILON);
+    let (t0, t1) = (
+        (near - camera.z_near) / depth,
+        (far - camera.z_near) / depth,
+    );
+    let mut slice = [Vec3::ZERO; 8];
+    for i in 0..4 {
+        slice[i] = corners[i].lerp(corners[i + 4], t0);
+        slice[i + 4] = corners[i].lerp(corners[i + 4], t1);
     }
-    for ndc in &mut ndc {
-        *ndc = light_view * inv * *ndc;
-        *ndc /= ndc.w;
+    let center = slice.iter().sum::<Vec3>() / 8.0;
+    let radius = slice
+        .iter()
+        .map(|corner| corner.distance(center))
+        .fold(0.0, f32::max)
+        .max(0.001);
+
+    let mut center = light_view.transform_point3(center);
+    let texel = radius * 2.0 / resolution as f32;
+    center.x = (center.x / texel).floor() * texel;
+    center.y = (center.y / texel).floor() * texel;
+
+    // the light looks down -Z, casters between the slice and the light have bigger z
+    let mut max_z = center.z + radius;
+    if let Some(bounds) = bounds {
+        max_z = max_z.max(bounds.transformed(light_view).max.z);
     }
+    let light_proj = Mat4::orthographic_rh_gl(
+        center.x - radius,
+        center.x + radius,
+        center.y - radius,
+        center.y + radius,
+        -max_z,
+        -(center.z - radius),
+    );
+    light_proj * light_view
+}
 
-    let cascades_split2 = [0.0, 0.3, 1.0];
-    let cascades_split4 = [0.0, 0.1, 0.2, 0.5, 1.0];
-    for cascade in 0..num_cascades {
-        let near;
-        let far;
-        if num_cascades == 1 {
-            near = 0.0;
-            far = 1.0;
-        } else if num_cascades == 2 {
-            near = cascades_split2[cascade];
-            far = cascades_split2[cascade + 1];
-        } else if num_cascades == 4 {
-            near = cascades_split4[cascade];
-            far = cascades_split4[cascade + 1];
-        } else {
-            panic!()
-        };
-
-        let ndc_debug = [
-            ndc_debug[0].lerp(ndc_debug[4], near),
-            ndc_debug[1].lerp(ndc_debug[5], near),
-            ndc_debug[2].lerp(ndc_debug[6], near),
-            ndc_debug[3].lerp(ndc_debug[7], near),
-            ndc_debug[0].lerp(ndc_debug[4], far),
-            ndc_debug[1].lerp(ndc_debug[5], far),
-            ndc_debug[2].lerp(ndc_debug[6], far),
-            ndc_debug[3].lerp(ndc_debug[7], far),
-        ];
-        let ndc1 = [
-            ndc[0].lerp(ndc[4], near),
-            ndc[1].lerp(ndc[5], near),
-            ndc[2].lerp(ndc[6], near),
-            ndc[3].lerp(ndc[7], near),
-            ndc[0].lerp(ndc[4], far),
-            ndc[1].lerp(ndc[5], far),
-            ndc[2].lerp(ndc[6], far),
-            ndc[3].lerp(ndc[7], far),
-        ];
-
-        let mut min = vec3(std::f32::MAX, std::f32::MAX, std::f32::MAX);
-        let mut max = vec3(-std::f32::MAX, -std::f32::MAX, -std::f32::MAX);
-
-        for ndc in &ndc1 {
-            min = min.min(ndc.xyz());
-            max = max.max(ndc.xyz());
-        }
-
-        let light_proj = Mat4::orthographic_rh_gl(min.x, max.x, min.y, max.y, -max.z, -min.z);
-        res[cascade] = (
-            light_proj * light_view,
-            ndc_debug,
-            camera.z_near + far * (camera.z_far - camera.z_near),
-        );
-    }
-    res
+/// Perspective view projection of a spot light's cone, `None` for other lights.
+fn spot_matrix(light: &Light, settings: &ShadowSettings) -> Option<Mat4> {
+    let LightKind::Spot {
+        outer_cone_angle, ..
+    } = light.kind
+    else {
+        return None;
+    };
+    let far = light.range.unwrap_or(settings.max_distance);
+    let near = (far * 0.001).max(0.01);
+    let up = if light.direction.y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+    let view = Mat4::look_at_rh(light.position, light.position + light.direction, up);
+    let fov = (outer_cone_angle * 2.0).clamp(0.01, 179f32.to_radians());
+    Some(Mat4::perspective_rh_gl(fov, 1.0, near, far) * view)
 }
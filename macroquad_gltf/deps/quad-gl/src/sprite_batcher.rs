@@ -82,3 +82,76 @@ impl SpriteBatcher {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use miniquad::{conf, PassAction, RenderingBackend, SoftwareContext};
+
+    /// Draws two rectangles in its only frame and keeps the screen's pixels.
+    struct Snapshot {
+        pixels: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl miniquad::EventHandler for Snapshot {
+        fn update(&mut self) {}
+
+        fn draw(&mut self) {
+            let (width, height) = miniquad::window::screen_size();
+            let ctx = SoftwareContext::new(width as u32, height as u32);
+            let screen = ctx.screen_texture();
+            let ctx: Box<miniquad::Context> = Box::new(ctx);
+            let quad_gl = crate::QuadGl::new(Arc::new(Mutex::new(ctx)));
+            let mut canvas = quad_gl.new_canvas();
+
+            // left half red, top right quarter green, in y down screen coordinates
+            canvas.draw_rectangle(0.0, 0.0, 4.0, 8.0, Color::new(1.0, 0.0, 0.0, 1.0));
+            canvas.draw_rectangle(4.0, 0.0, 4.0, 4.0, Color::new(0.0, 1.0, 0.0, 1.0));
+            let mut ctx = quad_gl.quad_ctx.lock().unwrap();
+            ctx.begin_default_pass(PassAction::clear_color(0.0, 0.0, 1.0, 1.0));
+            ctx.end_render_pass();
+            drop(ctx);
+            canvas.draw();
+
+            let mut pixels = self.pixels.lock().unwrap();
+            pixels.resize((width * height) as usize * 4, 0);
+            let mut ctx = quad_gl.quad_ctx.lock().unwrap();
+            ctx.texture_read_pixels(screen, &mut pixels);
+        }
+    }
+
+    #[test]
+    fn headless_snapshot() {
+        let pixels = Arc::new(Mutex::new(vec![]));
+        let snapshot = Snapshot {
+            pixels: pixels.clone(),
+        };
+        miniquad::start(
+            conf::Conf {
+                window_width: 8,
+                window_height: 8,
+                platform: conf::Platform {
+                    headless_frames: Some(1),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            move || Box::new(snapshot),
+        );
+
+        // rows bottom to top
+        let pixels = pixels.lock().unwrap();
+        assert_eq!(pixels.len(), 8 * 8 * 4);
+        let pixel = |x: usize, y: usize| &pixels[(y * 8 + x) * 4..(y * 8 + x) * 4 + 4];
+        for (x, y) in [(0, 0), (3, 0), (0, 7), (3, 7)] {
+            assert_eq!(pixel(x, y), [255, 0, 0, 255], "({x}, {y})");
+        }
+        for (x, y) in [(4, 4), (7, 7)] {
+            assert_eq!(pixel(x, y), [0, 255, 0, 255], "({x}, {y})");
+        }
+        for (x, y) in [(4, 0), (7, 3)] {
+            assert_eq!(pixel(x, y), [0, 0, 255, 255], "({x}, {y})");
+        }
+    }
+}
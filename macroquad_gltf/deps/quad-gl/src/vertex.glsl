@@ -1,27 +1,50 @@
 attribute vec3 in_position; // [[attribute(0)]]
 attribute vec2 in_uv; // [[attribute(1)]];
 attribute vec3 in_normal; // [[attribute(2)]];
-attribute vec3 in_inst; // [[attribute(3)]];
+attribute vec4 in_inst0; // [[attribute(3)]];
+attribute vec4 in_inst1; // [[attribute(4)]];
+attribute vec4 in_inst2; // [[attribute(5)]];
+attribute vec4 in_inst3; // [[attribute(6)]];
+attribute vec4 in_tint; // [[attribute(7)]];
+#ifdef HAS_SKIN
+attribute vec4 in_joints; // [[attribute(8)]];
+attribute vec4 in_weights; // [[attribute(9)]];
+#endif
 
 varying vec2 out_uv; // [[user(locn0)]];
 varying vec3 out_pos; // [[user(locn1)]];
 varying vec3 out_normal; // [[user(locn2)]];
-varying vec4 out_shadow[4]; // [[user(locn3)]];
-varying float out_clip_z; // [[user(locn4)]];
+varying vec4 out_tint; // [[user(locn5)]];
+varying vec4 out_screen; // [[user(locn6)]];
 
 uniform mat4 Model;
 uniform mat4 ModelInverse;
 uniform mat4 Projection;
-uniform mat4 ShadowProjection[4];
+#ifdef HAS_SKIN
+uniform mat4 Joints[64];
+#endif
 
 void main() {
-    vec3 p = in_position + in_inst;
+    vec3 p = in_position;
+    vec3 normal = in_normal;
+#ifdef HAS_SKIN
+    mat4 skin = in_weights.x * Joints[int(in_joints.x)]
+        + in_weights.y * Joints[int(in_joints.y)]
+        + in_weights.z * Joints[int(in_joints.z)]
+        + in_weights.w * Joints[int(in_joints.w)];
+    p = vec3(skin * vec4(p, 1.0));
+    normal = mat3(skin) * normal;
+#endif
+    mat4 inst = mat4(in_inst0, in_inst1, in_inst2, in_inst3);
+    p = vec3(inst * vec4(p, 1.0));
+    // cofactor matrix, the inverse transpose up to a positive scale
+    vec3 c0 = cross(inst[1].xyz, inst[2].xyz);
+    mat3 inst_normal = mat3(c0, cross(inst[2].xyz, inst[0].xyz), cross(inst[0].xyz, inst[1].xyz));
+    normal = inst_normal * normal * sign(dot(inst[0].xyz, c0));
     gl_Position = Projection * Model * vec4(p, 1);
-    out_clip_z = gl_Position.z;
+    out_screen = gl_Position;
     out_uv = in_uv;
-    out_normal = transpose(mat3(ModelInverse)) * in_normal;
+    out_tint = in_tint;
+    out_normal = transpose(mat3(ModelInverse)) * normal;
     out_pos = vec3(Model * vec4(p, 1.0));
-    for (int i = 0; i < 4; i++) {
-        out_shadow[i] = ShadowProjection[i] * Model * vec4(p, 1.0) * vec4(0.5) + vec4(0.5);
-    }
 }
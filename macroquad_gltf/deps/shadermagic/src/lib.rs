@@ -59,10 +59,39 @@ fn lower_gl_missing_math() -> &'static str {
 "#
 }
 
+/// Parse `layout(location = N) out vec4 name;`, an output of a fragment shader
+/// drawing into multiple render targets.
+fn fragment_output(line: &str) -> Option<(u32, &str)> {
+    let line = line.trim().strip_prefix("layout")?.trim_start();
+    let line = line.strip_prefix('(')?.trim_start();
+    let line = line.strip_prefix("location")?.trim_start();
+    let line = line.strip_prefix('=')?.trim_start();
+    let (location, line) = line.split_once(')')?;
+    let location = location.trim().parse().ok()?;
+    let line = line.trim_start().strip_prefix("out")?.trim_start();
+    let line = line.strip_prefix("vec4")?.trim_start();
+    let name = line.strip_suffix(';')?.trim();
+    Some((location, name))
+}
+
+fn fragment_outputs(input: &str) -> Vec<(u32, &str)> {
+    input.lines().filter_map(fragment_output).collect()
+}
+
+/// Versions without the layout qualifiers write the outputs into gl_FragData.
+fn push_gl_frag_data_outputs(processed: &mut String, input: &str) {
+    for (location, name) in fragment_outputs(input) {
+        processed.push_str(&format!("#define {} gl_FragData[{}]\n", name, location));
+    }
+}
+
 fn glsl_v100(input: &str, _kind: ShaderKind, defines: &[String]) -> String {
     let mut processed = String::new();
 
     processed.push_str("#version 100\n");
+    if !fragment_outputs(input).is_empty() {
+        processed.push_str("#extension GL_EXT_draw_buffers: require\n");
+    }
     processed.push_str("precision mediump float;\n");
     processed.push_str("float dFdx(float x) {return 0.0;}\n");
     processed.push_str("float dFdy(float x) {return 0.0;}\n");
@@ -79,8 +108,9 @@ fn glsl_v100(input: &str, _kind: ShaderKind, defines: &[String]) -> String {
         processed.push_str(&format!("#define {} 1\n", define));
     }
     processed.push_str("#define __GL 1\n");
+    push_gl_frag_data_outputs(&mut processed, input);
 
-    for line in input.lines() {
+    for line in input.lines().filter(|line| fragment_output(line).is_none()) {
         processed.push_str(&line);
         processed.push('\n');
     }
@@ -92,6 +122,9 @@ fn glsl_v100_webgl(input: &str, _kind: ShaderKind, defines: &[String]) -> String
     let mut processed = String::new();
 
     processed.push_str("#version 100\n");
+    if !fragment_outputs(input).is_empty() {
+        processed.push_str("#extension GL_EXT_draw_buffers: require\n");
+    }
     processed.push_str("#extension GL_EXT_shader_texture_lod: enable\n");
     processed.push_str("#extension GL_OES_standard_derivatives: enable\n");
     processed.push_str("precision mediump float;\n");
@@ -102,8 +135,9 @@ fn glsl_v100_webgl(input: &str, _kind: ShaderKind, defines: &[String]) -> String
     }
     processed.push_str("#define __GL 1\n");
     processed.push_str("#define sm_level(x) x\n");
+    push_gl_frag_data_outputs(&mut processed, input);
 
-    for line in input.lines() {
+    for line in input.lines().filter(|line| fragment_output(line).is_none()) {
         let line = line.replace("textureCubeLod", "textureCubeLodEXT");
         processed.push_str(&line);
         processed.push('\n');
@@ -123,8 +157,9 @@ fn glsl_v130(input: &str, _kind: ShaderKind, defines: &[String]) -> String {
         processed.push_str(&format!("#define {} 1\n", define));
     }
     processed.push_str("#define __GL 1\n");
+    push_gl_frag_data_outputs(&mut processed, input);
 
-    for line in input.lines() {
+    for line in input.lines().filter(|line| fragment_output(line).is_none()) {
         processed.push_str(&line);
         processed.push('\n');
     }
@@ -140,8 +175,11 @@ fn glsl_v330(input: &str, kind: ShaderKind, defines: &[String]) -> String {
         processed.push_str(&format!("#define {} 1\n", define));
     }
     processed.push_str("#define __GL 1\n");
+    // with multiple render targets the outputs are declared by the shader itself
     if let ShaderKind::Fragment = kind {
-        processed.push_str("out vec4 output_FragColor;\n");
+        if fragment_outputs(input).is_empty() {
+            processed.push_str("out vec4 output_FragColor;\n");
+        }
     }
     processed.push_str("#define sm_level(x) x\n");
 
@@ -177,8 +215,11 @@ fn glsl_v300es(input: &str, kind: ShaderKind, defines: &[String]) -> String {
         processed.push_str(&format!("#define {} 1\n", define));
     }
     processed.push_str("#define __GL 1\n");
+    // with multiple render targets the outputs are declared by the shader itself
     if let ShaderKind::Fragment = kind {
-        processed.push_str("out vec4 output_FragColor;\n");
+        if fragment_outputs(input).is_empty() {
+            processed.push_str("out vec4 output_FragColor;\n");
+        }
     }
     processed.push_str("#define sm_level(x) x\n");
 
@@ -239,7 +280,7 @@ pub fn choose_appropriate_shader<'a>(
     use miniquad::{Backend, ShaderSource};
 
     match context_info.backend {
-        Backend::OpenGl => {
+        Backend::OpenGl | Backend::Software => {
             if context_info.glsl_support.v300es {
                 ShaderSource::Glsl {
                     vertex: &shader.v300es.vertex,
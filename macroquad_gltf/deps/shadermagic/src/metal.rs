@@ -238,6 +238,7 @@ pub fn metal(
             main_curly_braces += count_braces(&line, '{');
             main_curly_braces -= count_braces(&line, '}');
             line = line.replace("gl_FragColor", "msl_out_color");
+            line = line.replace("discard;", "discard_fragment();");
             for (_, target) in &mrt_targets {
                 line = line.replace(target, &format!("msl_out_color.{target}"));
             }
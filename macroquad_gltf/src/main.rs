@@ -19,18 +19,19 @@ async fn game(ctx: macroquad::Context) {
     let helmet = scene.add_model(&helmet);
     let skybox = ctx
         .resources
-        .load_cubemap(
+        .load_cubemap(&[
             "assets/skybox/skybox_px.png",
             "assets/skybox/skybox_nx.png",
             "assets/skybox/skybox_py.png",
             "assets/skybox/skybox_ny.png",
             "assets/skybox/skybox_pz.png",
             "assets/skybox/skybox_nz.png",
-        )
+        ])
         .await
         .unwrap();
     let mut orbit = orbit_camera::OrbitCamera::new();
     orbit.camera.environment = Environment::Skybox(skybox);
+    orbit.fit(scene.bounding_sphere(&helmet));
     let mut canvas = ctx.new_canvas();
     loop {
         ctx.clear_screen(color::WHITE);
@@ -5,6 +5,7 @@ use macroquad::{
     quad_gl::{
         camera::{Camera, Environment, Projection},
         color,
+        scene::Sphere,
     },
 };
 
@@ -12,11 +13,14 @@ pub struct OrbitCamera {
     dolly_rig: CameraRig,
     pub camera: Camera,
     zoom: f32,
+    /// Zoom limits and speed are relative to the default 4 units distance.
+    scale: f32,
 }
 
 impl OrbitCamera {
     pub fn new() -> OrbitCamera {
         let dolly_rig: CameraRig = CameraRig::builder()
+            .with(Position::new(Vec3::ZERO))
             .with(YawPitch::new().yaw_degrees(45.0).pitch_degrees(-10.0))
             .with(Smooth::new_rotation(0.7))
             .with(Arm::new(Vec3::Z * 4.0))
@@ -38,9 +42,19 @@ impl OrbitCamera {
             dolly_rig,
             camera,
             zoom: 4.0,
+            scale: 1.0,
         }
     }
 
+    /// Orbit around the sphere's center, from far enough to see all of it.
+    pub fn fit(&mut self, sphere: Sphere) {
+        self.camera.frame(sphere);
+        self.zoom = self.camera.position.distance(self.camera.target);
+        self.scale = self.zoom / 4.0;
+        self.dolly_rig.driver_mut::<Position>().position = sphere.center.into();
+        self.dolly_rig.driver_mut::<Arm>().offset = (Vec3::Z * self.zoom).into();
+    }
+
     pub fn orbit(&mut self, ctx: &macroquad::Context) {
         if !ctx.root_ui().is_mouse_over(ctx.mouse_position())
             && ctx.is_mouse_button_down(MouseButton::Left)
@@ -50,8 +64,8 @@ impl OrbitCamera {
                 .rotate_yaw_pitch(ctx.mouse_delta().x * 100., ctx.mouse_delta().y * 100.);
         }
         if ctx.mouse_wheel().1 != 0.0 {
-            self.zoom -= ctx.mouse_wheel().1 * 0.4;
-            self.zoom = self.zoom.clamp(1.8, 10.0);
+            self.zoom -= ctx.mouse_wheel().1 * 0.4 * self.scale;
+            self.zoom = self.zoom.clamp(1.8 * self.scale, 10.0 * self.scale);
             self.dolly_rig.driver_mut::<Arm>().offset = (Vec3::Z * self.zoom).into();
         }
         let delta = 0.1;
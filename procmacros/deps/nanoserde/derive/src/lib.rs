@@ -21,6 +21,11 @@ mod serde_json;
 #[cfg(feature = "json")]
 use crate::serde_json::*;
 
+#[cfg(feature = "toml")]
+mod serde_toml;
+#[cfg(feature = "toml")]
+use crate::serde_toml::*;
+
 mod parse;
 
 #[cfg(feature = "binary")]
@@ -143,3 +148,43 @@ pub fn derive_de_json(input: proc_macro::TokenStream) -> proc_macro::TokenStream
 
     ts
 }
+
+#[cfg(feature = "toml")]
+#[proc_macro_derive(SerToml, attributes(nserde))]
+pub fn derive_ser_toml(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse::parse_data(input);
+
+    if let Some(proxy) = shared::attrs_proxy(&input.attributes()) {
+        return derive_ser_toml_proxy(&proxy, &input.name());
+    }
+
+    // ok we have an ident, its either a struct or a enum
+    let ts = match &input {
+        parse::Data::Struct(struct_) if struct_.named => derive_ser_toml_struct(struct_),
+        parse::Data::Struct(struct_) => derive_ser_toml_struct_unnamed(struct_),
+        parse::Data::Enum(enum_) => derive_ser_toml_enum(enum_),
+        _ => unimplemented!("Only structs and enums are supported"),
+    };
+
+    ts
+}
+
+#[cfg(feature = "toml")]
+#[proc_macro_derive(DeToml, attributes(nserde))]
+pub fn derive_de_toml(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse::parse_data(input);
+
+    if let Some(proxy) = shared::attrs_proxy(&input.attributes()) {
+        return derive_de_toml_proxy(&proxy, &input.name());
+    }
+
+    // ok we have an ident, its either a struct or a enum
+    let ts = match &input {
+        parse::Data::Struct(struct_) if struct_.named => derive_de_toml_struct(struct_),
+        parse::Data::Struct(struct_) => derive_de_toml_struct_unnamed(struct_),
+        parse::Data::Enum(enum_) => derive_de_toml_enum(enum_),
+        _ => unimplemented!("Only structs and enums are supported"),
+    };
+
+    ts
+}
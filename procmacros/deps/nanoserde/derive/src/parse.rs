@@ -199,12 +199,12 @@ impl Generic {
         }
     }
 
-    #[cfg(any(feature = "binary", feature = "json"))]
+    #[cfg(any(feature = "binary", feature = "json", feature = "toml"))]
     pub fn ident_only(&self) -> String {
         format!("{}{}", self.lifetime_prefix(), self.full())
     }
 
-    #[cfg(any(feature = "binary", feature = "json"))]
+    #[cfg(any(feature = "binary", feature = "json", feature = "toml"))]
     pub fn full_with_const(&self, extra_bounds: &[&str], bounds: bool) -> String {
         let bounds = match (bounds, &self) {
             (true, Generic::Lifetime { .. }) => self.get_bounds().join(" + "),
@@ -418,7 +418,7 @@ impl Category {
 }
 
 impl Type {
-    #[cfg(any(feature = "ron", feature = "json"))]
+    #[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
     pub fn base(&self) -> String {
         let mut base = match &self.ref_type {
             Some(inner) => match inner {
@@ -1541,7 +1541,8 @@ pub fn parse_data(input: TokenStream) -> Data {
             res = Data::Struct(struct_);
         }
         "enum" => {
-            let enum_ = next_enum(&mut source);
+            let mut enum_ = next_enum(&mut source);
+            enum_.attributes = attributes;
             res = Data::Enum(enum_);
         }
         "union" => unimplemented!("Unions are not supported"),
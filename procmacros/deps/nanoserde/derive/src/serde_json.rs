@@ -2,8 +2,10 @@ use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::{vec, vec::Vec};
 
-use crate::parse::{Category, Type};
-use crate::shared::{enum_bounds_strings, struct_bounds_strings};
+use crate::parse::{Category, Generic, Type};
+use crate::shared::{
+    enum_bounds_strings, ser_proxy_guard, struct_bounds_strings, variant_kind, EnumTag, VariantKind,
+};
 use crate::{
     parse::{Enum, Field, Struct},
     shared,
@@ -25,18 +27,44 @@ pub fn derive_ser_json_proxy(proxy_type: &str, type_: &str) -> TokenStream {
     .unwrap()
 }
 
-fn ser_proxy_guard(fieldname: &str, field: &Field) -> String {
-    if let Some(proxy) = crate::shared::attrs_proxy(&field.attributes) {
-        if field.ty.base() == "Option" {
-            format!(
-                "{{{fieldname}.as_ref().map(|f| {{let proxy: {proxy} = Into::into(f);proxy}})}}"
-            )
+/// Writes the named fields, `access` turns a field's name into the expression reading it.
+/// Expects `first_field_was_serialized` to be declared.
+fn ser_json_fields(fields: &[Field], access: impl Fn(&str) -> String) -> String {
+    let mut s = String::new();
+
+    for field in fields {
+        let struct_fieldname = field.field_name.clone().unwrap();
+        let json_fieldname =
+            shared::attrs_rename(&field.attributes).unwrap_or_else(|| struct_fieldname.clone());
+        let skip = shared::attrs_skip(&field.attributes);
+        if skip {
+            continue;
+        }
+        let proxied_field = ser_proxy_guard(&access(&struct_fieldname), field);
+
+        if shared::attrs_flatten(&field.attributes) {
+            l!(
+                s,
+                "s.flatten(d, &{}, &mut first_field_was_serialized);",
+                proxied_field
+            );
+        } else if field.ty.base() == "Option" {
+            l!(
+                s,
+                "if let Some(t) = &{} {{ if first_field_was_serialized {{ s.conl(); }};first_field_was_serialized = true;s.field(d+1, \"{}\");t.ser_json(d+1, s);}};",
+                proxied_field,
+                json_fieldname
+            );
         } else {
-            format!("{{let proxy: {proxy} = Into::into(&{fieldname});proxy}}",)
+            l!(
+                s,
+                "if first_field_was_serialized {{ s.conl(); }};first_field_was_serialized = true;s.field(d+1,\"{}\"); {}.ser_json(d+1, s);",
+                json_fieldname,
+                proxied_field
+            );
         }
-    } else {
-        format!("{fieldname}")
     }
+    s
 }
 
 pub fn derive_ser_json_struct(struct_: &Struct) -> TokenStream {
@@ -44,35 +72,9 @@ pub fn derive_ser_json_struct(struct_: &Struct) -> TokenStream {
     let (generic_w_bounds, generic_no_bounds) = struct_bounds_strings(struct_, "SerJson");
 
     l!(s, "let mut first_field_was_serialized = false;");
-
-    if struct_.fields.len() >= 1 {
-        for (_index, field) in struct_.fields.iter().enumerate() {
-            let struct_fieldname = field.field_name.clone().unwrap();
-            let json_fieldname =
-                shared::attrs_rename(&field.attributes).unwrap_or_else(|| struct_fieldname.clone());
-            let skip = shared::attrs_skip(&field.attributes);
-            if skip {
-                continue;
-            }
-            let proxied_field = ser_proxy_guard(&format!("self.{struct_fieldname}"), field);
-
-            if field.ty.base() == "Option" {
-                l!(
-                    s,
-                    "if let Some(t) = &{} {{ if first_field_was_serialized {{ s.conl(); }};first_field_was_serialized = true;s.field(d+1, \"{}\");t.ser_json(d+1, s);}};",
-                    proxied_field,
-                    json_fieldname
-                );
-            } else {
-                l!(
-                    s,
-                    "if first_field_was_serialized {{ s.conl(); }};first_field_was_serialized = true;s.field(d+1,\"{}\"); {}.ser_json(d+1, s);",
-                    json_fieldname,
-                    proxied_field
-                );
-            }
-        }
-    }
+    s.push_str(&ser_json_fields(&struct_.fields, |name| {
+        format!("self.{name}")
+    }));
 
     format!(
         "
@@ -96,52 +98,38 @@ pub fn derive_ser_json_struct(struct_: &Struct) -> TokenStream {
     .unwrap()
 }
 
-pub fn derive_de_json_named(name: &str, defaults: bool, fields: &[Field]) -> TokenStream {
+/// Parses the object into `name { .. }`.
+///
+/// Flattened fields take the fields of the object that didn't match any other field,
+/// or the ones in `flat_fields` when it's given, the `JsonValue` `de_json_flat` takes them from.
+/// Unknown fields are skipped unless `deny_unknown_fields` is set.
+pub fn derive_de_json_named(
+    name: &str,
+    defaults: bool,
+    deny_unknown_fields: bool,
+    fields: &[Field],
+    flat_fields: Option<&str>,
+) -> TokenStream {
     let mut local_vars = Vec::new();
+    let mut required = Vec::new();
     let mut struct_field_names = Vec::new();
     let mut json_field_names = Vec::new();
     let mut matches = Vec::new();
     let mut unwraps = Vec::new();
+    let mut flattened = Vec::new();
 
     let container_attr_default = defaults;
 
     for field in fields {
         let struct_fieldname = field.field_name.as_ref().unwrap().to_string();
         let localvar = format!("_{}", struct_fieldname);
-        let field_attr_default = shared::attrs_default(&field.attributes);
-        let field_attr_default_with = shared::attrs_default_with(&field.attributes);
-        let default_val = if let Some(v) = field_attr_default {
-            if let Some(mut val) = v {
-                if field.ty.base() == "String"
-                    || field.ty.wraps.as_ref().map_or(false, |wrapped| {
-                        wrapped.iter().any(|ty| ty.base() == "String")
-                    })
-                {
-                    val = format!("\"{}\".to_string()", val)
-                }
-                if field.ty.base() == "Option" {
-                    val = format!("Some({})", val);
-                }
-                Some(val)
-            } else {
-                if field.ty.base() != "Option" {
-                    Some(String::from("Default::default()"))
-                } else {
-                    Some(String::from("None"))
-                }
-            }
-        } else if let Some(mut v) = field_attr_default_with {
-            v.push_str("()");
-            Some(v)
-        } else {
-            None
-        };
+        let default_val = shared::attrs_default_value(field);
         let json_fieldname =
             shared::attrs_rename(&field.attributes).unwrap_or(struct_fieldname.clone());
         let proxy = crate::shared::attrs_proxy(&field.attributes);
         let skip = crate::shared::attrs_skip(&field.attributes);
 
-        let proxified_t = if let Some(proxy) = proxy {
+        let proxified_t = if let Some(proxy) = &proxy {
             if field.ty.base() == "Option" {
                 format!("Some(From::<&{proxy}>::from(&t))")
             } else {
@@ -151,8 +139,14 @@ pub fn derive_de_json_named(name: &str, defaults: bool, fields: &[Field]) -> Tok
             format!("t")
         };
 
-        if skip == false {
-            if field.ty.base() == "Option" {
+        if skip == false && shared::attrs_flatten(&field.attributes) {
+            unwraps.push(localvar.clone());
+            flattened.push((localvar, field));
+        } else if skip == false {
+            if field.ty.base() == "Option" && proxy.is_none() && default_val.is_none() {
+                // a missing key and an explicit null are both None
+                unwraps.push(format!("{}.flatten()", localvar));
+            } else if field.ty.base() == "Option" {
                 unwraps.push(format!(
                     "{{if let Some(t) = {} {{ {} }} else {{ {} }} }}",
                     localvar,
@@ -171,6 +165,7 @@ pub fn derive_de_json_named(name: &str, defaults: bool, fields: &[Field]) -> Tok
                     "{{if let Some(t) = {} {{ {} }} else {{return Err(s.err_nf(\"{}\"))}} }}",
                     localvar, proxified_t, struct_fieldname
                 ));
+                required.push((json_fieldname.clone(), localvar.clone()));
             }
             matches.push((json_fieldname.clone(), localvar.clone()));
             local_vars.push(localvar);
@@ -182,10 +177,21 @@ pub fn derive_de_json_named(name: &str, defaults: bool, fields: &[Field]) -> Tok
         json_field_names.push(json_fieldname);
     }
 
+    if deny_unknown_fields && !flattened.is_empty() {
+        panic!("#[nserde(deny_unknown_fields)] can't be used with #[nserde(flatten)]");
+    }
+
     let mut r = String::new();
+    l!(r, "let _errors = s.error_count();");
     for local_var in &local_vars {
         l!(r, "let mut {} = None;", local_var);
     }
+    if !flattened.is_empty() {
+        l!(
+            r,
+            "let mut _rest = nanoserde::JsonValue::Object(::core::default::Default::default());"
+        );
+    }
     l!(r, "s.curly_open(i) ?;");
     l!(r, "while let Some(_) = s.next_str() {");
 
@@ -194,9 +200,10 @@ pub fn derive_de_json_named(name: &str, defaults: bool, fields: &[Field]) -> Tok
         for (json_field_name, local_var) in matches.iter() {
             l!(
                 r,
-                "\"{}\" => {{s.next_colon(i) ?;{} = Some(DeJson::de_json(s, i) ?)}},",
+                "\"{}\" => {{s.next_colon(i) ?;{} = s.de_field(\"{}\", i) ?}},",
                 json_field_name,
-                local_var
+                local_var,
+                json_field_name
             );
         }
         // TODO: maybe introduce "exhaustive" attribute?
@@ -204,12 +211,44 @@ pub fn derive_de_json_named(name: &str, defaults: bool, fields: &[Field]) -> Tok
         //     r,
         //     "_ => return ::core::result::Result::Err(s.err_exp(&s.strbuf))"
         // );
-        l!(r, "_ => {s.next_colon(i)?; s.whole_field(i)?; }");
+        if deny_unknown_fields {
+            let known: Vec<String> = matches
+                .iter()
+                .map(|(json_field_name, _)| format!("\"{}\"", json_field_name))
+                .collect();
+            l!(
+                r,
+                "_ => {{let key = ::core::mem::take(&mut s.strbuf); let err = s.err_unknown(&key, &[{}]); s.report(&key, err)?; s.next_colon(i)?; s.whole_field(i)?; }}",
+                known.join(",")
+            );
+        } else if flattened.is_empty() {
+            l!(r, "_ => {s.next_colon(i)?; s.whole_field(i)?; }");
+        } else {
+            l!(r, "_ => {let key = ::core::mem::take(&mut s.strbuf); s.next_colon(i)?; let value: nanoserde::JsonValue = DeJson::de_json(s, i)?; if let Some(rest) = _rest.as_object_mut() { rest.insert(key, value); } }");
+        }
         l!(r, "}");
     }
     l!(r, "s.eat_comma_curly(i) ?");
     l!(r, "}");
     l!(r, "s.curly_close(i) ?;");
+    let required: Vec<String> = required
+        .iter()
+        .map(|(json_field_name, local_var)| {
+            format!("(\"{}\", {}.is_some())", json_field_name, local_var)
+        })
+        .collect();
+    l!(r, "s.check_fields(_errors, &[{}])?;", required.join(","));
+    // maps take everything left, so the structs go first
+    flattened.sort_by_key(|(_, field)| shared::is_map(&field.ty));
+    for (local_var, field) in &flattened {
+        l!(
+            r,
+            "let {} = <{} as DeJson<'de>>::de_json_flat(s, {})?;",
+            local_var,
+            field.ty.full(),
+            flat_fields.unwrap_or("&mut _rest")
+        );
+    }
     l!(r, "{} {{", name);
     for (field_name, unwrap) in struct_field_names.iter().zip(unwraps.iter()) {
         l!(r, "{}: {},", field_name, unwrap);
@@ -219,12 +258,30 @@ pub fn derive_de_json_named(name: &str, defaults: bool, fields: &[Field]) -> Tok
     r.parse().unwrap()
 }
 
+/// The generics of a `DeJson<'de>` impl, `'de` outliving the type's own lifetimes so its
+/// `&'a str` fields can borrow from the input.
+fn de_json_generics(generics: &[Generic], generic_w_bounds: &str) -> String {
+    let lifetimes = generics
+        .iter()
+        .filter(|generic| matches!(generic, Generic::Lifetime { .. }))
+        .map(Generic::ident_only)
+        .collect::<Vec<_>>();
+    let de = match lifetimes.is_empty() {
+        true => "'de".to_string(),
+        false => format!("'de: {}", lifetimes.join(" + ")),
+    };
+    match generic_w_bounds.strip_prefix('<') {
+        Some(generics) => format!("<{}, {}", de, generics),
+        None => format!("<{}>", de),
+    }
+}
+
 pub fn derive_de_json_proxy(proxy_type: &str, type_: &str) -> TokenStream {
     format!(
-        "impl DeJson for {} {{
+        "impl<'de> DeJson<'de> for {} {{
             #[allow(clippy::ignored_unit_patterns)]
-            fn de_json(_s: &mut nanoserde::DeJsonState, i: &mut core::str::Chars) -> ::core::result::Result<Self, nanoserde::DeJsonErr> {{
-                let proxy: {} = DeJson::deserialize_json(i)?;
+            fn de_json(s: &mut nanoserde::DeJsonState, i: &mut nanoserde::JsonInput<'de>) -> ::core::result::Result<Self, nanoserde::DeJsonErr> {{
+                let proxy: {} = DeJson::de_json(s, i)?;
                 ::core::result::Result::Ok(Into::into(&proxy))
             }}
         }}",
@@ -235,29 +292,66 @@ pub fn derive_de_json_proxy(proxy_type: &str, type_: &str) -> TokenStream {
 }
 
 pub fn derive_de_json_struct(struct_: &Struct) -> TokenStream {
+    let name = struct_
+        .name
+        .as_ref()
+        .expect("Cannot implement for anonymous struct");
+    let defaults = shared::attrs_default(&struct_.attributes).is_some()
+        || shared::attrs_default_with(&struct_.attributes).is_some();
+    let deny_unknown_fields = shared::attrs_deny_unknown_fields(&struct_.attributes);
     let body = derive_de_json_named(
-        &struct_
-            .name
-            .as_ref()
-            .expect("Cannot implement for anonymous struct"),
-        shared::attrs_default(&struct_.attributes).is_some()
-            || shared::attrs_default_with(&struct_.attributes).is_some(),
+        name,
+        defaults,
+        deny_unknown_fields,
+        &struct_.fields[..],
+        None,
+    );
+    let flat_body = derive_de_json_named(
+        name,
+        defaults,
+        deny_unknown_fields,
         &struct_.fields[..],
+        Some("fields"),
     );
-    let (generic_w_bounds, generic_no_bounds) = struct_bounds_strings(struct_, "DeJson");
+    let (generic_w_bounds, generic_no_bounds) = struct_bounds_strings(struct_, "DeJson<'de>");
+    let generic_w_bounds = de_json_generics(&struct_.generics, &generic_w_bounds);
+
+    // the fields a flattened struct takes out of its parent
+    let mut own_fields = String::new();
+    for field in &struct_.fields {
+        if !shared::attrs_skip(&field.attributes) && !shared::attrs_flatten(&field.attributes) {
+            let name = field.field_name.clone().unwrap();
+            l!(
+                own_fields,
+                "\"{}\",",
+                shared::attrs_rename(&field.attributes).unwrap_or(name)
+            );
+        }
+    }
 
     format!(
-        "impl{} DeJson for {}{} {{
+        "impl{} DeJson<'de> for {}{} {{
             #[allow(clippy::ignored_unit_patterns)]
-            fn de_json(s: &mut nanoserde::DeJsonState, i: &mut core::str::Chars) -> ::core::result::Result<Self,
+            fn de_json(s: &mut nanoserde::DeJsonState, i: &mut nanoserde::JsonInput<'de>) -> ::core::result::Result<Self,
             nanoserde::DeJsonErr> {{
                 ::core::result::Result::Ok({{ {} }})
             }}
-        }}", generic_w_bounds, struct_.name.as_ref().expect("Cannot implement for anonymous struct"), generic_no_bounds, body)
+
+            #[allow(clippy::ignored_unit_patterns)]
+            fn de_json_flat(s: &nanoserde::DeJsonState, fields: &mut nanoserde::JsonValue) -> ::core::result::Result<Self,
+            nanoserde::DeJsonErr> {{
+                let own = fields.take_fields(&[{}]);
+                s.de_buffered(&nanoserde::SerJson::serialize_json(&own), |s, i| ::core::result::Result::Ok({{ {} }}))
+            }}
+        }}", generic_w_bounds, name, generic_no_bounds, body, own_fields, flat_body)
         .parse().unwrap()
 }
 
 pub fn derive_ser_json_enum(enum_: &Enum) -> TokenStream {
+    let tag = shared::attrs_enum_tag(&enum_.attributes);
+    if !matches!(tag, EnumTag::External) {
+        return derive_ser_json_enum_tagged(enum_, &tag);
+    }
     let mut r = String::new();
 
     for variant in enum_.variants.iter() {
@@ -400,9 +494,14 @@ pub fn derive_ser_json_enum(enum_: &Enum) -> TokenStream {
 }
 
 pub fn derive_de_json_enum(enum_: &Enum) -> TokenStream {
+    let tag = shared::attrs_enum_tag(&enum_.attributes);
+    if !matches!(tag, EnumTag::External) {
+        return derive_de_json_enum_tagged(enum_, &tag);
+    }
     let mut r_units = String::new();
     let mut r_rest = String::new();
-    let (generic_w_bounds, generic_no_bounds) = enum_bounds_strings(enum_, "DeJson");
+    let (generic_w_bounds, generic_no_bounds) = enum_bounds_strings(enum_, "DeJson<'de>");
+    let generic_w_bounds = de_json_generics(&enum_.generics, &generic_w_bounds);
 
     for variant in &enum_.variants {
         let field_name = variant.field_name.clone().unwrap();
@@ -430,7 +529,9 @@ pub fn derive_de_json_enum(enum_: &Enum) -> TokenStream {
                 let body = derive_de_json_named(
                     &format!("Self::{}", &field_name),
                     false,
+                    shared::attrs_deny_unknown_fields(&enum_.attributes),
                     &contents.fields,
+                    None,
                 );
                 l!(r_rest, "\"{}\" => {{ {} }}, ", json_variant_name, body);
             }
@@ -460,11 +561,11 @@ pub fn derive_de_json_enum(enum_: &Enum) -> TokenStream {
     }
 
     let mut r = format!(
-        "impl{} DeJson for {}{} {{
+        "impl{} DeJson<'de> for {}{} {{
             #[allow(clippy::ignored_unit_patterns)]
-            fn de_json(s: &mut nanoserde::DeJsonState, i: &mut core::str::Chars) -> ::core::result::Result<Self, nanoserde::DeJsonErr> {{
+            fn de_json(s: &mut nanoserde::DeJsonState, i: &mut nanoserde::JsonInput<'de>) -> ::core::result::Result<Self, nanoserde::DeJsonErr> {{
                 match s.tok {{",
-        generic_w_bounds, generic_no_bounds, enum_.name,
+        generic_w_bounds, enum_.name, generic_no_bounds,
     );
 
     if !r_rest.is_empty() {
@@ -511,6 +612,280 @@ pub fn derive_de_json_enum(enum_: &Enum) -> TokenStream {
     r.parse().unwrap()
 }
 
+fn derive_ser_json_enum_tagged(enum_: &Enum, tag: &EnumTag) -> TokenStream {
+    let mut r = String::new();
+    let (generic_w_bounds, generic_no_bounds) = enum_bounds_strings(enum_, "SerJson");
+
+    for variant in &enum_.variants {
+        let field_name = variant.field_name.clone().unwrap();
+        let json_variant_name =
+            shared::attrs_rename(&variant.attributes).unwrap_or(field_name.clone());
+        let kind = variant_kind(&variant.ty);
+
+        let (pattern, fields) = match kind {
+            VariantKind::Unit => (format!("Self::{}", field_name), String::new()),
+            VariantKind::Struct(fields) => {
+                let names: Vec<String> = fields
+                    .iter()
+                    .map(|field| field.field_name.clone().unwrap())
+                    .collect();
+                (
+                    format!("Self::{} {{ {} }}", field_name, names.join(",")),
+                    ser_json_fields(fields, |name| format!("(*{name})")),
+                )
+            }
+            VariantKind::Tuple(len) => {
+                let names: Vec<String> = (0..len).map(|index| format!("f{}", index)).collect();
+                (
+                    format!("Self::{}({})", field_name, names.join(",")),
+                    String::new(),
+                )
+            }
+        };
+
+        let mut body = String::new();
+        match tag {
+            EnumTag::Internal(tag) => {
+                l!(
+                    body,
+                    "s.st_pre(); s.field(d+1, \"{}\"); s.label(\"{}\");",
+                    tag,
+                    json_variant_name
+                );
+                match kind {
+                    VariantKind::Unit => {}
+                    VariantKind::Struct(_) => {
+                        l!(body, "let mut first_field_was_serialized = true;");
+                        body.push_str(&fields);
+                    }
+                    VariantKind::Tuple(1) => {
+                        l!(body, "let mut first_field_was_serialized = true;");
+                        l!(body, "s.flatten(d, f0, &mut first_field_was_serialized);");
+                    }
+                    VariantKind::Tuple(_) => panic!(
+                        "Internally tagged enums only support unit, struct and newtype variants, {}::{} is a tuple",
+                        enum_.name, field_name
+                    ),
+                }
+                l!(body, "s.st_post(d);");
+            }
+            EnumTag::Adjacent(tag, content) => {
+                l!(
+                    body,
+                    "s.st_pre(); s.field(d+1, \"{}\"); s.label(\"{}\");",
+                    tag,
+                    json_variant_name
+                );
+                if !matches!(kind, VariantKind::Unit) {
+                    l!(body, "s.conl(); s.field(d+1, \"{}\");", content);
+                }
+                match kind {
+                    VariantKind::Unit => {}
+                    VariantKind::Struct(_) => {
+                        l!(
+                            body,
+                            "s.st_pre(); let mut first_field_was_serialized = false;"
+                        );
+                        body.push_str(&fields);
+                        l!(body, "s.st_post(d+1);");
+                    }
+                    VariantKind::Tuple(1) => l!(body, "f0.ser_json(d+1, s);"),
+                    VariantKind::Tuple(len) => {
+                        l!(body, "s.out.push('[');");
+                        for index in 0..len {
+                            if index != 0 {
+                                l!(body, "s.out.push(',');");
+                            }
+                            l!(body, "f{}.ser_json(d+1, s);", index);
+                        }
+                        l!(body, "s.out.push(']');");
+                    }
+                }
+                l!(body, "s.st_post(d);");
+            }
+            EnumTag::Untagged => match kind {
+                VariantKind::Unit => l!(body, "s.out.push_str(\"null\");"),
+                VariantKind::Struct(_) => {
+                    l!(
+                        body,
+                        "s.st_pre(); let mut first_field_was_serialized = false;"
+                    );
+                    body.push_str(&fields);
+                    l!(body, "s.st_post(d);");
+                }
+                VariantKind::Tuple(1) => l!(body, "f0.ser_json(d, s);"),
+                VariantKind::Tuple(len) => {
+                    l!(body, "s.out.push('[');");
+                    for index in 0..len {
+                        if index != 0 {
+                            l!(body, "s.out.push(',');");
+                        }
+                        l!(body, "f{}.ser_json(d, s);", index);
+                    }
+                    l!(body, "s.out.push(']');");
+                }
+            },
+            EnumTag::External => unreachable!(),
+        }
+
+        l!(r, "{} => {{ {} }}", pattern, body);
+    }
+
+    format!(
+        "
+        impl{} SerJson for {}{} {{
+            fn ser_json(&self, d: usize, s: &mut nanoserde::SerJsonState) {{
+                match self {{
+                    {}
+                }}
+            }}
+        }}",
+        generic_w_bounds, enum_.name, generic_no_bounds, r
+    )
+    .parse()
+    .unwrap()
+}
+
+fn derive_de_json_enum_tagged(enum_: &Enum, tag: &EnumTag) -> TokenStream {
+    let mut r = String::new();
+    let (generic_w_bounds, generic_no_bounds) = enum_bounds_strings(enum_, "DeJson<'de>");
+    let generic_w_bounds = de_json_generics(&enum_.generics, &generic_w_bounds);
+
+    match tag {
+        EnumTag::Internal(tag) | EnumTag::Adjacent(tag, _) => {
+            l!(
+                r,
+                "let mut _fields: nanoserde::JsonValue = DeJson::de_json(s, i)?;"
+            );
+            l!(
+                r,
+                "let _tag = match _fields.as_object_mut().and_then(|fields| fields.remove(\"{}\")) {{
+                    Some(nanoserde::JsonValue::String(tag)) => tag,
+                    _ => return ::core::result::Result::Err(s.err_nf(\"{}\")),
+                }};",
+                tag,
+                tag
+            );
+        }
+        EnumTag::Untagged => {
+            l!(
+                r,
+                "let _fields: nanoserde::JsonValue = DeJson::de_json(s, i)?;"
+            );
+        }
+        EnumTag::External => unreachable!(),
+    }
+    match tag {
+        EnumTag::Adjacent(_, content) => l!(
+            r,
+            "let _text = nanoserde::SerJson::serialize_json(&_fields.as_object_mut().and_then(|fields| fields.remove(\"{}\")).unwrap_or_default());",
+            content
+        ),
+        _ => l!(r, "let _text = nanoserde::SerJson::serialize_json(&_fields);"),
+    }
+
+    let mut r_variants = String::new();
+    for variant in &enum_.variants {
+        let field_name = variant.field_name.clone().unwrap();
+        let json_variant_name =
+            shared::attrs_rename(&variant.attributes).unwrap_or(field_name.clone());
+        let kind = variant_kind(&variant.ty);
+
+        // reads the variant's content out of `_text`
+        let parse = match kind {
+            VariantKind::Unit => String::new(),
+            VariantKind::Struct(fields) => format!(
+                "::core::result::Result::Ok({{ {} }})",
+                derive_de_json_named(
+                    &format!("Self::{}", field_name),
+                    false,
+                    shared::attrs_deny_unknown_fields(&enum_.attributes),
+                    fields,
+                    None
+                )
+            ),
+            VariantKind::Tuple(1) => format!(
+                "::core::result::Result::Ok(Self::{}(DeJson::de_json(s, i)?))",
+                field_name
+            ),
+            VariantKind::Tuple(len) => {
+                if matches!(tag, EnumTag::Internal(_)) {
+                    panic!(
+                        "Internally tagged enums only support unit, struct and newtype variants, {}::{} is a tuple",
+                        enum_.name, field_name
+                    );
+                }
+                let mut items = String::new();
+                for _ in 0..len {
+                    l!(
+                        items,
+                        "{let r = DeJson::de_json(s,i)?;s.eat_comma_block(i)?;r},"
+                    );
+                }
+                format!(
+                    "{{s.block_open(i)?;let r = Self::{}({}); s.block_close(i)?;::core::result::Result::Ok(r)}}",
+                    field_name, items
+                )
+            }
+        };
+
+        match (tag, kind) {
+            (EnumTag::Untagged, VariantKind::Unit) => l!(
+                r_variants,
+                "if _fields.is_null() {{ return ::core::result::Result::Ok(Self::{}); }}",
+                field_name
+            ),
+            (EnumTag::Untagged, _) => l!(
+                r_variants,
+                "if let ::core::result::Result::Ok(r) = s.de_buffered(&_text, |s, i| {}) {{ return ::core::result::Result::Ok(r); }}",
+                parse
+            ),
+            (_, VariantKind::Unit) => l!(
+                r_variants,
+                "\"{}\" => Self::{},",
+                json_variant_name,
+                field_name
+            ),
+            _ => l!(
+                r_variants,
+                "\"{}\" => s.de_buffered(&_text, |s, i| {})?,",
+                json_variant_name,
+                parse
+            ),
+        }
+    }
+
+    if matches!(tag, EnumTag::Untagged) {
+        l!(r, "{}", r_variants);
+        l!(
+            r,
+            "::core::result::Result::Err(s.err_parse(\"untagged enum {}\"))",
+            enum_.name
+        );
+    } else {
+        l!(
+            r,
+            "::core::result::Result::Ok(match _tag.as_str() {{
+                {}
+                _ => return ::core::result::Result::Err(s.err_enum(&_tag)),
+            }})",
+            r_variants
+        );
+    }
+
+    format!(
+        "impl{} DeJson<'de> for {}{} {{
+            #[allow(clippy::ignored_unit_patterns)]
+            fn de_json(s: &mut nanoserde::DeJsonState, i: &mut nanoserde::JsonInput<'de>) -> ::core::result::Result<Self, nanoserde::DeJsonErr> {{
+                {}
+            }}
+        }}",
+        generic_w_bounds, enum_.name, generic_no_bounds, r
+    )
+    .parse()
+    .unwrap()
+}
+
 pub fn derive_ser_json_struct_unnamed(struct_: &Struct) -> TokenStream {
     let mut body = String::new();
     let (generic_w_bounds, generic_no_bounds) = struct_bounds_strings(struct_, "SerJson");
@@ -561,7 +936,8 @@ pub fn derive_ser_json_struct_unnamed(struct_: &Struct) -> TokenStream {
 
 pub fn derive_de_json_struct_unnamed(struct_: &Struct) -> TokenStream {
     let mut body = String::new();
-    let (generic_w_bounds, generic_no_bounds) = struct_bounds_strings(struct_, "DeJson");
+    let (generic_w_bounds, generic_no_bounds) = struct_bounds_strings(struct_, "DeJson<'de>");
+    let generic_w_bounds = de_json_generics(&struct_.generics, &generic_w_bounds);
 
     let transparent = shared::attrs_transparent(&struct_.attributes);
 
@@ -594,9 +970,9 @@ pub fn derive_de_json_struct_unnamed(struct_: &Struct) -> TokenStream {
     };
 
     format! ("
-        impl{} DeJson for {}{} {{
+        impl{} DeJson<'de> for {}{} {{
             #[allow(clippy::ignored_unit_patterns)]
-            fn de_json(s: &mut nanoserde::DeJsonState, i: &mut core::str::Chars) -> ::core::result::Result<Self,nanoserde::DeJsonErr> {{
+            fn de_json(s: &mut nanoserde::DeJsonState, i: &mut nanoserde::JsonInput<'de>) -> ::core::result::Result<Self,nanoserde::DeJsonErr> {{
                 {}
                 ::core::result::Result::Ok(r)
             }}
@@ -10,7 +10,7 @@
 //! The main difference with "serde" and the reason why "nanoserde" is possible: there is no intermediate data model
 //! For each serialisation datatype there is a special macro.
 //!
-//! Derive macros available: `DeJson`, `SerJson`, `DeBin`, `SerBin`, `DeRon`, `SerRon`
+//! Derive macros available: `DeJson`, `SerJson`, `DeBin`, `SerBin`, `DeRon`, `SerRon`, `DeToml`, `SerToml`
 //!
 //! `nanoserde` supports some serialization customisation with `#[nserde()]` attributes.
 //! For `#[nserde(..)]` supported attributes for each format check [Features support matrix](https://github.com/not-fl3/nanoserde#features-support-matrix)
@@ -20,7 +20,12 @@
 
 extern crate alloc;
 
-#[cfg(any(feature = "binary", feature = "json", feature = "ron"))]
+#[cfg(any(
+    feature = "binary",
+    feature = "json",
+    feature = "ron",
+    feature = "toml"
+))]
 pub use nanoserde_derive::*;
 
 #[cfg(feature = "binary")]
@@ -42,3 +47,6 @@ pub use crate::serde_json::*;
 mod toml;
 #[cfg(feature = "toml")]
 pub use crate::toml::*;
+
+#[cfg(any(feature = "json", feature = "toml"))]
+mod suggest;
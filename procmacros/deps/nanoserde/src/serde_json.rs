@@ -1,11 +1,10 @@
-use core::str::Chars;
-
 // remove this after 1.81 is live
 #[cfg(not(feature = "std"))]
 use core::error::Error;
 #[cfg(feature = "std")]
 use std::error::Error;
 
+use alloc::borrow::{Cow, ToOwned};
 use alloc::boxed::Box;
 use alloc::collections::{BTreeMap, BTreeSet, LinkedList};
 use alloc::format;
@@ -50,6 +49,32 @@ impl SerJsonState {
         self.indent(d);
         self.out.push('}');
     }
+
+    /// Write the fields of `value`'s object into the object being written, for `#[nserde(flatten)]`.
+    /// Values that aren't objects write nothing.
+    pub fn flatten<T: SerJson + ?Sized>(
+        &mut self,
+        d: usize,
+        value: &T,
+        first_field_was_serialized: &mut bool,
+    ) {
+        let mut state = SerJsonState { out: String::new() };
+        value.ser_json(d, &mut state);
+        let fields = state
+            .out
+            .trim()
+            .strip_prefix('{')
+            .and_then(|out| out.strip_suffix('}'))
+            .unwrap_or("")
+            .trim();
+        if !fields.is_empty() {
+            if *first_field_was_serialized {
+                self.conl();
+            }
+            *first_field_was_serialized = true;
+            self.out.push_str(fields);
+        }
+    }
 }
 
 /// A trait for objects that can be serialized to JSON.
@@ -75,34 +100,336 @@ pub trait SerJson {
 }
 
 /// A trait for objects that can be deserialized from JSON.
-pub trait DeJson: Sized {
+///
+/// `'de` is the lifetime of the input, `&'de str` and `Cow<'de, str>` values borrow their text
+/// out of it instead of allocating. Use `DeJsonOwned` for the types that don't borrow.
+pub trait DeJson<'de>: Sized {
     /// Parse Self from the input string.
     ///
     /// This is a convenient wrapper around `de_json`.
-    fn deserialize_json(input: &str) -> Result<Self, DeJsonErr> {
-        let mut state = DeJsonState::default();
-        let mut chars = input.chars();
-        state.next(&mut chars);
-        state.next_tok(&mut chars)?;
-        DeJson::de_json(&mut state, &mut chars)
+    fn deserialize_json(input: &'de str) -> Result<Self, DeJsonErr> {
+        de_json_input(&mut JsonInput::from(input))
     }
 
-    /// Parse Self from the input string.
+    /// Parse Self from the bytes of a UTF-8 JSON text, like a file loaded in memory.
+    ///
+    /// ```rust
+    /// # use nanoserde::*;
+    /// let name: &str = DeJson::deserialize_json_bytes(br#""DamagedHelmet""#).unwrap();
+    /// assert_eq!(name, "DamagedHelmet");
+    /// ```
+    fn deserialize_json_bytes(input: &'de [u8]) -> Result<Self, DeJsonErr> {
+        match core::str::from_utf8(input) {
+            Ok(input) => Self::deserialize_json(input),
+            Err(err) => Err(DeJsonErr {
+                msg: format!("Invalid UTF-8 at byte {}", err.valid_up_to()),
+                line: 0,
+                col: 0,
+            }),
+        }
+    }
+
+    /// Parse Self from a reader, reading the input in chunks as it's parsed instead of
+    /// loading all of it first.
+    ///
+    /// Strings can't be borrowed out of a reader, `&str` values fail to parse, `Cow<str>`
+    /// ones are owned.
+    ///
+    /// ```rust
+    /// # use nanoserde::*;
+    /// let reader = std::io::Cursor::new(b"[1, 2, 3]");
+    /// let out: Vec<u32> = DeJson::deserialize_json_reader(reader).unwrap();
+    /// assert_eq!(out, [1, 2, 3]);
+    /// ```
+    #[cfg(feature = "std")]
+    fn deserialize_json_reader(reader: impl std::io::Read + 'de) -> Result<Self, DeJsonErr> {
+        let mut input = JsonInput::from_reader(reader);
+        let result = de_json_input(&mut input);
+        // the parser only saw the input end early
+        match input.read_error() {
+            Some(msg) => Err(DeJsonErr {
+                msg: msg.to_string(),
+                line: result.as_ref().err().map_or(0, |err| err.line),
+                col: result.as_ref().err().map_or(0, |err| err.col),
+            }),
+            None => result,
+        }
+    }
+
+    /// Parse Self from the input string, carrying on past the values that don't parse
+    /// to report every problem with the path of its value.
+    ///
+    /// Slower than `deserialize_json`, the input is checked to be valid JSON first.
+    /// Invalid JSON is still reported on its own.
+    ///
+    /// ```rust
+    /// # use nanoserde::*;
+    /// let errors = <Vec<u32>>::deserialize_json_all("[1, true, 3, -4]").unwrap_err();
+    /// assert_eq!(errors.len(), 2);
+    /// assert_eq!(errors[0].path, "[1]");
+    /// assert_eq!(errors[1].path, "[3]");
+    /// ```
+    fn deserialize_json_all(input: &'de str) -> Result<Self, Vec<DeJsonPathErr>> {
+        // nothing after a syntax error can be trusted
+        if let Err(err) = JsonValue::deserialize_json(input) {
+            return Err(alloc::vec![DeJsonPathErr {
+                path: String::new(),
+                err,
+            }]);
+        }
+
+        let mut state = DeJsonState {
+            errors: Some(Vec::new()),
+            ..Default::default()
+        };
+        let mut input = JsonInput::from(input);
+        state.next(&mut input);
+        let result = state
+            .next_tok(&mut input)
+            .and_then(|_| DeJson::de_json(&mut state, &mut input));
+        let errors = state.errors.take().unwrap_or_default();
+        match result {
+            Ok(value) if errors.is_empty() => Ok(value),
+            Err(err) if errors.is_empty() => Err(alloc::vec![DeJsonPathErr {
+                path: String::new(),
+                err,
+            }]),
+            _ => Err(errors),
+        }
+    }
+
+    /// Parse Self from the input.
     ///
     /// ```rust
     /// # use nanoserde::*;
     /// let mut state = DeJsonState::default();
-    /// let mut chars = "42".chars();
-    /// state.next(&mut chars);
-    /// state.next_tok(&mut chars).unwrap();
-    /// let out = u32::de_json(&mut state, &mut chars).unwrap();
+    /// let mut input = JsonInput::from("42");
+    /// state.next(&mut input);
+    /// state.next_tok(&mut input).unwrap();
+    /// let out = u32::de_json(&mut state, &mut input).unwrap();
     /// assert_eq!(out, 42);
     /// ```
-    fn de_json(state: &mut DeJsonState, input: &mut Chars) -> Result<Self, DeJsonErr>;
+    fn de_json(state: &mut DeJsonState, input: &mut JsonInput<'de>) -> Result<Self, DeJsonErr>;
+
+    /// Parse Self out of the fields of the object it's flattened into with `#[nserde(flatten)]`,
+    /// taking the fields it uses out of `fields`, a `JsonValue::Object`.
+    ///
+    /// Takes all the fields by default, derived structs only take their own.
+    fn de_json_flat(state: &DeJsonState, fields: &mut JsonValue) -> Result<Self, DeJsonErr> {
+        let object = core::mem::replace(fields, JsonValue::Object(BTreeMap::new()));
+        state.de_buffered(&object.serialize_json(), |s, i| DeJson::de_json(s, i))
+    }
+}
+
+/// A type that can be deserialized from JSON without borrowing from the input, to be used as
+/// the bound of generic code that parses its own strings.
+///
+/// ```rust
+/// # use nanoserde::*;
+/// fn load<T: DeJsonOwned>(json: String) -> T {
+///     T::deserialize_json(&json).unwrap()
+/// }
+/// assert_eq!(load::<Vec<String>>(r#"["a"]"#.to_string()), ["a"]);
+/// ```
+pub trait DeJsonOwned: for<'de> DeJson<'de> {}
+
+impl<T> DeJsonOwned for T where T: for<'de> DeJson<'de> {}
+
+fn de_json_input<'de, T: DeJson<'de>>(input: &mut JsonInput<'de>) -> Result<T, DeJsonErr> {
+    let mut state = DeJsonState::default();
+    state.next(input);
+    state.next_tok(input)?;
+    DeJson::de_json(&mut state, input)
+}
+
+/// The size of the chunks `JsonInput::from_reader` reads.
+#[cfg(feature = "std")]
+const JSON_READ_CHUNK: usize = 64 * 1024;
+
+/// The text a JSON deserialization reads: a string, borrowed or owned, or a reader it's
+/// read from in chunks.
+///
+/// ```rust
+/// # use nanoserde::*;
+/// let mut input = JsonInput::from("[1, 2]");
+/// assert_eq!(input.next(), Some('['));
+/// ```
+pub struct JsonInput<'de> {
+    buf: Cow<'de, str>,
+    pos: usize,
+    /// How many times `buf` was refilled from the reader, positions are only kept within a chunk.
+    chunk: usize,
+    /// Where the last string token's content is in `buf`, when it had no escapes to unescape.
+    borrowable: Option<(usize, usize)>,
+    #[cfg(feature = "std")]
+    reader: Option<JsonReader<'de>>,
+}
+
+#[cfg(feature = "std")]
+struct JsonReader<'de> {
+    read: Box<dyn std::io::Read + 'de>,
+    /// The start of a character split by the end of the last chunk.
+    partial: Vec<u8>,
+    error: Option<String>,
+}
+
+impl<'de> From<&'de str> for JsonInput<'de> {
+    fn from(input: &'de str) -> Self {
+        Self::new(Cow::Borrowed(input))
+    }
+}
+
+impl<'de> From<String> for JsonInput<'de> {
+    fn from(input: String) -> Self {
+        Self::new(Cow::Owned(input))
+    }
+}
+
+impl<'de> JsonInput<'de> {
+    fn new(buf: Cow<'de, str>) -> Self {
+        Self {
+            buf,
+            pos: 0,
+            chunk: 0,
+            borrowable: None,
+            #[cfg(feature = "std")]
+            reader: None,
+        }
+    }
+
+    /// Read the input out of `read` as it's needed.
+    #[cfg(feature = "std")]
+    pub fn from_reader(read: impl std::io::Read + 'de) -> Self {
+        Self {
+            reader: Some(JsonReader {
+                read: Box::new(read),
+                partial: Vec::new(),
+                error: None,
+            }),
+            ..Self::new(Cow::Owned(String::new()))
+        }
+    }
+
+    /// Why reading the input stopped before its end, when it wasn't valid UTF-8 or the reader failed.
+    #[cfg(feature = "std")]
+    pub fn read_error(&self) -> Option<&str> {
+        self.reader.as_ref()?.error.as_deref()
+    }
+
+    /// The rest of the current chunk.
+    fn rest(&self) -> &str {
+        &self.buf[self.pos..]
+    }
+
+    /// The content of the last string token, when it can be borrowed out of the input.
+    fn borrowed_str(&self) -> Option<&'de str> {
+        match (&self.buf, self.borrowable) {
+            (Cow::Borrowed(buf), Some((start, end))) => Some(&buf[start..end]),
+            _ => None,
+        }
+    }
+
+    /// Replace the chunk that was read with the next one, `false` at the end of the input.
+    #[cfg(feature = "std")]
+    fn refill(&mut self) -> bool {
+        let Some(reader) = &mut self.reader else {
+            return false;
+        };
+        if reader.error.is_some() {
+            return false;
+        }
+        // reusing the allocation of the last chunk
+        let mut bytes = match core::mem::take(&mut self.buf) {
+            Cow::Owned(buf) => buf.into_bytes(),
+            Cow::Borrowed(_) => Vec::new(),
+        };
+        self.pos = 0;
+        self.chunk += 1;
+        bytes.clear();
+        bytes.append(&mut reader.partial);
+        loop {
+            let len = bytes.len();
+            bytes.resize(len + JSON_READ_CHUNK, 0);
+            let read = reader.read.read(&mut bytes[len..]);
+            bytes.truncate(len + *read.as_ref().unwrap_or(&0));
+            match read {
+                Ok(0) if bytes.is_empty() => return false,
+                Ok(0) => {
+                    reader.error = Some("Invalid UTF-8 at the end of the input".to_string());
+                    return false;
+                }
+                Ok(_) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    reader.error = Some(format!("Cannot read the input: {}", err));
+                    return false;
+                }
+            }
+            let split = incomplete_char_len(&bytes);
+            if split < bytes.len() {
+                reader
+                    .partial
+                    .extend_from_slice(&bytes[bytes.len() - split..]);
+                bytes.truncate(bytes.len() - split);
+                break;
+            }
+        }
+        match String::from_utf8(bytes) {
+            Ok(buf) => {
+                self.buf = Cow::Owned(buf);
+                true
+            }
+            Err(_) => {
+                reader.error = Some("Invalid UTF-8 in the input".to_string());
+                false
+            }
+        }
+    }
+}
+
+/// The length of the character cut by the end of `bytes`, 0 when it ends with a whole one.
+#[cfg(feature = "std")]
+fn incomplete_char_len(bytes: &[u8]) -> usize {
+    for back in 1..=bytes.len().min(4) {
+        let byte = bytes[bytes.len() - back];
+        // continuation bytes are 10xxxxxx
+        if byte & 0xc0 != 0x80 {
+            let width = match byte {
+                0xf0.. => 4,
+                0xe0.. => 3,
+                0xc0.. => 2,
+                _ => 1,
+            };
+            return if width > back { back } else { 0 };
+        }
+    }
+    0
+}
+
+impl<'de> Iterator for JsonInput<'de> {
+    type Item = char;
+
+    #[inline]
+    fn next(&mut self) -> Option<char> {
+        if let Some(&byte) = self.buf.as_bytes().get(self.pos) {
+            if byte.is_ascii() {
+                self.pos += 1;
+                return Some(byte as char);
+            }
+            let c = self.rest().chars().next()?;
+            self.pos += c.len_utf8();
+            return Some(c);
+        }
+        #[cfg(feature = "std")]
+        if self.refill() {
+            return self.next();
+        }
+        None
+    }
 }
 
 /// A JSON parsed token.
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum DeJsonTok {
     Str,
     Char(char),
@@ -138,6 +465,10 @@ pub struct DeJsonState {
     pub identbuf: String,
     pub line: usize,
     pub col: usize,
+    /// The path of the value being parsed, kept while collecting errors.
+    path: String,
+    /// The errors collected by `deserialize_json_all`, `None` when not collecting.
+    errors: Option<Vec<DeJsonPathErr>>,
 }
 
 /// The error message when failing to deserialize a JSON string.
@@ -148,6 +479,26 @@ pub struct DeJsonErr {
     pub col: usize,
 }
 
+/// An error found by `DeJson::deserialize_json_all`, with the path of the value it's about,
+/// like `materials[3].pbrMetallicRoughness.baseColorFactor`.
+#[derive(Clone, Debug)]
+pub struct DeJsonPathErr {
+    pub path: String,
+    pub err: DeJsonErr,
+}
+
+impl core::fmt::Display for DeJsonPathErr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.path.is_empty() {
+            core::fmt::Display::fmt(&self.err, f)
+        } else {
+            write!(f, "{}: {}", self.path, self.err)
+        }
+    }
+}
+
+impl Error for DeJsonPathErr {}
+
 impl core::fmt::Debug for DeJsonErr {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(
@@ -169,7 +520,7 @@ impl core::fmt::Display for DeJsonErr {
 impl Error for DeJsonErr {}
 
 impl DeJsonState {
-    pub fn next(&mut self, i: &mut Chars) {
+    pub fn next(&mut self, i: &mut JsonInput) {
         if let Some(c) = i.next() {
             self.cur = c;
             if self.cur == '\n' {
@@ -191,6 +542,19 @@ impl DeJsonState {
         }
     }
 
+    /// An unknown key, suggesting the closest of the `known` ones when it looks like a typo.
+    pub fn err_unknown(&self, name: &str, known: &[&str]) -> DeJsonErr {
+        let msg = match crate::suggest::closest(name, known) {
+            Some(known) => format!("Unexpected key {}, did you mean {}?", name, known),
+            None => format!("Unexpected key {}", name),
+        };
+        DeJsonErr {
+            msg,
+            line: self.line,
+            col: self.col,
+        }
+    }
+
     pub fn err_nf(&self, name: &str) -> DeJsonErr {
         DeJsonErr {
             msg: format!("Key not found {}", name),
@@ -239,7 +603,168 @@ impl DeJsonState {
         }
     }
 
-    pub fn eat_comma_block(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
+    /// The number of errors collected so far, see `DeJson::deserialize_json_all`.
+    pub fn error_count(&self) -> usize {
+        self.errors.as_ref().map_or(0, Vec::len)
+    }
+
+    fn push_path(&mut self, segment: &str) -> usize {
+        let len = self.path.len();
+        if !self.path.is_empty() && !segment.starts_with('[') {
+            self.path.push('.');
+        }
+        self.path.push_str(segment);
+        len
+    }
+
+    /// Record `err` at the path of the `segment` field or index, or return it when not collecting errors.
+    pub fn report(&mut self, segment: &str, err: DeJsonErr) -> Result<(), DeJsonErr> {
+        if self.errors.is_none() {
+            return Err(err);
+        }
+        let len = self.push_path(segment);
+        let path = self.path.clone();
+        self.path.truncate(len);
+        if let Some(errors) = &mut self.errors {
+            errors.push(DeJsonPathErr { path, err });
+        }
+        Ok(())
+    }
+
+    /// Parse the value of the `name` field.
+    ///
+    /// When collecting errors, a value that fails is recorded at its path and skipped,
+    /// leaving `None`.
+    #[inline]
+    pub fn de_field<'de, T: DeJson<'de>>(
+        &mut self,
+        name: &str,
+        i: &mut JsonInput<'de>,
+    ) -> Result<Option<T>, DeJsonErr> {
+        if self.errors.is_none() {
+            return DeJson::de_json(self, i).map(Some);
+        }
+        self.de_collecting(name, i)
+    }
+
+    /// Parse the array item at `index`, like `de_field`.
+    #[inline]
+    pub fn de_item<'de, T: DeJson<'de>>(
+        &mut self,
+        index: usize,
+        i: &mut JsonInput<'de>,
+    ) -> Result<Option<T>, DeJsonErr> {
+        if self.errors.is_none() {
+            return DeJson::de_json(self, i).map(Some);
+        }
+        self.de_collecting(&format!("[{}]", index), i)
+    }
+
+    fn de_collecting<'de, T: DeJson<'de>>(
+        &mut self,
+        segment: &str,
+        i: &mut JsonInput<'de>,
+    ) -> Result<Option<T>, DeJsonErr> {
+        // where to come back to, to skip the value when it fails
+        let (chunk, pos, borrowable) = (i.chunk, i.pos, i.borrowable);
+        let (cur, tok, line, col) = (self.cur, self.tok.clone(), self.line, self.col);
+        let strbuf = match self.tok {
+            DeJsonTok::Str => Some(self.strbuf.clone()),
+            _ => None,
+        };
+
+        let len = self.push_path(segment);
+        let before = self.error_count();
+        let result = match DeJson::de_json(self, i) {
+            Ok(value) => Ok(Some(value)),
+            // a refilled input can't be rewound
+            Err(err) if i.chunk != chunk => Err(err),
+            // the errors of the fields inside are already there
+            Err(_) if self.error_count() != before => Ok(None),
+            Err(err) => {
+                let path = self.path.clone();
+                if let Some(errors) = &mut self.errors {
+                    errors.push(DeJsonPathErr {
+                        path,
+                        err: DeJsonErr { line, col, ..err },
+                    });
+                }
+                Ok(None)
+            }
+        };
+        self.path.truncate(len);
+
+        if let Ok(None) = result {
+            (i.pos, i.borrowable) = (pos, borrowable);
+            (self.cur, self.tok, self.line, self.col) = (cur, tok, line, col);
+            if let Some(strbuf) = strbuf {
+                self.strbuf = strbuf;
+            }
+            self.whole_field(i)?;
+        }
+        result
+    }
+
+    /// Fail when errors were collected since `since`, after recording the required fields
+    /// that weren't there, `fields` being the names and whether they were found.
+    pub fn check_fields(&mut self, since: usize, fields: &[(&str, bool)]) -> Result<(), DeJsonErr> {
+        if self.errors.is_none() {
+            return Ok(());
+        }
+        for (name, found) in fields {
+            if *found {
+                continue;
+            }
+            let len = self.push_path(name);
+            let path = self.path.clone();
+            self.path.truncate(len);
+            // a field that failed to parse isn't missing
+            let failed = self.errors.as_ref().is_some_and(|errors| {
+                errors[since..].iter().any(|error| {
+                    error.path.strip_prefix(path.as_str()).is_some_and(|rest| {
+                        rest.is_empty() || rest.starts_with('.') || rest.starts_with('[')
+                    })
+                })
+            });
+            if !failed {
+                let err = self.err_nf(name);
+                if let Some(errors) = &mut self.errors {
+                    errors.push(DeJsonPathErr { path, err });
+                }
+            }
+        }
+        if self.error_count() != since {
+            return Err(self.err_parse("value, the errors were collected"));
+        }
+        Ok(())
+    }
+
+    /// Parse a value that had to be read ahead, like the object of an internally tagged enum,
+    /// out of its JSON text. Errors are reported at the current position.
+    pub fn de_buffered<'de, T>(
+        &self,
+        input: &str,
+        f: impl FnOnce(&mut DeJsonState, &mut JsonInput<'de>) -> Result<T, DeJsonErr>,
+    ) -> Result<T, DeJsonErr> {
+        let mut state = DeJsonState::default();
+        // owned, `'de` is the lifetime of the outer input
+        let mut input = JsonInput::from(input.to_string());
+        state.next(&mut input);
+        let result = state
+            .next_tok(&mut input)
+            .and_then(|_| f(&mut state, &mut input));
+        let result = match result {
+            Ok(_) if state.tok != DeJsonTok::Eof => Err(state.err_token("end of the value")),
+            result => result,
+        };
+        result.map_err(|err| DeJsonErr {
+            msg: err.msg,
+            line: self.line,
+            col: self.col,
+        })
+    }
+
+    pub fn eat_comma_block(&mut self, i: &mut JsonInput) -> Result<(), DeJsonErr> {
         match self.tok {
             DeJsonTok::Comma => {
                 self.next_tok(i)?;
@@ -250,7 +775,7 @@ impl DeJsonState {
         }
     }
 
-    pub fn whole_field(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
+    pub fn whole_field(&mut self, i: &mut JsonInput) -> Result<(), DeJsonErr> {
         match self.tok {
             DeJsonTok::F64 { .. }
             | DeJsonTok::I64 { .. }
@@ -285,7 +810,7 @@ impl DeJsonState {
         }
     }
 
-    pub fn eat_comma_curly(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
+    pub fn eat_comma_curly(&mut self, i: &mut JsonInput) -> Result<(), DeJsonErr> {
         match self.tok {
             DeJsonTok::Comma => {
                 self.next_tok(i)?;
@@ -296,7 +821,7 @@ impl DeJsonState {
         }
     }
 
-    pub fn colon(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
+    pub fn colon(&mut self, i: &mut JsonInput) -> Result<(), DeJsonErr> {
         match self.tok {
             DeJsonTok::Colon => {
                 self.next_tok(i)?;
@@ -306,7 +831,7 @@ impl DeJsonState {
         }
     }
 
-    pub fn string(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
+    pub fn string(&mut self, i: &mut JsonInput) -> Result<(), DeJsonErr> {
         match &mut self.tok {
             DeJsonTok::Str => {
                 self.next_tok(i)?;
@@ -316,7 +841,7 @@ impl DeJsonState {
         }
     }
 
-    pub fn next_colon(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
+    pub fn next_colon(&mut self, i: &mut JsonInput) -> Result<(), DeJsonErr> {
         self.next_tok(i)?;
         self.colon(i)?;
         Ok(())
@@ -332,7 +857,7 @@ impl DeJsonState {
         }
     }
 
-    pub fn block_open(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
+    pub fn block_open(&mut self, i: &mut JsonInput) -> Result<(), DeJsonErr> {
         if self.tok == DeJsonTok::BlockOpen {
             self.next_tok(i)?;
             return Ok(());
@@ -340,7 +865,7 @@ impl DeJsonState {
         Err(self.err_token("["))
     }
 
-    pub fn block_close(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
+    pub fn block_close(&mut self, i: &mut JsonInput) -> Result<(), DeJsonErr> {
         if self.tok == DeJsonTok::BlockClose {
             self.next_tok(i)?;
             return Ok(());
@@ -348,7 +873,7 @@ impl DeJsonState {
         Err(self.err_token("]"))
     }
 
-    pub fn curly_open(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
+    pub fn curly_open(&mut self, i: &mut JsonInput) -> Result<(), DeJsonErr> {
         if self.tok == DeJsonTok::CurlyOpen {
             self.next_tok(i)?;
             return Ok(());
@@ -356,7 +881,7 @@ impl DeJsonState {
         Err(self.err_token("{"))
     }
 
-    pub fn curly_close(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
+    pub fn curly_close(&mut self, i: &mut JsonInput) -> Result<(), DeJsonErr> {
         if self.tok == DeJsonTok::CurlyClose {
             self.next_tok(i)?;
             return Ok(());
@@ -419,7 +944,7 @@ impl DeJsonState {
         Err(self.err_token("string"))
     }
 
-    pub fn next_tok(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
+    pub fn next_tok(&mut self, i: &mut JsonInput) -> Result<(), DeJsonErr> {
         while self.cur == '\n' || self.cur == '\r' || self.cur == '\t' || self.cur == ' ' {
             self.next(i);
         }
@@ -577,9 +1102,12 @@ impl DeJsonState {
             }
             '"' => {
                 self.strbuf.truncate(0);
+                let (chunk, start) = (i.chunk, i.pos);
+                let mut escaped = false;
                 self.next(i);
                 while self.cur != '"' {
                     if self.cur == '\\' {
+                        escaped = true;
                         self.next(i);
                         match self.cur {
                             'n' => self.strbuf.push('\n'),
@@ -607,9 +1135,19 @@ impl DeJsonState {
                             return Err(self.err_parse("string"));
                         }
                         self.strbuf.push(self.cur);
+                        // the plain characters after it in one go
+                        let rest = i.rest();
+                        let run = rest
+                            .bytes()
+                            .position(|b| matches!(b, b'"' | b'\\' | b'\n' | b'\0'))
+                            .unwrap_or(rest.len());
+                        self.strbuf.push_str(&rest[..run]);
+                        self.col += rest[..run].chars().count();
+                        i.pos += run;
                         self.next(i);
                     }
                 }
+                i.borrowable = (!escaped && i.chunk == chunk).then(|| (start, i.pos - 1));
                 self.next(i);
                 self.tok = DeJsonTok::Str;
                 return Ok(());
@@ -626,7 +1164,7 @@ impl DeJsonState {
     ///
     /// On illegal escapes or unpaired surrogates returns None (and caller
     /// should emit an error).
-    fn hex_unescape_char(&mut self, i: &mut Chars) -> Option<char> {
+    fn hex_unescape_char(&mut self, i: &mut JsonInput) -> Option<char> {
         self.next(i);
         let a = xdigit4(self, i)?;
         if let Some(c) = core::char::from_u32(a as u32) {
@@ -658,7 +1196,7 @@ impl DeJsonState {
         return None;
 
         // Helper to turn next 4 ascii hex digits into a u16
-        fn xdigit4(de: &mut DeJsonState, i: &mut Chars) -> Option<u16> {
+        fn xdigit4(de: &mut DeJsonState, i: &mut JsonInput) -> Option<u16> {
             // as tempting as it is to try to find a way to use from_str_radix on the
             // next 4 bytes from `i`, we'd still need to do validation to detect cases
             // like `\u+123` and such which makes it less attractive.
@@ -684,8 +1222,8 @@ macro_rules! impl_ser_de_json_unsigned {
             }
         }
 
-        impl DeJson for $ty {
-            fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<$ty, DeJsonErr> {
+        impl<'de> DeJson<'de> for $ty {
+            fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<$ty, DeJsonErr> {
                 let val = s.u64_range($max as u64)?;
                 s.next_tok(i)?;
                 return Ok(val as $ty);
@@ -702,8 +1240,8 @@ macro_rules! impl_ser_de_json_signed {
             }
         }
 
-        impl DeJson for $ty {
-            fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<$ty, DeJsonErr> {
+        impl<'de> DeJson<'de> for $ty {
+            fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<$ty, DeJsonErr> {
                 //s.is_prefix(p, i) ?;
                 let val = s.i64_range($min as i64, $max as i64)?;
                 s.next_tok(i)?;
@@ -721,8 +1259,8 @@ macro_rules! impl_ser_de_json_float {
             }
         }
 
-        impl DeJson for $ty {
-            fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<$ty, DeJsonErr> {
+        impl<'de> DeJson<'de> for $ty {
+            fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<$ty, DeJsonErr> {
                 //s.is_prefix(p, i) ?;
                 let val = s.as_f64()?;
                 s.next_tok(i)?;
@@ -757,11 +1295,11 @@ where
     }
 }
 
-impl<T> DeJson for Option<T>
+impl<'de, T> DeJson<'de> for Option<T>
 where
-    T: DeJson,
+    T: DeJson<'de>,
 {
-    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<Self, DeJsonErr> {
+    fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<Self, DeJsonErr> {
         if let DeJsonTok::Null = s.tok {
             s.next_tok(i)?;
             return Ok(None);
@@ -776,8 +1314,8 @@ impl SerJson for () {
     }
 }
 
-impl DeJson for () {
-    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<(), DeJsonErr> {
+impl<'de> DeJson<'de> for () {
+    fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<(), DeJsonErr> {
         if let DeJsonTok::Null = s.tok {
             s.next_tok(i)?;
             Ok(())
@@ -797,8 +1335,8 @@ impl SerJson for bool {
     }
 }
 
-impl DeJson for bool {
-    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<bool, DeJsonErr> {
+impl<'de> DeJson<'de> for bool {
+    fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<bool, DeJsonErr> {
         let val = s.as_bool()?;
         s.next_tok(i)?;
         Ok(val)
@@ -835,14 +1373,59 @@ macro_rules! impl_ser_json_string {
 impl_ser_json_string!(String);
 impl_ser_json_string!(str);
 
-impl DeJson for String {
-    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<String, DeJsonErr> {
+impl<'de> DeJson<'de> for String {
+    fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<String, DeJsonErr> {
         let val = s.as_string()?;
         s.next_tok(i)?;
         Ok(val)
     }
 }
 
+/// Borrowed out of the input, strings with escapes or read from a reader fail to parse:
+/// use `Cow<str>` for those.
+impl<'de: 'a, 'a> DeJson<'de> for &'a str {
+    fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<&'a str, DeJsonErr> {
+        if s.tok != DeJsonTok::Str {
+            return Err(s.err_token("string"));
+        }
+        let val = i
+            .borrowed_str()
+            .ok_or_else(|| s.err_parse("borrowed string, use Cow<str> for escaped strings"))?;
+        s.next_tok(i)?;
+        Ok(val)
+    }
+}
+
+/// Borrowed out of the input when it can be, owned otherwise.
+impl<'de: 'a, 'a> DeJson<'de> for Cow<'a, str> {
+    fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<Cow<'a, str>, DeJsonErr> {
+        let val = match i.borrowed_str() {
+            Some(val) if s.tok == DeJsonTok::Str => Cow::Borrowed(val),
+            _ => Cow::Owned(s.as_string()?),
+        };
+        s.next_tok(i)?;
+        Ok(val)
+    }
+}
+
+impl<T> SerJson for &T
+where
+    T: SerJson + ?Sized,
+{
+    fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+        (**self).ser_json(d, s)
+    }
+}
+
+impl<T> SerJson for Cow<'_, T>
+where
+    T: SerJson + ToOwned + ?Sized,
+{
+    fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+        (**self).ser_json(d, s)
+    }
+}
+
 impl<T> SerJson for Vec<T>
 where
     T: SerJson,
@@ -863,19 +1446,25 @@ where
     }
 }
 
-impl<T> DeJson for Vec<T>
+impl<'de, T> DeJson<'de> for Vec<T>
 where
-    T: DeJson,
+    T: DeJson<'de>,
 {
-    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<Vec<T>, DeJsonErr> {
+    fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<Vec<T>, DeJsonErr> {
         let mut out = Vec::new();
+        let errors = s.error_count();
         s.block_open(i)?;
 
+        let mut index = 0;
         while s.tok != DeJsonTok::BlockClose {
-            out.push(DeJson::de_json(s, i)?);
+            if let Some(item) = s.de_item(index, i)? {
+                out.push(item);
+            }
             s.eat_comma_block(i)?;
+            index += 1;
         }
         s.block_close(i)?;
+        s.check_fields(errors, &[])?;
         Ok(out)
     }
 }
@@ -902,11 +1491,11 @@ where
 }
 
 #[cfg(feature = "std")]
-impl<T> DeJson for std::collections::HashSet<T>
+impl<'de, T> DeJson<'de> for std::collections::HashSet<T>
 where
-    T: DeJson + core::hash::Hash + Eq,
+    T: DeJson<'de> + core::hash::Hash + Eq,
 {
-    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<Self, DeJsonErr> {
+    fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<Self, DeJsonErr> {
         let mut out = std::collections::HashSet::new();
         s.block_open(i)?;
 
@@ -939,11 +1528,11 @@ where
     }
 }
 
-impl<T> DeJson for LinkedList<T>
+impl<'de, T> DeJson<'de> for LinkedList<T>
 where
-    T: DeJson,
+    T: DeJson<'de>,
 {
-    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<LinkedList<T>, DeJsonErr> {
+    fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<LinkedList<T>, DeJsonErr> {
         let mut out = LinkedList::new();
         s.block_open(i)?;
 
@@ -976,11 +1565,11 @@ where
     }
 }
 
-impl<T> DeJson for BTreeSet<T>
+impl<'de, T> DeJson<'de> for BTreeSet<T>
 where
-    T: DeJson + Ord,
+    T: DeJson<'de> + Ord,
 {
-    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<BTreeSet<T>, DeJsonErr> {
+    fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<BTreeSet<T>, DeJsonErr> {
         let mut out = BTreeSet::new();
         s.block_open(i)?;
 
@@ -1020,11 +1609,11 @@ where
     }
 }
 
-impl<T, const N: usize> DeJson for [T; N]
+impl<'de, T, const N: usize> DeJson<'de> for [T; N]
 where
-    T: DeJson,
+    T: DeJson<'de>,
 {
-    fn de_json(o: &mut DeJsonState, d: &mut Chars) -> Result<Self, DeJsonErr> {
+    fn de_json(o: &mut DeJsonState, d: &mut JsonInput<'de>) -> Result<Self, DeJsonErr> {
         use core::mem::MaybeUninit;
 
         // waiting for uninit_array(or for array::try_from_fn) stabilization
@@ -1063,9 +1652,9 @@ where
     }
 }
 
-fn de_json_comma_block<T>(s: &mut DeJsonState, i: &mut Chars) -> Result<T, DeJsonErr>
+fn de_json_comma_block<'de, T>(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<T, DeJsonErr>
 where
-    T: DeJson,
+    T: DeJson<'de>,
 {
     let t = DeJson::de_json(s, i);
     s.eat_comma_block(i)?;
@@ -1086,12 +1675,12 @@ where
     }
 }
 
-impl<A, B> DeJson for (A, B)
+impl<'de, A, B> DeJson<'de> for (A, B)
 where
-    A: DeJson,
-    B: DeJson,
+    A: DeJson<'de>,
+    B: DeJson<'de>,
 {
-    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<(A, B), DeJsonErr> {
+    fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<(A, B), DeJsonErr> {
         s.block_open(i)?;
         let r = (de_json_comma_block(s, i)?, de_json_comma_block(s, i)?);
         s.block_close(i)?;
@@ -1116,13 +1705,13 @@ where
     }
 }
 
-impl<A, B, C> DeJson for (A, B, C)
+impl<'de, A, B, C> DeJson<'de> for (A, B, C)
 where
-    A: DeJson,
-    B: DeJson,
-    C: DeJson,
+    A: DeJson<'de>,
+    B: DeJson<'de>,
+    C: DeJson<'de>,
 {
-    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<(A, B, C), DeJsonErr> {
+    fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<(A, B, C), DeJsonErr> {
         s.block_open(i)?;
         let r = (
             de_json_comma_block(s, i)?,
@@ -1154,14 +1743,14 @@ where
     }
 }
 
-impl<A, B, C, D> DeJson for (A, B, C, D)
+impl<'de, A, B, C, D> DeJson<'de> for (A, B, C, D)
 where
-    A: DeJson,
-    B: DeJson,
-    C: DeJson,
-    D: DeJson,
+    A: DeJson<'de>,
+    B: DeJson<'de>,
+    C: DeJson<'de>,
+    D: DeJson<'de>,
 {
-    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<(A, B, C, D), DeJsonErr> {
+    fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<(A, B, C, D), DeJsonErr> {
         s.block_open(i)?;
         let r = (
             de_json_comma_block(s, i)?,
@@ -1200,12 +1789,12 @@ where
 }
 
 #[cfg(feature = "std")]
-impl<K, V> DeJson for std::collections::HashMap<K, V>
+impl<'de, K, V> DeJson<'de> for std::collections::HashMap<K, V>
 where
-    K: DeJson + Eq + core::hash::Hash,
-    V: DeJson,
+    K: DeJson<'de> + Eq + core::hash::Hash,
+    V: DeJson<'de>,
 {
-    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<Self, DeJsonErr> {
+    fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<Self, DeJsonErr> {
         let mut h = std::collections::HashMap::new();
         s.curly_open(i)?;
         while s.tok != DeJsonTok::CurlyClose {
@@ -1244,12 +1833,12 @@ where
     }
 }
 
-impl<K, V> DeJson for BTreeMap<K, V>
+impl<'de, K, V> DeJson<'de> for BTreeMap<K, V>
 where
-    K: DeJson + Eq + Ord,
-    V: DeJson,
+    K: DeJson<'de> + Eq + Ord,
+    V: DeJson<'de>,
 {
-    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<Self, DeJsonErr> {
+    fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<Self, DeJsonErr> {
         let mut h = BTreeMap::new();
         s.curly_open(i)?;
         while s.tok != DeJsonTok::CurlyClose {
@@ -1273,11 +1862,226 @@ where
     }
 }
 
-impl<T> DeJson for Box<T>
+impl<'de, T> DeJson<'de> for Box<T>
 where
-    T: DeJson,
+    T: DeJson<'de>,
 {
-    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<Box<T>, DeJsonErr> {
+    fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<Box<T>, DeJsonErr> {
         Ok(Box::new(DeJson::de_json(s, i)?))
     }
 }
+
+/// An untyped JSON value, for the parts of a document without a fixed schema.
+///
+/// Numbers are kept as `f64`, integers above 2^53 lose precision.
+///
+/// ```rust
+/// # use nanoserde::*;
+/// let value = JsonValue::deserialize_json(r#"{"a": [1, {"b": "c"}]}"#).unwrap();
+/// assert_eq!(value["a"][1]["b"].as_str(), Some("c"));
+/// assert_eq!(value.pointer("/a/0").and_then(JsonValue::as_f64), Some(1.0));
+/// assert!(value["missing"].is_null());
+/// ```
+#[derive(Clone, Debug, PartialEq, Default)]
+pub enum JsonValue {
+    #[default]
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(BTreeMap<String, JsonValue>),
+}
+
+static JSON_NULL: JsonValue = JsonValue::Null;
+
+impl JsonValue {
+    pub fn is_null(&self) -> bool {
+        matches!(self, JsonValue::Null)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The number, if it has no fractional part and fits into `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            JsonValue::Number(value)
+                if *value % 1.0 == 0.0 && *value >= i64::MIN as f64 && *value < i64::MAX as f64 =>
+            {
+                Some(*value as i64)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<JsonValue>> {
+        match self {
+            JsonValue::Array(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&BTreeMap<String, JsonValue>> {
+        match self {
+            JsonValue::Object(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_object_mut(&mut self) -> Option<&mut BTreeMap<String, JsonValue>> {
+        match self {
+            JsonValue::Object(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Move the named fields of an object into a new object, the fields a flattened struct uses.
+    pub fn take_fields(&mut self, names: &[&str]) -> JsonValue {
+        let mut taken = BTreeMap::new();
+        if let Some(object) = self.as_object_mut() {
+            for name in names {
+                if let Some(value) = object.remove(*name) {
+                    taken.insert(name.to_string(), value);
+                }
+            }
+        }
+        JsonValue::Object(taken)
+    }
+
+    /// The object's field, `None` for missing fields and non-objects.
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        self.as_object()?.get(key)
+    }
+
+    /// The array's element, `None` out of bounds and for non-arrays.
+    pub fn get_index(&self, index: usize) -> Option<&JsonValue> {
+        self.as_array()?.get(index)
+    }
+
+    /// Look up a value by a JSON pointer (RFC 6901), like `/meshes/0/name`.
+    ///
+    /// The empty path is the value itself, `~1` and `~0` in the segments stand for `/` and `~`.
+    pub fn pointer(&self, path: &str) -> Option<&JsonValue> {
+        if path.is_empty() {
+            return Some(self);
+        }
+        let path = path.strip_prefix('/')?;
+        path.split('/').try_fold(self, |value, segment| {
+            let segment = segment.replace("~1", "/").replace("~0", "~");
+            match value {
+                JsonValue::Object(object) => object.get(&segment),
+                JsonValue::Array(array) => array.get(segment.parse::<usize>().ok()?),
+                _ => None,
+            }
+        })
+    }
+}
+
+/// Missing fields and non-objects index to `JsonValue::Null`.
+impl core::ops::Index<&str> for JsonValue {
+    type Output = JsonValue;
+
+    fn index(&self, key: &str) -> &JsonValue {
+        self.get(key).unwrap_or(&JSON_NULL)
+    }
+}
+
+/// Out of bounds indices and non-arrays index to `JsonValue::Null`.
+impl core::ops::Index<usize> for JsonValue {
+    type Output = JsonValue;
+
+    fn index(&self, index: usize) -> &JsonValue {
+        self.get_index(index).unwrap_or(&JSON_NULL)
+    }
+}
+
+impl From<bool> for JsonValue {
+    fn from(value: bool) -> JsonValue {
+        JsonValue::Bool(value)
+    }
+}
+
+impl From<f64> for JsonValue {
+    fn from(value: f64) -> JsonValue {
+        JsonValue::Number(value)
+    }
+}
+
+impl From<&str> for JsonValue {
+    fn from(value: &str) -> JsonValue {
+        JsonValue::String(value.to_string())
+    }
+}
+
+impl From<String> for JsonValue {
+    fn from(value: String) -> JsonValue {
+        JsonValue::String(value)
+    }
+}
+
+impl From<Vec<JsonValue>> for JsonValue {
+    fn from(value: Vec<JsonValue>) -> JsonValue {
+        JsonValue::Array(value)
+    }
+}
+
+impl From<BTreeMap<String, JsonValue>> for JsonValue {
+    fn from(value: BTreeMap<String, JsonValue>) -> JsonValue {
+        JsonValue::Object(value)
+    }
+}
+
+impl SerJson for JsonValue {
+    fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+        match self {
+            JsonValue::Null => s.out.push_str("null"),
+            JsonValue::Bool(value) => value.ser_json(d, s),
+            // integers are written without the fraction, the way they were most likely read
+            JsonValue::Number(value) if *value % 1.0 == 0.0 && *value > -1e15 && *value < 1e15 => {
+                (*value as i64).ser_json(d, s)
+            }
+            JsonValue::Number(value) => value.ser_json(d, s),
+            JsonValue::String(value) => value.ser_json(d, s),
+            JsonValue::Array(value) => value.ser_json(d, s),
+            JsonValue::Object(value) => value.ser_json(d, s),
+        }
+    }
+}
+
+impl<'de> DeJson<'de> for JsonValue {
+    fn de_json(s: &mut DeJsonState, i: &mut JsonInput<'de>) -> Result<JsonValue, DeJsonErr> {
+        match s.tok {
+            DeJsonTok::Null => {
+                s.next_tok(i)?;
+                Ok(JsonValue::Null)
+            }
+            DeJsonTok::Bool(_) => Ok(JsonValue::Bool(DeJson::de_json(s, i)?)),
+            DeJsonTok::U64(_) | DeJsonTok::I64(_) | DeJsonTok::F64(_) => {
+                Ok(JsonValue::Number(DeJson::de_json(s, i)?))
+            }
+            DeJsonTok::Str => Ok(JsonValue::String(DeJson::de_json(s, i)?)),
+            DeJsonTok::BlockOpen => Ok(JsonValue::Array(DeJson::de_json(s, i)?)),
+            DeJsonTok::CurlyOpen => Ok(JsonValue::Object(DeJson::de_json(s, i)?)),
+            _ => Err(s.err_token("json value")),
+        }
+    }
+}
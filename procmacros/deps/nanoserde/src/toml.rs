@@ -1,4 +1,4 @@
-use core::str::Chars;
+use core::str::{Chars, FromStr};
 
 // remove this after 1.81 is live
 #[cfg(not(feature = "std"))]
@@ -6,6 +6,7 @@ use core::error::Error;
 #[cfg(feature = "std")]
 use std::error::Error;
 
+use alloc::boxed::Box;
 use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::{collections::BTreeMap, vec, vec::Vec};
@@ -42,7 +43,7 @@ macro_rules! ident_chars {
 /// Pattern matching a character that can terminate a valid ident.
 macro_rules! ident_term_chars {
     () => {
-        ' ' | '\t' | '\n' | '\0' | '=' | ']'
+        ' ' | '\t' | '\n' | '\0' | '=' | ']' | '}' | ','
     };
 }
 
@@ -77,6 +78,8 @@ pub enum TomlTok {
     Equals,
     BlockOpen,
     BlockClose,
+    CurlyOpen,
+    CurlyClose,
     Comma,
     Eof,
 }
@@ -108,14 +111,19 @@ impl From<TomlTok> for String {
             TomlTok::Equals => '='.to_string(),
             TomlTok::BlockOpen => '['.to_string(),
             TomlTok::BlockClose => ']'.to_string(),
+            TomlTok::CurlyOpen => '{'.to_string(),
+            TomlTok::CurlyClose => '}'.to_string(),
             TomlTok::Comma => ','.to_string(),
             TomlTok::Eof => '\0'.to_string(),
         }
     }
 }
 
+/// A TOML table, its keys and values.
+pub type TomlTable = BTreeMap<String, Toml>;
+
 /// A TOML value.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Toml {
     Str(String),
     Bool(bool),
@@ -123,6 +131,8 @@ pub enum Toml {
     Date(String),
     Array(Vec<BTreeMap<String, Toml>>),
     SimpleArray(Vec<Toml>),
+    /// An inline table, or any table with `TomlParser::parse_nested`.
+    Table(BTreeMap<String, Toml>),
 }
 
 impl core::ops::Index<usize> for Toml {
@@ -193,6 +203,75 @@ impl Toml {
             _ => panic!(),
         }
     }
+
+    /// Get the TOML value as a number, `None` if it isn't one
+    pub fn as_num(&self) -> Option<f64> {
+        match self {
+            Toml::Num(num) => Some(*num),
+            _ => None,
+        }
+    }
+
+    /// Get the TOML value as a string, `None` if it isn't one
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Toml::Str(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// Get the TOML value as a boolean, `None` if it isn't one
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Toml::Bool(boolean) => Some(*boolean),
+            _ => None,
+        }
+    }
+
+    /// Get the TOML value as a date, `None` if it isn't one
+    pub fn as_date(&self) -> Option<&str> {
+        match self {
+            Toml::Date(date) => Some(date),
+            _ => None,
+        }
+    }
+
+    /// Get the TOML value as an array of tables, `None` if it isn't one
+    pub fn as_arr(&self) -> Option<&Vec<BTreeMap<String, Toml>>> {
+        match self {
+            Toml::Array(array) => Some(array),
+            _ => None,
+        }
+    }
+
+    /// Get the TOML value as an array, `None` if it isn't one
+    pub fn as_simple_arr(&self) -> Option<&Vec<Toml>> {
+        match self {
+            Toml::SimpleArray(array) => Some(array),
+            _ => None,
+        }
+    }
+
+    /// Get the TOML value as a table, `None` if it isn't one
+    pub fn as_table(&self) -> Option<&BTreeMap<String, Toml>> {
+        match self {
+            Toml::Table(table) => Some(table),
+            _ => None,
+        }
+    }
+
+    /// What kind of value this is, for the error messages.
+    fn kind(&self) -> &'static str {
+        match self {
+            Toml::Str(_) => "string",
+            Toml::Bool(_) => "boolean",
+            Toml::Num(_) => "number",
+            Toml::Date(_) => "datetime",
+            Toml::Array(_) => "array of tables",
+            Toml::SimpleArray(_) => "array",
+            Toml::Table(_) => "table",
+        }
+    }
 }
 
 /// The error message when failing to parse a TOML string.
@@ -273,6 +352,20 @@ impl TomlParser {
         Ok(out.out)
     }
 
+    /// Parse a TOML string into nested tables, `[a]` and `b = 1` becoming the `b` field of
+    /// the `a` table rather than an `a.b` key, and arrays of tables becoming arrays of
+    /// `Toml::Table`s. This is what the `DeToml` derive reads.
+    ///
+    /// ```rust
+    /// # use nanoserde::*;
+    /// let parsed = TomlParser::parse_nested("[window]\nsize.width = 800").unwrap();
+    /// let window = parsed["window"].as_table().unwrap();
+    /// assert_eq!(window["size"].as_table().unwrap()["width"], Toml::Num(800.));
+    /// ```
+    pub fn parse_nested(data: &str) -> Result<BTreeMap<String, Toml>, TomlErr> {
+        Ok(nest(TomlParser::parse(data)?))
+    }
+
     fn parse_line(
         &mut self,
         i: &mut Chars,
@@ -291,7 +384,20 @@ impl TomlParser {
                 let tok = self.next_tok(i)?;
                 match tok {
                     TomlTok::Str(key) | TomlTok::Ident(key) => {
-                        *local_scope = key;
+                        // a table inside the current element of an array of tables, or a new one
+                        match &out.active_array_element {
+                            Some((array, _))
+                                if key.len() > array.len()
+                                    && key.starts_with(array.as_str())
+                                    && key[array.len()..].starts_with('.') =>
+                            {
+                                *local_scope = key[array.len() + 1..].to_string();
+                            }
+                            _ => {
+                                out.active_array_element = None;
+                                *local_scope = key;
+                            }
+                        }
                         let tok = self.next_tok(i)?;
                         if tok != TomlTok::BlockClose {
                             return Err(self.err_token(tok));
@@ -318,6 +424,7 @@ impl TomlParser {
                         if tok != TomlTok::BlockClose {
                             return Err(self.err_token(tok));
                         }
+                        local_scope.clear();
                         out.start_array(&key);
                     }
                     _ => return Err(self.err_token(tok)),
@@ -352,6 +459,33 @@ impl TomlParser {
                 }
                 Ok(Toml::SimpleArray(vals))
             }
+            TomlTok::CurlyOpen => {
+                let mut table = BTreeMap::new();
+                loop {
+                    let tok = self.next_tok(i)?;
+                    let key = match tok {
+                        TomlTok::CurlyClose => break,
+                        TomlTok::Comma => continue,
+                        TomlTok::Str(_)
+                        | TomlTok::Ident(_)
+                        | TomlTok::U64(_)
+                        | TomlTok::I64(_)
+                        | TomlTok::F64(_)
+                        | TomlTok::Bool(_)
+                        | TomlTok::Nan(_)
+                        | TomlTok::Inf(_)
+                        | TomlTok::Date(_) => String::from(tok),
+                        _ => return Err(self.err_token(tok)),
+                    };
+                    let tok = self.next_tok(i)?;
+                    if tok != TomlTok::Equals {
+                        return Err(self.err_token(tok));
+                    }
+                    let tok = self.next_tok(i)?;
+                    table.insert(key, self.to_val(tok, i)?);
+                }
+                Ok(Toml::Table(table))
+            }
             TomlTok::Str(v) => Ok(Toml::Str(v)),
             TomlTok::U64(v) => Ok(Toml::Num(v as f64)),
             TomlTok::I64(v) => Ok(Toml::Num(v as f64)),
@@ -447,6 +581,14 @@ impl TomlParser {
                     self.next(i);
                     return Ok(TomlTok::Equals);
                 }
+                '{' => {
+                    self.next(i);
+                    return Ok(TomlTok::CurlyOpen);
+                }
+                '}' => {
+                    self.next(i);
+                    return Ok(TomlTok::CurlyClose);
+                }
                 '#' => {
                     while self.cur != '\n' && self.cur != '\0' {
                         self.next(i);
@@ -469,6 +611,10 @@ impl TomlParser {
                         braces += 1;
                         self.next(i);
                     }
+                    if braces == 2 {
+                        // ""
+                        return Ok(TomlTok::Str(val));
+                    }
                     let escaped_string = braces == 3;
                     loop {
                         if self.cur == '"' && escaped_string == false {
@@ -487,12 +633,37 @@ impl TomlParser {
                             }
                             val.push_str(&tmp);
                         }
-                        if self.cur == '\\' {
-                            self.next(i);
-                        }
                         if self.cur == '\0' {
                             return Err(self.err_parse("string"));
                         }
+                        if self.cur == '\\' {
+                            self.next(i);
+                            let c = match self.cur {
+                                'b' => '\u{8}',
+                                't' => '\t',
+                                'n' => '\n',
+                                'f' => '\u{c}',
+                                'r' => '\r',
+                                'u' | 'U' => {
+                                    let len = if self.cur == 'u' { 4 } else { 8 };
+                                    let mut code = 0;
+                                    for _ in 0..len {
+                                        self.next(i);
+                                        code = code * 16
+                                            + self
+                                                .cur
+                                                .to_digit(16)
+                                                .ok_or(self.err_parse("escape"))?;
+                                    }
+                                    char::from_u32(code).ok_or(self.err_parse("escape"))?
+                                }
+                                '\0' => return Err(self.err_parse("string")),
+                                c => c,
+                            };
+                            val.push(c);
+                            self.next(i);
+                            continue;
+                        }
                         val.push(self.cur);
                         self.next(i);
                     }
@@ -581,30 +752,54 @@ impl TomlParser {
             self.next(i);
         }
 
-        if self.cur == '.' {
-            num.push(self.cur);
-            self.next(i);
-            while matches!(self.cur, '0'..='9' | '_') {
-                if self.cur != '_' {
-                    num.push(self.cur);
+        let exponent = |c: char, next: Option<char>| {
+            matches!(c, 'e' | 'E') && matches!(next, Some('0'..='9' | '+' | '-'))
+        };
+        if self.cur == '.' || exponent(self.cur, i.clone().next()) {
+            if self.cur == '.' {
+                num.push(self.cur);
+                self.next(i);
+                while matches!(self.cur, '0'..='9' | '_') {
+                    if self.cur != '_' {
+                        num.push(self.cur);
+                    }
+                    self.next(i);
                 }
+            }
+            if exponent(self.cur, i.clone().next()) {
+                num.push(self.cur);
                 self.next(i);
+                if matches!(self.cur, '+' | '-') {
+                    num.push(self.cur);
+                    self.next(i);
+                }
+                while matches!(self.cur, '0'..='9' | '_') {
+                    if self.cur != '_' {
+                        num.push(self.cur);
+                    }
+                    self.next(i);
+                }
             }
             if let Ok(num) = num.parse() {
                 return Ok(TomlTok::F64(num));
             } else {
                 return Err(self.err_parse("number"));
             }
-        } else if self.cur == '-' {
-            // lets assume its a date. whatever. i don't feel like more parsing today
+        } else if self.cur == '-' || self.cur == ':' {
+            // a date, a time or both, `TomlDatetime` checks them
             num.push(self.cur);
             self.next(i);
-            while matches!(self.cur, '0'..='9' | ':' | '-' | 'T') {
+            loop {
+                match self.cur {
+                    '0'..='9' | ':' | '-' | '+' | '.' | 'T' | 't' | 'Z' | 'z' => {}
+                    // the space between the date and the time
+                    ' ' if num.len() == 10 && matches!(i.clone().next(), Some('0'..='9')) => {}
+                    _ => break,
+                }
                 num.push(self.cur);
                 self.next(i);
             }
             return Ok(TomlTok::Date(num));
-            // TODO rework this
         }
 
         if matches!(self.cur, ident_chars!()) {
@@ -627,3 +822,778 @@ impl TomlParser {
         Err(self.err_parse("tokenizer"))
     }
 }
+
+/// Turn the flat `a.b` keys of `TomlParser::parse` into nested tables.
+fn nest(flat: BTreeMap<String, Toml>) -> BTreeMap<String, Toml> {
+    let mut out = BTreeMap::new();
+    for (key, value) in flat {
+        let value = match value {
+            Toml::Array(array) => Toml::SimpleArray(
+                array
+                    .into_iter()
+                    .map(|table| Toml::Table(nest(table)))
+                    .collect(),
+            ),
+            value => value,
+        };
+        insert_nested(&mut out, &key, value);
+    }
+    out
+}
+
+fn insert_nested(table: &mut BTreeMap<String, Toml>, key: &str, value: Toml) {
+    match key.split_once('.') {
+        Some((head, rest)) => {
+            let inner = table
+                .entry(head.to_string())
+                .or_insert_with(|| Toml::Table(BTreeMap::new()));
+            if let Toml::Table(inner) = inner {
+                insert_nested(inner, rest, value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+/// A TOML date-time: an offset date-time, a local date-time, a local date or a local time.
+///
+/// ```rust
+/// # use nanoserde::*;
+/// let datetime: TomlDatetime = "1979-05-27T07:32:00-08:00".parse().unwrap();
+/// assert_eq!(datetime.date, Some(TomlDate { year: 1979, month: 5, day: 27 }));
+/// assert_eq!(datetime.offset, Some(-8 * 60));
+/// assert_eq!(datetime.to_string(), "1979-05-27T07:32:00-08:00");
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TomlDatetime {
+    pub date: Option<TomlDate>,
+    pub time: Option<TomlTime>,
+    /// The offset from UTC in minutes, `None` for local date-times.
+    pub offset: Option<i16>,
+}
+
+/// The date of a `TomlDatetime`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TomlDate {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+/// The time of a `TomlDatetime`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TomlTime {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+}
+
+impl FromStr for TomlDatetime {
+    type Err = DeTomlErr;
+
+    fn from_str(s: &str) -> Result<Self, DeTomlErr> {
+        let err = || DeTomlErr::new(format!("Cannot parse datetime {}", s));
+        // a fixed width number
+        fn num<T: FromStr>(s: &str, range: core::ops::Range<usize>) -> Option<T> {
+            s.get(range)
+                .filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|digits| digits.parse().ok())
+        }
+
+        let mut datetime = TomlDatetime::default();
+        let mut rest = s;
+        if s.len() >= 10 && s.as_bytes()[4] == b'-' && s.as_bytes()[7] == b'-' {
+            let date = TomlDate {
+                year: num(s, 0..4).ok_or_else(err)?,
+                month: num(s, 5..7).ok_or_else(err)?,
+                day: num(s, 8..10).ok_or_else(err)?,
+            };
+            if !(1..=12).contains(&date.month) || !(1..=31).contains(&date.day) {
+                return Err(err());
+            }
+            datetime.date = Some(date);
+            rest = &s[10..];
+            if rest.is_empty() {
+                return Ok(datetime);
+            }
+            match rest.as_bytes()[0] {
+                b'T' | b't' | b' ' => rest = &rest[1..],
+                _ => return Err(err()),
+            }
+        }
+
+        if rest.len() < 8 || rest.as_bytes()[2] != b':' || rest.as_bytes()[5] != b':' {
+            return Err(err());
+        }
+        let mut time = TomlTime {
+            hour: num(rest, 0..2).ok_or_else(err)?,
+            minute: num(rest, 3..5).ok_or_else(err)?,
+            second: num(rest, 6..8).ok_or_else(err)?,
+            nanosecond: 0,
+        };
+        if time.hour > 23 || time.minute > 59 || time.second > 60 {
+            return Err(err());
+        }
+        rest = &rest[8..];
+        if let Some(fraction) = rest.strip_prefix('.') {
+            let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+            if digits == 0 {
+                return Err(err());
+            }
+            // digits past nanoseconds are dropped
+            let nanosecond = format!("{:0<9}", &fraction[..digits.min(9)]);
+            time.nanosecond = nanosecond.parse().map_err(|_| err())?;
+            rest = &fraction[digits..];
+        }
+        datetime.time = Some(time);
+
+        if !rest.is_empty() && datetime.date.is_none() {
+            // local times don't have an offset
+            return Err(err());
+        }
+        match rest {
+            "" => {}
+            "Z" | "z" => datetime.offset = Some(0),
+            _ if rest.len() == 6
+                && (rest.starts_with('+') || rest.starts_with('-'))
+                && rest.as_bytes()[3] == b':' =>
+            {
+                let hours: i16 = num(rest, 1..3).ok_or_else(err)?;
+                let minutes: i16 = num(rest, 4..6).ok_or_else(err)?;
+                let offset = hours * 60 + minutes;
+                datetime.offset = Some(if rest.starts_with('-') {
+                    -offset
+                } else {
+                    offset
+                });
+            }
+            _ => return Err(err()),
+        }
+        Ok(datetime)
+    }
+}
+
+impl core::fmt::Display for TomlDatetime {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if let Some(date) = &self.date {
+            write!(f, "{:04}-{:02}-{:02}", date.year, date.month, date.day)?;
+            if self.time.is_some() {
+                f.write_str("T")?;
+            }
+        }
+        if let Some(time) = &self.time {
+            write!(f, "{:02}:{:02}:{:02}", time.hour, time.minute, time.second)?;
+            if time.nanosecond != 0 {
+                let fraction = format!("{:09}", time.nanosecond);
+                write!(f, ".{}", fraction.trim_end_matches('0'))?;
+            }
+        }
+        match self.offset {
+            Some(0) => f.write_str("Z"),
+            Some(offset) => write!(
+                f,
+                "{}{:02}:{:02}",
+                if offset < 0 { '-' } else { '+' },
+                offset.abs() / 60,
+                offset.abs() % 60
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The error message when failing to deserialize a TOML value.
+#[derive(Clone)]
+pub struct DeTomlErr {
+    pub msg: String,
+    /// Where the value is, like `window.size[1]`, empty for the whole document.
+    pub path: String,
+}
+
+impl DeTomlErr {
+    fn new(msg: String) -> DeTomlErr {
+        DeTomlErr {
+            msg,
+            path: String::new(),
+        }
+    }
+
+    pub fn err_nf(name: &str) -> DeTomlErr {
+        DeTomlErr::new(format!("Key not found {}", name))
+    }
+
+    pub fn err_unknown(name: &str, known: &[&str]) -> DeTomlErr {
+        DeTomlErr::new(match crate::suggest::closest(name, known) {
+            Some(known) => format!("Unexpected key {}, did you mean {}?", name, known),
+            None => format!("Unexpected key {}", name),
+        })
+        .in_field(name)
+    }
+
+    pub fn err_type(what: &str, value: &Toml) -> DeTomlErr {
+        DeTomlErr::new(format!("Expected {}, found {}", what, value.kind()))
+    }
+
+    pub fn err_range(what: &str) -> DeTomlErr {
+        DeTomlErr::new(format!("Value out of range for {}", what))
+    }
+
+    pub fn err_enum(name: &str) -> DeTomlErr {
+        DeTomlErr::new(format!("Enum not defined {}", name))
+    }
+
+    /// Put the error inside the `key` field of a table.
+    pub fn in_field(mut self, key: &str) -> DeTomlErr {
+        self.path = match self.path.starts_with('[') || self.path.is_empty() {
+            true => format!("{}{}", key, self.path),
+            false => format!("{}.{}", key, self.path),
+        };
+        self
+    }
+
+    /// Put the error inside the `index` item of an array.
+    pub fn at_index(mut self, index: usize) -> DeTomlErr {
+        self.path = match self.path.starts_with('[') || self.path.is_empty() {
+            true => format!("[{}]{}", index, self.path),
+            false => format!("[{}].{}", index, self.path),
+        };
+        self
+    }
+}
+
+impl From<TomlErr> for DeTomlErr {
+    fn from(err: TomlErr) -> DeTomlErr {
+        DeTomlErr::new(format!(
+            "{}, line:{} col:{}",
+            err.msg,
+            err.line + 1,
+            err.col + 1
+        ))
+    }
+}
+
+impl core::fmt::Debug for DeTomlErr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.path.is_empty() {
+            true => write!(f, "Toml deserialize error: {}", self.msg),
+            false => write!(f, "Toml deserialize error: {}, at {}", self.msg, self.path),
+        }
+    }
+}
+
+impl core::fmt::Display for DeTomlErr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl Error for DeTomlErr {}
+
+/// The internal state of a TOML serialization.
+pub struct SerTomlState {
+    pub out: String,
+}
+
+impl SerTomlState {
+    /// Write a key, quoted unless it's a bare key.
+    pub fn key(&mut self, key: &str) {
+        if !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            self.out.push_str(key);
+        } else {
+            self.string(key);
+        }
+    }
+
+    /// Write a basic string, escaped.
+    pub fn string(&mut self, string: &str) {
+        self.out.push('"');
+        for c in string.chars() {
+            match c {
+                '\u{8}' => self.out.push_str("\\b"),
+                '\t' => self.out.push_str("\\t"),
+                '\n' => self.out.push_str("\\n"),
+                '\u{c}' => self.out.push_str("\\f"),
+                '\r' => self.out.push_str("\\r"),
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                c if c.is_control() => self.out.push_str(&format!("\\u{:04X}", c as u32)),
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+    }
+
+    /// Write a value inline, tables as inline tables.
+    pub fn value(&mut self, value: &Toml) {
+        match value {
+            Toml::Str(string) => self.string(string),
+            Toml::Bool(boolean) => self.out.push_str(if *boolean { "true" } else { "false" }),
+            Toml::Num(num) if num.is_nan() => self.out.push_str("nan"),
+            Toml::Num(num) if num.is_infinite() => {
+                self.out.push_str(if *num < 0. { "-inf" } else { "inf" })
+            }
+            // integral numbers are written as integers, as long as f64 keeps them exact
+            Toml::Num(num) if *num % 1. == 0. && *num > -1e15 && *num < 1e15 => {
+                self.out.push_str(&format!("{}", *num as i64))
+            }
+            Toml::Num(num) => self.out.push_str(&format!("{:?}", num)),
+            Toml::Date(date) => self.out.push_str(date),
+            Toml::Array(tables) => {
+                self.out.push('[');
+                for (index, table) in tables.iter().enumerate() {
+                    if index != 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.inline_table(table);
+                }
+                self.out.push(']');
+            }
+            Toml::SimpleArray(items) => {
+                self.out.push('[');
+                for (index, item) in items.iter().enumerate() {
+                    if index != 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.value(item);
+                }
+                self.out.push(']');
+            }
+            Toml::Table(table) => self.inline_table(table),
+        }
+    }
+
+    fn inline_table(&mut self, table: &BTreeMap<String, Toml>) {
+        if table.is_empty() {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push_str("{ ");
+        for (index, (key, value)) in table.iter().enumerate() {
+            if index != 0 {
+                self.out.push_str(", ");
+            }
+            self.key(key);
+            self.out.push_str(" = ");
+            self.value(value);
+        }
+        self.out.push_str(" }");
+    }
+
+    /// Write the table at `path`: its plain values first, then its tables under `[path.key]`
+    /// headers and its arrays of tables under `[[path.key]]` headers.
+    pub fn table(&mut self, path: &str, table: &BTreeMap<String, Toml>) {
+        for (key, value) in table {
+            if !matches!(value, Toml::Table(_)) && as_tables(value).is_none() {
+                self.key(key);
+                self.out.push_str(" = ");
+                self.value(value);
+                self.out.push('\n');
+            }
+        }
+        for (key, value) in table {
+            if let Toml::Table(inner) = value {
+                let child = self.child_path(path, key);
+                // a table with only tables inside doesn't need its own header
+                if inner
+                    .values()
+                    .any(|value| !matches!(value, Toml::Table(_)) && as_tables(value).is_none())
+                    || inner.is_empty()
+                {
+                    self.separate();
+                    self.out.push('[');
+                    self.out.push_str(&child);
+                    self.out.push_str("]\n");
+                }
+                self.table(&child, inner);
+            }
+        }
+        for (key, value) in table {
+            if let Some(tables) = as_tables(value) {
+                let child = self.child_path(path, key);
+                for table in tables {
+                    self.separate();
+                    self.out.push_str("[[");
+                    self.out.push_str(&child);
+                    self.out.push_str("]]\n");
+                    for (key, value) in table {
+                        self.key(key);
+                        self.out.push_str(" = ");
+                        self.value(value);
+                        self.out.push('\n');
+                    }
+                }
+            }
+        }
+    }
+
+    /// The path of `key` inside the table at `path`, for headers.
+    fn child_path(&mut self, path: &str, key: &str) -> String {
+        let mut child = String::from(path);
+        if !child.is_empty() {
+            child.push('.');
+        }
+        let start = self.out.len();
+        self.key(key);
+        child.push_str(&self.out[start..]);
+        self.out.truncate(start);
+        child
+    }
+
+    /// An empty line before a header, unless at the start.
+    fn separate(&mut self) {
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+    }
+}
+
+/// The tables of an array of tables, written under `[[key]]` headers.
+fn as_tables(value: &Toml) -> Option<Vec<&BTreeMap<String, Toml>>> {
+    match value {
+        Toml::Array(tables) if !tables.is_empty() => Some(tables.iter().collect()),
+        Toml::SimpleArray(items) if !items.is_empty() => items.iter().map(Toml::as_table).collect(),
+        _ => None,
+    }
+}
+
+/// A trait for objects that can be serialized to TOML.
+pub trait SerToml {
+    /// Serialize Self to a TOML string.
+    ///
+    /// Tables are written as documents, other values on their own.
+    ///
+    /// ```rust
+    /// # use nanoserde::*;
+    /// let mut config = std::collections::BTreeMap::new();
+    /// config.insert("name".to_string(), "Helmet".to_string());
+    /// assert_eq!(config.serialize_toml(), "name = \"Helmet\"\n");
+    /// ```
+    fn serialize_toml(&self) -> String {
+        let mut s = SerTomlState { out: String::new() };
+        match self.ser_toml() {
+            Toml::Table(table) => s.table("", &table),
+            value => s.value(&value),
+        }
+        s.out
+    }
+
+    /// Convert Self into a TOML value.
+    fn ser_toml(&self) -> Toml;
+}
+
+/// A trait for objects that can be deserialized from TOML.
+pub trait DeToml: Sized {
+    /// Parse Self from a TOML string.
+    ///
+    /// ```rust
+    /// # use nanoserde::*;
+    /// let config: std::collections::BTreeMap<String, Vec<u8>> =
+    ///     DeToml::deserialize_toml("color = [255, 128, 0]").unwrap();
+    /// assert_eq!(config["color"], vec![255, 128, 0]);
+    /// ```
+    fn deserialize_toml(input: &str) -> Result<Self, DeTomlErr> {
+        DeToml::de_toml(&Toml::Table(TomlParser::parse_nested(input)?))
+    }
+
+    /// Read Self from a TOML value.
+    fn de_toml(value: &Toml) -> Result<Self, DeTomlErr>;
+
+    /// Read Self from the `key` field of a table, `None` if it isn't there.
+    fn de_toml_field(table: &BTreeMap<String, Toml>, key: &str) -> Result<Option<Self>, DeTomlErr> {
+        table
+            .get(key)
+            .map(|value| Self::de_toml(value).map_err(|err| err.in_field(key)))
+            .transpose()
+    }
+}
+
+macro_rules! impl_ser_de_toml_unsigned {
+    ( $ ty: ident, $ max: expr) => {
+        impl SerToml for $ty {
+            fn ser_toml(&self) -> Toml {
+                Toml::Num(*self as f64)
+            }
+        }
+
+        impl DeToml for $ty {
+            fn de_toml(value: &Toml) -> Result<$ty, DeTomlErr> {
+                match value {
+                    Toml::Num(num) if *num % 1. == 0. && *num >= 0. && *num <= $max as f64 => {
+                        Ok(*num as $ty)
+                    }
+                    Toml::Num(_) => Err(DeTomlErr::err_range(stringify!($ty))),
+                    _ => Err(DeTomlErr::err_type("integer", value)),
+                }
+            }
+        }
+    };
+}
+
+macro_rules! impl_ser_de_toml_signed {
+    ( $ ty: ident, $ min: expr, $ max: expr) => {
+        impl SerToml for $ty {
+            fn ser_toml(&self) -> Toml {
+                Toml::Num(*self as f64)
+            }
+        }
+
+        impl DeToml for $ty {
+            fn de_toml(value: &Toml) -> Result<$ty, DeTomlErr> {
+                match value {
+                    Toml::Num(num)
+                        if *num % 1. == 0. && *num >= $min as f64 && *num <= $max as f64 =>
+                    {
+                        Ok(*num as $ty)
+                    }
+                    Toml::Num(_) => Err(DeTomlErr::err_range(stringify!($ty))),
+                    _ => Err(DeTomlErr::err_type("integer", value)),
+                }
+            }
+        }
+    };
+}
+
+macro_rules! impl_ser_de_toml_float {
+    ( $ ty: ident) => {
+        impl SerToml for $ty {
+            fn ser_toml(&self) -> Toml {
+                Toml::Num(*self as f64)
+            }
+        }
+
+        impl DeToml for $ty {
+            fn de_toml(value: &Toml) -> Result<$ty, DeTomlErr> {
+                match value {
+                    Toml::Num(num) => Ok(*num as $ty),
+                    _ => Err(DeTomlErr::err_type("number", value)),
+                }
+            }
+        }
+    };
+}
+
+impl_ser_de_toml_unsigned!(usize, usize::MAX);
+impl_ser_de_toml_unsigned!(u64, u64::MAX);
+impl_ser_de_toml_unsigned!(u32, u32::MAX);
+impl_ser_de_toml_unsigned!(u16, u16::MAX);
+impl_ser_de_toml_unsigned!(u8, u8::MAX);
+impl_ser_de_toml_signed!(i64, i64::MIN, i64::MAX);
+impl_ser_de_toml_signed!(i32, i32::MIN, i32::MAX);
+impl_ser_de_toml_signed!(i16, i16::MIN, i16::MAX);
+impl_ser_de_toml_signed!(i8, i8::MIN, i8::MAX);
+impl_ser_de_toml_float!(f64);
+impl_ser_de_toml_float!(f32);
+
+impl SerToml for bool {
+    fn ser_toml(&self) -> Toml {
+        Toml::Bool(*self)
+    }
+}
+
+impl DeToml for bool {
+    fn de_toml(value: &Toml) -> Result<bool, DeTomlErr> {
+        value
+            .as_bool()
+            .ok_or_else(|| DeTomlErr::err_type("boolean", value))
+    }
+}
+
+impl SerToml for String {
+    fn ser_toml(&self) -> Toml {
+        Toml::Str(self.clone())
+    }
+}
+
+impl DeToml for String {
+    fn de_toml(value: &Toml) -> Result<String, DeTomlErr> {
+        value
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| DeTomlErr::err_type("string", value))
+    }
+}
+
+impl SerToml for str {
+    fn ser_toml(&self) -> Toml {
+        Toml::Str(self.to_string())
+    }
+}
+
+impl SerToml for TomlDatetime {
+    fn ser_toml(&self) -> Toml {
+        Toml::Date(self.to_string())
+    }
+}
+
+impl DeToml for TomlDatetime {
+    fn de_toml(value: &Toml) -> Result<TomlDatetime, DeTomlErr> {
+        match value {
+            Toml::Date(date) => date.parse(),
+            _ => Err(DeTomlErr::err_type("datetime", value)),
+        }
+    }
+}
+
+impl SerToml for Toml {
+    fn ser_toml(&self) -> Toml {
+        self.clone()
+    }
+}
+
+impl DeToml for Toml {
+    fn de_toml(value: &Toml) -> Result<Toml, DeTomlErr> {
+        Ok(value.clone())
+    }
+}
+
+impl<T> SerToml for Vec<T>
+where
+    T: SerToml,
+{
+    fn ser_toml(&self) -> Toml {
+        Toml::SimpleArray(self.iter().map(SerToml::ser_toml).collect())
+    }
+}
+
+impl<T> DeToml for Vec<T>
+where
+    T: DeToml,
+{
+    fn de_toml(value: &Toml) -> Result<Vec<T>, DeTomlErr> {
+        match value {
+            Toml::SimpleArray(items) => items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| T::de_toml(item).map_err(|err| err.at_index(index)))
+                .collect(),
+            Toml::Array(tables) => tables
+                .iter()
+                .enumerate()
+                .map(|(index, table)| {
+                    T::de_toml(&Toml::Table(table.clone())).map_err(|err| err.at_index(index))
+                })
+                .collect(),
+            _ => Err(DeTomlErr::err_type("array", value)),
+        }
+    }
+}
+
+impl<T, const N: usize> SerToml for [T; N]
+where
+    T: SerToml,
+{
+    fn ser_toml(&self) -> Toml {
+        Toml::SimpleArray(self.iter().map(SerToml::ser_toml).collect())
+    }
+}
+
+impl<T, const N: usize> DeToml for [T; N]
+where
+    T: DeToml,
+{
+    fn de_toml(value: &Toml) -> Result<[T; N], DeTomlErr> {
+        let items: Vec<T> = DeToml::de_toml(value)?;
+        items
+            .try_into()
+            .map_err(|_| DeTomlErr::new(format!("Expected an array of {}", N)))
+    }
+}
+
+/// A present value, missing fields are left to the derive.
+impl<T> DeToml for Option<T>
+where
+    T: DeToml,
+{
+    fn de_toml(value: &Toml) -> Result<Option<T>, DeTomlErr> {
+        Ok(Some(T::de_toml(value)?))
+    }
+}
+
+impl<T> SerToml for Box<T>
+where
+    T: SerToml,
+{
+    fn ser_toml(&self) -> Toml {
+        (**self).ser_toml()
+    }
+}
+
+impl<T> DeToml for Box<T>
+where
+    T: DeToml,
+{
+    fn de_toml(value: &Toml) -> Result<Box<T>, DeTomlErr> {
+        Ok(Box::new(DeToml::de_toml(value)?))
+    }
+}
+
+impl<V> SerToml for BTreeMap<String, V>
+where
+    V: SerToml,
+{
+    fn ser_toml(&self) -> Toml {
+        Toml::Table(
+            self.iter()
+                .map(|(key, value)| (key.clone(), value.ser_toml()))
+                .collect(),
+        )
+    }
+}
+
+impl<V> DeToml for BTreeMap<String, V>
+where
+    V: DeToml,
+{
+    fn de_toml(value: &Toml) -> Result<BTreeMap<String, V>, DeTomlErr> {
+        value
+            .as_table()
+            .ok_or_else(|| DeTomlErr::err_type("table", value))?
+            .iter()
+            .map(|(key, value)| {
+                V::de_toml(value)
+                    .map(|value| (key.clone(), value))
+                    .map_err(|err| err.in_field(key))
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<V> SerToml for std::collections::HashMap<String, V>
+where
+    V: SerToml,
+{
+    fn ser_toml(&self) -> Toml {
+        Toml::Table(
+            self.iter()
+                .map(|(key, value)| (key.clone(), value.ser_toml()))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl<V> DeToml for std::collections::HashMap<String, V>
+where
+    V: DeToml,
+{
+    fn de_toml(value: &Toml) -> Result<std::collections::HashMap<String, V>, DeTomlErr> {
+        value
+            .as_table()
+            .ok_or_else(|| DeTomlErr::err_type("table", value))?
+            .iter()
+            .map(|(key, value)| {
+                V::de_toml(value)
+                    .map(|value| (key.clone(), value))
+                    .map_err(|err| err.in_field(key))
+            })
+            .collect()
+    }
+}
@@ -2,6 +2,7 @@
 use nanoserde::{DeJson, SerJson};
 
 use std::{
+    borrow::Cow,
     collections::{BTreeMap, BTreeSet, LinkedList},
     fmt::Debug,
     sync::atomic::AtomicBool,
@@ -1044,3 +1045,478 @@ fn test_deser_oversized_value() {
         )
     );
 }
+
+#[test]
+fn json_value() {
+    use nanoserde::JsonValue;
+
+    let json = r#"{
+        "null": null,
+        "bool": true,
+        "int": -3,
+        "float": 0.5,
+        "str": "a/b",
+        "arr": [1, "two", [3]],
+        "obj": { "a~b": { "c": false } }
+    }"#;
+
+    let value: JsonValue = DeJson::deserialize_json(json).unwrap();
+    assert!(value["null"].is_null());
+    assert_eq!(value["bool"].as_bool(), Some(true));
+    assert_eq!(value["int"].as_i64(), Some(-3));
+    assert_eq!(value["float"].as_f64(), Some(0.5));
+    assert_eq!(value["float"].as_i64(), None);
+    assert_eq!(value["str"].as_str(), Some("a/b"));
+    assert_eq!(value["arr"][1], JsonValue::String("two".to_string()));
+    assert_eq!(value["arr"][2][0].as_f64(), Some(3.0));
+    assert!(value["arr"][5].is_null());
+    assert!(value["missing"]["deeper"].is_null());
+    assert_eq!(value.get("missing"), None);
+
+    assert_eq!(value.pointer(""), Some(&value));
+    assert_eq!(
+        value.pointer("/arr/2/0").and_then(JsonValue::as_f64),
+        Some(3.0)
+    );
+    assert_eq!(value.pointer("/obj/a~0b/c"), Some(&JsonValue::Bool(false)));
+    assert_eq!(value.pointer("/arr/x"), None);
+    assert_eq!(value.pointer("arr"), None);
+
+    let serialized = value.serialize_json();
+    assert_eq!(
+        serialized,
+        r#"{"arr":[1,"two",[3]],"bool":true,"float":0.5,"int":-3,"null":null,"obj":{"a~b":{"c":false}},"str":"a/b"}"#
+    );
+    assert_eq!(
+        <JsonValue as DeJson>::deserialize_json(&serialized).unwrap(),
+        value
+    );
+}
+
+#[test]
+fn json_value_field() {
+    use nanoserde::JsonValue;
+
+    #[derive(DeJson, SerJson, PartialEq, Debug)]
+    pub struct Node {
+        name: String,
+        extras: Option<JsonValue>,
+        #[nserde(default)]
+        extensions: BTreeMap<String, JsonValue>,
+    }
+
+    let json = r#"{
+        "name": "n",
+        "extras": { "tags": ["a", "b"], "weight": 2 },
+        "extensions": { "EXT_unknown": { "x": [0.25] } }
+    }"#;
+
+    let node: Node = DeJson::deserialize_json(json).unwrap();
+    let extras = node.extras.as_ref().unwrap();
+    assert_eq!(extras["tags"][1].as_str(), Some("b"));
+    assert_eq!(extras["weight"].as_f64(), Some(2.0));
+    assert_eq!(
+        node.extensions["EXT_unknown"].pointer("/x/0"),
+        Some(&JsonValue::Number(0.25))
+    );
+
+    let node2: Node = DeJson::deserialize_json(&node.serialize_json()).unwrap();
+    assert_eq!(node, node2);
+
+    assert!(<JsonValue as DeJson>::deserialize_json("}").is_err());
+}
+
+#[test]
+fn enum_tagging() {
+    #[derive(DeJson, SerJson, PartialEq, Debug)]
+    pub struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(DeJson, SerJson, PartialEq, Debug)]
+    #[nserde(tag = "type")]
+    pub enum Internal {
+        Empty,
+        Circle { radius: f32 },
+        Point(Point),
+    }
+
+    #[derive(DeJson, SerJson, PartialEq, Debug)]
+    #[nserde(tag = "t", content = "c")]
+    pub enum Adjacent {
+        Empty,
+        Circle {
+            radius: f32,
+        },
+        #[nserde(rename = "pt")]
+        Point(Point),
+        Pair(i32, String),
+    }
+
+    #[derive(DeJson, SerJson, PartialEq, Debug)]
+    #[nserde(untagged)]
+    pub enum Untagged {
+        Empty,
+        Number(i32),
+        Text(String),
+        Circle { radius: f32 },
+        Pair(i32, String),
+    }
+
+    let values = [
+        (Internal::Empty, r#"{"type":"Empty"}"#),
+        (
+            Internal::Circle { radius: 0.5 },
+            r#"{"type":"Circle","radius":0.5}"#,
+        ),
+        (
+            Internal::Point(Point { x: 1, y: 2 }),
+            r#"{"type":"Point","x":1,"y":2}"#,
+        ),
+    ];
+    for (value, json) in values {
+        assert_eq!(value.serialize_json(), json);
+        assert_eq!(Internal::deserialize_json(json).unwrap(), value);
+    }
+    // the tag doesn't have to come first
+    assert_eq!(
+        Internal::deserialize_json(r#"{"y": 4, "type": "Point", "x": 3}"#).unwrap(),
+        Internal::Point(Point { x: 3, y: 4 })
+    );
+    assert!(Internal::deserialize_json(r#"{"radius": 0.5}"#).is_err());
+    assert!(Internal::deserialize_json(r#"{"type": "Square"}"#).is_err());
+
+    let values = [
+        (Adjacent::Empty, r#"{"t":"Empty"}"#),
+        (
+            Adjacent::Circle { radius: 0.5 },
+            r#"{"t":"Circle","c":{"radius":0.5}}"#,
+        ),
+        (
+            Adjacent::Point(Point { x: 1, y: 2 }),
+            r#"{"t":"pt","c":{"x":1,"y":2}}"#,
+        ),
+        (
+            Adjacent::Pair(1, "a".to_string()),
+            r#"{"t":"Pair","c":[1,"a"]}"#,
+        ),
+    ];
+    for (value, json) in values {
+        assert_eq!(value.serialize_json(), json);
+        assert_eq!(Adjacent::deserialize_json(json).unwrap(), value);
+    }
+    assert_eq!(
+        Adjacent::deserialize_json(r#"{"c": [2, "b"], "t": "Pair"}"#).unwrap(),
+        Adjacent::Pair(2, "b".to_string())
+    );
+
+    let values = [
+        (Untagged::Empty, "null"),
+        (Untagged::Number(3), "3"),
+        (Untagged::Text("a".to_string()), r#""a""#),
+        (Untagged::Circle { radius: 0.5 }, r#"{"radius":0.5}"#),
+        (Untagged::Pair(1, "a".to_string()), r#"[1,"a"]"#),
+    ];
+    for (value, json) in values {
+        assert_eq!(value.serialize_json(), json);
+        assert_eq!(Untagged::deserialize_json(json).unwrap(), value);
+    }
+    assert!(Untagged::deserialize_json("true").is_err());
+}
+
+#[test]
+fn flatten() {
+    #[derive(DeJson, SerJson, PartialEq, Debug)]
+    pub struct Position {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(DeJson, SerJson, PartialEq, Debug)]
+    pub struct Entity {
+        name: String,
+        #[nserde(flatten)]
+        position: Position,
+        #[nserde(flatten)]
+        rest: BTreeMap<String, nanoserde::JsonValue>,
+    }
+
+    let json = r#"{"x": 1.0, "name": "e", "hp": 3, "y": 2.0, "tags": ["a"]}"#;
+    let entity: Entity = DeJson::deserialize_json(json).unwrap();
+    assert_eq!(entity.name, "e");
+    assert_eq!(entity.position, Position { x: 1.0, y: 2.0 });
+    assert_eq!(entity.rest.len(), 2);
+    assert_eq!(entity.rest["hp"].as_i64(), Some(3));
+    assert_eq!(entity.rest["tags"][0].as_str(), Some("a"));
+
+    let serialized = entity.serialize_json();
+    assert_eq!(
+        serialized,
+        r#"{"name":"e","x":1.0,"y":2.0,"hp":3,"tags":["a"]}"#
+    );
+    assert_eq!(Entity::deserialize_json(&serialized).unwrap(), entity);
+
+    assert!(Entity::deserialize_json(r#"{"name": "e", "x": 1.0}"#).is_err());
+}
+
+#[test]
+fn deny_unknown_fields() {
+    #[derive(DeJson, PartialEq, Debug)]
+    #[nserde(deny_unknown_fields)]
+    pub struct Config {
+        color: String,
+        size: Option<u32>,
+    }
+
+    let config: Config = DeJson::deserialize_json(r#"{"color": "red", "size": 2}"#).unwrap();
+    assert_eq!(config.size, Some(2));
+
+    let err = Config::deserialize_json(r#"{"colour": "red"}"#).unwrap_err();
+    assert_eq!(err.msg, "Unexpected key colour, did you mean color?");
+    let err = Config::deserialize_json(r#"{"color": "red", "weight": 1}"#).unwrap_err();
+    assert_eq!(err.msg, "Unexpected key weight");
+}
+
+#[test]
+fn collect_errors() {
+    #[derive(DeJson, PartialEq, Debug)]
+    pub struct Pbr {
+        #[nserde(rename = "baseColorFactor")]
+        base_color_factor: [f32; 4],
+        metallic: Option<f32>,
+    }
+
+    #[derive(DeJson, PartialEq, Debug)]
+    pub struct Material {
+        name: String,
+        #[nserde(rename = "pbrMetallicRoughness")]
+        pbr: Pbr,
+    }
+
+    #[derive(DeJson, PartialEq, Debug)]
+    #[nserde(deny_unknown_fields)]
+    pub struct Document {
+        materials: Vec<Material>,
+        scale: f32,
+    }
+
+    let json = r#"{
+        "materials": [
+            { "name": "a", "pbrMetallicRoughness": { "baseColorFactor": [1, 1, 1, 1] } },
+            { "name": "b", "pbrMetallicRoughness": { "baseColorFactor": "red" } },
+            { "pbrMetallicRoughness": { "baseColorFactor": [0, 0, 0, 1], "metallic": true } }
+        ],
+        "scael": 2
+    }"#;
+    let errors = Document::deserialize_json_all(json).unwrap_err();
+    let paths: Vec<&str> = errors.iter().map(|error| error.path.as_str()).collect();
+    assert_eq!(
+        paths,
+        [
+            "materials[1].pbrMetallicRoughness.baseColorFactor",
+            "materials[2].pbrMetallicRoughness.metallic",
+            "materials[2].name",
+            "scael",
+            "scale",
+        ]
+    );
+    assert_eq!(errors[2].err.msg, "Key not found name");
+    assert_eq!(
+        errors[3].to_string(),
+        "scael: Json Deserialize error: Unexpected key scael, did you mean scale?, line:7 col:17"
+    );
+
+    // the fast path still stops at the first one
+    assert!(Document::deserialize_json(json).is_err());
+
+    let json = r#"{"materials": [{"name": "a", "pbrMetallicRoughness": {"baseColorFactor": [1, 0, 0, 1]}}], "scale": 1}"#;
+    let document = Document::deserialize_json_all(json).unwrap();
+    assert_eq!(document, Document::deserialize_json(json).unwrap());
+
+    let errors = Document::deserialize_json_all(r#"{"materials": [}"#).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, "");
+}
+
+#[test]
+fn borrowed_strings() {
+    #[derive(DeJson, SerJson, Debug, PartialEq)]
+    struct Image<'a> {
+        name: &'a str,
+        uri: Cow<'a, str>,
+        #[nserde(rename = "mimeType")]
+        mime_type: Option<&'a str>,
+    }
+
+    let json = r#"{"name": "albedo", "uri": "data:image/png;base64,iVBORw0KGgo=", "mimeType": "image/png"}"#;
+    let image = Image::deserialize_json(json).unwrap();
+    assert_eq!(image.name, "albedo");
+    assert_eq!(image.mime_type, Some("image/png"));
+    assert!(matches!(image.uri, Cow::Borrowed(_)));
+    // pointing into the input, not a copy
+    let range = json.as_bytes().as_ptr_range();
+    assert!(range.contains(&image.uri.as_ptr()));
+    assert_eq!(
+        Image::deserialize_json(&image.serialize_json()).unwrap(),
+        image
+    );
+
+    // escaped strings can only be owned
+    let json = r#"{"name": "albedo", "uri": "textures\/albedo.png"}"#;
+    let image = Image::deserialize_json(json).unwrap();
+    assert_eq!(image.uri, "textures/albedo.png");
+    assert!(matches!(image.uri, Cow::Owned(_)));
+    let err = Image::deserialize_json(r#"{"name": "\u00e9", "uri": ""}"#).unwrap_err();
+    assert!(err.msg.contains("Cow<str>"));
+
+    // rewinding over a value that failed keeps the strings after it borrowed
+    #[derive(DeJson, Debug)]
+    #[allow(dead_code)]
+    struct Document<'a> {
+        scale: Option<f32>,
+        images: Vec<Image<'a>>,
+    }
+
+    let json =
+        r#"{"scale": "big", "images": [{"name": 1, "uri": "a"}, {"name": "b", "uri": "b"}]}"#;
+    let errors = Document::deserialize_json_all(json).unwrap_err();
+    let paths: Vec<&str> = errors.iter().map(|error| error.path.as_str()).collect();
+    assert_eq!(paths, ["scale", "images[0].name"]);
+}
+
+#[test]
+fn bytes_input() {
+    let out: Vec<String> = DeJson::deserialize_json_bytes("[\"π\", \"é\"]".as_bytes()).unwrap();
+    assert_eq!(out, ["π", "é"]);
+
+    let err = <Vec<String>>::deserialize_json_bytes(b"[\"\xff\"]").unwrap_err();
+    assert_eq!(err.msg, "Invalid UTF-8 at byte 2");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn reader_input() {
+    use std::io::{Cursor, Read};
+
+    /// Reads a few bytes at a time, splitting strings and characters between chunks.
+    struct Trickle<R>(R);
+
+    impl<R: Read> Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(3);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[derive(DeJson, PartialEq, Debug)]
+    struct Mesh<'a> {
+        name: Cow<'a, str>,
+        weights: Vec<f32>,
+        extras: HashMap<String, String>,
+    }
+
+    let json = r#"{
+        "name": "Hélmet ✓ 😀",
+        // a comment
+        "weights": [0.5, 1e-3, 2],
+        "extras": {"author": "\u00e9t\u00e9 \ud83d\ude00", "long": "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"}
+    }"#;
+    let expected = Mesh::deserialize_json(json).unwrap();
+    let mesh = Mesh::deserialize_json_reader(Trickle(json.as_bytes())).unwrap();
+    assert_eq!(mesh, expected);
+    assert!(matches!(mesh.name, Cow::Owned(_)));
+    let mesh = Mesh::deserialize_json_reader(Cursor::new(json)).unwrap();
+    assert_eq!(mesh, expected);
+
+    // strings can't be borrowed out of a reader
+    assert!(<&str>::deserialize_json_reader(Cursor::new("\"a\"")).is_err());
+
+    let err = String::deserialize_json_reader(Cursor::new(b"\"a\xe2\x9c")).unwrap_err();
+    assert_eq!(err.msg, "Invalid UTF-8 at the end of the input");
+    let err = String::deserialize_json_reader(Trickle(&b"\"a\xffbc\""[..])).unwrap_err();
+    assert_eq!(err.msg, "Invalid UTF-8 in the input");
+
+    struct Failing;
+
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("disk on fire"))
+        }
+    }
+
+    let err = String::deserialize_json_reader(Failing).unwrap_err();
+    assert_eq!(err.msg, "Cannot read the input: disk on fire");
+}
+#[cfg(feature = "std")]
+#[test]
+fn gltf_input_paths() {
+    use nanoserde::JsonValue;
+
+    #[derive(DeJson, Debug)]
+    struct Gltf<T> {
+        buffers: Vec<Buffer<T>>,
+        images: Option<Vec<Buffer<T>>>,
+        accessors: JsonValue,
+        #[nserde(rename = "bufferViews")]
+        buffer_views: JsonValue,
+        nodes: JsonValue,
+    }
+
+    #[derive(DeJson, Debug)]
+    struct Buffer<T> {
+        uri: Option<T>,
+    }
+
+    impl<T: AsRef<str>> Gltf<T> {
+        fn uris(&self) -> Vec<Option<&str>> {
+            let images = self.images.iter().flatten();
+            self.buffers
+                .iter()
+                .chain(images)
+                .map(|b| b.uri.as_ref().map(AsRef::as_ref))
+                .collect()
+        }
+
+        fn values(&self) -> [&JsonValue; 3] {
+            [&self.accessors, &self.buffer_views, &self.nodes]
+        }
+    }
+
+    let json = r#"{
+        "asset": {"version": "2.0", "generator": "nanoserde"},
+        "buffers": [{"byteLength": 12, "uri": "data:application/octet-stream;base64,AAAAAAAAgD8AAABA"}],
+        "bufferViews": [{"buffer": 0, "byteOffset": 0, "byteLength": 12, "target": 34962}],
+        "accessors": [{"bufferView": 0, "componentType": 5126, "count": 3, "type": "SCALAR", "min": [0], "max": [2]}],
+        "images": [{"uri": "textures/Default_albedo.jpg"}, {"uri": null, "bufferView": 0}],
+        "nodes": [{"name": "node_damagedHelmet", "mesh": 0, "rotation": [0.7071068, 0, 0, 0.7071068]}]
+    }"#;
+    let expected = [
+        Some("data:application/octet-stream;base64,AAAAAAAAgD8AAABA"),
+        Some("textures/Default_albedo.jpg"),
+        None,
+    ];
+
+    let owned = Gltf::<String>::deserialize_json(json).unwrap();
+    assert_eq!(owned.uris(), expected);
+    assert_eq!(
+        owned.nodes[0]["name"],
+        JsonValue::String("node_damagedHelmet".to_string())
+    );
+    assert_eq!(owned.buffer_views[0]["target"].as_f64(), Some(34962.0));
+
+    let borrowed = Gltf::<&str>::deserialize_json(json).unwrap();
+    assert_eq!(borrowed.uris(), expected);
+    assert_eq!(borrowed.values(), owned.values());
+    let uri = borrowed.buffers[0].uri.unwrap();
+    assert!(json.as_bytes().as_ptr_range().contains(&uri.as_ptr()));
+
+    let bytes = Gltf::<Cow<str>>::deserialize_json_bytes(json.as_bytes()).unwrap();
+    assert_eq!(bytes.uris(), expected);
+    assert_eq!(bytes.values(), owned.values());
+    assert!(matches!(bytes.buffers[0].uri, Some(Cow::Borrowed(_))));
+
+    let reader = Gltf::<String>::deserialize_json_reader(json.as_bytes()).unwrap();
+    assert_eq!(reader.uris(), expected);
+    assert_eq!(reader.values(), owned.values());
+}
@@ -583,3 +583,118 @@ fn test_deser_oversized_value() {
         )
     );
 }
+
+#[test]
+fn enum_tagging() {
+    #[derive(DeRon, SerRon, PartialEq, Debug)]
+    pub struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(DeRon, SerRon, PartialEq, Debug)]
+    #[nserde(tag = "type")]
+    pub enum Internal {
+        Empty,
+        Circle { radius: f32 },
+        Point(Point),
+    }
+
+    #[derive(DeRon, SerRon, PartialEq, Debug)]
+    #[nserde(tag = "t", content = "c")]
+    pub enum Adjacent {
+        Empty,
+        Circle { radius: f32 },
+        Point(Point),
+        Pair(i32, String),
+    }
+
+    #[derive(DeRon, SerRon, PartialEq, Debug)]
+    #[nserde(untagged)]
+    pub enum Untagged {
+        Empty,
+        Number(i32),
+        Text(String),
+        Circle { radius: f32 },
+        Pair(i32, String),
+    }
+
+    for value in [
+        Internal::Empty,
+        Internal::Circle { radius: 0.5 },
+        Internal::Point(Point { x: 1, y: 2 }),
+    ] {
+        assert_eq!(
+            Internal::deserialize_ron(&value.serialize_ron()).unwrap(),
+            value
+        );
+    }
+    assert_eq!(
+        Internal::deserialize_ron(r#"(y: 4, type: "Point", x: 3)"#).unwrap(),
+        Internal::Point(Point { x: 3, y: 4 })
+    );
+    assert!(Internal::deserialize_ron("(radius: 0.5)").is_err());
+
+    for value in [
+        Adjacent::Empty,
+        Adjacent::Circle { radius: 0.5 },
+        Adjacent::Point(Point { x: 1, y: 2 }),
+        Adjacent::Pair(1, "a".to_string()),
+    ] {
+        assert_eq!(
+            Adjacent::deserialize_ron(&value.serialize_ron()).unwrap(),
+            value
+        );
+    }
+    assert_eq!(
+        Adjacent::deserialize_ron(r#"(c: (x: 1, y: 2), t: "Point")"#).unwrap(),
+        Adjacent::Point(Point { x: 1, y: 2 })
+    );
+
+    for value in [
+        Untagged::Empty,
+        Untagged::Number(3),
+        Untagged::Text("a".to_string()),
+        Untagged::Circle { radius: 0.5 },
+        Untagged::Pair(1, "a".to_string()),
+    ] {
+        assert_eq!(
+            Untagged::deserialize_ron(&value.serialize_ron()).unwrap(),
+            value
+        );
+    }
+    assert!(Untagged::deserialize_ron("true").is_err());
+}
+
+#[test]
+fn flatten() {
+    #[derive(DeRon, SerRon, PartialEq, Debug)]
+    pub struct Position {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(DeRon, SerRon, PartialEq, Debug)]
+    pub struct Entity {
+        name: String,
+        #[nserde(flatten)]
+        position: Position,
+        #[nserde(flatten)]
+        rest: BTreeMap<String, i32>,
+    }
+
+    let ron = r#"(x: 1.0, name: "e", hp: 3, y: 2.0, level: 7)"#;
+    let entity: Entity = DeRon::deserialize_ron(ron).unwrap();
+    assert_eq!(entity.name, "e");
+    assert_eq!(entity.position, Position { x: 1.0, y: 2.0 });
+    assert_eq!(entity.rest.len(), 2);
+    assert_eq!(entity.rest["hp"], 3);
+    assert_eq!(entity.rest["level"], 7);
+
+    assert_eq!(
+        Entity::deserialize_ron(&entity.serialize_ron()).unwrap(),
+        entity
+    );
+
+    assert!(Entity::deserialize_ron(r#"(name: "e", x: 1.0)"#).is_err());
+}
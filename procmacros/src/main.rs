@@ -1,6 +1,7 @@
 use nanoserde::DeJson;
 
-#[derive(DeJson)]
+#[derive(DeJson, Debug)]
+#[nserde(deny_unknown_fields)]
 pub struct Test {
     pub a: f32,
     pub b: f32,
@@ -17,4 +18,16 @@ fn main() {
 
     let test: Test = DeJson::deserialize_json(json).unwrap();
     assert_eq!(test.a, 1.);
+
+    // a typo in a key is an error instead of a silent `None`
+    let json = r#"{
+        "a": 1,
+        "dd": "hello"
+    }"#;
+
+    let errors = Test::deserialize_json_all(json).unwrap_err();
+    for error in &errors {
+        println!("{}", error);
+    }
+    assert_eq!(errors.len(), 2);
 }